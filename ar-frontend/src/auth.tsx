@@ -2,13 +2,35 @@ import { createContext } from "react";
 import * as jose from "jose";
 import { z } from "zod";
 import { initLogin } from "./network/idp";
+import { baseAPIUrl } from "./network/fetch";
 
 export type AuthContext = {
   token: string | null;
-  setToken: (token: string) => void;
-  getToken: () => string;
+  refreshToken: string | null;
+  setToken: (token: string, refreshToken?: string) => void;
+  getToken: () => Promise<string>;
 };
 
+const refreshResponseSchema = z.object({
+  access_token: z.string(),
+  refresh_token: z.string(),
+});
+
+async function refreshSession(refreshToken: string) {
+  const response = await fetch(`${baseAPIUrl}/connect/human/refresh`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ refresh_token: refreshToken }),
+  });
+
+  if (!response.ok) {
+    return null;
+  }
+
+  const result = refreshResponseSchema.safeParse(await response.json());
+  return result.success ? result.data : null;
+}
+
 const tokenSchema = z.object({
   exp: z.number(),
   company_id: z.string(),
@@ -52,22 +74,43 @@ export function isAuthenticated(token: string | null): boolean {
 
 import { create } from "zustand";
 
+let pendingRefresh: Promise<string | null> | null = null;
+
 export const useAuthStore = create<AuthContext>((set, get) => ({
   token: null,
-  setToken: (token: string) => set({ token }),
-  getToken: () => {
-    const token = get().token;
+  refreshToken: null,
+  setToken: (token: string, refreshToken?: string) =>
+    set({ token, refreshToken: refreshToken ?? null }),
+  getToken: async () => {
+    const { token, refreshToken } = get();
 
     if (token === null) {
       initLogin();
       throw new Error("initiating login");
     }
 
-    if (!isAuthenticated(token)) {
-      initLogin();
-      throw new Error("logging in required");
+    if (isAuthenticated(token)) {
+      return token;
+    }
+
+    // refresh tokens are single use, so concurrent requests share one refresh
+    if (refreshToken !== null) {
+      pendingRefresh ??= refreshSession(refreshToken).then((refreshed) => {
+        pendingRefresh = null;
+        set({
+          token: refreshed?.access_token ?? token,
+          refreshToken: refreshed?.refresh_token ?? null,
+        });
+        return refreshed?.access_token ?? null;
+      });
+
+      const refreshedToken = await pendingRefresh;
+      if (refreshedToken !== null) {
+        return refreshedToken;
+      }
     }
 
-    return token;
+    initLogin();
+    throw new Error("logging in required");
   },
 }));
@@ -39,7 +39,7 @@ export function useAuthenticatedFetch() {
     input: RequestInfo | URL,
     init?: RequestInit,
   ) => {
-    const token = useAuthStore.getState().getToken();
+    const token = await useAuthStore.getState().getToken();
 
     const headers = {
       Authorization: `Bearer ${token}`,
@@ -11,7 +11,11 @@ export const Route = createFileRoute("/callback")({
   validateSearch: validateSearch,
   component: Component,
   beforeLoad: ({ search }) => {
-    useAuthStore.getState().setToken(search.token);
+    // the refresh token is passed in the fragment, so it is never sent to a server
+    const refreshToken =
+      new URLSearchParams(window.location.hash.slice(1)).get("refresh_token") ??
+      undefined;
+    useAuthStore.getState().setToken(search.token, refreshToken);
     const tokenContent = getTokenContent(search.token);
 
     if (search.state) {
@@ -18,6 +18,7 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context: Option<Json>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -9,3 +9,10 @@ pub mod policy;
 pub mod policy_set;
 pub mod policy_set_template;
 pub mod audit_event;
+pub mod token_revocation;
+pub mod refresh_token;
+pub mod auth_request;
+pub mod client_assertion_jti;
+pub mod policy_set_suspension;
+pub mod policy_set_change;
+pub mod replication_delivery;
@@ -21,6 +21,9 @@ pub struct Model {
     #[sea_orm(default_value = "now()")]
     #[serde(default = "default_created")]
     pub created: DateTimeUtc,
+    #[sea_orm(column_type = "Text", nullable)]
+    #[serde(default)]
+    pub dataspace: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
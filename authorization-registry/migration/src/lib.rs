@@ -6,6 +6,14 @@ mod m20250613_125956_policy_set_template_description_column;
 mod m20250619_124921_add_audit_log_table;
 mod m20250624_113240_policy_set_creation_column;
 mod m20250728_104738_audit_log_entry;
+mod m20251018_120000_audit_event_search_indexes;
+mod m20251018_130000_token_revocation;
+mod m20251018_140000_refresh_token;
+mod m20251018_150000_auth_request;
+mod m20251018_160000_client_assertion_jti;
+mod m20251019_100000_policy_set_suspension;
+mod m20251019_110000_policy_set_dataspace_column;
+mod m20251019_120000_policy_set_replication;
 
 pub struct Migrator;
 
@@ -19,6 +27,14 @@ impl MigratorTrait for Migrator {
             Box::new(m20250619_124921_add_audit_log_table::Migration),
             Box::new(m20250624_113240_policy_set_creation_column::Migration),
             Box::new(m20250728_104738_audit_log_entry::Migration),
+            Box::new(m20251018_120000_audit_event_search_indexes::Migration),
+            Box::new(m20251018_130000_token_revocation::Migration),
+            Box::new(m20251018_140000_refresh_token::Migration),
+            Box::new(m20251018_150000_auth_request::Migration),
+            Box::new(m20251018_160000_client_assertion_jti::Migration),
+            Box::new(m20251019_100000_policy_set_suspension::Migration),
+            Box::new(m20251019_110000_policy_set_dataspace_column::Migration),
+            Box::new(m20251019_120000_policy_set_replication::Migration),
         ]
     }
 }
@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20250619_124921_add_audit_log_table::AuditEvent;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const CONTEXT_INDEX: &str = "idx_audit_event_context";
+const ENTRY_ID_INDEX: &str = "idx_audit_event_entry_id";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // json does not support GIN indexes or jsonpath operators, so the context is stored as jsonb
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE audit_event ALTER COLUMN context TYPE jsonb USING context::jsonb",
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON audit_event USING GIN (context)",
+                CONTEXT_INDEX
+            ))
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name(ENTRY_ID_INDEX)
+                    .table(AuditEvent::Table)
+                    .col(AuditEvent::EntryId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(ENTRY_ID_INDEX)
+                    .table(AuditEvent::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(CONTEXT_INDEX)
+                    .table(AuditEvent::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE audit_event ALTER COLUMN context TYPE json USING context::json",
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+pub enum TokenRevocation {
+    Table,
+    Id,
+    Jti,
+    UserId,
+    CompanyId,
+    IssuedBefore,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TokenRevocation::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TokenRevocation::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(TokenRevocation::Jti).text())
+                    .col(ColumnDef::new(TokenRevocation::UserId).text())
+                    .col(ColumnDef::new(TokenRevocation::CompanyId).text())
+                    .col(ColumnDef::new(TokenRevocation::IssuedBefore).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(TokenRevocation::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TokenRevocation::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, column) in [
+            ("idx_token_revocation_jti", TokenRevocation::Jti),
+            ("idx_token_revocation_user_id", TokenRevocation::UserId),
+            (
+                "idx_token_revocation_company_id",
+                TokenRevocation::CompanyId,
+            ),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .if_not_exists()
+                        .name(name)
+                        .table(TokenRevocation::Table)
+                        .col(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TokenRevocation::Table).to_owned())
+            .await
+    }
+}
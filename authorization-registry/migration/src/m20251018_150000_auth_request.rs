@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+pub enum AuthRequest {
+    Table,
+    Id,
+    State,
+    Nonce,
+    CodeVerifier,
+    RedirectUri,
+    ClientState,
+    CreatedAt,
+    ExpiresAt,
+    UsedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthRequest::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuthRequest::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AuthRequest::State)
+                            .text()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(AuthRequest::Nonce).text().not_null())
+                    .col(ColumnDef::new(AuthRequest::CodeVerifier).text().not_null())
+                    .col(ColumnDef::new(AuthRequest::RedirectUri).text().not_null())
+                    .col(ColumnDef::new(AuthRequest::ClientState).text())
+                    .col(
+                        ColumnDef::new(AuthRequest::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AuthRequest::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuthRequest::UsedAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthRequest::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+pub enum ClientAssertionJti {
+    Table,
+    Id,
+    Issuer,
+    Jti,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ClientAssertionJti::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ClientAssertionJti::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ClientAssertionJti::Issuer).text().not_null())
+                    .col(ColumnDef::new(ClientAssertionJti::Jti).text().not_null())
+                    .col(
+                        ColumnDef::new(ClientAssertionJti::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ClientAssertionJti::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_client_assertion_jti_issuer_jti")
+                    .table(ClientAssertionJti::Table)
+                    .col(ClientAssertionJti::Issuer)
+                    .col(ClientAssertionJti::Jti)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ClientAssertionJti::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::PolicySet;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+pub enum PolicySetSuspension {
+    Table,
+    PolicySetId,
+    Party,
+    Reason,
+    SuspendedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PolicySetSuspension::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PolicySetSuspension::PolicySetId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PolicySetSuspension::Party).text().not_null())
+                    .col(ColumnDef::new(PolicySetSuspension::Reason).text().not_null())
+                    .col(
+                        ColumnDef::new(PolicySetSuspension::SuspendedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(PolicySetSuspension::PolicySetId)
+                            .col(PolicySetSuspension::Party),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-policy_set_suspension-policy_set")
+                            .from(
+                                PolicySetSuspension::Table,
+                                PolicySetSuspension::PolicySetId,
+                            )
+                            .to(PolicySet::Table, PolicySet::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PolicySetSuspension::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,112 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+pub enum PolicySetChange {
+    Table,
+    PolicySetId,
+    Version,
+    ChangedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum ReplicationDelivery {
+    Table,
+    Target,
+    PolicySetId,
+    RemoteId,
+    DeliveredVersion,
+    Attempts,
+    LastError,
+    NextAttemptAt,
+    UpdatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // no foreign key to policy_set, the change of a deleted policy set still has to be
+        // delivered to the replication targets
+        manager
+            .create_table(
+                Table::create()
+                    .table(PolicySetChange::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PolicySetChange::PolicySetId)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PolicySetChange::Version)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PolicySetChange::ChangedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReplicationDelivery::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ReplicationDelivery::Target).text().not_null())
+                    .col(
+                        ColumnDef::new(ReplicationDelivery::PolicySetId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ReplicationDelivery::RemoteId).text().null())
+                    .col(
+                        ColumnDef::new(ReplicationDelivery::DeliveredVersion)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ReplicationDelivery::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(ReplicationDelivery::LastError).text().null())
+                    .col(
+                        ColumnDef::new(ReplicationDelivery::NextAttemptAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ReplicationDelivery::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ReplicationDelivery::Target)
+                            .col(ReplicationDelivery::PolicySetId),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReplicationDelivery::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(PolicySetChange::Table).to_owned())
+            .await
+    }
+}
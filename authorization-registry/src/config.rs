@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use ishare::ishare::AllowedDataspaces;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +11,18 @@ fn default_jwt_expiry_seconds() -> u64 {
     3600
 }
 
+fn default_refresh_token_expiry_seconds() -> i64 {
+    86400
+}
+
+fn default_session_lifetime_seconds() -> i64 {
+    604800
+}
+
+fn default_auth_request_expiry_seconds() -> i64 {
+    600
+}
+
 fn default_de_expiry_seconds() -> i64 {
     3600
 }
@@ -78,6 +92,417 @@ pub struct FrontendConfig {
     pub footer: FooterConfig,
 }
 
+fn default_audit_log_retention_interval_seconds() -> u64 {
+    3600
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditLogRetentionConfig {
+    // folder in which the compressed archives and their manifests are written
+    pub archive_folder: String,
+    #[serde(default = "default_audit_log_retention_interval_seconds")]
+    pub interval_seconds: u64,
+    // retention for event types that are not listed in `event_types`. keep forever when not set
+    pub default_retention_days: Option<i64>,
+    // retention in days per event type, e.g. "dmi:ar:delegation:request": 30
+    #[serde(default)]
+    pub event_types: HashMap<String, i64>,
+}
+
+// at most `requests` requests per `window_seconds`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitBudget {
+    pub requests: u64,
+    pub window_seconds: i64,
+}
+
+// budgets of a route group. The party is the client_id of token requests or the company of the
+// access token
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RateLimitGroupConfig {
+    pub per_party: Option<RateLimitBudget>,
+    pub per_ip: Option<RateLimitBudget>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RateLimitConfig {
+    // `POST /connect/machine/token`
+    #[serde(default)]
+    pub machine_token: RateLimitGroupConfig,
+    // `POST /delegation`
+    #[serde(default)]
+    pub delegation: RateLimitGroupConfig,
+    // take the source ip from the first address of X-Forwarded-For. Only enable this behind a
+    // proxy that sets the header
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+fn default_party_cache_ttl_seconds() -> i64 {
+    300
+}
+
+fn default_party_cache_negative_ttl_seconds() -> i64 {
+    60
+}
+
+fn default_party_cache_max_stale_seconds() -> i64 {
+    3600
+}
+
+// caching of the party info of the satellite. A ttl of 0 disables the cache
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PartyCacheConfig {
+    #[serde(default = "default_party_cache_ttl_seconds")]
+    pub ttl_seconds: i64,
+    // parties that are unknown, inactive or outside the dataspace
+    #[serde(default = "default_party_cache_negative_ttl_seconds")]
+    pub negative_ttl_seconds: i64,
+    // how long after the ttl an entry is still used when the satellite can not be reached
+    #[serde(default = "default_party_cache_max_stale_seconds")]
+    pub max_stale_seconds: i64,
+}
+
+impl Default for PartyCacheConfig {
+    fn default() -> Self {
+        return Self {
+            ttl_seconds: default_party_cache_ttl_seconds(),
+            negative_ttl_seconds: default_party_cache_negative_ttl_seconds(),
+            max_stale_seconds: default_party_cache_max_stale_seconds(),
+        };
+    }
+}
+
+// checks on the party info of the satellite for the parties of policy sets, policies and
+// delegation evidence, on top of the party being active. All checks are off by default
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PartyChecksConfig {
+    // the adherence of a party has to cover the validity period of the delegation evidence
+    #[serde(default)]
+    pub adherence: bool,
+    // a party has to hold one of the roles for the period, an empty list disables the check
+    #[serde(default)]
+    pub service_provider_roles: Vec<String>,
+    #[serde(default)]
+    pub policy_issuer_roles: Vec<String>,
+    #[serde(default)]
+    pub access_subject_roles: Vec<String>,
+}
+
+fn default_party_revalidation_interval_seconds() -> u64 {
+    3600
+}
+
+// periodic revalidation of the parties of the stored policy sets. Policy sets with a party that
+// is no longer valid are suspended. An interval of 0 disables the revalidation
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PartyRevalidationConfig {
+    #[serde(default = "default_party_revalidation_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+impl Default for PartyRevalidationConfig {
+    fn default() -> Self {
+        return Self {
+            interval_seconds: default_party_revalidation_interval_seconds(),
+        };
+    }
+}
+
+fn default_dataspace_id() -> String {
+    "default".to_owned()
+}
+
+// a dataspace with its own satellite, next to the dataspace of `satellite_url`
+#[derive(Deserialize, Clone, Debug)]
+pub struct DataspaceSatelliteConfig {
+    pub id: String,
+    pub satellite_url: String,
+    pub satellite_eori: String,
+    pub ishare_ca_path: String,
+    pub dataspace_config: Option<AllowedDataspaces>,
+}
+
+// serve the party information from a local snapshot instead of the satellite, for test
+// environments and demos without access to a satellite
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OfflineSatelliteConfig {
+    pub snapshot_path: String,
+}
+
+// an external iSHARE PDP that decides on access to policy sets and the audit log, instead of the
+// policy sets of this registry
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PdpConfig {
+    pub url: String,
+    pub eori: String,
+}
+
+fn default_replication_interval_seconds() -> u64 {
+    30
+}
+
+fn default_replication_max_attempts() -> i32 {
+    10
+}
+
+fn default_replication_retry_seconds() -> i64 {
+    60
+}
+
+// a pdp or peer authorization registry that serves the `/policy-set` api of this registry and
+// receives a copy of every policy set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplicationTargetConfig {
+    pub id: String,
+    pub url: String,
+    pub eori: String,
+}
+
+// the retry delay doubles with every failed attempt. A delivery that failed `max_attempts` times
+// is retried after the next change of the policy set or a resync of the target
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplicationConfig {
+    #[serde(default = "default_replication_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_replication_max_attempts")]
+    pub max_attempts: i32,
+    #[serde(default = "default_replication_retry_seconds")]
+    pub retry_seconds: i64,
+    #[serde(default)]
+    pub targets: Vec<ReplicationTargetConfig>,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        return Self {
+            interval_seconds: default_replication_interval_seconds(),
+            max_attempts: default_replication_max_attempts(),
+            retry_seconds: default_replication_retry_seconds(),
+            targets: vec![],
+        };
+    }
+}
+
+fn default_federation_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_federation_failure_threshold() -> u32 {
+    5
+}
+
+fn default_federation_open_seconds() -> i64 {
+    60
+}
+
+// the authorization registry of policy issuers that keep their policies elsewhere
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoteRegistryConfig {
+    pub id: String,
+    pub url: String,
+    pub eori: String,
+    pub policy_issuers: Vec<String>,
+}
+
+// delegation requests for the policy issuers of a remote registry are forwarded to that registry.
+// After `failure_threshold` failed requests in a row the registry is not asked for
+// `open_seconds`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FederationConfig {
+    #[serde(default = "default_federation_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_federation_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_federation_open_seconds")]
+    pub open_seconds: i64,
+    #[serde(default)]
+    pub registries: Vec<RemoteRegistryConfig>,
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        return Self {
+            timeout_ms: default_federation_timeout_ms(),
+            failure_threshold: default_federation_failure_threshold(),
+            open_seconds: default_federation_open_seconds(),
+            registries: vec![],
+        };
+    }
+}
+
+// where the jti of used client assertions is stored. `postgres` is needed when several
+// instances of the authorization registry run behind a load balancer
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayStoreBackend {
+    #[default]
+    Memory,
+    Postgres,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum JwtAlgorithm {
+    RS256,
+    ES256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JwtVerificationKeyConfig {
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    pub public_key_path: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JwtSigningConfig {
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    // pem encoded private key (pkcs8 for ES256)
+    pub private_key_path: String,
+    pub public_key_path: String,
+    // additional public keys that are still accepted, e.g. the previous key during rotation
+    #[serde(default)]
+    pub verification_keys: Vec<JwtVerificationKeyConfig>,
+}
+
+fn default_oidc_scope() -> String {
+    "openid profile email".to_owned()
+}
+
+fn default_oidc_id_token_algorithm() -> JwtAlgorithm {
+    JwtAlgorithm::RS256
+}
+
+fn default_company_id_claim() -> String {
+    "company_id".to_owned()
+}
+
+fn default_company_name_claim() -> String {
+    "company_name".to_owned()
+}
+
+fn default_name_claim() -> String {
+    "name".to_owned()
+}
+
+fn default_email_claim() -> String {
+    "email".to_owned()
+}
+
+fn default_roles_claim() -> String {
+    "realm_access.roles".to_owned()
+}
+
+// dot separated paths of the id token claims, e.g. "realm_access.roles"
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OidcClaimMapping {
+    #[serde(default = "default_company_id_claim")]
+    pub company_id: String,
+    #[serde(default = "default_company_name_claim")]
+    pub company_name: String,
+    #[serde(default = "default_name_claim")]
+    pub name: String,
+    #[serde(default = "default_email_claim")]
+    pub email: String,
+    #[serde(default = "default_roles_claim")]
+    pub roles: String,
+}
+
+impl Default for OidcClaimMapping {
+    fn default() -> Self {
+        return Self {
+            company_id: default_company_id_claim(),
+            company_name: default_company_name_claim(),
+            name: default_name_claim(),
+            email: default_email_claim(),
+            roles: default_roles_claim(),
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OidcIdentityProviderConfig {
+    // the discovery document is read from `{issuer_url}/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    #[serde(default = "default_oidc_scope")]
+    pub scope: String,
+    #[serde(default)]
+    pub claims: OidcClaimMapping,
+    // the algorithm of the id token when the key of the provider does not name one
+    #[serde(default = "default_oidc_id_token_algorithm")]
+    pub id_token_algorithm: JwtAlgorithm,
+}
+
+fn default_admin_roles() -> Vec<String> {
+    vec!["dexspace_admin".to_owned()]
+}
+
+// realm access roles that give access to the admin endpoints
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdminRolesConfig {
+    // all admin endpoints
+    #[serde(default = "default_admin_roles")]
+    pub admin: Vec<String>,
+    // reading and managing the policy sets of all parties
+    #[serde(default)]
+    pub policy_admin: Vec<String>,
+    // creating and deleting policy set templates
+    #[serde(default)]
+    pub template_admin: Vec<String>,
+    // the status of the audit log
+    #[serde(default)]
+    pub audit_reader: Vec<String>,
+    // reading policy sets and the status of the audit log
+    #[serde(default)]
+    pub read_only_admin: Vec<String>,
+}
+
+impl Default for AdminRolesConfig {
+    fn default() -> Self {
+        return Self {
+            admin: default_admin_roles(),
+            policy_admin: vec![],
+            template_admin: vec![],
+            audit_reader: vec![],
+            read_only_admin: vec![],
+        };
+    }
+}
+
+impl AdminRolesConfig {
+    fn with_admin(&self, roles: &[&Vec<String>]) -> Vec<String> {
+        return self
+            .admin
+            .iter()
+            .chain(roles.iter().flat_map(|r| r.iter()))
+            .cloned()
+            .collect();
+    }
+
+    pub fn admin_roles(&self) -> Vec<String> {
+        return self.with_admin(&[]);
+    }
+
+    pub fn policy_read_roles(&self) -> Vec<String> {
+        return self.with_admin(&[&self.policy_admin, &self.read_only_admin]);
+    }
+
+    pub fn policy_write_roles(&self) -> Vec<String> {
+        return self.with_admin(&[&self.policy_admin]);
+    }
+
+    pub fn template_write_roles(&self) -> Vec<String> {
+        return self.with_admin(&[&self.template_admin]);
+    }
+
+    pub fn audit_read_roles(&self) -> Vec<String> {
+        return self.with_admin(&[&self.audit_reader, &self.read_only_admin]);
+    }
+}
+
 fn default_service_name() -> String {
     "Dexes Authorization Registry".to_owned()
 }
@@ -86,16 +511,49 @@ fn default_service_name() -> String {
 pub struct Config {
     pub frontend: FrontendConfig,
     pub client_eori: String,
-    pub idp_url: String,
-    pub idp_eori: String,
+    // iSHARE identity provider, only used when `oidc_identity_provider` is not configured
+    pub idp_url: Option<String>,
+    pub idp_eori: Option<String>,
+    pub oidc_identity_provider: Option<OidcIdentityProviderConfig>,
     pub client_cert_path: String,
     pub client_cert_pass: String,
     pub satellite_url: String,
     pub ishare_ca_path: String,
     pub satellite_eori: String,
-    pub jwt_secret: String,
+    // HS256 secret, only used when `jwt_signing` is not configured
+    pub jwt_secret: Option<String>,
+    pub jwt_signing: Option<JwtSigningConfig>,
     #[serde(default = "default_jwt_expiry_seconds")]
     pub jwt_expiry_seconds: u64,
+    // a refresh token that is not used within this period expires
+    #[serde(default = "default_refresh_token_expiry_seconds")]
+    pub refresh_token_expiry_seconds: i64,
+    // h2m sessions can not be refreshed beyond this period after logging in
+    #[serde(default = "default_session_lifetime_seconds")]
+    pub session_lifetime_seconds: i64,
+    // time a user has to log in at the identity provider before the callback is rejected
+    #[serde(default = "default_auth_request_expiry_seconds")]
+    pub auth_request_expiry_seconds: i64,
+    // where the H2M login may redirect to with the token: exact URIs, origins or patterns with `*`.
+    // An empty list refuses every H2M login
+    #[serde(default)]
+    pub allowed_redirect_uris: Vec<String>,
+    #[serde(default)]
+    pub client_assertion_replay_store: ReplayStoreBackend,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub party_cache: PartyCacheConfig,
+    pub offline_satellite: Option<OfflineSatelliteConfig>,
+    pub pdp: Option<PdpConfig>,
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+    #[serde(default)]
+    pub federation: FederationConfig,
+    #[serde(default)]
+    pub party_checks: PartyChecksConfig,
+    #[serde(default)]
+    pub party_revalidation: PartyRevalidationConfig,
     pub database_url: String,
     #[serde(default = "default_listen_address")]
     pub listen_address: String,
@@ -111,8 +569,16 @@ pub struct Config {
     #[serde(default = "default_delegation_allows_service_providers")]
     pub delegation_allows_service_providers: bool,
     pub dataspace_config: Option<AllowedDataspaces>,
+    // id of the dataspace of `satellite_url`, used when a request does not name a dataspace
+    #[serde(default = "default_dataspace_id")]
+    pub default_dataspace_id: String,
+    #[serde(default)]
+    pub dataspaces: Vec<DataspaceSatelliteConfig>,
     #[serde(default = "default_service_name")]
     pub service_name: String,
+    pub audit_log_retention: Option<AuditLogRetentionConfig>,
+    #[serde(default)]
+    pub admin_roles: AdminRolesConfig,
 }
 
 pub fn read_config(path: String) -> Config {
@@ -0,0 +1,83 @@
+use anyhow::Context;
+use ar_entity::auth_request::{
+    ActiveModel as ActiveAuthRequest, Column, Entity as AuthRequest, Model as AuthRequestModel,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use uuid::Uuid;
+
+pub struct InsertAuthRequest {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub redirect_uri: String,
+    pub client_state: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub async fn insert_auth_request(
+    now: DateTime<Utc>,
+    auth_request: InsertAuthRequest,
+    db: &DatabaseConnection,
+) -> anyhow::Result<()> {
+    let active_model = ActiveAuthRequest {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        state: ActiveValue::Set(auth_request.state),
+        nonce: ActiveValue::Set(auth_request.nonce),
+        code_verifier: ActiveValue::Set(auth_request.code_verifier),
+        redirect_uri: ActiveValue::Set(auth_request.redirect_uri),
+        client_state: ActiveValue::Set(auth_request.client_state),
+        created_at: ActiveValue::Set(now),
+        expires_at: ActiveValue::Set(auth_request.expires_at),
+        used_at: ActiveValue::Set(None),
+    };
+
+    AuthRequest::insert(active_model)
+        .exec(db)
+        .await
+        .context("Error inserting auth request into db")?;
+
+    Ok(())
+}
+
+pub async fn get_by_state(
+    state: &str,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Option<AuthRequestModel>> {
+    let auth_request = AuthRequest::find()
+        .filter(Column::State.eq(state))
+        .one(db)
+        .await
+        .context("Error retrieving auth request from db")?;
+
+    return Ok(auth_request);
+}
+
+// returns false when the auth request has already been used
+pub async fn mark_used(
+    id: Uuid,
+    now: DateTime<Utc>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<bool> {
+    let result = AuthRequest::update_many()
+        .col_expr(Column::UsedAt, Expr::value(now))
+        .filter(Column::Id.eq(id))
+        .filter(Column::UsedAt.is_null())
+        .exec(db)
+        .await
+        .context(format!("Error marking auth request '{}' as used", id))?;
+
+    return Ok(result.rows_affected == 1);
+}
+
+pub async fn delete_expired(now: DateTime<Utc>, db: &DatabaseConnection) -> anyhow::Result<u64> {
+    let result = AuthRequest::delete_many()
+        .filter(Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await
+        .context("Error deleting expired auth requests from db")?;
+
+    return Ok(result.rows_affected);
+}
@@ -0,0 +1,55 @@
+use anyhow::Context;
+use ar_entity::client_assertion_jti::{
+    ActiveModel as ActiveClientAssertionJti, Column, Entity as ClientAssertionJti,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+
+// returns false when the jti of the issuer has already been stored and has not expired
+pub async fn insert_if_absent(
+    now: DateTime<Utc>,
+    issuer: &str,
+    jti: &str,
+    expires_at: DateTime<Utc>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<bool> {
+    ClientAssertionJti::delete_many()
+        .filter(Column::Issuer.eq(issuer))
+        .filter(Column::Jti.eq(jti))
+        .filter(Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await
+        .context("Error deleting expired client assertion jti from db")?;
+
+    let active_model = ActiveClientAssertionJti {
+        id: ActiveValue::Set(uuid::Uuid::new_v4()),
+        issuer: ActiveValue::Set(issuer.to_owned()),
+        jti: ActiveValue::Set(jti.to_owned()),
+        expires_at: ActiveValue::Set(expires_at),
+        created_at: ActiveValue::Set(now),
+    };
+
+    let rows_affected = ClientAssertionJti::insert(active_model)
+        .on_conflict(
+            OnConflict::columns([Column::Issuer, Column::Jti])
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await
+        .context("Error inserting client assertion jti into db")?;
+
+    return Ok(rows_affected == 1);
+}
+
+pub async fn delete_expired(now: DateTime<Utc>, db: &DatabaseConnection) -> anyhow::Result<u64> {
+    let result = ClientAssertionJti::delete_many()
+        .filter(Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await
+        .context("Error deleting expired client assertion jtis from db")?;
+
+    return Ok(result.rows_affected);
+}
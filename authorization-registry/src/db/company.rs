@@ -4,11 +4,12 @@ use ar_entity::company::Entity as Company;
 use ar_entity::company::Model as CompanyModel;
 use sea_orm::{entity::*, query::*, ActiveValue, DatabaseConnection, EntityTrait};
 
+// returns the id of the company and whether it was newly inserted
 pub async fn insert_if_not_exists<T: ConnectionTrait>(
     eori: &str,
     name: &str,
     db: &T,
-) -> anyhow::Result<String> {
+) -> anyhow::Result<(String, bool)> {
     let company = Company::find()
         .filter(ar_entity::company::Column::Id.eq(eori))
         .one(&*db)
@@ -18,25 +19,27 @@ pub async fn insert_if_not_exists<T: ConnectionTrait>(
             eori, name
         ))?;
 
-    let company_id = match company {
+    let (company_id, inserted) = match company {
         None => {
             let active_model = ActiveCompany {
                 id: ActiveValue::set(eori.to_owned()),
                 name: ActiveValue::set(name.to_owned()),
             };
-            Company::insert(active_model)
+            let id = Company::insert(active_model)
                 .exec(&*db)
                 .await
                 .context(format!(
                     "Error inserting company to db with eori '{}' and name '{}'",
                     eori, name
                 ))?
-                .last_insert_id
+                .last_insert_id;
+
+            (id, true)
         }
-        Some(model) => model.id,
+        Some(model) => (model.id, false),
     };
 
-    return Ok(company_id);
+    return Ok((company_id, inserted));
 }
 
 pub async fn _get_company_by_id(
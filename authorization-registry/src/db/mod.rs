@@ -1,4 +1,10 @@
+pub mod auth_request;
+pub mod client_assertion_jti;
 pub mod company;
 pub mod policy;
+pub mod policy_set_suspension;
 pub mod policy_set_template;
+pub mod refresh_token;
+pub mod replication;
+pub mod token_revocation;
 pub mod user;
@@ -91,6 +91,8 @@ pub struct MatchingPolicySetRow {
     pub policies: Vec<DelegationEvidencePolicy>,
     pub licenses: Vec<String>,
     pub max_delegation_depth: i32,
+    #[serde(default)]
+    pub dataspace: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, ToSchema)]
@@ -237,6 +239,7 @@ pub async fn get_policy_sets_with_policies(
             ps.policy_issuer as policy_issuer,
             ps.licenses as licenses,
             ps.max_delegation_depth as max_delegation_depth,
+            ps.dataspace as dataspace,
             coalesce(
                 array_agg(
                     json_build_object(
@@ -301,14 +304,22 @@ pub async fn get_policy_sets_with_policies(
     })
 }
 
+// `dataspace` is None for the default dataspace
 pub async fn get_policy_sets_with_policies_for_creating_de(
     access_subject: String,
     policy_issuer: String,
+    dataspace: Option<String>,
     db: &DatabaseConnection,
 ) -> anyhow::Result<Vec<MatchingPolicySetRow>> {
     let mut values: Vec<Value> = Vec::new();
     let mut conditions = Vec::new();
 
+    conditions.push(format!(
+        "ps.dataspace is not distinct from ${}",
+        values.len() + 1
+    ));
+    values.push(dataspace.into());
+
     conditions.push(format!("access_subject like ${}", values.len() + 1));
     values.push(format!("%{}%", &access_subject).into());
 
@@ -330,6 +341,7 @@ pub async fn get_policy_sets_with_policies_for_creating_de(
                 ps.policy_issuer as policy_issuer,
                 ps.licenses as licenses,
                 ps.max_delegation_depth as max_delegation_depth,
+                ps.dataspace as dataspace,
                 coalesce(
                     array_agg(
                         json_build_object(
@@ -357,6 +369,10 @@ pub async fn get_policy_sets_with_policies_for_creating_de(
                 policy p
                     on p.policy_set = ps.id
             where {}
+            -- suspended policy sets are not used for delegation evidence
+            and not exists (
+                select 1 from policy_set_suspension s where s.policy_set_id = ps.id
+            )
             group by
                 ps.id 
         "#,
@@ -393,6 +409,7 @@ pub async fn get_policy_set_with_policies(
             ps.policy_issuer as policy_issuer,
             ps.licenses as licenses,
             ps.max_delegation_depth as max_delegation_depth,
+            ps.dataspace as dataspace,
             coalesce(
                 array_agg(
                     json_build_object(
@@ -460,6 +477,7 @@ pub async fn insert_policy_set<C: ConnectionTrait>(
     policy_issuer: &str,
     licences: &Vec<String>,
     max_delegation_depth: &i32,
+    dataspace: Option<String>,
     db: &C,
 ) -> anyhow::Result<Uuid> {
     let policy_set_id = Uuid::new_v4();
@@ -471,6 +489,7 @@ pub async fn insert_policy_set<C: ConnectionTrait>(
         policy_issuer: sea_orm::ActiveValue::set(policy_issuer.to_owned()),
         max_delegation_depth: sea_orm::ActiveValue::set(max_delegation_depth.to_owned()),
         created: sea_orm::ActiveValue::set(now),
+        dataspace: sea_orm::ActiveValue::set(dataspace),
     };
 
     let policy_set_id = ar_entity::policy_set::Entity::insert(active_policy_set)
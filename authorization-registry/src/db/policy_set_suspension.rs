@@ -0,0 +1,106 @@
+use anyhow::Context;
+use ar_entity::policy_set_suspension::{
+    ActiveModel as ActivePolicySetSuspension, Column, Entity as PolicySetSuspension, Model,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, FromQueryResult,
+    QueryFilter, QueryOrder, Statement,
+};
+use uuid::Uuid;
+
+#[derive(Debug, FromQueryResult)]
+pub struct PartyReference {
+    pub policy_set_id: Uuid,
+    pub party: String,
+    // policy_issuer, access_subject or service_provider
+    pub function: String,
+    pub dataspace: Option<String>,
+}
+
+// every party of the stored policy sets and policies, with the function it has in the policy set
+pub async fn get_party_references(db: &DatabaseConnection) -> anyhow::Result<Vec<PartyReference>> {
+    let stmt = Statement::from_string(
+        sea_orm::DatabaseBackend::Postgres,
+        r#"
+            select
+                ps.id as policy_set_id,
+                ps.policy_issuer as party,
+                'policy_issuer' as function,
+                ps.dataspace as dataspace
+            from policy_set ps
+            union
+            select ps.id, ps.access_subject, 'access_subject', ps.dataspace
+            from policy_set ps
+            union
+            select ps.id, sp, 'service_provider', ps.dataspace
+            from policy p
+            join policy_set ps on ps.id = p.policy_set,
+            unnest(p.service_providers) as sp
+        "#,
+    );
+
+    let references = PartyReference::find_by_statement(stmt)
+        .all(db)
+        .await
+        .context("Error fetching the parties of the policy sets from db")?;
+
+    return Ok(references);
+}
+
+pub async fn get_suspensions(db: &DatabaseConnection) -> anyhow::Result<Vec<Model>> {
+    let suspensions = PolicySetSuspension::find()
+        .order_by_asc(Column::SuspendedAt)
+        .all(db)
+        .await
+        .context("Error fetching policy set suspensions from db")?;
+
+    return Ok(suspensions);
+}
+
+pub async fn is_suspended(policy_set_id: Uuid, db: &DatabaseConnection) -> anyhow::Result<bool> {
+    let suspension = PolicySetSuspension::find()
+        .filter(Column::PolicySetId.eq(policy_set_id))
+        .one(db)
+        .await
+        .context("Error fetching policy set suspension from db")?;
+
+    return Ok(suspension.is_some());
+}
+
+pub async fn insert_suspension<C: ConnectionTrait>(
+    now: DateTime<Utc>,
+    policy_set_id: Uuid,
+    party: &str,
+    reason: &str,
+    db: &C,
+) -> anyhow::Result<()> {
+    let active_model = ActivePolicySetSuspension {
+        policy_set_id: ActiveValue::Set(policy_set_id),
+        party: ActiveValue::Set(party.to_owned()),
+        reason: ActiveValue::Set(reason.to_owned()),
+        suspended_at: ActiveValue::Set(now),
+    };
+
+    PolicySetSuspension::insert(active_model)
+        .exec_without_returning(db)
+        .await
+        .context("Error inserting policy set suspension into db")?;
+
+    return Ok(());
+}
+
+pub async fn delete_suspension<C: ConnectionTrait>(
+    policy_set_id: Uuid,
+    party: &str,
+    db: &C,
+) -> anyhow::Result<()> {
+    PolicySetSuspension::delete_many()
+        .filter(Column::PolicySetId.eq(policy_set_id))
+        .filter(Column::Party.eq(party))
+        .exec(db)
+        .await
+        .context("Error deleting policy set suspension from db")?;
+
+    return Ok(());
+}
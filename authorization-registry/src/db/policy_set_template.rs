@@ -1,5 +1,5 @@
 use anyhow::Context;
-use sea_orm::{DatabaseConnection, EntityTrait};
+use sea_orm::{ConnectionTrait, DatabaseConnection, EntityTrait};
 use serde::Deserialize;
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -35,9 +35,9 @@ pub struct InsertPolicySetTemplate {
     description: Option<String>,
 }
 
-pub async fn insert_policy_set_template(
+pub async fn insert_policy_set_template<T: ConnectionTrait>(
     new_ps_template: InsertPolicySetTemplate,
-    db: &DatabaseConnection,
+    db: &T,
 ) -> anyhow::Result<Uuid> {
     let to_insert = ar_entity::policy_set_template::ActiveModel {
         id: sea_orm::ActiveValue::Set(uuid::Uuid::new_v4()),
@@ -57,7 +57,7 @@ pub async fn insert_policy_set_template(
     Ok(inserted_id)
 }
 
-pub async fn delete_policy_template(id: Uuid, db: &DatabaseConnection) -> anyhow::Result<()> {
+pub async fn delete_policy_template<T: ConnectionTrait>(id: Uuid, db: &T) -> anyhow::Result<()> {
     tracing::info!("Deleting policy set template with id: {}", &id);
     ar_entity::policy_set_template::Entity::delete_by_id(id)
         .exec(db)
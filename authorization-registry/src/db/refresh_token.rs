@@ -0,0 +1,131 @@
+use anyhow::Context;
+use ar_entity::refresh_token::{
+    ActiveModel as ActiveRefreshToken, Column, Entity as RefreshToken, Model as RefreshTokenModel,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use uuid::Uuid;
+
+pub struct InsertRefreshToken {
+    pub token_hash: String,
+    pub family_id: Uuid,
+    pub company_id: String,
+    pub user_id: String,
+    pub realm_access_roles: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+    pub session_expires_at: DateTime<Utc>,
+}
+
+pub async fn insert_refresh_token(
+    now: DateTime<Utc>,
+    refresh_token: InsertRefreshToken,
+    db: &DatabaseConnection,
+) -> anyhow::Result<()> {
+    let active_model = ActiveRefreshToken {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        token_hash: ActiveValue::Set(refresh_token.token_hash),
+        family_id: ActiveValue::Set(refresh_token.family_id),
+        company_id: ActiveValue::Set(refresh_token.company_id),
+        user_id: ActiveValue::Set(refresh_token.user_id),
+        realm_access_roles: ActiveValue::Set(serde_json::json!(refresh_token.realm_access_roles)),
+        created_at: ActiveValue::Set(now),
+        expires_at: ActiveValue::Set(refresh_token.expires_at),
+        session_expires_at: ActiveValue::Set(refresh_token.session_expires_at),
+        used_at: ActiveValue::Set(None),
+        revoked_at: ActiveValue::Set(None),
+    };
+
+    RefreshToken::insert(active_model)
+        .exec(db)
+        .await
+        .context("Error inserting refresh token into db")?;
+
+    Ok(())
+}
+
+pub async fn get_by_hash(
+    token_hash: &str,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Option<RefreshTokenModel>> {
+    let refresh_token = RefreshToken::find()
+        .filter(Column::TokenHash.eq(token_hash))
+        .one(db)
+        .await
+        .context("Error retrieving refresh token from db")?;
+
+    return Ok(refresh_token);
+}
+
+// returns false when the refresh token has already been used
+pub async fn mark_used(
+    id: Uuid,
+    now: DateTime<Utc>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<bool> {
+    let result = RefreshToken::update_many()
+        .col_expr(Column::UsedAt, Expr::value(now))
+        .filter(Column::Id.eq(id))
+        .filter(Column::UsedAt.is_null())
+        .exec(db)
+        .await
+        .context(format!("Error marking refresh token '{}' as used", id))?;
+
+    return Ok(result.rows_affected == 1);
+}
+
+pub async fn revoke_family(
+    family_id: Uuid,
+    now: DateTime<Utc>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<u64> {
+    let result = RefreshToken::update_many()
+        .col_expr(Column::RevokedAt, Expr::value(now))
+        .filter(Column::FamilyId.eq(family_id))
+        .filter(Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .context(format!(
+            "Error revoking refresh token family '{}'",
+            family_id
+        ))?;
+
+    return Ok(result.rows_affected);
+}
+
+// revokes the refresh tokens of all sessions of a user, or of all users of a company
+pub async fn revoke_subject(
+    user_id: Option<&str>,
+    company_id: Option<&str>,
+    now: DateTime<Utc>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<u64> {
+    let mut query = RefreshToken::update_many()
+        .col_expr(Column::RevokedAt, Expr::value(now))
+        .filter(Column::RevokedAt.is_null());
+
+    if let Some(user_id) = user_id {
+        query = query.filter(Column::UserId.eq(user_id));
+    }
+    if let Some(company_id) = company_id {
+        query = query.filter(Column::CompanyId.eq(company_id));
+    }
+
+    let result = query
+        .exec(db)
+        .await
+        .context("Error revoking refresh tokens")?;
+
+    return Ok(result.rows_affected);
+}
+
+pub async fn delete_expired(now: DateTime<Utc>, db: &DatabaseConnection) -> anyhow::Result<u64> {
+    let result = RefreshToken::delete_many()
+        .filter(Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await
+        .context("Error deleting expired refresh tokens from db")?;
+
+    return Ok(result.rows_affected);
+}
@@ -0,0 +1,227 @@
+use anyhow::Context;
+use ar_entity::replication_delivery::{
+    ActiveModel as ActiveReplicationDelivery, Column, Entity as ReplicationDelivery, Model,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    FromQueryResult, QueryFilter, QueryOrder, Statement,
+};
+use uuid::Uuid;
+
+#[derive(Debug, FromQueryResult)]
+pub struct PendingDelivery {
+    pub policy_set_id: Uuid,
+    pub version: i64,
+    pub remote_id: Option<String>,
+    pub delivered_version: i64,
+    pub attempts: i32,
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct DeliveryCounts {
+    pub delivered: i64,
+    pub pending: i64,
+    pub failed: i64,
+}
+
+// marks the policy set as changed for every replication target. Deliveries that gave up are
+// tried again for the new version
+pub async fn record_change<C: ConnectionTrait>(
+    now: DateTime<Utc>,
+    policy_set_id: Uuid,
+    db: &C,
+) -> anyhow::Result<()> {
+    let stmt = Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Postgres,
+        r#"
+            insert into policy_set_change (policy_set_id, version, changed_at)
+            values ($1, 1, $2)
+            on conflict (policy_set_id) do update
+            set version = policy_set_change.version + 1, changed_at = excluded.changed_at
+        "#,
+        vec![policy_set_id.into(), now.into()],
+    );
+    db.execute(stmt)
+        .await
+        .context("Error recording policy set change in db")?;
+
+    ReplicationDelivery::update_many()
+        .col_expr(Column::Attempts, sea_orm::sea_query::Expr::value(0))
+        .col_expr(
+            Column::NextAttemptAt,
+            sea_orm::sea_query::Expr::value(Option::<DateTime<Utc>>::None),
+        )
+        .filter(Column::PolicySetId.eq(policy_set_id))
+        .exec(db)
+        .await
+        .context("Error resetting replication deliveries in db")?;
+
+    return Ok(());
+}
+
+// marks every stored policy set as changed, without bumping the version of policy sets that
+// already have a change
+pub async fn record_all_policy_sets<C: ConnectionTrait>(
+    now: DateTime<Utc>,
+    db: &C,
+) -> anyhow::Result<()> {
+    let stmt = Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Postgres,
+        r#"
+            insert into policy_set_change (policy_set_id, version, changed_at)
+            select id, 1, $1 from policy_set
+            on conflict (policy_set_id) do nothing
+        "#,
+        vec![now.into()],
+    );
+    db.execute(stmt)
+        .await
+        .context("Error recording policy set changes in db")?;
+
+    return Ok(());
+}
+
+// the changes that are not yet delivered to the target and are due for an attempt
+pub async fn get_pending_deliveries(
+    now: DateTime<Utc>,
+    target: &str,
+    max_attempts: i32,
+    limit: u64,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Vec<PendingDelivery>> {
+    let stmt = Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Postgres,
+        r#"
+            select
+                c.policy_set_id as policy_set_id,
+                c.version as version,
+                d.remote_id as remote_id,
+                coalesce(d.delivered_version, 0) as delivered_version,
+                coalesce(d.attempts, 0) as attempts
+            from policy_set_change c
+            left join replication_delivery d
+                on d.policy_set_id = c.policy_set_id and d.target = $1
+            where c.version > coalesce(d.delivered_version, 0)
+                and coalesce(d.attempts, 0) < $2
+                and (d.next_attempt_at is null or d.next_attempt_at <= $3)
+            order by c.changed_at
+            limit $4
+        "#,
+        vec![
+            target.into(),
+            max_attempts.into(),
+            now.into(),
+            (limit as i64).into(),
+        ],
+    );
+
+    let pending = PendingDelivery::find_by_statement(stmt)
+        .all(db)
+        .await
+        .context("Error fetching pending replication deliveries from db")?;
+
+    return Ok(pending);
+}
+
+pub async fn save_delivery<C: ConnectionTrait>(delivery: Model, db: &C) -> anyhow::Result<()> {
+    let active_model: ActiveReplicationDelivery = delivery.into();
+
+    ReplicationDelivery::insert(active_model)
+        .on_conflict(
+            OnConflict::columns([Column::Target, Column::PolicySetId])
+                .update_columns([
+                    Column::RemoteId,
+                    Column::DeliveredVersion,
+                    Column::Attempts,
+                    Column::LastError,
+                    Column::NextAttemptAt,
+                    Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await
+        .context("Error saving replication delivery in db")?;
+
+    return Ok(());
+}
+
+pub async fn get_delivery_counts(
+    target: &str,
+    max_attempts: i32,
+    db: &DatabaseConnection,
+) -> anyhow::Result<DeliveryCounts> {
+    let stmt = Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Postgres,
+        r#"
+            select
+                count(*) filter (
+                    where c.version <= coalesce(d.delivered_version, 0)
+                ) as delivered,
+                count(*) filter (
+                    where c.version > coalesce(d.delivered_version, 0)
+                        and coalesce(d.attempts, 0) < $2
+                ) as pending,
+                count(*) filter (
+                    where c.version > coalesce(d.delivered_version, 0)
+                        and d.attempts >= $2
+                ) as failed
+            from policy_set_change c
+            left join replication_delivery d
+                on d.policy_set_id = c.policy_set_id and d.target = $1
+        "#,
+        vec![target.into(), max_attempts.into()],
+    );
+
+    let counts = DeliveryCounts::find_by_statement(stmt)
+        .one(db)
+        .await
+        .context("Error counting replication deliveries in db")?
+        .context("Counting replication deliveries returned no rows")?;
+
+    return Ok(counts);
+}
+
+// deliveries of which the last attempt failed
+pub async fn get_failed_deliveries(
+    target: &str,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Vec<Model>> {
+    let deliveries = ReplicationDelivery::find()
+        .filter(Column::Target.eq(target))
+        .filter(Column::LastError.is_not_null())
+        .order_by_desc(Column::UpdatedAt)
+        .all(db)
+        .await
+        .context("Error fetching failed replication deliveries from db")?;
+
+    return Ok(deliveries);
+}
+
+// every change has to be delivered to the target again. The remote ids are kept, so the copies
+// that are already at the target are replaced instead of duplicated
+pub async fn reset_deliveries<C: ConnectionTrait>(
+    now: DateTime<Utc>,
+    target: &str,
+    db: &C,
+) -> anyhow::Result<()> {
+    ReplicationDelivery::update_many()
+        .col_expr(Column::DeliveredVersion, sea_orm::sea_query::Expr::value(0))
+        .col_expr(Column::Attempts, sea_orm::sea_query::Expr::value(0))
+        .col_expr(
+            Column::LastError,
+            sea_orm::sea_query::Expr::value(Option::<String>::None),
+        )
+        .col_expr(
+            Column::NextAttemptAt,
+            sea_orm::sea_query::Expr::value(Option::<DateTime<Utc>>::None),
+        )
+        .col_expr(Column::UpdatedAt, sea_orm::sea_query::Expr::value(now))
+        .filter(Column::Target.eq(target))
+        .exec(db)
+        .await
+        .context("Error resetting replication deliveries in db")?;
+
+    return Ok(());
+}
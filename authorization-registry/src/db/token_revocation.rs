@@ -0,0 +1,85 @@
+use anyhow::Context;
+use ar_entity::token_revocation::{
+    ActiveModel as ActiveTokenRevocation, Column, Entity as TokenRevocation,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter,
+};
+
+pub struct InsertTokenRevocation {
+    pub jti: Option<String>,
+    pub user_id: Option<String>,
+    pub company_id: Option<String>,
+    pub issued_before: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub async fn insert_token_revocation(
+    now: DateTime<Utc>,
+    revocation: InsertTokenRevocation,
+    db: &DatabaseConnection,
+) -> anyhow::Result<()> {
+    let active_model = ActiveTokenRevocation {
+        id: ActiveValue::Set(uuid::Uuid::new_v4()),
+        jti: ActiveValue::Set(revocation.jti),
+        user_id: ActiveValue::Set(revocation.user_id),
+        company_id: ActiveValue::Set(revocation.company_id),
+        issued_before: ActiveValue::Set(revocation.issued_before),
+        expires_at: ActiveValue::Set(revocation.expires_at),
+        created_at: ActiveValue::Set(now),
+    };
+
+    TokenRevocation::insert(active_model)
+        .exec(db)
+        .await
+        .context("Error inserting token revocation into db")?;
+
+    Ok(())
+}
+
+pub async fn is_revoked(
+    jti: &str,
+    user_id: Option<&str>,
+    company_id: &str,
+    issued_at: DateTime<Utc>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<bool> {
+    let mut condition = Condition::any().add(
+        Condition::all()
+            .add(Column::CompanyId.eq(company_id))
+            .add(Column::UserId.is_null())
+            .add(Column::IssuedBefore.gt(issued_at)),
+    );
+
+    if !jti.is_empty() {
+        condition = condition.add(Column::Jti.eq(jti));
+    }
+
+    if let Some(user_id) = user_id {
+        condition = condition.add(
+            Condition::all()
+                .add(Column::UserId.eq(user_id))
+                .add(Column::IssuedBefore.gt(issued_at)),
+        );
+    }
+
+    let count = TokenRevocation::find()
+        .filter(condition)
+        .count(db)
+        .await
+        .context("Error retrieving token revocations from db")?;
+
+    return Ok(count > 0);
+}
+
+pub async fn delete_expired(now: DateTime<Utc>, db: &DatabaseConnection) -> anyhow::Result<u64> {
+    let result = TokenRevocation::delete_many()
+        .filter(Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await
+        .context("Error deleting expired token revocations from db")?;
+
+    return Ok(result.rows_affected);
+}
@@ -4,6 +4,7 @@ use ar_entity::ishare_user::Entity as User;
 use ar_entity::ishare_user::Model as UserModel;
 use sea_orm::{entity::*, query::*, ActiveValue, DatabaseConnection, EntityTrait};
 
+// returns the id of the user and whether it was newly inserted
 pub async fn insert_if_not_exists<T: ConnectionTrait>(
     idp_sub: String,
     email: String,
@@ -12,7 +13,7 @@ pub async fn insert_if_not_exists<T: ConnectionTrait>(
     idp_eori: String,
     idp_url: String,
     db: &T,
-) -> anyhow::Result<String> {
+) -> anyhow::Result<(String, bool)> {
     let user = User::find()
         .filter(ar_entity::ishare_user::Column::Id.eq(&idp_sub))
         .one(&*db)
@@ -22,7 +23,7 @@ pub async fn insert_if_not_exists<T: ConnectionTrait>(
             idp_sub
         ))?;
 
-    let user_id = match user {
+    let (user_id, inserted) = match user {
         None => {
             let active_model = ActiveUser {
                 id: ActiveValue::set(idp_sub),
@@ -32,16 +33,18 @@ pub async fn insert_if_not_exists<T: ConnectionTrait>(
                 idp_eori: ActiveValue::set(idp_eori),
                 idp_url: ActiveValue::set(idp_url),
             };
-            User::insert(active_model)
+            let id = User::insert(active_model)
                 .exec(&*db)
                 .await
                 .context("Error inserting user into db with")?
-                .last_insert_id
+                .last_insert_id;
+
+            (id, true)
         }
-        Some(model) => model.id,
+        Some(model) => (model.id, false),
     };
 
-    return Ok(user_id);
+    return Ok((user_id, inserted));
 }
 
 pub async fn _get_user_by_id(
@@ -1,15 +1,32 @@
-use crate::config::FrontendConfig;
-use crate::routes::audit_log::get_audit_log_routes;
+use crate::config::{
+    AdminRolesConfig, AuditLogRetentionConfig, FrontendConfig, PartyChecksConfig,
+    ReplayStoreBackend,
+};
+use crate::routes::audit_log::{get_audit_log_routes, get_audit_log_trailing_slash_routes};
+use crate::services::client_assertion_replay::{DbReplayStore, InMemoryReplayStore, ReplayStore};
+use crate::services::dataspace::{Dataspace, Dataspaces};
+use crate::services::federation::{Federation, RemoteRegistry};
+use crate::services::identity_provider::IdentityProvider;
 use crate::services::idp_connector::IdpConnector;
+use crate::services::ishare_idp::IShareIdentityProvider;
 use crate::services::ishare_provider::{ISHAREProvider, SatelliteProvider};
+use crate::services::oidc_idp::OidcIdentityProvider;
+use crate::services::parties_snapshot::{
+    refresh_snapshot, PartiesSnapshot, SnapshotSatelliteProvider,
+};
+use crate::services::party_cache::PartyCache;
+use crate::services::pdp_provider::{AuthorizationProvider, PDPProvider};
+use crate::services::rate_limit::RateLimiter;
+use crate::services::replication::{Replication, ReplicationTarget};
 use crate::services::server_token::ServerToken;
+use crate::services::token_revocation::DbRevocationStore;
 use ar_migration::{Migrator, MigratorTrait};
 
 use axum::async_trait;
 use axum::extract::MatchedPath;
 use axum::Extension;
 use axum::{extract::FromRef, Router};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use ishare::ishare::ISHARE;
 use routes::admin::get_admin_routes;
 use routes::capabilities::get_capabilities_routes;
@@ -20,6 +37,7 @@ use routes::policy_set_template::get_policy_set_template_routes;
 use sea_orm::Database;
 use sea_orm::DatabaseConnection;
 use seed::apply_seeds;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
 use tower_http::trace::{DefaultOnResponse, TraceLayer};
@@ -69,7 +87,7 @@ impl Modify for SecurityAddon {
         title = "Authorization Registry",
         description = "Authorization Registry API that conforms to the iSHARE framework to manage policy storage and authorization delegation.
 
-Authentication is required for most endpoints. Non-admin routes can be accessed with either a Machine-to-Machine (M2M) token or a Human-to-Machine (H2M) token, obtainable via the authentication endpoints (/connect/machine/token for M2M, /connect/human/auth for H2M). Admin routes require an H2M token with the dexspace_admin role.
+Authentication is required for most endpoints. Non-admin routes can be accessed with either a Machine-to-Machine (M2M) token or a Human-to-Machine (H2M) token, obtainable via the authentication endpoints (/connect/machine/token for M2M, /connect/human/auth for H2M). Admin routes require an H2M token with an admin role, dexspace_admin by default. M2M tokens can be restricted to the delegation, policy:read, policy:write and audit:read scopes through the scope parameter of the token request.
 
 Policy management endpoints allow participants to create and manage authorization policy sets that define what rights are delegated to which service consumers. Policies follow the iSHARE Delegation Evidence format to ensure interoperability across the iSHARE network.
 
@@ -85,6 +103,9 @@ Admin routes provide additional capabilities for managing policies across all pa
         routes::connect::get_machine_token,
         routes::connect::get_auth,
         routes::connect::get_auth_callback,
+        routes::connect::get_jwks,
+        routes::connect::refresh_human_token,
+        routes::connect::logout,
         routes::policy_set::get_all_policy_sets,
         routes::policy_set::get_policy_set,
         routes::policy_set::insert_policy_set,
@@ -102,6 +123,16 @@ Admin routes provide additional capabilities for managing policies across all pa
         routes::admin::get_all_policy_sets,
         routes::admin::insert_policy_set_template,
         routes::admin::delete_policy_set_template,
+        routes::admin::get_audit_log_status,
+        routes::admin::get_rate_limit_status,
+        routes::admin::get_party_cache,
+        routes::admin::flush_party_cache,
+        routes::admin::flush_party_cache_entry,
+        routes::admin::get_policy_set_suspensions,
+        routes::admin::revalidate_parties,
+        routes::admin::get_replication_status,
+        routes::admin::resync_replication_target,
+        routes::admin::revoke_tokens,
         routes::policy_set_template::get_policy_set_template,
         routes::policy_set_template::get_policy_set_templates,
     )
@@ -113,6 +144,21 @@ struct ApiDoc;
 struct Args {
     #[arg(short, long, default_value = "./.config.json")]
     config_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Fetch the parties of the parties snapshot from the satellite and write a new snapshot
+    RefreshPartiesSnapshot {
+        /// Defaults to the `snapshot_path` of `offline_satellite`
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Party to add to the snapshot, can be repeated
+        #[arg(short, long)]
+        party: Vec<String>,
+    },
 }
 
 #[async_trait]
@@ -142,13 +188,30 @@ pub struct AppConfig {
     pub delegation_allows_service_providers: bool,
     pub frontend: FrontendConfig,
     pub service_name: String,
+    pub audit_log_retention: Option<AuditLogRetentionConfig>,
+    pub refresh_token_expiry_seconds: i64,
+    pub session_lifetime_seconds: i64,
+    pub auth_request_expiry_seconds: i64,
+    pub allowed_redirect_uris: Vec<String>,
+    pub admin_roles: AdminRolesConfig,
+    pub party_checks: PartyChecksConfig,
 }
 
 #[derive(Clone)]
 pub struct AppState {
     server_token: Arc<ServerToken>,
     satellite_provider: Arc<dyn SatelliteProvider>,
+    identity_provider: Arc<dyn IdentityProvider>,
     time_provider: Arc<dyn TimeProvider>,
+    rate_limiter: Arc<RateLimiter>,
+    // `satellite_provider` is the satellite of the default dataspace
+    dataspaces: Arc<Dataspaces>,
+    // access to policy sets and the audit log is decided by an external pdp when configured
+    authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
+    // targets that receive a copy of the policy sets
+    replication: Arc<Replication>,
+    // remote registries of policy issuers that keep their policies elsewhere
+    federation: Arc<Federation>,
     de_expiry_seconds: i64,
     config: Arc<AppConfig>,
 }
@@ -160,13 +223,24 @@ impl FromRef<AppState> for Arc<ServerToken> {
 }
 
 pub fn get_app(db: DatabaseConnection, app_state: AppState, disable_cors_check: bool) -> Router {
-    let connect_routes = get_connect_routes();
-    let admin_routes = get_admin_routes(app_state.server_token.clone());
-    let delegation_routes = get_delegation_routes(app_state.server_token.clone());
+    let connect_routes = get_connect_routes(
+        app_state.server_token.clone(),
+        app_state.rate_limiter.clone(),
+    );
+    let admin_routes = get_admin_routes(
+        app_state.server_token.clone(),
+        &app_state.config.admin_roles,
+    );
+    let delegation_routes = get_delegation_routes(
+        app_state.server_token.clone(),
+        app_state.rate_limiter.clone(),
+    );
     let policy_set_routes = get_policy_set_routes(app_state.server_token.clone());
     let capabilities_routes = get_capabilities_routes();
     let policy_set_template_routes = get_policy_set_template_routes(app_state.server_token.clone());
     let audit_log_routes = get_audit_log_routes(app_state.server_token.clone());
+    let audit_log_trailing_slash_routes =
+        get_audit_log_trailing_slash_routes(app_state.server_token.clone());
     let config_routes = routes::config::get_config_routes();
 
     let app = Router::new()
@@ -176,9 +250,9 @@ pub fn get_app(db: DatabaseConnection, app_state: AppState, disable_cors_check:
         .nest("/policy-set", policy_set_routes)
         .nest("/capabilities", capabilities_routes)
         .nest("/policy-set-template", policy_set_template_routes)
-        .nest("/audit-log", audit_log_routes.clone())
-        .nest("/audit-log/", audit_log_routes)
         .nest("/config", config_routes)
+        .nest("/audit-log", audit_log_routes)
+        .nest("/audit-log/", audit_log_trailing_slash_routes)
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .layer(
             TraceLayer::new_for_http()
@@ -208,6 +282,34 @@ async fn main() {
 
     control_plane_logging::init_tracing();
 
+    let ishare = Arc::new(
+        ISHARE::new(
+            config.client_cert_path.clone(),
+            config.client_cert_pass.clone(),
+            config.satellite_url.clone(),
+            Some(config.ishare_ca_path.clone()),
+            config.client_eori.clone(),
+            config.satellite_eori.clone(),
+            config.dataspace_config.clone(),
+        )
+        .unwrap(),
+    );
+
+    if let Some(Command::RefreshPartiesSnapshot { output, party }) = args.command {
+        let path = output
+            .or(config
+                .offline_satellite
+                .map(|offline| offline.snapshot_path))
+            .expect("either '--output' or 'offline_satellite' has to be configured");
+        let snapshot = refresh_snapshot(&ishare, &path, &party).await.unwrap();
+        tracing::info!(
+            "wrote {} parties to snapshot '{}'",
+            snapshot.parties.len(),
+            path
+        );
+        return;
+    }
+
     tracing::info!("Deploy route: {}", config.deploy_route);
 
     let db = Database::connect(config.database_url.clone())
@@ -217,27 +319,203 @@ async fn main() {
     Migrator::up(&db, None).await.unwrap();
     apply_seeds(&db, &config).await;
 
-    let server_token = ServerToken::new(config.jwt_secret, config.jwt_expiry_seconds);
-    let ishare = Arc::new(
-        ISHARE::new(
-            config.client_cert_path,
-            config.client_cert_pass,
-            config.satellite_url,
-            Some(config.ishare_ca_path),
-            config.client_eori.clone(),
-            config.satellite_eori,
+    let server_token = match &config.jwt_signing {
+        Some(jwt_signing) => {
+            ServerToken::from_signing_config(jwt_signing, config.jwt_expiry_seconds).unwrap()
+        }
+        None => ServerToken::new(
+            config
+                .jwt_secret
+                .clone()
+                .expect("either 'jwt_secret' or 'jwt_signing' has to be configured"),
+            config.jwt_expiry_seconds,
+        ),
+    };
+    let server_token =
+        Arc::new(server_token.with_revocation_store(Arc::new(DbRevocationStore::new(db.clone()))));
+    services::token_revocation::spawn_purge_job(server_token.clone());
+
+    let time_provider: Arc<dyn TimeProvider> = Arc::new(RealTimeProvider::new());
+    let replay_store: Arc<dyn ReplayStore> = match config.client_assertion_replay_store {
+        ReplayStoreBackend::Memory => Arc::new(InMemoryReplayStore::new()),
+        ReplayStoreBackend::Postgres => Arc::new(DbReplayStore::new(db.clone())),
+    };
+    services::client_assertion_replay::spawn_purge_job(replay_store.clone(), time_provider.clone());
+
+    let party_cache = Arc::new(PartyCache::new(config.party_cache.clone()));
+    let sat_provider: Arc<dyn SatelliteProvider> = match &config.offline_satellite {
+        Some(offline) => {
+            tracing::warn!(
+                "offline satellite mode, using parties snapshot '{}'",
+                offline.snapshot_path
+            );
+            Arc::new(SnapshotSatelliteProvider::new(
+                ishare.clone(),
+                PartiesSnapshot::read(&offline.snapshot_path).unwrap(),
+                config.dataspace_config.clone(),
+                &db,
+                replay_store.clone(),
+            ))
+        }
+        None => Arc::new(ISHAREProvider::new(
+            ishare.clone(),
+            &db,
+            replay_store.clone(),
+            party_cache.clone(),
             config.dataspace_config.clone(),
-        )
-        .unwrap(),
+        )),
+    };
+
+    let mut dataspaces = Dataspaces::new(
+        config.default_dataspace_id.clone(),
+        Dataspace {
+            satellite_provider: sat_provider.clone(),
+            party_cache,
+        },
     );
-    let idp_connector =
-        IdpConnector::new(config.idp_url, config.client_eori.clone(), config.idp_eori);
-    let sat_provider = ISHAREProvider::new(ishare.clone(), &db, &idp_connector);
-    let time_provider = RealTimeProvider::new();
+    for dataspace in config.dataspaces.iter() {
+        let dataspace_ishare = Arc::new(
+            ISHARE::new(
+                config.client_cert_path.clone(),
+                config.client_cert_pass.clone(),
+                dataspace.satellite_url.clone(),
+                Some(dataspace.ishare_ca_path.clone()),
+                config.client_eori.clone(),
+                dataspace.satellite_eori.clone(),
+                dataspace.dataspace_config.clone(),
+            )
+            .unwrap(),
+        );
+        let dataspace_party_cache = Arc::new(PartyCache::new(config.party_cache.clone()));
+
+        tracing::info!(
+            "dataspace '{}' with satellite '{}'",
+            dataspace.id,
+            dataspace.satellite_url
+        );
+        dataspaces
+            .add(
+                dataspace.id.clone(),
+                Dataspace {
+                    satellite_provider: Arc::new(ISHAREProvider::new(
+                        dataspace_ishare,
+                        &db,
+                        replay_store.clone(),
+                        dataspace_party_cache.clone(),
+                        dataspace.dataspace_config.clone(),
+                    )),
+                    party_cache: dataspace_party_cache,
+                },
+            )
+            .unwrap();
+    }
+    let dataspaces = Arc::new(dataspaces);
+    let identity_provider: Arc<dyn IdentityProvider> = match config.oidc_identity_provider {
+        Some(oidc) => Arc::new(OidcIdentityProvider::new(oidc)),
+        None => Arc::new(IShareIdentityProvider::new(
+            ishare.clone(),
+            sat_provider.clone(),
+            IdpConnector::new(
+                config
+                    .idp_url
+                    .expect("either 'idp_url' or 'oidc_identity_provider' has to be configured"),
+                config.client_eori.clone(),
+                config
+                    .idp_eori
+                    .expect("either 'idp_eori' or 'oidc_identity_provider' has to be configured"),
+            ),
+        )),
+    };
+
+    if let Some(retention) = config.audit_log_retention.clone() {
+        services::audit_log_retention::spawn_retention_job(
+            retention,
+            time_provider.clone(),
+            db.clone(),
+        );
+    }
+
+    services::refresh_token::spawn_purge_job(time_provider.clone(), db.clone());
+    services::auth_request::spawn_purge_job(time_provider.clone(), db.clone());
+    services::party_revalidation::spawn_revalidation_job(
+        config.party_revalidation.clone(),
+        config.party_checks.clone(),
+        dataspaces.clone(),
+        time_provider.clone(),
+        db.clone(),
+    );
+
+    let authorization_provider = config.pdp.as_ref().map(|pdp| {
+        tracing::info!(
+            "access to policy sets and the audit log is decided by pdp '{}'",
+            pdp.url
+        );
+        Arc::new(PDPProvider::new(&pdp.url, &pdp.eori, ishare.clone()))
+            as Arc<dyn AuthorizationProvider>
+    });
+
+    let replication_targets = config
+        .replication
+        .targets
+        .iter()
+        .map(|target| {
+            tracing::info!(
+                "policy sets are replicated to '{}' at '{}'",
+                target.id,
+                target.url
+            );
+            (
+                target.id.clone(),
+                Arc::new(PDPProvider::new(&target.url, &target.eori, ishare.clone()))
+                    as Arc<dyn ReplicationTarget>,
+            )
+        })
+        .collect();
+    let replication = Arc::new(Replication::new(
+        config.replication.clone(),
+        replication_targets,
+    ));
+    services::replication::spawn_replication_job(
+        replication.clone(),
+        time_provider.clone(),
+        db.clone(),
+    );
+
+    let remote_registries = config
+        .federation
+        .registries
+        .iter()
+        .map(|registry| {
+            tracing::info!(
+                "delegation requests for {} are forwarded to '{}' at '{}'",
+                registry.policy_issuers.join(", "),
+                registry.id,
+                registry.url
+            );
+            (
+                registry.clone(),
+                Arc::new(PDPProvider::new(
+                    &registry.url,
+                    &registry.eori,
+                    ishare.clone(),
+                )) as Arc<dyn RemoteRegistry>,
+            )
+        })
+        .collect();
+    let federation = Arc::new(Federation::new(&config.federation, remote_registries).unwrap());
+
+    let rate_limiter = Arc::new(RateLimiter::new(config.rate_limit, time_provider.clone()));
+
     let app_state = AppState {
-        server_token: Arc::new(server_token),
-        satellite_provider: Arc::new(sat_provider),
-        time_provider: Arc::new(time_provider),
+        server_token,
+        satellite_provider: sat_provider,
+        identity_provider,
+        time_provider,
+        rate_limiter,
+        dataspaces,
+        authorization_provider,
+        replication,
+        federation,
         de_expiry_seconds: config.de_expiry_seconds,
         config: Arc::new(AppConfig {
             deploy_route: config.deploy_route.clone(),
@@ -246,6 +524,13 @@ async fn main() {
             delegation_allows_service_providers: config.delegation_allows_service_providers,
             frontend: config.frontend,
             service_name: config.service_name,
+            audit_log_retention: config.audit_log_retention,
+            refresh_token_expiry_seconds: config.refresh_token_expiry_seconds,
+            session_lifetime_seconds: config.session_lifetime_seconds,
+            auth_request_expiry_seconds: config.auth_request_expiry_seconds,
+            allowed_redirect_uris: config.allowed_redirect_uris,
+            admin_roles: config.admin_roles,
+            party_checks: config.party_checks,
         }),
     };
 
@@ -257,5 +542,10 @@ async fn main() {
         .await
         .unwrap();
 
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
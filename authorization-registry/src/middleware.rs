@@ -1,20 +1,29 @@
 use crate::{
-    error::{AppError, ExpectedError},
-    services::server_token::{Human, Role},
+    error::{AppError, ErrorResponse, ExpectedError},
+    services::{
+        rate_limit::{RateLimitGroup, RateLimiter},
+        server_token::{Human, Role, Scope, ServiceAccessTokenClaims},
+    },
     utils::extract_bearer_token,
     ServerToken,
 };
 
+use anyhow::Context;
 use axum::{
     body::Body,
-    extract::{Request, State},
-    http::HeaderMap,
+    extract::{ConnectInfo, Request, State},
+    http::{header::RETRY_AFTER, HeaderMap, Method},
     middleware::Next,
-    response::IntoResponse,
-    Extension,
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
+use jsonwebtoken::TokenData;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct RealmAccess {
@@ -36,7 +45,7 @@ pub async fn extract_role_middleware(
     next: Next,
 ) -> Result<impl IntoResponse, AppError> {
     let token_string = extract_bearer_token(&header)?;
-    let token = server_token.decode_token(&token_string)?;
+    let token = server_token.verify_token(&token_string).await?;
 
     req.extensions_mut().insert(token.claims.role.clone());
     req.extensions_mut().insert(token);
@@ -100,6 +109,167 @@ pub async fn auth_role_middleware(
     return Ok((status, headers, body));
 }
 
+// scopes a token needs for the routes of a router. Reading requires the read scope,
+// all other methods the write scope
+#[derive(Clone, Copy, Debug)]
+pub struct RequiredScopes {
+    pub read: Scope,
+    pub write: Scope,
+}
+
+impl RequiredScopes {
+    pub fn new(read: Scope, write: Scope) -> Self {
+        return Self { read, write };
+    }
+
+    pub fn all(scope: Scope) -> Self {
+        return Self::new(scope, scope);
+    }
+}
+
+pub async fn scope_middleware(
+    State(required_scopes): State<RequiredScopes>,
+    Extension(token): Extension<TokenData<ServiceAccessTokenClaims>>,
+    req: Request,
+    next: Next,
+) -> Result<(StatusCode, HeaderMap, Body), AppError> {
+    let required_scope = match *req.method() {
+        Method::GET | Method::HEAD => required_scopes.read,
+        _ => required_scopes.write,
+    };
+
+    if !token.claims.has_scope(required_scope) {
+        return Err(AppError::Expected(ExpectedError {
+            status_code: StatusCode::FORBIDDEN,
+            message: format!(
+                "Your access token does not have the '{}' scope",
+                required_scope.as_str()
+            ),
+            reason: format!(
+                "Company '{}' with scope '{}' does not have required scope '{}'",
+                token.claims.role.get_company_id(),
+                token.claims.scope.clone().unwrap_or_default(),
+                required_scope.as_str()
+            ),
+            metadata: None,
+        }));
+    }
+
+    let res = next.run(req).await;
+    let status = res.status().clone();
+    let headers = res.headers().clone();
+    let body = res.into_body();
+
+    return Ok((status, headers, body));
+}
+
+const MAX_RATE_LIMITED_BODY_BYTES: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+struct ClientIdForm {
+    client_id: Option<String>,
+}
+
+fn get_source_ip(req: &Request, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for {
+        let forwarded_for = req
+            .headers()
+            .get("X-Forwarded-For")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+
+        if forwarded_for.is_some() {
+            return forwarded_for;
+        }
+    }
+
+    return req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|connect_info| connect_info.0.ip());
+}
+
+// the party of token requests is the client_id of the form, which has to be read from the body
+async fn extract_client_id(req: Request) -> Result<(Request, Option<String>), AppError> {
+    let (parts, body) = req.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_RATE_LIMITED_BODY_BYTES)
+        .await
+        .map_err(|e| {
+            return AppError::Expected(ExpectedError {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Invalid request body".to_owned(),
+                reason: format!("Error reading request body: {}", e),
+                metadata: None,
+            });
+        })?;
+
+    let client_id = serde_urlencoded::from_bytes::<ClientIdForm>(&bytes)
+        .ok()
+        .and_then(|form| form.client_id);
+
+    return Ok((Request::from_parts(parts, Body::from(bytes)), client_id));
+}
+
+// limits the requests of a route group per party and per source ip. Routes that require an
+// access token need this layer after `extract_role_middleware`
+pub async fn rate_limit_middleware(
+    State((rate_limiter, group)): State<(Arc<RateLimiter>, RateLimitGroup)>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !rate_limiter.is_enabled(group) {
+        return Ok(next.run(req).await);
+    }
+
+    let ip = get_source_ip(&req, rate_limiter.trust_forwarded_for());
+    let role_party = req.extensions().get::<Role>().map(|r| r.get_company_id());
+    if let Some(party) = role_party {
+        let retry_after = rate_limiter
+            .check(group, Some(&party), ip)
+            .context("Error checking rate limit")?;
+
+        return match retry_after {
+            Some(retry_after) => Ok(too_many_requests(retry_after)),
+            None => Ok(next.run(req).await),
+        };
+    }
+
+    // before authentication only the ip address is counted, so nobody can use up the budget of
+    // another party. Only the requests that the route accepts are counted for the client_id
+    let (req, client_id) = extract_client_id(req).await?;
+    let mut retry_after = rate_limiter
+        .check(group, None, ip)
+        .context("Error checking rate limit")?;
+    if let (None, Some(client_id)) = (retry_after, &client_id) {
+        retry_after = rate_limiter
+            .check_unauthenticated_party(group, client_id)
+            .context("Error checking rate limit")?;
+    }
+
+    if let Some(retry_after) = retry_after {
+        return Ok(too_many_requests(retry_after));
+    }
+
+    let response = next.run(req).await;
+    if let (true, Some(client_id)) = (response.status().is_success(), &client_id) {
+        rate_limiter
+            .count_authenticated_party(group, client_id)
+            .context("Error counting rate limit")?;
+    }
+
+    return Ok(response);
+}
+
+fn too_many_requests(retry_after: i64) -> Response {
+    return (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_after.to_string())],
+        Json(ErrorResponse::new("Too many requests, try again later")),
+    )
+        .into_response();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,4 +406,144 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK)
     }
+
+    fn get_scoped_router() -> Router {
+        async fn hello() -> String {
+            return "Hello world".to_owned();
+        }
+
+        return Router::new()
+            .route("/", get(hello).post(hello))
+            .layer(from_fn_with_state(
+                RequiredScopes::new(Scope::PolicyRead, Scope::PolicyWrite),
+                scope_middleware,
+            ))
+            .layer(from_fn_with_state(
+                std::sync::Arc::new(get_test_service()),
+                extract_role_middleware,
+            ));
+    }
+
+    async fn scoped_request(method: &str, token_header: String) -> StatusCode {
+        return get_scoped_router()
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .method(method)
+                    .header(AUTHORIZATION, token_header)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status();
+    }
+
+    #[tokio::test]
+    async fn test_scope_read_only() {
+        let token_header = server_token_test_helper::get_scoped_machine_token_header(
+            "NL.COMPANY".to_owned(),
+            &[Scope::PolicyRead],
+        );
+
+        assert_eq!(
+            scoped_request("GET", token_header.clone()).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            scoped_request("POST", token_header).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scope_missing() {
+        let token_header = server_token_test_helper::get_scoped_machine_token_header(
+            "NL.COMPANY".to_owned(),
+            &[Scope::Delegation, Scope::AuditRead],
+        );
+
+        assert_eq!(
+            scoped_request("GET", token_header).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scope_human_token() {
+        assert_eq!(
+            scoped_request(
+                "POST",
+                server_token_test_helper::get_human_token_header(None, None)
+            )
+            .await,
+            StatusCode::OK
+        );
+    }
+
+    fn get_role_router(roles: Vec<String>) -> Router {
+        async fn get_hello(Extension(_human): Extension<Human>) -> String {
+            return "Hello world".to_owned();
+        }
+
+        let server_token = std::sync::Arc::new(get_test_service());
+        return Router::new()
+            .route("/", get(get_hello))
+            .layer(from_fn_with_state(roles, auth_role_middleware))
+            .layer(from_fn_with_state(
+                server_token.clone(),
+                extract_human_middleware,
+            ))
+            .layer(from_fn_with_state(
+                server_token.clone(),
+                extract_role_middleware,
+            ));
+    }
+
+    #[tokio::test]
+    async fn test_auth_role() {
+        let router = get_role_router(vec!["policy_admin".to_owned(), "admin".to_owned()]);
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(
+                        AUTHORIZATION,
+                        server_token_test_helper::get_human_token_header_with_roles(&[
+                            "member",
+                            "policy_admin",
+                        ]),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK)
+    }
+
+    #[tokio::test]
+    async fn test_auth_role_missing() {
+        let router = get_role_router(vec!["policy_admin".to_owned()]);
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(
+                        AUTHORIZATION,
+                        server_token_test_helper::get_human_token_header_with_roles(&[
+                            "read_only_admin",
+                        ]),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+    }
 }
@@ -14,50 +14,103 @@ use std::sync::Arc;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::config::{AdminRolesConfig, AuditLogRetentionConfig};
 use crate::{
     db::policy::{self as policy_store, MatchingPolicySetRow, PolicySetsWithPagination},
+    db::replication as replication_store,
     error::ExpectedError,
     services::{
         audit_log::{
             log_event, PolicyAdded, PolicyRemoved, PolicyReplaced, PolicySetDeletedEventMetadata,
-            PolicySetEditedEventMetadata,
+            PolicySetEditedEventMetadata, PolicySetTemplateCreatedEventMetadata,
+            PolicySetTemplateDeletedEventMetadata,
         },
+        audit_log_retention::{self, AuditLogTableStats},
+        party_cache::PartyCacheEntry,
+        party_compliance::validate_service_providers,
+        party_revalidation::RevalidationSummary,
         policy::InsertPolicySetWithPolicies,
+        rate_limit::RateLimitGroupStatus,
+        replication::ReplicationTargetStatus,
     },
 };
 use crate::{db::policy_set_template::InsertPolicySetTemplate, services::policy as policy_service};
 use crate::{error::AppError, error::ErrorResponse, AppState};
 use crate::{
     middleware::{auth_role_middleware, extract_human_middleware, extract_role_middleware},
-    services::server_token::ServerToken,
+    services::server_token::{RevocationSubject, ServerToken},
 };
 
-pub fn get_admin_routes(server_token: Arc<ServerToken>) -> Router<AppState> {
-    return Router::new()
+pub fn get_admin_routes(
+    server_token: Arc<ServerToken>,
+    admin_roles: &AdminRolesConfig,
+) -> Router<AppState> {
+    let policy_read_routes = Router::new()
+        .route("/policy-set", get(get_all_policy_sets))
+        .route("/policy-set/:id", get(get_policy_set))
+        .route("/policy-set/:id/policy/:policy_id", get(get_policy))
+        .route("/policy-set-suspension", get(get_policy_set_suspensions))
+        .route_layer(from_fn_with_state(
+            admin_roles.policy_read_roles(),
+            auth_role_middleware,
+        ));
+
+    let policy_write_routes = Router::new()
+        .route("/policy-set", post(insert_policy_set))
+        .route("/policy-set/:id", delete(delete_policy_set))
+        .route("/policy-set/:id/policy", post(add_policy_to_policy_set))
+        .route(
+            "/policy-set/:id/policy/:policy_id",
+            delete(delete_policy_from_policy_set).put(replace_policy_in_policy_set),
+        )
+        .route_layer(from_fn_with_state(
+            admin_roles.policy_write_roles(),
+            auth_role_middleware,
+        ));
+
+    let template_write_routes = Router::new()
         .route(
             "/policy-set-template/:id",
             delete(delete_policy_set_template),
         )
         .route("/policy-set-template", post(insert_policy_set_template))
+        .route_layer(from_fn_with_state(
+            admin_roles.template_write_roles(),
+            auth_role_middleware,
+        ));
+
+    let audit_read_routes = Router::new()
+        .route("/audit-log/status", get(get_audit_log_status))
+        .route_layer(from_fn_with_state(
+            admin_roles.audit_read_roles(),
+            auth_role_middleware,
+        ));
+
+    let admin_only_routes = Router::new()
+        .route("/token-revocation", post(revoke_tokens))
+        .route("/rate-limit/status", get(get_rate_limit_status))
         .route(
-            "/policy-set",
-            post(insert_policy_set).get(get_all_policy_sets),
-        )
-        .route(
-            "/policy-set/:id",
-            get(get_policy_set).delete(delete_policy_set),
+            "/party-cache",
+            get(get_party_cache).delete(flush_party_cache),
         )
-        .route("/policy-set/:id/policy", post(add_policy_to_policy_set))
+        .route("/party-cache/:eori", delete(flush_party_cache_entry))
+        .route("/party-revalidation", post(revalidate_parties))
+        .route("/replication", get(get_replication_status))
         .route(
-            "/policy-set/:id/policy/:policy_id",
-            delete(delete_policy_from_policy_set)
-                .put(replace_policy_in_policy_set)
-                .get(get_policy),
+            "/replication/:target/resync",
+            post(resync_replication_target),
         )
-        .layer(from_fn_with_state(
-            vec!["dexspace_admin".to_owned()],
+        .route_layer(from_fn_with_state(
+            admin_roles.admin_roles(),
             auth_role_middleware,
-        ))
+        ));
+
+    return Router::new()
+        .merge(policy_read_routes)
+        .merge(policy_write_routes)
+        .merge(template_write_routes)
+        .merge(audit_read_routes)
+        .merge(admin_only_routes)
         .layer(from_fn(extract_human_middleware))
         .layer(from_fn_with_state(server_token, extract_role_middleware));
 }
@@ -88,8 +141,31 @@ pub fn get_admin_routes(server_token: Arc<ServerToken>) -> Router<AppState> {
 async fn delete_policy_set_template(
     WithRejection(Path(id), _): WithRejection<Path<Uuid>, AppError>,
     Extension(db): Extension<DatabaseConnection>,
+    State(app_state): State<AppState>,
 ) -> Result<(), AppError> {
-    crate::db::policy_set_template::delete_policy_template(id, &db).await?;
+    let transaction = db.begin().await.context("error starting db transaction")?;
+
+    crate::db::policy_set_template::delete_policy_template(id, &transaction).await?;
+
+    log_event(
+        app_state.time_provider.now(),
+        id.to_string(),
+        crate::services::audit_log::EventType::ArPolicySetTemplateDeleted(
+            PolicySetTemplateDeletedEventMetadata {
+                policy_set_template_id: id.to_owned(),
+            },
+        ),
+        None,
+        None,
+        &transaction,
+    )
+    .await
+    .context("Error logging policy set template deleted event")?;
+
+    transaction
+        .commit()
+        .await
+        .context("error commiting transaction to db")?;
 
     Ok(())
 }
@@ -138,26 +214,40 @@ async fn insert_policy_set_template(
     WithRejection(Json(body), _): WithRejection<Json<InsertPolicySetTemplate>, AppError>,
 ) -> Result<Json<InsertPolicySetTemplateResponse>, AppError> {
     for p in body.policies.iter() {
-        for sp in p.service_providers.iter() {
-            app_state
-                .satellite_provider
-                .validate_party(app_state.time_provider.now(), sp)
-                .await
-                .map_err(|e| {
-                    AppError::Expected(ExpectedError {
-                        status_code: StatusCode::BAD_REQUEST,
-                        message: format!(
-                            "Unable to verify service provider '{}' as valid iSHARE party",
-                            &sp
-                        ),
-                        reason: format!("{:?}", e),
-                        metadata: None,
-                    })
-                })?;
-        }
+        validate_service_providers(
+            app_state.time_provider.now(),
+            app_state.satellite_provider.as_ref(),
+            &app_state.config.party_checks,
+            &p.service_providers,
+        )
+        .await?;
     }
 
-    let inserted_id = crate::db::policy_set_template::insert_policy_set_template(body, &db).await?;
+    let transaction = db.begin().await.context("error starting db transaction")?;
+
+    let inserted_id =
+        crate::db::policy_set_template::insert_policy_set_template(body, &transaction).await?;
+
+    log_event(
+        app_state.time_provider.now(),
+        inserted_id.to_string(),
+        crate::services::audit_log::EventType::ArPolicySetTemplateCreated(
+            PolicySetTemplateCreatedEventMetadata {
+                policy_set_template_id: inserted_id.to_owned(),
+            },
+        ),
+        None,
+        None,
+        &transaction,
+    )
+    .await
+    .context("Error logging policy set template created event")?;
+
+    transaction
+        .commit()
+        .await
+        .context("error commiting transaction to db")?;
+
     let response = InsertPolicySetTemplateResponse { uuid: inserted_id };
 
     Ok(Json(response))
@@ -267,23 +357,15 @@ async fn add_policy_to_policy_set(
     State(app_state): State<AppState>,
     Json(body): Json<Policy>,
 ) -> Result<Json<ar_entity::policy::Model>, AppError> {
-    for sp in body.target.environment.service_providers.iter() {
-        app_state
-            .satellite_provider
-            .validate_party(app_state.time_provider.now(), sp)
-            .await
-            .map_err(|e| {
-                AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: format!(
-                        "Unable to verify service provider '{}' as valid iSHARE party",
-                        &sp
-                    ),
-                    reason: format!("{:?}", e),
-                    metadata: None,
-                })
-            })?;
-    }
+    let satellite_provider =
+        policy_service::get_policy_set_satellite_provider(&id, &app_state.dataspaces, &db).await?;
+    validate_service_providers(
+        app_state.time_provider.now(),
+        satellite_provider.as_ref(),
+        &app_state.config.party_checks,
+        &body.target.environment.service_providers,
+    )
+    .await?;
 
     let transaction = db.begin().await.context("error starting db connection")?;
 
@@ -305,6 +387,10 @@ async fn add_policy_to_policy_set(
     .await
     .context("error logging policy added event")?;
 
+    replication_store::record_change(app_state.time_provider.now(), id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -364,23 +450,19 @@ async fn replace_policy_in_policy_set(
     State(app_state): State<AppState>,
     Json(body): Json<Policy>,
 ) -> Result<Json<ar_entity::policy::Model>, AppError> {
-    for sp in body.target.environment.service_providers.iter() {
-        app_state
-            .satellite_provider
-            .validate_party(app_state.time_provider.now(), sp)
-            .await
-            .map_err(|e| {
-                AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: format!(
-                        "Unable to verify service provider '{}' as valid iSHARE party",
-                        &sp
-                    ),
-                    reason: format!("{:?}", e),
-                    metadata: None,
-                })
-            })?;
-    }
+    let satellite_provider = policy_service::get_policy_set_satellite_provider(
+        &policy_set_id,
+        &app_state.dataspaces,
+        &db,
+    )
+    .await?;
+    validate_service_providers(
+        app_state.time_provider.now(),
+        satellite_provider.as_ref(),
+        &app_state.config.party_checks,
+        &body.target.environment.service_providers,
+    )
+    .await?;
 
     let transaction = db.begin().await.context("error starting db transaction")?;
 
@@ -403,6 +485,10 @@ async fn replace_policy_in_policy_set(
     .await
     .context("Error logging policy set edited event")?;
 
+    replication_store::record_change(app_state.time_provider.now(), policy_set_id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -463,6 +549,10 @@ async fn delete_policy_set(
     .await
     .context("Error logging policy set deleted event")?;
 
+    replication_store::record_change(app_state.time_provider.now(), id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -527,6 +617,10 @@ async fn delete_policy_from_policy_set(
     .await
     .context("Error logging policy set edited event")?;
 
+    replication_store::record_change(app_state.time_provider.now(), policy_set_id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -632,7 +726,8 @@ async fn insert_policy_set(
         app_state.time_provider.now(),
         &body,
         &db,
-        app_state.satellite_provider,
+        &app_state.dataspaces,
+        &app_state.config.party_checks,
     )
     .await?;
 
@@ -700,6 +795,426 @@ async fn get_all_policy_sets(
     Ok(Json(policy_sets))
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+struct AuditLogStatusResponse {
+    #[serde(flatten)]
+    stats: AuditLogTableStats,
+    #[schema(value_type = Option<Object>)]
+    retention: Option<AuditLogRetentionConfig>,
+}
+
+/// Report the size of the audit log and the oldest retained event (admin access)
+#[utoipa::path(
+    get,
+    path = "/admin/audit-log/status",
+    tag = "Audit Log - Admin",
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Audit log table size, number of events, oldest retained event and the configured retention",
+            content_type = "application/json",
+            body = AuditLogStatusResponse
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn get_audit_log_status(
+    Extension(db): Extension<DatabaseConnection>,
+    State(app_state): State<AppState>,
+) -> Result<Json<AuditLogStatusResponse>, AppError> {
+    let stats = audit_log_retention::get_audit_log_table_stats(&db).await?;
+
+    Ok(Json(AuditLogStatusResponse {
+        stats,
+        retention: app_state.config.audit_log_retention.clone(),
+    }))
+}
+
+/// Report the rate limit budgets, request counters and currently limited parties and IP addresses (admin access)
+#[utoipa::path(
+    get,
+    path = "/admin/rate-limit/status",
+    tag = "Rate Limit - Admin",
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Rate limit status per route group",
+            content_type = "application/json",
+            body = [RateLimitGroupStatus]
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn get_rate_limit_status(
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<RateLimitGroupStatus>>, AppError> {
+    let status = app_state.rate_limiter.get_status()?;
+
+    Ok(Json(status))
+}
+
+#[derive(Deserialize)]
+struct PartyCacheQuery {
+    // the default dataspace when left out
+    dataspace: Option<String>,
+}
+
+/// List the cached party information of the satellite (admin access)
+#[utoipa::path(
+    get,
+    path = "/admin/party-cache",
+    tag = "Party Cache - Admin",
+    params(
+        ("dataspace" = Option<String>, Query, description = "Dataspace of the party cache, the default dataspace when left out")
+    ),
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Cached parties with the answer of the satellite and when it expires",
+            content_type = "application/json",
+            body = [PartyCacheEntry]
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn get_party_cache(
+    State(app_state): State<AppState>,
+    Query(query): Query<PartyCacheQuery>,
+) -> Result<Json<Vec<PartyCacheEntry>>, AppError> {
+    let entries = app_state
+        .dataspaces
+        .get(query.dataspace.as_deref())?
+        .party_cache
+        .get_entries(app_state.time_provider.now())?;
+
+    Ok(Json(entries))
+}
+
+#[derive(Serialize, ToSchema)]
+struct FlushPartyCacheResponse {
+    flushed: u64,
+}
+
+/// Remove all parties from the party cache (admin access)
+#[utoipa::path(
+    delete,
+    path = "/admin/party-cache",
+    tag = "Party Cache - Admin",
+    params(
+        ("dataspace" = Option<String>, Query, description = "Dataspace of the party cache, the default dataspace when left out")
+    ),
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Number of parties removed from the cache",
+            content_type = "application/json",
+            body = FlushPartyCacheResponse
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn flush_party_cache(
+    State(app_state): State<AppState>,
+    Query(query): Query<PartyCacheQuery>,
+) -> Result<Json<FlushPartyCacheResponse>, AppError> {
+    let flushed = app_state
+        .dataspaces
+        .get(query.dataspace.as_deref())?
+        .party_cache
+        .clear()?;
+    tracing::info!("flushed {} parties from the party cache", flushed);
+
+    Ok(Json(FlushPartyCacheResponse { flushed }))
+}
+
+/// Remove a party from the party cache, so it is fetched from the satellite again (admin access)
+#[utoipa::path(
+    delete,
+    path = "/admin/party-cache/{eori}",
+    tag = "Party Cache - Admin",
+    params(
+        ("eori" = String, Path, description = "EORI of the party"),
+        ("dataspace" = Option<String>, Query, description = "Dataspace of the party cache, the default dataspace when left out")
+    ),
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Party has been removed from the cache",
+        ),
+        (
+            status = 404,
+            description = "Party is not in the cache",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Party is not cached"))
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn flush_party_cache_entry(
+    State(app_state): State<AppState>,
+    Path(eori): Path<String>,
+    Query(query): Query<PartyCacheQuery>,
+) -> Result<(), AppError> {
+    if !app_state
+        .dataspaces
+        .get(query.dataspace.as_deref())?
+        .party_cache
+        .remove(&eori)?
+    {
+        return Err(AppError::Expected(ExpectedError {
+            status_code: StatusCode::NOT_FOUND,
+            message: "Party is not cached".to_owned(),
+            reason: format!("Party '{}' is not in the party cache", eori),
+            metadata: None,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Revoke all access tokens that have been issued to a user or company up to now (admin access)
+#[utoipa::path(
+    post,
+    path = "/admin/token-revocation",
+    tag = "Authentication - Admin",
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    request_body(
+        content = RevocationSubject,
+        example = json!({"company": "NL.EORI.LIFEELEC4DMI"})
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Access tokens of the user or company have been revoked",
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn revoke_tokens(
+    State(server_token): State<Arc<ServerToken>>,
+    WithRejection(Json(subject), _): WithRejection<Json<RevocationSubject>, AppError>,
+) -> Result<(), AppError> {
+    tracing::info!("revoking access tokens of {:?}", &subject);
+    server_token.revoke_all_tokens(subject).await?;
+
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+struct PolicySetSuspension {
+    policy_set_id: Uuid,
+    party: String,
+    reason: String,
+    #[schema(value_type = String, format = DateTime)]
+    suspended_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// List the policy sets that are suspended because one of their parties is no longer valid (admin access)
+#[utoipa::path(
+    get,
+    path = "/admin/policy-set-suspension",
+    tag = "Policy Management - Admin",
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Suspended policy sets with the party and the reason of the suspension",
+            content_type = "application/json",
+            body = [PolicySetSuspension]
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn get_policy_set_suspensions(
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<Json<Vec<PolicySetSuspension>>, AppError> {
+    let suspensions = crate::db::policy_set_suspension::get_suspensions(&db)
+        .await?
+        .into_iter()
+        .map(|s| PolicySetSuspension {
+            policy_set_id: s.policy_set_id,
+            party: s.party,
+            reason: s.reason,
+            suspended_at: s.suspended_at,
+        })
+        .collect();
+
+    Ok(Json(suspensions))
+}
+
+/// Revalidate the parties of all policy sets now, instead of waiting for the periodic revalidation (admin access)
+#[utoipa::path(
+    post,
+    path = "/admin/party-revalidation",
+    tag = "Policy Management - Admin",
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Number of checked parties and of suspended and lifted policy sets",
+            content_type = "application/json",
+            body = RevalidationSummary
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn revalidate_parties(
+    Extension(db): Extension<DatabaseConnection>,
+    State(app_state): State<AppState>,
+) -> Result<Json<RevalidationSummary>, AppError> {
+    let summary = crate::services::party_revalidation::revalidate_parties(
+        app_state.time_provider.now(),
+        &app_state.dataspaces,
+        &app_state.config.party_checks,
+        &db,
+    )
+    .await?;
+
+    Ok(Json(summary))
+}
+
+/// Delivery status of the policy sets per replication target (admin access)
+#[utoipa::path(
+    get,
+    path = "/admin/replication",
+    tag = "Policy Management - Admin",
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Delivered, pending and failed policy sets and the last errors per replication target",
+            content_type = "application/json",
+            body = [ReplicationTargetStatus]
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        )
+    )
+ )]
+async fn get_replication_status(
+    Extension(db): Extension<DatabaseConnection>,
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<ReplicationTargetStatus>>, AppError> {
+    let statuses =
+        crate::services::replication::get_replication_status(&app_state.replication, &db).await?;
+
+    Ok(Json(statuses))
+}
+
+/// Send all policy sets to a replication target again, and remove the copies of deleted policy sets from it (admin access)
+#[utoipa::path(
+    post,
+    path = "/admin/replication/{target}/resync",
+    tag = "Policy Management - Admin",
+    params(
+        ("target" = String, Path, description = "Id of the replication target")
+    ),
+    security(
+        ("h2m_bearer_admin" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Delivery status of the target after scheduling the resync",
+            content_type = "application/json",
+            body = ReplicationTargetStatus
+        ),
+        (
+            status = 401,
+            description = "Authentication failed",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unauthorized"))
+        ),
+        (
+            status = 404,
+            description = "Replication target not found",
+            content_type = "application/json",
+            example = json!(ErrorResponse::new("Unknown replication target 'peer'"))
+        )
+    )
+ )]
+async fn resync_replication_target(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(target): Path<String>,
+    State(app_state): State<AppState>,
+) -> Result<Json<ReplicationTargetStatus>, AppError> {
+    let status = crate::services::replication::resync_target(
+        app_state.time_provider.now(),
+        &app_state.replication,
+        &target,
+        &db,
+    )
+    .await?;
+
+    Ok(Json(status))
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
@@ -714,6 +1229,7 @@ mod test {
     };
     use http_body_util::BodyExt;
     use reqwest::header::AUTHORIZATION;
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
     use serde_json::json;
     use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
     use tower::ServiceExt;
@@ -868,7 +1384,7 @@ mod test {
         )
         .unwrap();
 
-        let app = get_test_app(db);
+        let app = get_test_app(db.clone());
         let response = app
             .oneshot(
                 Request::builder()
@@ -886,6 +1402,22 @@ mod test {
 
         assert_eq!(response.status(), StatusCode::OK);
 
+        let events = ar_entity::audit_event::Entity::find()
+            .filter(ar_entity::audit_event::Column::EntryId.eq(body.uuid.to_string()))
+            .all(&db)
+            .await
+            .unwrap();
+
+        let mut event_types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+        event_types.sort();
+        assert_eq!(
+            event_types,
+            vec![
+                "dmi:ar:policy_set_template:created",
+                "dmi:ar:policy_set_template:deleted"
+            ]
+        );
+
         Ok(())
     }
 
@@ -1407,7 +1939,7 @@ mod test {
     ) -> sqlx::Result<()> {
         let db = init_test_db(&conn_option).await;
 
-        let app = get_test_app(db);
+        let app = get_test_app(db.clone());
 
         let request_body = create_request_body(&json!(
             {
@@ -1482,6 +2014,451 @@ mod test {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::OK);
+        // the new policy set is picked up by the replication
+        assert_eq!(
+            ar_entity::policy_set_change::Entity::find()
+                .all(&db)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_insert_policy_set_dataspace(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db.clone());
+
+        let insert = |dataspace: &str| {
+            let request_body = create_request_body(&json!({
+                "policies": [{
+                    "target": {
+                        "resource": {
+                            "type": "test-iden",
+                            "identifiers": ["test"],
+                            "attributes": ["*"]
+                        },
+                        "actions": ["Read"],
+                        "environment": {
+                            "serviceProviders": ["asdf"]
+                        }
+                    },
+                    "rules": [
+                        {
+                            "effect": "Permit"
+                        }
+                    ]
+                }],
+                "target": {
+                    "accessSubject": "sadfasdf"
+                },
+                "policyIssuer": "sss",
+                "licences": [],
+                "maxDelegationDepth": 2,
+                "dataspace": dataspace
+            }));
+
+            return Request::builder()
+                .uri("/admin/policy-set")
+                .method("POST")
+                .header(
+                    AUTHORIZATION,
+                    server_token::server_token_test_helper::get_human_token_header(None, None),
+                )
+                .header("Content-Type", "application/json")
+                .body(Body::new(request_body))
+                .unwrap();
+        };
+
+        let stored_dataspace = |response: axum::response::Response| async {
+            let body: serde_json::Value =
+                serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                    .unwrap();
+            let id = uuid::Uuid::parse_str(body["uuid"].as_str().unwrap()).unwrap();
+
+            return ar_entity::policy_set::Entity::find_by_id(id)
+                .one(&db)
+                .await
+                .unwrap()
+                .unwrap()
+                .dataspace;
+        };
+
+        let response = app.clone().oneshot(insert("other")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(stored_dataspace(response).await, Some("other".to_owned()));
+
+        // policy sets of the default dataspace are stored without a dataspace
+        let response = app.clone().oneshot(insert("default")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(stored_dataspace(response).await, None);
+
+        let response = app.oneshot(insert("unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_get_audit_log_status(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+
+        crate::services::audit_log::log_event(
+            chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+                .unwrap()
+                .to_utc(),
+            "".to_owned(),
+            crate::services::audit_log::EventType::DmiDelegationRequest(
+                ishare::delegation_request::DelegationRequest {
+                    policy_issuer: "pi".to_owned(),
+                    target: ishare::delegation_request::DelegationTarget {
+                        access_subject: "as".to_owned(),
+                    },
+                    policy_sets: vec![],
+                },
+            ),
+            None,
+            None,
+            &db,
+        )
+        .await
+        .unwrap();
+
+        let app = get_test_app(db);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/audit-log/status")
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(None, None),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+
+        assert_eq!(body["event_count"], 1);
+        assert_eq!(body["oldest_event"], "2025-06-11T09:00:00Z");
+        assert!(body["table_size_bytes"].as_i64().unwrap() > 0);
+
+        Ok(())
+    }
+
+    async fn get_policy_sets_status(app: &axum::Router, token_header: &str) -> StatusCode {
+        return app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/policy-set")
+                    .method("GET")
+                    .header(AUTHORIZATION, token_header)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status();
+    }
+
+    async fn post_token_revocation(app: &axum::Router, subject: serde_json::Value) -> StatusCode {
+        return app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/token-revocation")
+                    .method("POST")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(None, None),
+                    )
+                    .header("Content-Type", "application/json")
+                    .body(create_request_body(&subject))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status();
+    }
+
+    #[sqlx::test]
+    async fn test_revoke_company_tokens(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let revoked_token = server_token::server_token_test_helper::get_human_token_header(
+            Some("revoked-company".to_owned()),
+            None,
+        );
+        let other_token = server_token::server_token_test_helper::get_machine_token_header(Some(
+            "other-company".to_owned(),
+        ));
+
+        assert_eq!(
+            get_policy_sets_status(&app, &revoked_token).await,
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            post_token_revocation(&app, json!({"company": "revoked-company"})).await,
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            get_policy_sets_status(&app, &revoked_token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_policy_sets_status(&app, &other_token).await,
+            StatusCode::OK
+        );
+
+        // a token issued right after the revocation, such as the token of a new login, is valid
+        let new_token = server_token::server_token_test_helper::get_human_token_header(
+            Some("revoked-company".to_owned()),
+            None,
+        );
+        assert_eq!(
+            get_policy_sets_status(&app, &new_token).await,
+            StatusCode::OK
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_revoke_user_tokens(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let revoked_token = server_token::server_token_test_helper::get_human_token_header(
+            Some("company".to_owned()),
+            Some("revoked-user".to_owned()),
+        );
+        let colleague_token = server_token::server_token_test_helper::get_human_token_header(
+            Some("company".to_owned()),
+            Some("colleague".to_owned()),
+        );
+
+        assert_eq!(
+            post_token_revocation(&app, json!({"user": "revoked-user"})).await,
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            get_policy_sets_status(&app, &revoked_token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_policy_sets_status(&app, &colleague_token).await,
+            StatusCode::OK
+        );
+
+        Ok(())
+    }
+
+    async fn admin_request_status(
+        app: &axum::Router,
+        method: &str,
+        uri: &str,
+        realm_access_roles: &[&str],
+    ) -> StatusCode {
+        return app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .method(method)
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header_with_roles(
+                            realm_access_roles,
+                        ),
+                    )
+                    .header("Content-Type", "application/json")
+                    .body(create_request_body(&json!({})))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status();
+    }
+
+    #[sqlx::test]
+    async fn test_admin_roles(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let read_only_admin = &["read_only_admin"];
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/policy-set", read_only_admin).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/audit-log/status", read_only_admin).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "POST", "/admin/policy-set", read_only_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            admin_request_status(&app, "POST", "/admin/policy-set-template", read_only_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let policy_admin = &["policy_admin"];
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/policy-set", policy_admin).await,
+            StatusCode::OK
+        );
+        // passes the role check, but the body is not a valid policy set
+        assert_eq!(
+            admin_request_status(&app, "POST", "/admin/policy-set", policy_admin).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/audit-log/status", policy_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let template_admin = &["template_admin"];
+        assert_eq!(
+            admin_request_status(&app, "POST", "/admin/policy-set-template", template_admin).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/policy-set", template_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let audit_reader = &["audit_reader"];
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/audit-log/status", audit_reader).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/policy-set", audit_reader).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // revoking tokens is reserved for full admins
+        for roles in [read_only_admin, policy_admin, template_admin, audit_reader] {
+            assert_eq!(
+                admin_request_status(&app, "POST", "/admin/token-revocation", roles).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        assert_eq!(
+            admin_request_status(&app, "POST", "/admin/token-revocation", &["dexspace_admin"])
+                .await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/rate-limit/status", read_only_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/rate-limit/status", &["dexspace_admin"])
+                .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "DELETE", "/admin/party-cache", read_only_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/party-cache", &["dexspace_admin"]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "DELETE", "/admin/party-cache", &["dexspace_admin"]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(
+                &app,
+                "DELETE",
+                "/admin/party-cache/NL.UNKNOWN",
+                &["dexspace_admin"]
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            admin_request_status(&app, "POST", "/admin/party-revalidation", read_only_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            admin_request_status(
+                &app,
+                "POST",
+                "/admin/party-revalidation",
+                &["dexspace_admin"]
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/policy-set-suspension", read_only_admin)
+                .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/replication", read_only_admin).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            admin_request_status(&app, "GET", "/admin/replication", &["dexspace_admin"]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(
+                &app,
+                "POST",
+                "/admin/replication/peer/resync",
+                &["dexspace_admin"]
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            admin_request_status(
+                &app,
+                "POST",
+                "/admin/replication/unknown/resync",
+                &["dexspace_admin"]
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
 
         Ok(())
     }
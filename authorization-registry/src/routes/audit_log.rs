@@ -1,26 +1,56 @@
 use axum::{
+    body::Body,
     extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
     middleware::from_fn_with_state,
+    response::{IntoResponse, Response},
     routing::get,
     Extension, Json, Router,
 };
 use chrono::{DateTime, Utc};
 use sea_orm::DatabaseConnection;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::AppError,
-    middleware::extract_role_middleware,
+    error::{AppError, ExpectedError},
+    middleware::{extract_role_middleware, scope_middleware, RequiredScopes},
     services::{
-        audit_log::AuditEventWithIssAndSub,
-        server_token::{Role, ServerToken},
+        audit_log::{
+            parse_context_filter, AuditEventFilter, AuditEventStatsRow, AuditEventWithIssAndSub,
+            AuditLogExportFormat, StatsBucket,
+        },
+        server_token::{Role, Scope, ServerToken},
     },
     AppState,
 };
 
 pub fn get_audit_log_routes(server_token: std::sync::Arc<ServerToken>) -> Router<AppState> {
-    return Router::new()
+    let router = Router::new()
         .route("/", get(retrieve_audit_log_entries))
+        .route("/export", get(export_audit_log_entries))
+        .route("/stats", get(retrieve_audit_log_stats));
+
+    return with_audit_log_layers(router, server_token);
+}
+
+// nesting the other routes under "/audit-log/" as well would overlap with "/audit-log"
+pub fn get_audit_log_trailing_slash_routes(
+    server_token: std::sync::Arc<ServerToken>,
+) -> Router<AppState> {
+    let router = Router::new().route("/", get(retrieve_audit_log_entries));
+
+    return with_audit_log_layers(router, server_token);
+}
+
+fn with_audit_log_layers(
+    router: Router<AppState>,
+    server_token: std::sync::Arc<ServerToken>,
+) -> Router<AppState> {
+    return router
+        .layer(from_fn_with_state(
+            RequiredScopes::all(Scope::AuditRead),
+            scope_middleware,
+        ))
         .layer(from_fn_with_state(
             server_token.clone(),
             extract_role_middleware,
@@ -39,6 +69,10 @@ struct RetrieveAuditLogEntriesQuery {
     max_results: u64,
     #[serde(rename = "eventTypes")]
     event_types: Option<String>,
+    party: Option<String>,
+    #[serde(rename = "entryId")]
+    entry_id: Option<String>,
+    context: Option<String>,
 }
 
 async fn retrieve_audit_log_entries(
@@ -49,13 +83,21 @@ async fn retrieve_audit_log_entries(
 ) -> Result<Json<Vec<AuditEventWithIssAndSub>>, AppError> {
     let requester_company_id = role.get_company_id();
 
+    let filter = AuditEventFilter {
+        from: query.from,
+        to: query.to,
+        event_types: query.event_types,
+        party: query.party,
+        entry_id: query.entry_id,
+        context: parse_context_filter(query.context.as_deref().unwrap_or(""))?,
+    };
+
     let events = crate::services::audit_log::retrieve_events(
         &requester_company_id,
-        query.from,
-        query.to,
+        &filter,
         query.max_results,
-        query.event_types,
         app_state.time_provider,
+        app_state.authorization_provider.clone(),
         &app_state.config,
         &db,
     )
@@ -64,6 +106,129 @@ async fn retrieve_audit_log_entries(
     Ok(Json(events))
 }
 
+fn default_stats_bucket() -> StatsBucket {
+    StatsBucket::Day
+}
+
+#[derive(Deserialize)]
+struct RetrieveAuditLogStatsQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    #[serde(rename = "eventTypes")]
+    event_types: Option<String>,
+    party: Option<String>,
+    #[serde(default = "default_stats_bucket")]
+    bucket: StatsBucket,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RetrieveAuditLogStatsResponse {
+    bucket: StatsBucket,
+    stats: Vec<AuditEventStatsRow>,
+}
+
+async fn retrieve_audit_log_stats(
+    Query(query): Query<RetrieveAuditLogStatsQuery>,
+    Extension(db): Extension<DatabaseConnection>,
+    State(app_state): State<AppState>,
+    Extension(role): Extension<Role>,
+) -> Result<Json<RetrieveAuditLogStatsResponse>, AppError> {
+    let filter = AuditEventFilter {
+        from: query.from,
+        to: query.to,
+        event_types: query.event_types,
+        party: query.party,
+        ..Default::default()
+    };
+
+    let stats = crate::services::audit_log::retrieve_event_stats(
+        &role.get_company_id(),
+        &filter,
+        query.bucket,
+        app_state.time_provider,
+        app_state.authorization_provider.clone(),
+        &app_state.config,
+        &db,
+    )
+    .await?;
+
+    Ok(Json(RetrieveAuditLogStatsResponse {
+        bucket: query.bucket,
+        stats,
+    }))
+}
+
+#[derive(Deserialize)]
+struct ExportAuditLogEntriesQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    #[serde(rename = "eventTypes")]
+    event_types: Option<String>,
+    party: Option<String>,
+    #[serde(rename = "entryId")]
+    entry_id: Option<String>,
+    context: Option<String>,
+}
+
+async fn export_audit_log_entries(
+    Query(query): Query<ExportAuditLogEntriesQuery>,
+    headers: HeaderMap,
+    Extension(db): Extension<DatabaseConnection>,
+    State(app_state): State<AppState>,
+    Extension(role): Extension<Role>,
+) -> Result<Response, AppError> {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("");
+
+    let format = match AuditLogExportFormat::from_accept_header(accept) {
+        Some(format) => format,
+        None => {
+            return Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::NOT_ACCEPTABLE,
+                message: "Unsupported export format. Use 'text/csv' or 'application/x-ndjson' in the Accept header".to_owned(),
+                reason: format!("unsupported accept header '{}'", accept),
+                metadata: None,
+            }));
+        }
+    };
+
+    let filter = AuditEventFilter {
+        from: query.from,
+        to: query.to,
+        event_types: query.event_types,
+        party: query.party,
+        entry_id: query.entry_id,
+        context: parse_context_filter(query.context.as_deref().unwrap_or(""))?,
+    };
+
+    let stream = crate::services::audit_log::export_events(
+        role.get_company_id(),
+        filter,
+        format,
+        app_state.time_provider,
+        app_state.authorization_provider.clone(),
+        app_state.config,
+        db,
+    )
+    .await?;
+
+    let content_disposition = format!(
+        "attachment; filename=\"audit-log.{}\"",
+        format.file_extension()
+    );
+
+    return Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_owned()),
+            (header::CONTENT_DISPOSITION, content_disposition),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response());
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -74,7 +239,10 @@ mod tests {
         AuditEventWithIssAndSub, EditedType, PolicyAdded, PolicyRemoved, PolicyReplaced,
     };
     use crate::services::server_token;
-    use crate::test_helpers::helpers::{create_request_body, get_test_app, init_test_db};
+    use crate::test_helpers::helpers::{
+        create_request_body, get_test_app, get_test_app_with_authorization_provider, init_test_db,
+        TestAuthorizationProvider,
+    };
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
     use http_body_util::BodyExt;
@@ -148,6 +316,27 @@ mod tests {
 
         assert_eq!(audit_log.len(), 500);
 
+        // the trailing slash is served as well
+        let audit_log_response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/audit-log/")
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.44444".to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(audit_log_response.status(), StatusCode::OK);
+
         let audit_log_response = get_test_app(db.clone())
             .oneshot(
                 Request::builder()
@@ -1852,4 +2041,677 @@ mod tests {
 
         Ok(())
     }
+
+    #[sqlx::test]
+    async fn test_export_ndjson(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+
+        // more events than fit into a single page to make sure all pages are streamed
+        for (i, policy_issuer) in ["NL.PARTY1", "NL.PARTY2"].iter().enumerate() {
+            for _ in 0..600 {
+                crate::services::audit_log::log_event(
+                    chrono::DateTime::parse_from_rfc3339("2025-08-11T09:00:00Z")
+                        .unwrap()
+                        .to_utc(),
+                    format!("entry-{}", i),
+                    crate::services::audit_log::EventType::DmiDelegationRequest(
+                        DelegationRequest {
+                            policy_issuer: policy_issuer.to_string(),
+                            target: DelegationTarget {
+                                access_subject: "NL.SUBJECT".to_owned(),
+                            },
+                            policy_sets: vec![],
+                        },
+                    ),
+                    None,
+                    None,
+                    &db,
+                )
+                .await
+                .unwrap();
+            }
+        }
+
+        let export = |uri: &str| {
+            get_test_app(db.clone()).oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.44444".to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .header("Accept", "application/x-ndjson")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = export("/audit-log/export?eventTypes=dmi:ar:delegation:request")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "application/x-ndjson"
+        );
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let events: Vec<AuditEventWithIssAndSub> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 1200);
+        let unique_ids: std::collections::HashSet<&String> = events.iter().map(|e| &e.id).collect();
+        assert_eq!(unique_ids.len(), 1200);
+
+        let response = export("/audit-log/export?party=NL.PARTY2").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let events: Vec<AuditEventWithIssAndSub> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 600);
+        assert!(events
+            .iter()
+            .all(|e| e.context.get("policyIssuer").unwrap() == "NL.PARTY2"));
+
+        let response = export("/audit-log/export?party=NL.SUBJECT&entryId=entry-0")
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let events: Vec<AuditEventWithIssAndSub> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 600);
+        assert!(events
+            .iter()
+            .all(|e| e.context.get("entryId").unwrap() == "entry-0"));
+    }
+
+    #[sqlx::test]
+    async fn test_export_csv(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+
+        crate::services::audit_log::log_event(
+            chrono::DateTime::parse_from_rfc3339("2025-08-11T09:00:00Z")
+                .unwrap()
+                .to_utc(),
+            "".to_owned(),
+            crate::services::audit_log::EventType::DmiDelegationRequest(DelegationRequest {
+                policy_issuer: "pi".to_owned(),
+                target: DelegationTarget {
+                    access_subject: "as".to_owned(),
+                },
+                policy_sets: vec![],
+            }),
+            None,
+            None,
+            &db,
+        )
+        .await
+        .unwrap();
+
+        let response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/audit-log/export?eventTypes=dmi:ar:delegation:request")
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.44444".to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .header("Accept", "text/csv")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"audit-log.csv\""
+        );
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "id,timestamp,type,source,iss,sub,context,data");
+        assert!(lines[1].contains(",dmi:ar:delegation:request,AR,"));
+        assert!(lines[1].contains("\"\"policyIssuer\"\":\"\"pi\"\""));
+    }
+
+    #[sqlx::test]
+    async fn test_export_unsupported_format(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+
+        let response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/audit-log/export")
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.44444".to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .header("Accept", "application/xml")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[sqlx::test]
+    async fn test_failed_login_audit_event(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+
+        let token_response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/machine/token")
+                    .method("POST")
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(Body::from(
+                        "grant_type=client_credentials&client_assertion_type=urn:ietf:params:oauth:client-assertion-type:jwt-bearer&client_id=NL.BAD_ACTOR&client_assertion=invalid&scope=iSHARE",
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(token_response.status(), StatusCode::BAD_REQUEST);
+
+        let audit_log_response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri("/audit-log?eventTypes=dmi:ar:authentication:failed")
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.44444".to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let audit_log: Vec<AuditEventWithIssAndSub> = serde_json::from_slice(
+            &audit_log_response
+                .into_body()
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(audit_log.len(), 1);
+        let context = &audit_log[0].context;
+        assert_eq!(context.get("login_type").unwrap(), "m2m");
+        assert_eq!(context.get("client_id").unwrap(), "NL.BAD_ACTOR");
+        assert_eq!(context.get("reason").unwrap(), "invalid client assertion");
+        assert_eq!(context.get("entryId").unwrap(), "NL.BAD_ACTOR");
+    }
+
+    async fn query_audit_log(
+        db: &sea_orm::DatabaseConnection,
+        uri: &str,
+    ) -> (StatusCode, Vec<AuditEventWithIssAndSub>) {
+        let response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.44444".to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        if status != StatusCode::OK {
+            return (status, vec![]);
+        }
+
+        return (status, serde_json::from_slice(&body).unwrap());
+    }
+
+    async fn insert_search_events(db: &sea_orm::DatabaseConnection) -> Uuid {
+        let policy_set_id = Uuid::new_v4();
+
+        crate::services::audit_log::log_event(
+            chrono::DateTime::parse_from_rfc3339("2025-08-11T09:00:00Z")
+                .unwrap()
+                .to_utc(),
+            "".to_owned(),
+            crate::services::audit_log::EventType::DmiDelegationRequest(DelegationRequest {
+                policy_issuer: "NL.ISSUER".to_owned(),
+                target: DelegationTarget {
+                    access_subject: "NL.SUBJECT".to_owned(),
+                },
+                policy_sets: vec![],
+            }),
+            None,
+            None,
+            db,
+        )
+        .await
+        .unwrap();
+
+        crate::services::audit_log::log_event(
+            chrono::DateTime::parse_from_rfc3339("2025-08-11T09:00:00Z")
+                .unwrap()
+                .to_utc(),
+            policy_set_id.to_string(),
+            crate::services::audit_log::EventType::ArPolicySetEdited(
+                crate::services::audit_log::PolicySetEditedEventMetadata {
+                    policy_set_id,
+                    edited_type: EditedType::PolicyRemoved(PolicyRemoved {
+                        policy_id: Uuid::parse_str("d3bfe3a1-3f5e-4b1b-9b5a-2d1b7f0b8c11").unwrap(),
+                    }),
+                },
+            ),
+            None,
+            None,
+            db,
+        )
+        .await
+        .unwrap();
+
+        crate::services::audit_log::log_event(
+            chrono::DateTime::parse_from_rfc3339("2025-08-11T09:00:00Z")
+                .unwrap()
+                .to_utc(),
+            policy_set_id.to_string(),
+            crate::services::audit_log::EventType::ArPolicySetDeleted(
+                crate::services::audit_log::PolicySetDeletedEventMetadata { policy_set_id },
+            ),
+            None,
+            None,
+            db,
+        )
+        .await
+        .unwrap();
+
+        return policy_set_id;
+    }
+
+    #[sqlx::test]
+    async fn test_entry_id_query(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+        let policy_set_id = insert_search_events(&db).await;
+
+        let (status, audit_log) =
+            query_audit_log(&db, &format!("/audit-log?entryId={}", policy_set_id)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(audit_log.len(), 2);
+        assert!(audit_log
+            .iter()
+            .all(|e| e.context.get("entryId").unwrap() == &policy_set_id.to_string()));
+
+        let (_, audit_log) = query_audit_log(
+            &db,
+            &format!(
+                "/audit-log?entryId={}&eventTypes=dmi:ar:policy_set:deleted",
+                policy_set_id
+            ),
+        )
+        .await;
+
+        assert_eq!(audit_log.len(), 1);
+    }
+
+    #[sqlx::test]
+    async fn test_party_query(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+        insert_search_events(&db).await;
+
+        // the access subject is nested in the context of the delegation request
+        let (status, audit_log) = query_audit_log(&db, "/audit-log?party=NL.SUBJECT").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(audit_log.len(), 1);
+        assert_eq!(audit_log[0].event_type, "dmi:ar:delegation:request");
+
+        let (_, audit_log) = query_audit_log(&db, "/audit-log?party=NL.ISSUER").await;
+        assert_eq!(audit_log.len(), 1);
+
+        let (_, audit_log) = query_audit_log(&db, "/audit-log?party=NL.NOBODY").await;
+        assert_eq!(audit_log.len(), 0);
+    }
+
+    #[sqlx::test]
+    async fn test_context_query(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+        let policy_set_id = insert_search_events(&db).await;
+
+        let (status, audit_log) = query_audit_log(
+            &db,
+            "/audit-log?context=policy_id:d3bfe3a1-3f5e-4b1b-9b5a-2d1b7f0b8c11",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(audit_log.len(), 1);
+        assert_eq!(audit_log[0].event_type, "dmi:ar:policy_set:edited");
+
+        let (_, audit_log) = query_audit_log(
+            &db,
+            &format!(
+                "/audit-log?context=policy_set_id:{},edit_type:PolicyRemoved",
+                policy_set_id
+            ),
+        )
+        .await;
+        assert_eq!(audit_log.len(), 1);
+
+        let (_, audit_log) =
+            query_audit_log(&db, "/audit-log?context=accessSubject:NL.SUBJECT").await;
+        assert_eq!(audit_log.len(), 1);
+        assert_eq!(audit_log[0].event_type, "dmi:ar:delegation:request");
+
+        let (_, audit_log) =
+            query_audit_log(&db, "/audit-log?context=accessSubject:NL.ISSUER").await;
+        assert_eq!(audit_log.len(), 0);
+
+        let (status, _) = query_audit_log(&db, "/audit-log?context=policy_id").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    async fn get_audit_log_stats(
+        db: &sea_orm::DatabaseConnection,
+        uri: &str,
+        company_id: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = get_test_app(db.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some(company_id.to_owned()),
+                            Some("lovely-user".to_owned()),
+                        ),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        return (status, serde_json::from_slice(&body).unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_stats(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+
+        for (timestamp, policy_issuer) in [
+            ("2025-08-11T09:10:00Z", "NL.ISSUER1"),
+            ("2025-08-11T09:50:00Z", "NL.ISSUER1"),
+            ("2025-08-11T10:10:00Z", "NL.ISSUER1"),
+            ("2025-08-11T10:20:00Z", "NL.ISSUER2"),
+            ("2025-08-12T09:00:00Z", "NL.ISSUER1"),
+        ] {
+            crate::services::audit_log::log_event(
+                chrono::DateTime::parse_from_rfc3339(timestamp)
+                    .unwrap()
+                    .to_utc(),
+                "".to_owned(),
+                crate::services::audit_log::EventType::DmiDelegationRequest(DelegationRequest {
+                    policy_issuer: policy_issuer.to_owned(),
+                    target: DelegationTarget {
+                        access_subject: "NL.SUBJECT".to_owned(),
+                    },
+                    policy_sets: vec![],
+                }),
+                None,
+                None,
+                &db,
+            )
+            .await
+            .unwrap();
+        }
+
+        let (status, stats) = get_audit_log_stats(
+            &db,
+            "/audit-log/stats?eventTypes=dmi:ar:delegation:request",
+            "NL.44444",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["bucket"], "day");
+        assert_eq!(
+            stats["stats"],
+            json!([
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": null, "outcome": null, "count": 3},
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER2", "resourceType": null, "outcome": null, "count": 1},
+                {"bucket": "2025-08-12T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": null, "outcome": null, "count": 1},
+            ])
+        );
+
+        let (_, stats) = get_audit_log_stats(
+            &db,
+            "/audit-log/stats?bucket=hour&party=NL.ISSUER1&to=2025-08-11T23:00:00Z",
+            "NL.44444",
+        )
+        .await;
+
+        assert_eq!(stats["bucket"], "hour");
+        assert_eq!(
+            stats["stats"],
+            json!([
+                {"bucket": "2025-08-11T09:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": null, "outcome": null, "count": 2},
+                {"bucket": "2025-08-11T10:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": null, "outcome": null, "count": 1},
+            ])
+        );
+
+        let (_, stats) = get_audit_log_stats(
+            &db,
+            "/audit-log/stats?bucket=week&eventTypes=dmi:ar:delegation:request",
+            "NL.44444",
+        )
+        .await;
+
+        assert_eq!(
+            stats["stats"],
+            json!([
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": null, "outcome": null, "count": 4},
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER2", "resourceType": null, "outcome": null, "count": 1},
+            ])
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_stats_resource_type_and_outcome(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+
+        let policy = |resource_type: &str| {
+            json!({
+                "target": {
+                    "resource": {
+                        "type": resource_type,
+                        "identifiers": ["*"],
+                        "attributes": ["*"]
+                    },
+                    "actions": ["Read"]
+                },
+                "rules": [{ "effect": "Permit" }]
+            })
+        };
+        for (resource_types, outcome) in [
+            (vec!["Container"], Some("Permit")),
+            (vec!["Container"], Some("Deny")),
+            (vec!["Container", "Vessel"], Some("Permit")),
+            (vec!["Vessel"], None),
+        ] {
+            let delegation_request: DelegationRequest = serde_json::from_value(json!({
+                "policyIssuer": "NL.ISSUER1",
+                "target": { "accessSubject": "NL.SUBJECT" },
+                "policySets": [{
+                    "policies": resource_types.iter().map(|r| policy(r)).collect::<Vec<_>>()
+                }]
+            }))
+            .unwrap();
+            let mut data = json!({ "allowed": outcome.is_some() });
+            if let Some(outcome) = outcome {
+                data["outcome"] = json!(outcome);
+            }
+
+            crate::services::audit_log::log_event(
+                chrono::DateTime::parse_from_rfc3339("2025-08-11T09:10:00Z")
+                    .unwrap()
+                    .to_utc(),
+                "".to_owned(),
+                crate::services::audit_log::EventType::DmiDelegationRequest(delegation_request),
+                None,
+                Some(data),
+                &db,
+            )
+            .await
+            .unwrap();
+        }
+
+        let (status, stats) = get_audit_log_stats(
+            &db,
+            "/audit-log/stats?eventTypes=dmi:ar:delegation:request",
+            "NL.44444",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        // a request for several resource types is counted for each of them
+        assert_eq!(
+            stats["stats"],
+            json!([
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": "Container", "outcome": "Deny", "count": 1},
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": "Container", "outcome": "Permit", "count": 2},
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": "Vessel", "outcome": "Permit", "count": 1},
+                {"bucket": "2025-08-11T00:00:00Z", "eventType": "dmi:ar:delegation:request", "party": "NL.ISSUER1", "resourceType": "Vessel", "outcome": null, "count": 1},
+            ])
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_stats_without_delegation_evidence(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+
+        insert_policy_set_fixture("./fixtures/policy_set_audit_log.json", &db).await;
+
+        let (status, _) = get_audit_log_stats(&db, "/audit-log/stats", "NL.NO_ACCESS").await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_stats_access_by_pdp(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        let app = get_test_app_with_authorization_provider(
+            db,
+            TestAuthorizationProvider {
+                grants: vec![(
+                    "NL.NO_ACCESS".to_owned(),
+                    "AuditLog".to_owned(),
+                    "Read".to_owned(),
+                )],
+            },
+        );
+
+        for (company_id, expected_status) in [
+            ("NL.NO_ACCESS", StatusCode::OK),
+            ("NL.OTHER", StatusCode::UNAUTHORIZED),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/audit-log/stats")
+                        .method("GET")
+                        .header(
+                            AUTHORIZATION,
+                            server_token::server_token_test_helper::get_human_token_header(
+                                Some(company_id.to_owned()),
+                                Some("lovely-user".to_owned()),
+                            ),
+                        )
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), expected_status, "{}", company_id);
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::Context;
 use axum::{
     extract::{Host, State},
-    http::HeaderMap,
+    http::{HeaderMap, Method},
     routing::get,
     Json, Router,
 };
@@ -13,6 +13,7 @@ use serde::Serialize;
 
 use crate::{
     error::{AppError, ExpectedError},
+    services::{dataspace::DATASPACE_HEADER, server_token::Scope},
     AppState,
 };
 use utoipa::ToSchema;
@@ -21,47 +22,173 @@ pub fn get_capabilities_routes() -> Router<AppState> {
     return Router::new().route("/", get(get_capabilities));
 }
 
-pub fn create_capabilities(party_id: &str, api_url: &str, show_private: bool) -> Capabilities {
-    let mut supported_features: Vec<SupportedFeatures> = vec![SupportedFeatures::Public(vec![
-        SupportedFeature {
-            id: "ebb696ab-bda7-44a9-8cec-382183d58d9d".to_owned(),
-            feature: "access token".to_owned(),
-            url: format!("{}{}", api_url, "/connect/machine/token"),
-            description: "retrieve machine access token for M2M authentication".to_owned(),
-            token_endpoint: None,
-        },
-        SupportedFeature {
-            id: "ebb696ab-bda7-44a9-8cec-382183d58d9d".to_owned(),
-            feature: "human access token".to_owned(),
-            url: format!("{}{}", api_url, "/connect/machine/token"),
+const TOKEN_PATH: &str = "/connect/machine/token";
+
+// a feature of the authorization registry, advertised in the capabilities. The id of a feature
+// never changes, `path` is relative to the deploy route
+pub struct CapabilityFeature {
+    pub id: &'static str,
+    pub feature: &'static str,
+    // the iSHARE capabilities have no method, the tests request the route with it
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub method: Method,
+    pub path: &'static str,
+    pub description: String,
+    // only shown to authenticated callers
+    pub restricted: bool,
+    // the feature requires an access token of the token endpoint
+    pub requires_token: bool,
+}
+
+// the features of the routes mounted by `get_app`, described for the configuration of the app
+// state and leaving out features that are not enabled
+pub fn get_capability_features(app_state: &AppState) -> Vec<CapabilityFeature> {
+    // with a pdp the pdp decides which policy sets and events a party can access
+    let access = match app_state.authorization_provider {
+        Some(_) => ", access is decided by the policy decision point",
+        None => "",
+    };
+
+    let mut features = vec![CapabilityFeature {
+        id: "ebb696ab-bda7-44a9-8cec-382183d58d9d",
+        feature: "access token",
+        method: Method::POST,
+        path: TOKEN_PATH,
+        description: format!(
+            "retrieve machine access token for M2M authentication. Supported scopes: iSHARE {}",
+            Scope::to_claim(&Scope::ALL)
+        ),
+        restricted: false,
+        requires_token: false,
+    }];
+
+    // a human login can only redirect back to one of the allowed redirect uris
+    if !app_state.config.allowed_redirect_uris.is_empty() {
+        features.push(CapabilityFeature {
+            id: "e634b172-ba56-4f7e-90c4-4674e81f2eee",
+            feature: "human access token",
+            method: Method::GET,
+            path: "/connect/human/auth",
             description: "retrieve human access token for H2M authentication".to_owned(),
-            token_endpoint: None,
-        },
-        SupportedFeature {
-            id: "d7d27d71-2755-4eea-bb97-bfa5ce8addef".to_owned(),
-            feature: "capabilities".to_owned(),
-            url: format!("{}/capabilities", api_url),
-            description: "retrieve capabilities".to_owned(),
-            token_endpoint: Some(format!("{}/connect/machine/token", api_url)),
-        },
-        SupportedFeature {
-            id: "igds-auditlog".to_owned(),
-            feature: "igds-auditlog".to_owned(),
-            token_endpoint: Some(format!("{}/connect/machine/token", api_url)),
-            url: format!("{}/audit-log", api_url),
-            description: "InformationGrid DataSharing auditlog".to_string(),
-        },
-    ])];
-
-    if show_private {
-        supported_features.push(SupportedFeatures::Restricted(vec![SupportedFeature {
-            id: "aaf5162b-82f2-4bf2-9eaa-e01b380e7ec3".to_owned(),
-            url: format!("{}{}", api_url, "/delegation"),
-            feature: "iSHARE delegation request".to_owned(),
-            description: "issue iSHARE delegation evidence based on your delegation request"
+            restricted: false,
+            requires_token: false,
+        });
+    }
+
+    features.push(CapabilityFeature {
+        id: "d7d27d71-2755-4eea-bb97-bfa5ce8addef",
+        feature: "capabilities",
+        method: Method::GET,
+        path: "/capabilities",
+        description: "retrieve capabilities".to_owned(),
+        restricted: false,
+        requires_token: true,
+    });
+    features.push(CapabilityFeature {
+        id: "igds-auditlog",
+        feature: "igds-auditlog",
+        method: Method::GET,
+        path: "/audit-log",
+        description: format!(
+            "InformationGrid DataSharing auditlog. Required scope: {}{}",
+            Scope::AuditRead.as_str(),
+            access
+        ),
+        restricted: false,
+        requires_token: true,
+    });
+
+    // tokens signed with a shared secret can not be verified by other parties
+    if !app_state.server_token.get_jwks().keys.is_empty() {
+        features.push(CapabilityFeature {
+            id: "decd5183-b6f4-43af-a5c0-e6831624a0a6",
+            feature: "jwks",
+            method: Method::GET,
+            path: "/connect/jwks",
+            description: "public keys to verify access tokens of the authorization registry"
                 .to_owned(),
-            token_endpoint: Some(format!("{}{}", api_url, "/connect/machine/token")),
-        }]));
+            restricted: false,
+            requires_token: false,
+        });
+    }
+
+    let mut delegation_description = format!(
+        "issue iSHARE delegation evidence based on your delegation request. Required scope: {}",
+        Scope::Delegation.as_str()
+    );
+    let dataspaces = app_state.dataspaces.ids();
+    if dataspaces.len() > 1 {
+        delegation_description += &format!(
+            ". Select the dataspace with the '{}' header: {}",
+            DATASPACE_HEADER,
+            dataspaces.join(", ")
+        );
+    }
+    let federated_issuers = app_state.federation.get_policy_issuers();
+    if !federated_issuers.is_empty() {
+        delegation_description += &format!(
+            ". Evidence of policy issuers {} includes the policies of their remote registry",
+            federated_issuers.join(", ")
+        );
+    }
+    features.push(CapabilityFeature {
+        id: "aaf5162b-82f2-4bf2-9eaa-e01b380e7ec3",
+        feature: "iSHARE delegation request",
+        method: Method::POST,
+        path: "/delegation",
+        description: delegation_description,
+        restricted: true,
+        requires_token: true,
+    });
+    features.push(CapabilityFeature {
+        id: "ba047a74-693a-4e27-8715-c298241e361a",
+        feature: "policy management",
+        method: Method::GET,
+        path: "/policy-set",
+        description: format!(
+            "manage the policy sets you issued. Required scopes: {} to read and {} to change them{}",
+            Scope::PolicyRead.as_str(),
+            Scope::PolicyWrite.as_str(),
+            access
+        ),
+        restricted: true,
+        requires_token: true,
+    });
+
+    return features;
+}
+
+pub fn create_capabilities(
+    party_id: &str,
+    api_url: &str,
+    features: &[CapabilityFeature],
+    show_private: bool,
+) -> Capabilities {
+    let to_supported_feature = |f: &CapabilityFeature| SupportedFeature {
+        id: f.id.to_owned(),
+        feature: f.feature.to_owned(),
+        url: format!("{}{}", api_url, f.path),
+        description: f.description.clone(),
+        token_endpoint: f
+            .requires_token
+            .then(|| format!("{}{}", api_url, TOKEN_PATH)),
+    };
+
+    let mut supported_features: Vec<SupportedFeatures> = vec![SupportedFeatures::Public(
+        features
+            .iter()
+            .filter(|f| !f.restricted)
+            .map(to_supported_feature)
+            .collect(),
+    )];
+
+    let restricted: Vec<SupportedFeature> = features
+        .iter()
+        .filter(|f| f.restricted)
+        .map(to_supported_feature)
+        .collect();
+    if show_private && !restricted.is_empty() {
+        supported_features.push(SupportedFeatures::Restricted(restricted));
     }
 
     return Capabilities {
@@ -149,7 +276,13 @@ async fn get_capabilities(
     };
 
     let api_url = [scheme, host, app_state.config.deploy_route.clone()].join("");
-    let capabilities = create_capabilities(&app_state.config.client_eori, &api_url, show_private);
+    let features = get_capability_features(&app_state);
+    let capabilities = create_capabilities(
+        &app_state.config.client_eori,
+        &api_url,
+        &features,
+        show_private,
+    );
 
     let capabilities_token = app_state
         .satellite_provider
@@ -162,15 +295,187 @@ async fn get_capabilities(
 
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
     use axum::{
         body::Body,
-        http::{Request, StatusCode},
+        http::{Method, Request, StatusCode},
     };
 
+    use ishare::ishare::{SupportedFeature, SupportedFeatures};
+    use sea_orm::DatabaseConnection;
     use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
     use tower::ServiceExt;
 
+    use crate::{
+        config::{FederationConfig, RateLimitConfig},
+        services::federation::Federation,
+        AppState,
+    };
+
     use super::super::super::test_helpers::helpers::*;
+    use super::{create_capabilities, get_capability_features, CapabilityFeature};
+
+    fn get_app_state(db: DatabaseConnection) -> AppState {
+        return get_test_app_state(db, RateLimitConfig::default(), None);
+    }
+
+    // the advertised features, with whether they are restricted
+    fn get_supported_features(
+        features: &[CapabilityFeature],
+        api_url: &str,
+        show_private: bool,
+    ) -> Vec<(bool, SupportedFeature)> {
+        let capabilities = create_capabilities("NL.AR", api_url, features, show_private);
+
+        return capabilities
+            .capabilities_info
+            .supported_versions
+            .into_iter()
+            .flat_map(|v| v.supported_features)
+            .flat_map(|f| match f {
+                SupportedFeatures::Public(f) => {
+                    f.into_iter().map(|f| (false, f)).collect::<Vec<_>>()
+                }
+                SupportedFeatures::Restricted(f) => f.into_iter().map(|f| (true, f)).collect(),
+            })
+            .collect();
+    }
+
+    #[sqlx::test]
+    async fn test_create_capabilities(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let features = get_capability_features(&get_app_state(db));
+
+        let public = get_supported_features(&features, "https://ar.example/api", false);
+        assert!(
+            public.iter().all(|(restricted, _)| !restricted),
+            "restricted features are only shown to authenticated callers"
+        );
+
+        let all = get_supported_features(&features, "https://ar.example/api", true);
+        let mut ids: Vec<&String> = all.iter().map(|(_, f)| &f.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), all.len(), "feature ids are unique");
+
+        let url = |feature: &str| {
+            return all
+                .iter()
+                .find(|(_, f)| f.feature == feature)
+                .map(|(restricted, f)| (*restricted, f.url.clone()));
+        };
+        assert_eq!(
+            url("human access token"),
+            Some((
+                false,
+                "https://ar.example/api/connect/human/auth".to_owned()
+            ))
+        );
+        assert_eq!(
+            url("iSHARE delegation request"),
+            Some((true, "https://ar.example/api/delegation".to_owned()))
+        );
+        // the test service signs its tokens with a shared secret
+        assert_eq!(url("jwks"), None);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_capabilities_follow_app_state(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let description = |features: &[CapabilityFeature], feature: &str| {
+            return features
+                .iter()
+                .find(|f| f.feature == feature)
+                .map(|f| f.description.clone());
+        };
+
+        let features = get_capability_features(&get_app_state(db.clone()));
+        let delegation = description(&features, "iSHARE delegation request").unwrap();
+        assert!(delegation.contains("'dataspace-id' header: default, other"));
+        assert!(delegation.contains("policy issuers NL.REMOTE_ISSUER"));
+        assert!(!description(&features, "policy management")
+            .unwrap()
+            .contains("policy decision point"));
+
+        let mut app_state = get_test_app_state(
+            db.clone(),
+            RateLimitConfig::default(),
+            Some(Arc::new(TestAuthorizationProvider::default())),
+        );
+        app_state.federation =
+            Arc::new(Federation::new(&FederationConfig::default(), vec![]).unwrap());
+        Arc::get_mut(&mut app_state.config)
+            .unwrap()
+            .allowed_redirect_uris = vec![];
+
+        let features = get_capability_features(&app_state);
+        assert!(!description(&features, "iSHARE delegation request")
+            .unwrap()
+            .contains("remote registry"));
+        assert!(description(&features, "policy management")
+            .unwrap()
+            .contains("policy decision point"));
+        assert!(description(&features, "igds-auditlog")
+            .unwrap()
+            .contains("policy decision point"));
+        // without redirect uris a human login can not complete
+        assert_eq!(description(&features, "human access token"), None);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_capabilities_urls_resolve(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let features = get_capability_features(&get_app_state(db.clone()));
+        let app = get_test_app(db);
+
+        // the test app is deployed at the root
+        let supported = get_supported_features(&features, "", true);
+        assert_eq!(supported.len(), features.len());
+        let token_endpoints = supported.iter().flat_map(|(_, f)| f.token_endpoint.iter());
+        let routes = features
+            .iter()
+            .map(|f| (f.method.clone(), f.path.to_owned()))
+            .chain(token_endpoints.map(|url| (Method::POST, url.clone())));
+
+        for (method, url) in routes {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method.clone())
+                        .uri(&url)
+                        .header("Host", "Example.com")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert!(
+                ![StatusCode::NOT_FOUND, StatusCode::METHOD_NOT_ALLOWED]
+                    .contains(&response.status()),
+                "advertised feature '{} {}' is not a route",
+                method,
+                url
+            );
+        }
+
+        Ok(())
+    }
 
     #[sqlx::test]
     async fn auth_header_not_bearer_plus_value(
@@ -1,29 +1,62 @@
-use crate::error::{AppError, ErrorResponse};
-use crate::services::ishare_provider::OAuthRequestForm;
+use crate::error::{AppError, ErrorResponse, ExpectedError};
+use crate::middleware::{extract_human_middleware, extract_role_middleware, rate_limit_middleware};
+use crate::services::account;
+use crate::services::audit_log::{
+    log_event, AuthenticationFailedEventMetadata, EventType, LoginType,
+};
+use crate::services::auth_request::{self, PendingAuthRequest};
+use crate::services::dataspace::Dataspaces;
+use crate::services::identity_provider::{IdpUser, OAuthRequestForm};
+use crate::services::rate_limit::{RateLimitGroup, RateLimiter};
+use crate::services::redirect_uri::validate_redirect_uri;
+use crate::services::refresh_token;
+use crate::services::server_token::{Scope, ServiceAccessTokenClaims};
 use crate::{services::server_token::ServerToken, AppState};
 use anyhow::Context;
 use axum::extract::Query;
+use axum::http::header::{COOKIE, SET_COOKIE};
+use axum::http::{HeaderName, StatusCode};
 use axum::response::Redirect;
 use axum::Json;
 use axum::{
     extract::Host,
     extract::State,
     http::HeaderMap,
+    middleware::{from_fn, from_fn_with_state},
     routing::{get, post},
-    Form, Router,
+    Extension, Form, Router,
 };
 use axum_extra::extract::WithRejection;
+use jsonwebtoken::{jwk::JwkSet, TokenData};
 use reqwest::Url;
+use sea_orm::{DatabaseConnection, TransactionTrait};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use utoipa::ToSchema;
 
-pub fn get_connect_routes() -> Router<AppState> {
+pub fn get_connect_routes(
+    server_token: Arc<ServerToken>,
+    rate_limiter: Arc<RateLimiter>,
+) -> Router<AppState> {
     let router = Router::new()
-        .route("/machine/token", post(get_machine_token))
+        .route(
+            "/machine/token",
+            post(get_machine_token).layer(from_fn_with_state(
+                (rate_limiter, RateLimitGroup::MachineToken),
+                rate_limit_middleware,
+            )),
+        )
         .route("/human/auth_params", get(get_auth_params))
         .route("/human/auth", get(get_auth).post(post_auth))
-        .route("/human/auth/code", get(get_auth_callback));
+        .route("/human/auth/code", get(get_auth_callback))
+        .route("/human/refresh", post(refresh_human_token))
+        .route("/jwks", get(get_jwks))
+        .route(
+            "/logout",
+            post(logout)
+                .layer(from_fn(extract_human_middleware))
+                .layer(from_fn_with_state(server_token, extract_role_middleware)),
+        );
 
     return router;
 }
@@ -47,12 +80,73 @@ fn get_server_base_url(
     return Ok(server_base_url);
 }
 
-#[derive(Deserialize, Serialize)]
+// failing to write the audit event should not hide the original authentication error
+async fn log_failed_login(
+    now: chrono::DateTime<chrono::Utc>,
+    login_type: LoginType,
+    client_id: Option<String>,
+    err: &AppError,
+    db: &DatabaseConnection,
+) {
+    let reason = match err {
+        AppError::Expected(e) => e.reason.clone(),
+        e => e.to_string(),
+    };
+
+    if let Err(e) = log_event(
+        now,
+        client_id.clone().unwrap_or_default(),
+        EventType::ArAuthenticationFailed(AuthenticationFailedEventMetadata {
+            login_type,
+            client_id,
+            reason,
+        }),
+        None,
+        None,
+        db,
+    )
+    .await
+    {
+        tracing::error!("error logging failed login: {:?}", e);
+    }
+}
+
+#[derive(Deserialize)]
 struct AuthQuery {
     redirect_uri: String,
     state: Option<String>,
 }
 
+const AUTH_STATE_COOKIE: &str = "ar_auth_state";
+
+// binds a login to the browser that started it, the callback is only accepted with this cookie
+fn auth_state_cookie(
+    server_base_url: &str,
+    deploy_route: &str,
+    value: &str,
+    max_age: i64,
+) -> String {
+    let secure = match server_base_url.starts_with("https://") {
+        true => "; Secure",
+        false => "",
+    };
+
+    return format!(
+        "{AUTH_STATE_COOKIE}={value}; Path={deploy_route}/connect/human; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}"
+    );
+}
+
+fn get_auth_state_cookie(headers: &HeaderMap) -> Option<String> {
+    return headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == AUTH_STATE_COOKIE)
+        .map(|(_, value)| value.to_owned());
+}
+
 /// Initiate iSHARE H2M flow by redirecting to the iSHARE identity provider
 #[utoipa::path(
     get,
@@ -67,12 +161,13 @@ struct AuthQuery {
             status = 302,
             description = "Redirects to iSHARE identity provider for authentication",
             headers(
-                ("Location" = String, description = "iSHARE identity provider URL")
+                ("Location" = String, description = "iSHARE identity provider URL"),
+                ("Set-Cookie" = String, description = "Cookie binding the login to this browser")
             )
         ),
         (
             status = 400,
-            description = "Invalid request parameters",
+            description = "Invalid request parameters or redirect_uri is not allowed",
             content_type = "application/json",
             body = ErrorResponse
         )
@@ -80,42 +175,66 @@ struct AuthQuery {
  )]
 async fn get_auth(
     State(app_state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
     Host(host): Host,
     headers: HeaderMap,
     Query(query): Query<AuthQuery>,
-) -> Result<Redirect, AppError> {
+) -> Result<([(HeaderName, String); 1], Redirect), AppError> {
+    validate_redirect_uri(&query.redirect_uri, &app_state.config.allowed_redirect_uris)?;
+
     let server_base_url = get_server_base_url(headers, host, &app_state.config.deploy_route)?;
-    let state = serde_json::to_string(&query).map_err(|e| {
-        return AppError::Unexpected(anyhow::anyhow!("error serialising auth query: {}", e));
-    })?;
+    let auth_request = auth_request::create_auth_request(
+        app_state.time_provider.now(),
+        query.redirect_uri,
+        query.state,
+        app_state.config.auth_request_expiry_seconds,
+        &db,
+    )
+    .await?;
 
     let redirect_url = app_state
-        .satellite_provider
-        .handle_h2m_redirect_url_request(&server_base_url, &state)
+        .identity_provider
+        .get_authorization_url(&server_base_url, &auth_request)
+        .await
         .map_err(|err| {
             tracing::error!("error handling h2m redirect request");
             err
         })?;
 
-    return Ok(Redirect::to(&redirect_url));
+    let cookie = auth_state_cookie(
+        &server_base_url,
+        &app_state.config.deploy_route,
+        &auth_request.state,
+        app_state.config.auth_request_expiry_seconds,
+    );
+
+    return Ok(([(SET_COOKIE, cookie)], Redirect::to(&redirect_url)));
 }
 
 async fn get_auth_params(
     State(app_state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
     Host(host): Host,
     headers: HeaderMap,
     Query(query): Query<AuthQuery>,
 ) -> Result<Json<OAuthRequestForm>, AppError> {
     tracing::info!("handle post auth");
 
+    validate_redirect_uri(&query.redirect_uri, &app_state.config.allowed_redirect_uris)?;
+
     let server_base_url = get_server_base_url(headers, host, &app_state.config.deploy_route)?;
-    let state = serde_json::to_string(&query).map_err(|e| {
-        return AppError::Unexpected(anyhow::anyhow!("error serialising auth query: {}", e));
-    })?;
+    let auth_request = auth_request::create_auth_request(
+        app_state.time_provider.now(),
+        query.redirect_uri,
+        query.state,
+        app_state.config.auth_request_expiry_seconds,
+        &db,
+    )
+    .await?;
 
     let oauth_form = app_state
-        .satellite_provider
-        .get_h2m_redirect_form(&server_base_url, &state)
+        .identity_provider
+        .get_authorization_form(&server_base_url, &auth_request)
         .await
         .map_err(|err| {
             tracing::error!("error handling h2m redirect request");
@@ -125,12 +244,40 @@ async fn get_auth_params(
     return Ok(axum::Json(oauth_form));
 }
 
-async fn post_auth(State(app_state): State<AppState>) -> Result<Redirect, AppError> {
+#[derive(Deserialize)]
+struct PostAuthForm {
+    state: String,
+}
+
+// the frontend posts the form of `get_auth_params`, which is forwarded to the identity provider.
+// The cookie is set here because fetching the form does not store cookies
+async fn post_auth(
+    State(app_state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
+    Host(host): Host,
+    headers: HeaderMap,
+    Form(form): Form<PostAuthForm>,
+) -> Result<([(HeaderName, String); 1], Redirect), AppError> {
     tracing::info!("handle post auth");
 
-    let redirect_url = app_state.satellite_provider.get_h2m_redirect_base_url();
+    // a cross-site form could otherwise plant the state of another login in the browser
+    auth_request::check_pending_auth_request(app_state.time_provider.now(), &form.state, &db)
+        .await?;
+
+    let server_base_url = get_server_base_url(headers, host, &app_state.config.deploy_route)?;
+    let redirect_url = app_state
+        .identity_provider
+        .get_authorization_endpoint()
+        .await?;
+
+    let cookie = auth_state_cookie(
+        &server_base_url,
+        &app_state.config.deploy_route,
+        &form.state,
+        app_state.config.auth_request_expiry_seconds,
+    );
 
-    return Ok(Redirect::temporary(&redirect_url));
+    return Ok(([(SET_COOKIE, cookie)], Redirect::temporary(&redirect_url)));
 }
 
 #[derive(Deserialize)]
@@ -139,21 +286,48 @@ struct AuthCallbackQuery {
     state: String,
 }
 
+async fn authenticate_callback(
+    app_state: &AppState,
+    server_base_url: &str,
+    headers: &HeaderMap,
+    query: &AuthCallbackQuery,
+    db: &DatabaseConnection,
+) -> Result<(PendingAuthRequest, IdpUser), AppError> {
+    if get_auth_state_cookie(headers).as_deref() != Some(query.state.as_str()) {
+        return Err(AppError::Expected(ExpectedError {
+            status_code: StatusCode::UNAUTHORIZED,
+            message: "Invalid authorization callback".to_owned(),
+            reason: "State does not belong to the browser that started the login".to_owned(),
+            metadata: None,
+        }));
+    }
+
+    let pending =
+        auth_request::consume_auth_request(app_state.time_provider.now(), &query.state, db).await?;
+
+    let idp_user = app_state
+        .identity_provider
+        .handle_callback(server_base_url, &query.code, &pending.authorization_request)
+        .await?;
+
+    return Ok((pending, idp_user));
+}
+
 /// OAuth2 callback endpoint to exchange auth code for token.
 #[utoipa::path(
     get,
     path = "/connect/human/auth/code",
     tag = "Authentication",
     params(
-        ("code" = String, Query, description = "Authorization code from iSHARE identity provider"),
+        ("code" = String, Query, description = "Authorization code from the identity provider"),
         ("state" = String, Query, description = "State parameter from initial auth request")
     ),
     responses(
         (
             status = 302,
-            description = "Redirects back to original redirect_uri with access token",
+            description = "Redirects back to original redirect_uri with access token and refresh token",
             headers(
-                ("Location" = String, description = "Original redirect_uri with token appended")
+                ("Location" = String, description = "Original redirect_uri with the token appended to the query and the refresh_token in the fragment")
             )
         ),
         (
@@ -164,7 +338,7 @@ struct AuthCallbackQuery {
         ),
         (
             status = 401,
-            description = "Authorization code validation failed",
+            description = "Unknown, expired or replayed state, state not bound to this browser, or authorization code validation failed",
             content_type = "application/json",
             body = ErrorResponse
         )
@@ -172,33 +346,53 @@ struct AuthCallbackQuery {
  )]
 async fn get_auth_callback(
     State(state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
     State(server_token): State<Arc<ServerToken>>,
     Host(host): Host,
     headers: HeaderMap,
     query: Query<AuthCallbackQuery>,
-) -> Result<Redirect, AppError> {
+) -> Result<([(HeaderName, String); 1], Redirect), AppError> {
     tracing::info!("Handle auth callback");
 
-    let server_base_url = get_server_base_url(headers, host, &state.config.deploy_route)?;
+    let server_base_url = get_server_base_url(headers.clone(), host, &state.config.deploy_route)?;
+
+    let (pending, idp_user) =
+        match authenticate_callback(&state, &server_base_url, &headers, &query, &db).await {
+            Ok(result) => result,
+            Err(err) => {
+                tracing::error!("error handling h2m auth callback");
+                log_failed_login(state.time_provider.now(), LoginType::H2M, None, &err, &db).await;
+                return Err(err);
+            }
+        };
 
-    let (company_id, user_option) = state
-        .satellite_provider
-        .handle_h2m_auth_callback(&server_base_url, &query.code)
+    let transaction = db.begin().await.context("Error starting db transaction")?;
+    let (company_id, user_option) =
+        account::ensure_idp_user(state.time_provider.now(), idp_user, &transaction).await?;
+    transaction
+        .commit()
         .await
-        .map_err(|err| {
-            tracing::error!("error handling h2m auth callback");
-            err
-        })?;
+        .context("Error commiting transaction to db")?;
+
+    let session = refresh_token::start_session(
+        state.time_provider.now(),
+        company_id.clone(),
+        &user_option,
+        state.config.refresh_token_expiry_seconds,
+        state.config.session_lifetime_seconds,
+        &db,
+    )
+    .await?;
 
     let action_token = server_token
-        .create_token(company_id, Some(user_option))
+        .create_session_token(company_id, Some(user_option), Some(session.session_id))
         .map_err(|err| {
             tracing::error!("error creating access token");
             err
         })?;
 
-    let auth_query = serde_json::from_str::<AuthQuery>(&query.state).unwrap();
-    let mut redirect_url = Url::parse(&auth_query.redirect_uri).unwrap();
+    let mut redirect_url =
+        Url::parse(&pending.redirect_uri).context("Error parsing redirect uri")?;
     let mut query: Vec<(std::borrow::Cow<str>, std::borrow::Cow<str>)> =
         redirect_url.query_pairs().collect();
 
@@ -207,7 +401,7 @@ async fn get_auth_callback(
         std::borrow::Cow::Borrowed(&action_token),
     ));
 
-    if let Some(state) = &auth_query.state {
+    if let Some(state) = &pending.client_state {
         query.push((
             std::borrow::Cow::Borrowed("state"),
             std::borrow::Cow::Borrowed(state),
@@ -217,8 +411,141 @@ async fn get_auth_callback(
     let query_strings: Vec<String> = query.iter().map(|c| format!("{}={}", c.0, c.1)).collect();
     let query_string = query_strings.join("&");
     redirect_url.set_query(Some(&query_string));
+    // the fragment is not sent to any server, so the refresh token does not end up in logs or
+    // Referer headers
+    redirect_url.set_fragment(Some(&format!(
+        "refresh_token={}",
+        urlencoding::encode(&session.refresh_token)
+    )));
+
+    // the login has been completed, the cookie is not needed anymore
+    let cookie = auth_state_cookie(&server_base_url, &state.config.deploy_route, "", 0);
 
-    return Ok(Redirect::to(redirect_url.as_str()));
+    return Ok(([(SET_COOKIE, cookie)], Redirect::to(redirect_url.as_str())));
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct RefreshTokenRequest {
+    refresh_token: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+struct RefreshTokenResponse {
+    access_token: String,
+    refresh_token: String,
+    token_type: String,
+    expires_in: u64,
+}
+
+/// Exchange a H2M refresh token for a new access token and refresh token
+#[utoipa::path(
+    post,
+    path = "/connect/human/refresh",
+    tag = "Authentication",
+    request_body(
+        content = RefreshTokenRequest,
+        description = "Refresh token obtained from the auth callback or a previous refresh. It can only be used once"
+    ),
+    responses(
+        (
+            status = 200,
+            description = "New access token and refresh token",
+            content_type = "application/json",
+            body = RefreshTokenResponse
+        ),
+        (
+            status = 401,
+            description = "Refresh token is unknown, expired, revoked or has already been used",
+            content_type = "application/json",
+            body = ErrorResponse
+        )
+    )
+ )]
+async fn refresh_human_token(
+    State(state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
+    WithRejection(Json(body), _): WithRejection<Json<RefreshTokenRequest>, AppError>,
+) -> Result<Json<RefreshTokenResponse>, AppError> {
+    let refreshed = refresh_token::refresh_session(
+        state.time_provider.now(),
+        &body.refresh_token,
+        state.config.refresh_token_expiry_seconds,
+        &db,
+    )
+    .await?;
+
+    let access_token = state.server_token.create_session_token(
+        refreshed.company_id,
+        Some(refreshed.user),
+        Some(refreshed.session.session_id),
+    )?;
+
+    Ok(Json(RefreshTokenResponse {
+        access_token,
+        refresh_token: refreshed.session.refresh_token,
+        token_type: "Bearer".to_owned(),
+        expires_in: state.server_token.jwt_expiry_seconds,
+    }))
+}
+
+/// Revoke the H2M access token used to call this endpoint and the refresh tokens of its session
+#[utoipa::path(
+    post,
+    path = "/connect/logout",
+    tag = "Authentication",
+    security(
+        ("bearer" = [])
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Access token and refresh tokens have been revoked"
+        ),
+        (
+            status = 401,
+            description = "Missing, invalid or machine access token",
+            content_type = "application/json",
+            body = ErrorResponse
+        )
+    )
+ )]
+async fn logout(
+    State(state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(token): Extension<TokenData<ServiceAccessTokenClaims>>,
+) -> Result<(), AppError> {
+    state
+        .server_token
+        .revoke_token(&token.claims)
+        .await
+        .context("Error revoking access token")?;
+
+    tracing::info!("revoked access token '{}'", &token.claims.jti);
+
+    if let Some(session_id) = &token.claims.sid {
+        refresh_token::end_session(state.time_provider.now(), session_id, &db).await?;
+        tracing::info!("revoked refresh tokens of session '{}'", session_id);
+    }
+
+    Ok(())
+}
+
+/// Public keys to verify access tokens issued by this authorization registry
+#[utoipa::path(
+    get,
+    path = "/connect/jwks",
+    tag = "Authentication",
+    responses(
+        (
+            status = 200,
+            description = "JSON Web Key Set with the public keys of the access tokens. Empty when tokens are signed with a shared secret (HS256)",
+            content_type = "application/json",
+            body = Object
+        )
+    )
+ )]
+async fn get_jwks(State(server_token): State<Arc<ServerToken>>) -> Json<JwkSet> {
+    return Json(server_token.get_jwks());
 }
 
 #[derive(Serialize, Debug, ToSchema)]
@@ -226,6 +553,8 @@ struct TokenResponse {
     access_token: String,
     token_type: String,
     expires_in: u64,
+    // the scopes that have been granted
+    scope: String,
 }
 
 #[derive(Deserialize, Debug, ToSchema)]
@@ -234,6 +563,8 @@ struct TokenRequest {
     client_assertion_type: String,
     client_id: String,
     client_assertion: String,
+    // space separated, has to contain iSHARE and may restrict the token to
+    // `delegation`, `policy:read`, `policy:write` and `audit:read`
     scope: String,
 }
 
@@ -270,27 +601,726 @@ struct TokenRequest {
  )]
 #[axum_macros::debug_handler]
 async fn get_machine_token(
+    headers: HeaderMap,
     State(state): State<AppState>,
+    Extension(db): Extension<DatabaseConnection>,
     body: WithRejection<Form<TokenRequest>, AppError>,
 ) -> Result<Json<TokenResponse>, AppError> {
-    let company_id = state
-        .satellite_provider
+    let now = state.time_provider.now();
+    let scopes = match Scope::parse_request_scope(&body.scope) {
+        Ok(scopes) => scopes,
+        Err(err) => {
+            log_failed_login(now, LoginType::M2M, Some(body.client_id.clone()), &err, &db).await;
+            return Err(err);
+        }
+    };
+
+    // the client is authenticated at the satellite of the dataspace it belongs to, and the token
+    // is only valid in that dataspace
+    let dataspace = state
+        .dataspaces
+        .resolve(Dataspaces::from_headers(&headers)?.as_deref())?;
+    let satellite_provider = state.dataspaces.satellite_provider(dataspace.as_deref())?;
+
+    let company_id = match satellite_provider
         .handle_m2m_authentication(
-            state.time_provider.now(),
+            now,
             &body.client_id,
             &body.grant_type,
             &body.client_assertion,
             &body.client_assertion_type,
-            &body.scope,
+            // the additional scopes are handled by the authorization registry itself
+            "iSHARE",
             state.config.validate_m2m_certificate,
         )
-        .await?;
+        .await
+    {
+        Ok(company_id) => company_id,
+        Err(err) => {
+            log_failed_login(now, LoginType::M2M, Some(body.client_id.clone()), &err, &db).await;
+            return Err(err);
+        }
+    };
 
-    let service_access_token = state.server_token.create_token(company_id, None)?;
+    let service_access_token = state
+        .server_token
+        .create_machine_token(company_id, &scopes, dataspace)?;
 
     Ok(Json(TokenResponse {
         access_token: service_access_token,
         expires_in: state.server_token.jwt_expiry_seconds,
         token_type: "Bearer".to_owned(),
+        scope: Scope::to_claim(&scopes),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use reqwest::header::{AUTHORIZATION, COOKIE, SET_COOKIE};
+    use serde_json::json;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+    use tower::ServiceExt;
+
+    use crate::config::{RateLimitBudget, RateLimitConfig, RateLimitGroupConfig};
+    use crate::services::server_token::server_token_test_helper::{
+        get_human_token_header, get_human_token_header_with_roles, get_machine_token_header,
+        get_test_service,
+    };
+    use crate::services::server_token::Role;
+    use crate::test_helpers::helpers::{
+        create_request_body, get_test_app, get_test_app_with_rate_limit, init_test_db,
+    };
+
+    async fn logout(app: &axum::Router, token_header: &str) -> StatusCode {
+        return app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/logout")
+                    .method("POST")
+                    .header(AUTHORIZATION, token_header)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status();
+    }
+
+    fn get_location(response: &axum::response::Response) -> reqwest::Url {
+        let location = response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap();
+
+        return reqwest::Url::parse(location).unwrap();
+    }
+
+    fn get_query(url: &reqwest::Url) -> std::collections::HashMap<String, String> {
+        return url.query_pairs().into_owned().collect();
+    }
+
+    // starts a login and returns the state and the cookie binding it to the browser
+    async fn start_login(app: &axum::Router) -> (String, String) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(
+                        "/connect/human/auth?redirect_uri=http://frontend/callback&state=/policies",
+                    )
+                    .method("GET")
+                    .header("Host", "localhost")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+        let state = get_query(&get_location(&response))
+            .get("state")
+            .unwrap()
+            .to_owned();
+
+        assert_eq!(cookie, format!("ar_auth_state={}", state));
+
+        return (state, cookie);
+    }
+
+    async fn auth_callback(
+        app: &axum::Router,
+        state: &str,
+        cookie: Option<&str>,
+    ) -> axum::response::Response {
+        let callback_url = reqwest::Url::parse_with_params(
+            "http://localhost/connect/human/auth/code",
+            &[("code", "code"), ("state", state)],
+        )
+        .unwrap();
+
+        let mut request = Request::builder()
+            .uri(callback_url.as_str())
+            .method("GET")
+            .header("Host", "localhost");
+
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+
+        return app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+
+    // logs in through the h2m callback and returns the access token and refresh token
+    async fn login(app: &axum::Router) -> (String, String) {
+        let (state, cookie) = start_login(app).await;
+        let response = auth_callback(app, &state, Some(&cookie)).await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let location = get_location(&response);
+        let query = get_query(&location);
+        assert_eq!(query.get("state").unwrap(), "/policies");
+        assert!(!query.contains_key("refresh_token"));
+
+        let fragment: std::collections::HashMap<String, String> =
+            serde_urlencoded::from_str(location.fragment().unwrap()).unwrap();
+
+        return (
+            query.get("token").unwrap().to_owned(),
+            fragment.get("refresh_token").unwrap().to_owned(),
+        );
+    }
+
+    async fn refresh(app: &axum::Router, refresh_token: &str) -> axum::response::Response {
+        return app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/human/refresh")
+                    .method("POST")
+                    .header("Content-Type", "application/json")
+                    .body(create_request_body(
+                        &json!({ "refresh_token": refresh_token }),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_auth_callback_replay(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let (state, cookie) = start_login(&app).await;
+
+        let response = auth_callback(&app, &state, Some(&cookie)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let set_cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(set_cookie.contains("Max-Age=0"));
+
+        let response = auth_callback(&app, &state, Some(&cookie)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_auth_callback_state_mismatch(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let (state, _) = start_login(&app).await;
+        let (_, other_cookie) = start_login(&app).await;
+
+        let response = auth_callback(&app, &state, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = auth_callback(&app, &state, Some(&other_cookie)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = auth_callback(&app, "unknown", Some("ar_auth_state=unknown")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // a rejected callback does not use up the login of the right browser
+        let (other_state, other_cookie) = start_login(&app).await;
+        let response = auth_callback(&app, &other_state, Some(&other_cookie)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[sqlx::test]
+    async fn test_auth_params(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/human/auth_params?redirect_uri=http://frontend/callback")
+                    .method("GET")
+                    .header("Host", "localhost")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        let state = body["state"].as_str().unwrap().to_owned();
+        assert_eq!(body["code_challenge_method"], "S256");
+        assert!(body["code_challenge"].as_str().is_some());
+
+        // posting the form to the authorization registry binds the login to the browser
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/human/auth")
+                    .method("POST")
+                    .header("Host", "localhost")
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!(
+                        "response_type=code&client_id=client_id&state={}",
+                        &state
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+        let cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+
+        let response = auth_callback(&app, &state, Some(&cookie)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[sqlx::test]
+    async fn test_post_auth_unknown_state(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/human/auth")
+                    .method("POST")
+                    .header("Host", "localhost")
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(Body::from(
+                        "response_type=code&client_id=client_id&state=state-of-the-attacker",
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(response.headers().get(SET_COOKIE).is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_redirect_uri_not_allowed(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        for uri in [
+            "/connect/human/auth?redirect_uri=https://evil.com/callback",
+            "/connect/human/auth_params?redirect_uri=https://evil.com/callback",
+            "/connect/human/auth?redirect_uri=http://frontend/callback.evil.com",
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .method("GET")
+                        .header("Host", "localhost")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+
+            let body: serde_json::Value =
+                serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                    .unwrap();
+            assert_eq!(body["error"], "Redirect URI is not allowed");
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_get_jwks(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        let response = get_test_app(db)
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/jwks")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+
+        // the test app uses a shared secret, which must never be published
+        assert_eq!(body, json!({ "keys": [] }));
+    }
+
+    #[sqlx::test]
+    async fn test_logout(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let token = get_human_token_header(None, None);
+
+        assert_eq!(logout(&app, &token).await, StatusCode::OK);
+        // the revoked token can not be used anymore, not even to log out again
+        assert_eq!(logout(&app, &token).await, StatusCode::UNAUTHORIZED);
+
+        // tokens that have not been revoked are not affected
+        let other_token = get_human_token_header(None, None);
+        assert_eq!(logout(&app, &other_token).await, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn test_logout_machine_token(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let token = get_machine_token_header(None);
+
+        assert_eq!(logout(&app, &token).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_token_rotation(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let (_, refresh_token) = login(&app).await;
+
+        let response = refresh(&app, &refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        let rotated_refresh_token = body["refresh_token"].as_str().unwrap().to_owned();
+        let access_token = format!("Bearer {}", body["access_token"].as_str().unwrap());
+
+        assert_ne!(rotated_refresh_token, refresh_token);
+        assert_eq!(body["token_type"], "Bearer");
+
+        // reusing a refresh token revokes the whole session
+        assert_eq!(
+            refresh(&app, &refresh_token).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            refresh(&app, &rotated_refresh_token).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // access tokens that have already been issued stay valid until they expire
+        assert_eq!(logout(&app, &access_token).await, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn test_logout_revokes_refresh_token(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let (access_token, refresh_token) = login(&app).await;
+
+        assert_eq!(
+            logout(&app, &format!("Bearer {}", access_token)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            refresh(&app, &refresh_token).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_token_revocation_revokes_refresh_token(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let (_, refresh_token) = login(&app).await;
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/token-revocation")
+                    .method("POST")
+                    .header(AUTHORIZATION, get_human_token_header(None, None))
+                    .header("Content-Type", "application/json")
+                    .body(create_request_body(&json!({"company": "A_company"})))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(
+            refresh(&app, &refresh_token).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_unknown_token(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        assert_eq!(
+            refresh(&app, "unknown").await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    async fn request_machine_token(app: &axum::Router, scope: &str) -> axum::response::Response {
+        return app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/machine/token")
+                    .method("POST")
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!(
+                        "grant_type=client_credentials&client_assertion_type=urn:ietf:params:oauth:client-assertion-type:jwt-bearer&client_id=NL.CLIENT&client_assertion=assertion&scope={}",
+                        scope
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_machine_token_scope(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let response = request_machine_token(&app, "iSHARE%20policy:read").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(body["scope"], "policy:read");
+
+        let token_header = format!("Bearer {}", body["access_token"].as_str().unwrap());
+        let policy_set_request = |method: &str| {
+            Request::builder()
+                .uri("/policy-set")
+                .method(method)
+                .header(AUTHORIZATION, &token_header)
+                .header("Content-Type", "application/json")
+                .body(create_request_body(&json!({})))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(policy_set_request("GET"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(policy_set_request("POST"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    async fn test_machine_token_invalid_scope(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let response = request_machine_token(&app, "iSHARE%20policy:delete").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = request_machine_token(&app, "audit:read").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // only requesting the iSHARE scope grants all scopes
+        let response = request_machine_token(&app, "iSHARE").await;
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(
+            body["scope"],
+            "delegation policy:read policy:write audit:read"
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_machine_token_dataspace(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/connect/machine/token")
+                    .method("POST")
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .header("dataspace-id", "other")
+                    .body(Body::from(
+                        "grant_type=client_credentials&client_assertion_type=urn:ietf:params:oauth:client-assertion-type:jwt-bearer&client_id=NL.CLIENT&client_assertion=assertion&scope=iSHARE",
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        let claims = get_test_service()
+            .decode_token(&body["access_token"].as_str().unwrap().to_owned())
+            .unwrap()
+            .claims;
+        match claims.role {
+            Role::Machine(machine) => assert_eq!(machine.dataspace.as_deref(), Some("other")),
+            role => panic!("unexpected role: {:?}", role),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_machine_token_rate_limit(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app_with_rate_limit(
+            db,
+            RateLimitConfig {
+                machine_token: RateLimitGroupConfig {
+                    per_party: Some(RateLimitBudget {
+                        requests: 2,
+                        window_seconds: 60,
+                    }),
+                    per_ip: None,
+                },
+                ..Default::default()
+            },
+        );
+
+        // requests that are not accepted do not use up the budget of the client_id
+        for _ in 0..3 {
+            let response = request_machine_token(&app, "audit:read").await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        for _ in 0..2 {
+            let response = request_machine_token(&app, "iSHARE").await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = request_machine_token(&app, "iSHARE").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "60");
+
+        // other routes are not limited by the budget of the token endpoint
+        let (_, refresh_token) = login(&app).await;
+        assert_eq!(refresh(&app, &refresh_token).await.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/rate-limit/status")
+                    .method("GET")
+                    .header(
+                        AUTHORIZATION,
+                        get_human_token_header_with_roles(&["dexspace_admin"]),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(body[0]["group"], "machine_token");
+        assert_eq!(body[0]["requests"], 6);
+        assert_eq!(body[0]["rejected"], 1);
+        assert_eq!(body[0]["limited"][0]["kind"], "party");
+        assert_eq!(body[0]["limited"][0]["key"], "NL.CLIENT");
+    }
+}
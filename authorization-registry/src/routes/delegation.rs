@@ -11,16 +11,34 @@ use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{AppError, ErrorResponse, ExpectedError};
-use crate::middleware::extract_role_middleware;
+use crate::middleware::{
+    extract_role_middleware, rate_limit_middleware, scope_middleware, RequiredScopes,
+};
 use crate::services::audit_log::log_event;
+use crate::services::dataspace::Dataspaces;
 use crate::services::delegation as delegation_service;
-use crate::services::server_token::{Role, ServerToken};
+use crate::services::ishare_provider::SatelliteProvider;
+use crate::services::party_compliance::{check_party_compliance, PartyFunction};
+use crate::services::rate_limit::{RateLimitGroup, RateLimiter};
+use crate::services::server_token::{Role, Scope, ServerToken};
 use crate::AppState;
-use ishare::delegation_request::DelegationRequestContainer;
+use ishare::delegation_evidence::DelegationEvidenceContainer;
+use ishare::delegation_request::{DelegationRequest, DelegationRequestContainer};
 
-pub fn get_delegation_routes(server_token: std::sync::Arc<ServerToken>) -> Router<AppState> {
+pub fn get_delegation_routes(
+    server_token: std::sync::Arc<ServerToken>,
+    rate_limiter: std::sync::Arc<RateLimiter>,
+) -> Router<AppState> {
     Router::new()
         .route("/", post(post_delegation))
+        .layer(from_fn_with_state(
+            RequiredScopes::all(Scope::Delegation),
+            scope_middleware,
+        ))
+        .layer(from_fn_with_state(
+            (rate_limiter, RateLimitGroup::Delegation),
+            rate_limit_middleware,
+        ))
         .layer(from_fn_with_state(server_token, extract_role_middleware))
 }
 
@@ -70,8 +88,15 @@ async fn post_delegation(
     app_state: State<AppState>,
     body: WithRejection<Json<DelegationRequestContainer>, AppError>,
 ) -> Result<Response, AppError> {
-    match app_state
-        .satellite_provider
+    // the parties are validated at, and the evidence is signed for, the satellite of the dataspace
+    let dataspace = app_state
+        .dataspaces
+        .resolve_for_role(Dataspaces::from_headers(&headers)?.as_deref(), &role)?;
+    let satellite_provider = app_state
+        .dataspaces
+        .satellite_provider(dataspace.as_deref())?;
+
+    match satellite_provider
         .validate_party(
             app_state.time_provider.now(),
             &body.delegation_request.policy_issuer,
@@ -92,8 +117,7 @@ async fn post_delegation(
         }
     }
 
-    match app_state
-        .satellite_provider
+    match satellite_provider
         .validate_party(
             app_state.time_provider.now(),
             &body.delegation_request.target.access_subject,
@@ -116,27 +140,29 @@ async fn post_delegation(
 
     let now = app_state.time_provider.now();
 
-    log_event(
-        now,
-        "".to_owned(),
-        crate::services::audit_log::EventType::DmiDelegationRequest(
-            body.delegation_request.clone(),
-        ),
-        None,
-        None,
-        &db,
-    )
-    .await?;
-
-    if !crate::services::delegation::check_delegation_access(
+    let delegation_access = crate::services::delegation::check_delegation_access(
         now,
         &role.get_company_id(),
         &body.delegation_request,
         &body.previous_steps,
         app_state.config.delegation_allows_service_providers,
-        app_state.satellite_provider.clone(),
-    ) {
-        return Err(AppError::Expected(ExpectedError {
+        satellite_provider.clone(),
+        &app_state.federation,
+    )
+    .await;
+
+    let evidence = if delegation_access.allowed {
+        get_delegation_evidence(
+            now,
+            &body.delegation_request,
+            dataspace,
+            satellite_provider.as_ref(),
+            &app_state,
+            &db,
+        )
+        .await
+    } else {
+        Err(AppError::Expected(ExpectedError {
             status_code: StatusCode::BAD_REQUEST,
             message: format!("not allowed to request delegation evidence"),
             reason: format!(
@@ -144,10 +170,96 @@ async fn post_delegation(
                 &role.get_company_id()
             ),
             metadata: None,
-        }));
+        }))
+    };
+
+    // requests that are refused are logged without an outcome
+    let request_data = delegation_service::DelegationRequestData {
+        access: &delegation_access,
+        outcome: evidence
+            .as_ref()
+            .ok()
+            .map(|c| delegation_service::get_outcome(&c.delegation_evidence)),
+    };
+    log_event(
+        now,
+        "".to_owned(),
+        crate::services::audit_log::EventType::DmiDelegationRequest(
+            body.delegation_request.clone(),
+        ),
+        None,
+        Some(
+            serde_json::to_value(&request_data)
+                .context("Error serializing delegation request data")?,
+        ),
+        &db,
+    )
+    .await?;
+
+    let delegation_evidence_container = evidence?;
+
+    let token = satellite_provider
+        .create_delegation_token(&role.get_company_id(), &delegation_evidence_container)
+        .context("Error creating delegation token")?;
+
+    let response = match headers.get(ACCEPT).map(|x| x.as_bytes()) {
+        Some(b"application/json") => Json(delegation_evidence_container).into_response(),
+        _ => Json(DelegationResponse {
+            delegation_token: token,
+        })
+        .into_response(),
+    };
+
+    return Ok(response);
+}
+
+// checks the parties and the request, and creates the evidence from the local policy sets and
+// those of the remote registry of the policy issuer
+async fn get_delegation_evidence(
+    now: chrono::DateTime<chrono::Utc>,
+    delegation_request: &DelegationRequest,
+    dataspace: Option<String>,
+    satellite_provider: &dyn SatelliteProvider,
+    app_state: &AppState,
+    db: &DatabaseConnection,
+) -> Result<DelegationEvidenceContainer, AppError> {
+    // the parties have to comply for the whole validity period of the evidence
+    let valid_until = now + chrono::TimeDelta::seconds(app_state.de_expiry_seconds);
+    let mut parties = vec![
+        (
+            delegation_request.policy_issuer.clone(),
+            PartyFunction::PolicyIssuer,
+        ),
+        (
+            delegation_request.target.access_subject.clone(),
+            PartyFunction::AccessSubject,
+        ),
+    ];
+    parties.extend(
+        delegation_request
+            .policy_sets
+            .iter()
+            .flat_map(|ps| ps.policies.iter())
+            .filter_map(|p| p.target.environment.as_ref())
+            .flat_map(|e| e.service_providers.iter())
+            .filter(|sp| *sp != "*")
+            .map(|sp| (sp.clone(), PartyFunction::ServiceProvider)),
+    );
+    parties.sort();
+    parties.dedup();
+    for (party, function) in parties.iter() {
+        check_party_compliance(
+            satellite_provider,
+            &app_state.config.party_checks,
+            party,
+            *function,
+            now,
+            valid_until,
+        )
+        .await?;
     }
 
-    for ps in &body.delegation_request.policy_sets {
+    for ps in delegation_request.policy_sets.iter() {
         for policy in &ps.policies {
             if policy.target.resource.resource_type == "*" {
                 return Err(AppError::Expected(ExpectedError {
@@ -178,28 +290,31 @@ async fn post_delegation(
         }
     }
 
-    let delegation_evidence_container = delegation_service::create_delegation_evidence(
-        &body.delegation_request,
+    let mut delegation_evidence_container = delegation_service::create_delegation_evidence(
+        delegation_request,
         app_state.time_provider.clone(),
         app_state.de_expiry_seconds,
-        &db,
+        dataspace,
+        db,
     )
     .await?;
 
-    let token = app_state
-        .satellite_provider
-        .create_delegation_token(&role.get_company_id(), &delegation_evidence_container)
-        .context("Error creating delegation token")?;
-
-    let response = match headers.get(ACCEPT).map(|x| x.as_bytes()) {
-        Some(b"application/json") => Json(delegation_evidence_container).into_response(),
-        _ => Json(DelegationResponse {
-            delegation_token: token,
-        })
-        .into_response(),
-    };
+    // the policy issuer may keep (some of) its policies in another registry
+    if !delegation_service::permits_all(&delegation_evidence_container.delegation_evidence) {
+        if let Some(remote_evidence) = app_state
+            .federation
+            .get_remote_evidence(now, delegation_request, satellite_provider)
+            .await
+        {
+            delegation_service::merge_remote_evidence(
+                &mut delegation_evidence_container,
+                delegation_request,
+                remote_evidence,
+            );
+        }
+    }
 
-    return Ok(response);
+    return Ok(delegation_evidence_container);
 }
 
 #[cfg(test)]
@@ -221,6 +336,7 @@ mod test {
     };
     use http_body_util::BodyExt;
     use reqwest::header::AUTHORIZATION;
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
     use serde_json;
     use serde_json::json;
     use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
@@ -373,6 +489,474 @@ mod test {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn test_delegation_evidence_previous_steps(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+
+        let app = get_test_app(db.clone());
+        let request = |previous_steps: Vec<String>| {
+            Request::builder()
+                .uri("/delegation")
+                .method("POST")
+                .header(
+                    AUTHORIZATION,
+                    server_token::server_token_test_helper::get_human_token_header(
+                        Some("NL.55555".to_owned()),
+                        None,
+                    ),
+                )
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json")
+                .body(create_request_body(&json!({
+                    "delegationRequest": {
+                        "policyIssuer": "NL.24244",
+                        "target": {
+                            "accessSubject": "NL.44444"
+                        },
+                        "policySets": [{
+                            "policies": [{
+                                "target": {
+                                    "resource": {
+                                        "type": "TestResource",
+                                        "identifiers": ["test4"],
+                                        "attributes": ["zingers"]
+                                    },
+                                    "actions": ["Read"]
+                                },
+                                "rules": [{ "effect": "Permit" }]
+                            }]
+                        }]
+                    },
+                    "previous_steps": previous_steps
+                })))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(vec![create_test_client_assertion(
+                "NL.44444", "NL.55555",
+            )]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(request(vec![
+                create_test_client_assertion("NL.44444", "NL.55555"),
+                create_test_client_assertion("NL.OTHER", "NL.55555"),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // the outcome of the previous steps is kept with the delegation request
+        let events = ar_entity::audit_event::Entity::find()
+            .filter(ar_entity::audit_event::Column::EventType.eq("dmi:ar:delegation:request"))
+            .all(&db)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        let data: Vec<serde_json::Value> = events.into_iter().filter_map(|e| e.data).collect();
+        assert!(data.contains(&json!({
+            "allowed": true,
+            "outcome": "Deny",
+            "previous_steps": [
+                { "step": 0, "type": "client_assertion", "issuer": "NL.44444", "valid": true }
+            ]
+        })));
+        let denied = data.iter().find(|d| d["allowed"] == false).unwrap();
+        assert!(denied.get("outcome").is_none());
+        assert_eq!(denied["previous_steps"][1]["issuer"], "NL.OTHER");
+        assert_eq!(denied["previous_steps"][1]["valid"], false);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_delegation_evidence_remote_registry(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+
+        let app = get_test_app(db);
+        let request = |policy_issuer: &str| {
+            Request::builder()
+                .uri("/delegation")
+                .method("POST")
+                .header(
+                    AUTHORIZATION,
+                    server_token::server_token_test_helper::get_human_token_header(
+                        Some("NL.44444".to_owned()),
+                        None,
+                    ),
+                )
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json")
+                .body(create_request_body(&json!({
+                    "delegationRequest": {
+                        "policyIssuer": policy_issuer,
+                        "target": {
+                            "accessSubject": "NL.44444"
+                        },
+                        "policySets": [{
+                            "policies": [{
+                                "target": {
+                                    "resource": {
+                                        "type": "TestResource",
+                                        "identifiers": ["test4"],
+                                        "attributes": ["zingers"]
+                                    },
+                                    "actions": ["Read"]
+                                },
+                                "rules": [{ "effect": "Permit" }]
+                            }]
+                        }]
+                    }
+                })))
+                .unwrap()
+        };
+        let effect = |body: DelegationEvidenceContainer| {
+            return body.delegation_evidence.policy_sets[0].policies[0].rules[0]
+                .effect
+                .clone();
+        };
+
+        // the policies of "NL.REMOTE_ISSUER" are kept in the test remote registry
+        let response = app
+            .clone()
+            .oneshot(request("NL.REMOTE_ISSUER"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: DelegationEvidenceContainer =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(body.delegation_evidence.policy_sets.len(), 1);
+        assert_eq!(effect(body), "Permit");
+
+        let response = app.oneshot(request("NL.24244")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: DelegationEvidenceContainer =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(effect(body), "Deny");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_delegation_evidence_adherence_ends_before_expiry(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+
+        let app = get_test_app(db);
+        let request_body = create_request_body(&json!({
+            "delegationRequest": {
+                "policyIssuer": "NL.24244",
+                "target": {
+                    "accessSubject": "NL.NO_ROLES"
+                },
+                "policySets": [
+                    {
+                        "policies": [
+                            {
+                                "target": {
+                                    "resource": {
+                                        "type": "TestResource",
+                                        "identifiers": ["test4"],
+                                        "attributes": ["zingers"]
+                                    },
+                                    "actions": ["Read"],
+                                    "environment": {
+                                        "serviceProviders": ["good-company"]
+                                    }
+                                },
+                                "rules": [
+                                    {
+                                        "effect": "Permit"
+                                    }
+                                ]
+                            }
+                        ]
+                    }
+                ]
+            }
+        }));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/delegation")
+                    .method("POST")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.NO_ROLES".to_owned()),
+                            None,
+                        ),
+                    )
+                    .header("Content-Type", "application/json")
+                    .header("Accept", "application/json")
+                    .body(Body::new(request_body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // the adherence of the access subject ends before the evidence expires
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("access subject 'NL.NO_ROLES' adherence"));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_delegation_evidence_service_provider_compliance(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+
+        let app = get_test_app(db);
+        let request = |requester: &str, access_subject: &str| {
+            let request_body = create_request_body(&json!({
+                "delegationRequest": {
+                    "policyIssuer": "NL.24244",
+                    "target": {
+                        "accessSubject": access_subject
+                    },
+                    "policySets": [
+                        {
+                            "policies": [
+                                {
+                                    "target": {
+                                        "resource": {
+                                            "type": "TestResource",
+                                            "identifiers": ["test4"],
+                                            "attributes": ["zingers"]
+                                        },
+                                        "actions": ["Read"],
+                                        "environment": {
+                                            "serviceProviders": ["NL.NO_ROLES"]
+                                        }
+                                    },
+                                    "rules": [
+                                        {
+                                            "effect": "Permit"
+                                        }
+                                    ]
+                                }
+                            ]
+                        }
+                    ]
+                }
+            }));
+
+            return Request::builder()
+                .uri("/delegation")
+                .method("POST")
+                .header(
+                    AUTHORIZATION,
+                    server_token::server_token_test_helper::get_human_token_header(
+                        Some(requester.to_owned()),
+                        None,
+                    ),
+                )
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json")
+                .body(Body::new(request_body))
+                .unwrap();
+        };
+
+        // the service provider does not hold the ServiceProvider role, and its adherence ends
+        // before the evidence expires
+        let response = app
+            .clone()
+            .oneshot(request("NL.44444", "NL.44444"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("service provider 'NL.NO_ROLES' adherence"));
+
+        // the compliance of the parties is not revealed to requesters without access
+        let response = app
+            .oneshot(request("NL.OTHER", "NL.NO_ROLES"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(body["error"], "not allowed to request delegation evidence");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_delegation_evidence_dataspace(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        // the policy set belongs to the default dataspace
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+
+        let app = get_test_app(db);
+        let request = |token: String, dataspace: Option<&str>| {
+            let request_body = create_request_body(&json!({
+                "delegationRequest": {
+                    "policyIssuer": "NL.24244",
+                    "target": {
+                        "accessSubject": "NL.44444"
+                    },
+                    "policySets": [
+                        {
+                            "policies": [
+                                {
+                                    "target": {
+                                        "resource": {
+                                            "type": "TestResource",
+                                            "identifiers": ["test4"],
+                                            "attributes": ["zingers"]
+                                        },
+                                        "actions": ["Read"],
+                                        "environment": {
+                                            "serviceProviders": ["good-company"]
+                                        }
+                                    },
+                                    "rules": [
+                                        {
+                                            "effect": "Permit"
+                                        }
+                                    ]
+                                }
+                            ]
+                        }
+                    ]
+                }
+            }));
+
+            let mut request = Request::builder()
+                .uri("/delegation")
+                .method("POST")
+                .header(AUTHORIZATION, token)
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json");
+            if let Some(dataspace) = dataspace {
+                request = request.header("dataspace-id", dataspace);
+            }
+
+            return request.body(Body::new(request_body)).unwrap();
+        };
+        let human_token = || {
+            server_token::server_token_test_helper::get_human_token_header(
+                Some("NL.44444".to_owned()),
+                None,
+            )
+        };
+        let machine_token = |dataspace: &str| {
+            server_token::server_token_test_helper::get_dataspace_machine_token_header(
+                "NL.44444".to_owned(),
+                dataspace,
+            )
+        };
+
+        let effect = |body: DelegationEvidenceContainer| {
+            return body.delegation_evidence.policy_sets[0].policies[0].rules[0]
+                .effect
+                .clone();
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(human_token(), Some("default")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: DelegationEvidenceContainer =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(effect(body), "Permit");
+
+        // policy sets of another dataspace do not apply
+        let response = app
+            .clone()
+            .oneshot(request(human_token(), Some("other")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: DelegationEvidenceContainer =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(effect(body), "Deny");
+
+        let response = app
+            .clone()
+            .oneshot(request(human_token(), Some("unknown")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // a machine token applies to the dataspace it was issued for
+        let response = app
+            .clone()
+            .oneshot(request(machine_token("other"), None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: DelegationEvidenceContainer =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(effect(body), "Deny");
+
+        let response = app
+            .clone()
+            .oneshot(request(machine_token("other"), Some("other")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(request(machine_token("other"), Some("default")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let default_machine_token =
+            server_token::server_token_test_helper::get_machine_token_header(Some(
+                "NL.44444".to_owned(),
+            ));
+        let response = app
+            .oneshot(request(default_machine_token, Some("other")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn test_delegation_evidence_retrieve_jwt(
         _pool_options: PgPoolOptions,
@@ -785,7 +1369,9 @@ mod test {
                     rules: vec![ResourceRule::Permit],
                 }],
                 max_delegation_depth: 1,
+                dataspace: None,
             },
+            None,
             &db,
         )
         .await
@@ -1,6 +1,7 @@
 use anyhow::Context;
 use ar_entity::delegation_evidence::Policy;
 use axum::extract::{Path, Query};
+use axum::http::HeaderMap;
 use axum::routing::delete;
 use axum::{
     extract::State, middleware::from_fn_with_state, routing::post, Extension, Json, Router,
@@ -15,10 +16,14 @@ use uuid::Uuid;
 
 use crate::db::policy::{MatchingPolicySetRow, PolicySetsWithPagination};
 use crate::error::{ErrorResponse, ExpectedError};
+use crate::services::dataspace::{Dataspaces, DATASPACE_HEADER};
 use crate::services::policy::{self as policy_service, InsertPolicySetWithPolicies};
 use crate::{db::policy as policy_store, services::server_token::Role};
 use crate::{error::AppError, AppState};
-use crate::{middleware::extract_role_middleware, services::server_token::ServerToken};
+use crate::{
+    middleware::{extract_role_middleware, scope_middleware, RequiredScopes},
+    services::server_token::{Scope, ServerToken},
+};
 
 pub fn get_policy_set_routes(server_token: Arc<ServerToken>) -> Router<AppState> {
     return Router::new()
@@ -29,6 +34,10 @@ pub fn get_policy_set_routes(server_token: Arc<ServerToken>) -> Router<AppState>
             "/:id/policy/:policy_id",
             delete(delete_policy_from_policy_set).put(replace_policy_in_policy_set),
         )
+        .layer(from_fn_with_state(
+            RequiredScopes::new(Scope::PolicyRead, Scope::PolicyWrite),
+            scope_middleware,
+        ))
         .layer(from_fn_with_state(server_token, extract_role_middleware));
 }
 
@@ -136,6 +145,7 @@ async fn delete_policy_from_policy_set(
         &policy_id,
         &app_state.config.client_eori,
         app_state.time_provider,
+        app_state.authorization_provider.clone(),
         &db,
     )
     .await?;
@@ -192,6 +202,7 @@ async fn get_policy_set(
         &id,
         &app_state.config.client_eori,
         app_state.time_provider,
+        app_state.authorization_provider.clone(),
         &db,
     )
     .await?;
@@ -273,7 +284,9 @@ async fn replace_policy_in_policy_set(
         body,
         &app_state.config.client_eori,
         app_state.time_provider,
-        app_state.satellite_provider,
+        app_state.authorization_provider.clone(),
+        &app_state.dataspaces,
+        &app_state.config.party_checks,
         &db,
     )
     .await?;
@@ -345,7 +358,9 @@ async fn add_policy_to_policy_set(
         body,
         &app_state.config.client_eori,
         app_state.time_provider,
-        app_state.satellite_provider,
+        app_state.authorization_provider.clone(),
+        &app_state.dataspaces,
+        &app_state.config.party_checks,
         &db,
     )
     .await?;
@@ -401,6 +416,7 @@ async fn delete_policy_set(
         &id,
         &app_state.config.client_eori,
         app_state.time_provider,
+        app_state.authorization_provider.clone(),
         &db,
     )
     .await?;
@@ -454,11 +470,37 @@ pub struct InsertPolicySetResponse {
     )
  )]
 async fn insert_policy_set(
+    headers: HeaderMap,
     Extension(db): Extension<DatabaseConnection>,
     Extension(role): Extension<Role>,
     State(app_state): State<AppState>,
     WithRejection(Json(body), _): WithRejection<Json<InsertPolicySetWithPolicies>, AppError>,
 ) -> Result<Json<InsertPolicySetResponse>, AppError> {
+    // the dataspace of the body has to agree with the dataspace-id header
+    let requested_dataspace = match (Dataspaces::from_headers(&headers)?, &body.dataspace) {
+        (Some(header), Some(dataspace))
+            if app_state.dataspaces.resolve(Some(&header))?
+                != app_state.dataspaces.resolve(Some(dataspace))? =>
+        {
+            return Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!(
+                    "Dataspace '{}' does not match the '{}' header",
+                    dataspace, DATASPACE_HEADER
+                ),
+                reason: format!(
+                    "dataspace '{}' of the policy set differs from dataspace '{}' of the header",
+                    dataspace, header
+                ),
+                metadata: None,
+            }));
+        }
+        (header, dataspace) => header.or(dataspace.clone()),
+    };
+    let dataspace = app_state
+        .dataspaces
+        .resolve_for_role(requested_dataspace.as_deref(), &role)?;
+
     let policy_set_id = policy_service::insert_policy_set_with_policies(
         app_state.time_provider.now(),
         &role.get_company_id(),
@@ -466,7 +508,10 @@ async fn insert_policy_set(
         &db,
         &app_state.config.client_eori,
         app_state.time_provider.clone(),
-        app_state.satellite_provider.clone(),
+        app_state.authorization_provider.clone(),
+        &app_state.dataspaces,
+        dataspace,
+        &app_state.config.party_checks,
     )
     .await?;
 
@@ -484,7 +529,9 @@ mod test {
         body::Body,
         http::{Request, StatusCode},
     };
+    use http_body_util::BodyExt;
     use reqwest::header::AUTHORIZATION;
+    use sea_orm::EntityTrait;
     use serde_json::json;
     use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
     use tower::ServiceExt;
@@ -610,6 +657,103 @@ mod test {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn test_insert_policy_set_machine_token_dataspace(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let app = get_test_app(db.clone());
+
+        let insert = |header: Option<&str>, dataspace: Option<&str>| {
+            let mut body = json!({
+                "policies": [{
+                    "target": {
+                        "resource": {
+                            "type": "test-iden2",
+                            "identifiers": ["test"],
+                            "attributes": ["*"]
+                        },
+                        "actions": ["Read"],
+                        "environment": {
+                            "serviceProviders": ["asdf"]
+                        }
+                    },
+                    "rules": [{ "effect": "Permit" }]
+                }],
+                "target": {
+                    "accessSubject": "sadfasdf"
+                },
+                "policyIssuer": "nice-company",
+                "licences": [],
+                "maxDelegationDepth": 2
+            });
+            if let Some(dataspace) = dataspace {
+                body["dataspace"] = json!(dataspace);
+            }
+
+            let mut request = Request::builder()
+                .uri("/policy-set")
+                .method("POST")
+                .header(
+                    AUTHORIZATION,
+                    server_token::server_token_test_helper::get_dataspace_machine_token_header(
+                        "nice-company".to_owned(),
+                        "other",
+                    ),
+                )
+                .header("Content-Type", "application/json");
+            if let Some(header) = header {
+                request = request.header("dataspace-id", header);
+            }
+
+            return request.body(create_request_body(&body)).unwrap();
+        };
+
+        // without a dataspace the policy set is created in the dataspace of the token
+        let response = app.clone().oneshot(insert(None, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        let id = uuid::Uuid::parse_str(body["uuid"].as_str().unwrap()).unwrap();
+        let policy_set = ar_entity::policy_set::Entity::find_by_id(id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(policy_set.dataspace, Some("other".to_owned()));
+
+        let response = app
+            .clone()
+            .oneshot(insert(Some("other"), Some("other")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(insert(Some("default"), None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app
+            .clone()
+            .oneshot(insert(None, Some("default")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app
+            .oneshot(insert(Some("other"), Some("default")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn test_insert_policy_set_different_policy_issuer_without_de(
         _pool_options: PgPoolOptions,
@@ -887,6 +1031,51 @@ mod test {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn test_delete_policy_set_user_not_issuer_with_pdp(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+        // delegation evidence in the registry itself is not used when there is a pdp
+        insert_policy_set_fixture("./fixtures/policy_set4.json", &db).await;
+
+        let app = get_test_app_with_authorization_provider(
+            db,
+            TestAuthorizationProvider {
+                grants: vec![(
+                    "asdfasdf".to_owned(),
+                    "PDP.Policy".to_owned(),
+                    "Delete".to_owned(),
+                )],
+            },
+        );
+
+        let delete = |company_id: &str| {
+            return Request::builder()
+                .uri("/policy-set/84b7fba4-05f3-4af8-9d84-dde384abe881")
+                .method("DELETE")
+                .header(
+                    AUTHORIZATION,
+                    server_token::server_token_test_helper::get_human_token_header(
+                        Some(company_id.to_owned()),
+                        None,
+                    ),
+                )
+                .body(Body::empty())
+                .unwrap();
+        };
+
+        let response = app.clone().oneshot(delete("NL.44444")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.oneshot(delete("asdfasdf")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn test_add_policy_to_policy_set(
         _pool_options: PgPoolOptions,
@@ -940,6 +1129,66 @@ mod test {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn test_add_policy_to_policy_set_service_provider_without_role(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+
+        let app = get_test_app(db);
+
+        let request_body = create_request_body(&json!({
+            "target": {
+                "resource": {
+                    "type": "test-iden2",
+                    "identifiers": ["test", "test-2"],
+                    "attributes": ["*"]
+                },
+                "actions": ["Read"],
+                "environment": {
+                    "serviceProviders": ["NL.NO_ROLES"]
+                }
+            },
+            "rules": [
+                {
+                    "effect": "Permit"
+                }
+            ]
+        }));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/policy-set/84b7fba4-05f3-4af8-9d84-dde384abe881/policy")
+                    .method("POST")
+                    .header(
+                        AUTHORIZATION,
+                        server_token::server_token_test_helper::get_human_token_header(
+                            Some("NL.24244".to_owned()),
+                            None,
+                        ),
+                    )
+                    .header("Content-Type", "application/json")
+                    .body(Body::new(request_body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert!(body["error"].as_str().unwrap().starts_with(
+            "service provider 'NL.NO_ROLES' does not hold any of the roles [ServiceProvider]"
+        ));
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn test_replace_policy_in_policy_set(
         _pool_options: PgPoolOptions,
@@ -6,8 +6,8 @@ use uuid::Uuid;
 
 use crate::{
     error::{AppError, ErrorResponse, ExpectedError},
-    middleware::extract_role_middleware,
-    services::server_token::ServerToken,
+    middleware::{extract_role_middleware, scope_middleware, RequiredScopes},
+    services::server_token::{Scope, ServerToken},
     AppState,
 };
 
@@ -17,6 +17,10 @@ pub fn get_policy_set_template_routes(
     return Router::new()
         .route("/", get(get_policy_set_templates))
         .route("/:id", get(get_policy_set_template))
+        .layer(from_fn_with_state(
+            RequiredScopes::all(Scope::PolicyRead),
+            scope_middleware,
+        ))
         .layer(from_fn_with_state(server_token, extract_role_middleware));
 }
 
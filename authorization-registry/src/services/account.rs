@@ -0,0 +1,204 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sea_orm::ConnectionTrait;
+
+use crate::{
+    db::{company as company_store, user as user_store},
+    services::{
+        audit_log::{
+            log_event, CompanyCreatedEventMetadata, EventType, LoginType, UserCreatedEventMetadata,
+        },
+        identity_provider::IdpUser,
+        server_token::UserOption,
+    },
+};
+
+pub struct NewUser {
+    pub idp_sub: String,
+    pub email: String,
+    pub fullname: String,
+    pub company_id: String,
+    pub idp_eori: String,
+    pub idp_url: String,
+}
+
+// inserts the company if it doesn't exist yet and logs an audit event when it was created
+pub async fn ensure_company<T: ConnectionTrait>(
+    now: DateTime<Utc>,
+    eori: &str,
+    name: &str,
+    login_type: LoginType,
+    db: &T,
+) -> anyhow::Result<String> {
+    let (company_id, inserted) = company_store::insert_if_not_exists(eori, name, db)
+        .await
+        .context("Error inserting company into db")?;
+
+    if inserted {
+        log_event(
+            now,
+            company_id.clone(),
+            EventType::ArCompanyCreated(CompanyCreatedEventMetadata {
+                company_id: company_id.clone(),
+                company_name: name.to_owned(),
+                login_type,
+            }),
+            None,
+            None,
+            db,
+        )
+        .await
+        .context("Error logging company created event")?;
+    }
+
+    return Ok(company_id);
+}
+
+// inserts the user if it doesn't exist yet and logs an audit event when it was created
+pub async fn ensure_user<T: ConnectionTrait>(
+    now: DateTime<Utc>,
+    user: NewUser,
+    db: &T,
+) -> anyhow::Result<String> {
+    let company_id = user.company_id.clone();
+    let idp_eori = user.idp_eori.clone();
+
+    let (user_id, inserted) = user_store::insert_if_not_exists(
+        user.idp_sub,
+        user.email,
+        user.fullname,
+        user.company_id,
+        user.idp_eori,
+        user.idp_url,
+        db,
+    )
+    .await
+    .context("Error inserting user into db")?;
+
+    if inserted {
+        log_event(
+            now,
+            user_id.clone(),
+            EventType::ArUserCreated(UserCreatedEventMetadata {
+                user_id: user_id.clone(),
+                company_id,
+                idp_eori,
+            }),
+            None,
+            None,
+            db,
+        )
+        .await
+        .context("Error logging user created event")?;
+    }
+
+    return Ok(user_id);
+}
+
+// registers the company and user of a h2m login, returns the company id and the user for the access token
+pub async fn ensure_idp_user<T: ConnectionTrait>(
+    now: DateTime<Utc>,
+    idp_user: IdpUser,
+    db: &T,
+) -> anyhow::Result<(String, UserOption)> {
+    let company_id = ensure_company(
+        now,
+        &idp_user.company_id,
+        &idp_user.company_name,
+        LoginType::H2M,
+        db,
+    )
+    .await?;
+
+    let user_id = ensure_user(
+        now,
+        NewUser {
+            idp_sub: idp_user.sub,
+            email: idp_user.email,
+            fullname: idp_user.fullname,
+            company_id: company_id.clone(),
+            idp_eori: idp_user.idp_id,
+            idp_url: idp_user.idp_url,
+        },
+        db,
+    )
+    .await?;
+
+    return Ok((
+        company_id,
+        UserOption {
+            user_id,
+            realm_access_roles: idp_user.roles,
+        },
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::EntityTrait;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use super::*;
+    use crate::test_helpers::helpers::init_test_db;
+
+    #[sqlx::test]
+    async fn test_ensure_company_and_user_logs_once(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let now = chrono::DateTime::parse_from_rfc3339("2025-08-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        for _ in 0..2 {
+            let company_id = ensure_company(now, "NL.NEW", "New Company", LoginType::H2M, &db)
+                .await
+                .unwrap();
+            assert_eq!(company_id, "NL.NEW");
+
+            let user_id = ensure_user(
+                now,
+                NewUser {
+                    idp_sub: "new-user".to_owned(),
+                    email: "new-user@example.com".to_owned(),
+                    fullname: "New User".to_owned(),
+                    company_id,
+                    idp_eori: "NL.IDP".to_owned(),
+                    idp_url: "https://idp.example.com".to_owned(),
+                },
+                &db,
+            )
+            .await
+            .unwrap();
+            assert_eq!(user_id, "new-user");
+        }
+
+        let events = ar_entity::audit_event::Entity::find()
+            .all(&db)
+            .await
+            .unwrap();
+
+        let company_events: Vec<_> = events
+            .iter()
+            .filter(|e| e.event_type == "dmi:ar:company:created")
+            .collect();
+        assert_eq!(company_events.len(), 1);
+        assert_eq!(company_events[0].entry_id, "NL.NEW");
+        assert_eq!(
+            company_events[0].context.as_ref().unwrap()["login_type"],
+            "h2m"
+        );
+
+        let user_events: Vec<_> = events
+            .iter()
+            .filter(|e| e.event_type == "dmi:ar:user:created")
+            .collect();
+        assert_eq!(user_events.len(), 1);
+        assert_eq!(user_events[0].entry_id, "new-user");
+        assert_eq!(
+            user_events[0].context.as_ref().unwrap()["company_id"],
+            "NL.NEW"
+        );
+    }
+}
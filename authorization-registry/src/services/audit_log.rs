@@ -2,7 +2,9 @@ use std::{collections::HashMap, fmt, sync::Arc};
 
 use anyhow::Context;
 use ar_entity::audit_event::{ActiveModel as AuditEventModel, Entity as AuditEventEntity};
+use axum::body::Bytes;
 use chrono::{DateTime, Utc};
+use futures::Stream;
 use ishare::{
     delegation_evidence::verify_delegation_evidence,
     delegation_request::{
@@ -12,8 +14,9 @@ use ishare::{
 };
 use reqwest::StatusCode;
 use sea_orm::{
-    ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait,
-    QueryFilter, QuerySelect,
+    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    DbBackend, EntityTrait, FromQueryResult, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    Select, Statement,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -22,6 +25,7 @@ use uuid::Uuid;
 use crate::{
     error::{AppError, ExpectedError},
     services::delegation::create_delegation_evidence,
+    services::pdp_provider::AuthorizationProvider,
     AppConfig, TimeProvider,
 };
 
@@ -66,11 +70,70 @@ pub struct PolicySetDeletedEventMetadata {
     pub policy_set_id: Uuid,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct PolicySetSuspendedEventMetadata {
+    pub policy_set_id: Uuid,
+    pub party: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PolicySetSuspensionLiftedEventMetadata {
+    pub policy_set_id: Uuid,
+    pub party: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PolicySetTemplateCreatedEventMetadata {
+    pub policy_set_template_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PolicySetTemplateDeletedEventMetadata {
+    pub policy_set_template_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LoginType {
+    M2M,
+    H2M,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompanyCreatedEventMetadata {
+    pub company_id: String,
+    pub company_name: String,
+    pub login_type: LoginType,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserCreatedEventMetadata {
+    pub user_id: String,
+    pub company_id: String,
+    pub idp_eori: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuthenticationFailedEventMetadata {
+    pub login_type: LoginType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    pub reason: String,
+}
+
 pub enum EventType {
     DmiDelegationRequest(DelegationRequest),
     ArPolicySetCreated(PolicySetCreatedEventMetadata),
     ArPolicySetEdited(PolicySetEditedEventMetadata),
     ArPolicySetDeleted(PolicySetDeletedEventMetadata),
+    ArPolicySetSuspended(PolicySetSuspendedEventMetadata),
+    ArPolicySetSuspensionLifted(PolicySetSuspensionLiftedEventMetadata),
+    ArPolicySetTemplateCreated(PolicySetTemplateCreatedEventMetadata),
+    ArPolicySetTemplateDeleted(PolicySetTemplateDeletedEventMetadata),
+    ArCompanyCreated(CompanyCreatedEventMetadata),
+    ArUserCreated(UserCreatedEventMetadata),
+    ArAuthenticationFailed(AuthenticationFailedEventMetadata),
 }
 
 impl EventType {
@@ -89,6 +152,27 @@ impl EventType {
             Self::ArPolicySetDeleted(meta_data) => Ok(Some(
                 serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
             )),
+            Self::ArPolicySetSuspended(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
+            Self::ArPolicySetSuspensionLifted(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
+            Self::ArPolicySetTemplateCreated(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
+            Self::ArPolicySetTemplateDeleted(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
+            Self::ArCompanyCreated(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
+            Self::ArUserCreated(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
+            Self::ArAuthenticationFailed(meta_data) => Ok(Some(
+                serde_json::to_value(meta_data).context("Error parsing serde_json value")?,
+            )),
         }
     }
 }
@@ -100,6 +184,13 @@ impl fmt::Display for EventType {
             EventType::ArPolicySetCreated(_) => "dmi:ar:policy_set:created",
             EventType::ArPolicySetEdited(_) => "dmi:ar:policy_set:edited",
             EventType::ArPolicySetDeleted(_) => "dmi:ar:policy_set:deleted",
+            EventType::ArPolicySetSuspended(_) => "dmi:ar:policy_set:suspended",
+            EventType::ArPolicySetSuspensionLifted(_) => "dmi:ar:policy_set:suspension_lifted",
+            EventType::ArPolicySetTemplateCreated(_) => "dmi:ar:policy_set_template:created",
+            EventType::ArPolicySetTemplateDeleted(_) => "dmi:ar:policy_set_template:deleted",
+            EventType::ArCompanyCreated(_) => "dmi:ar:company:created",
+            EventType::ArUserCreated(_) => "dmi:ar:user:created",
+            EventType::ArAuthenticationFailed(_) => "dmi:ar:authentication:failed",
         };
         write!(f, "{}", s)
     }
@@ -197,57 +288,72 @@ fn add_iss_and_sub_and_id_to_context(
     };
 }
 
-pub async fn retrieve_events(
+pub async fn check_audit_log_access(
     controller_eori: &str,
-    from: Option<chrono::DateTime<Utc>>,
-    to: Option<chrono::DateTime<Utc>>,
-    max_results: u64,
-    event_types: Option<String>,
     time_provider: Arc<dyn TimeProvider>,
+    authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
     app_config: &AppConfig,
     db: &DatabaseConnection,
-) -> Result<Vec<AuditEventWithIssAndSub>, AppError> {
+) -> Result<(), AppError> {
     tracing::info!(
         "checking if delegation evidence exists that '{}' can access the audit log",
         controller_eori
     );
 
-    let delegation_evidence_container = create_delegation_evidence(
-        &DelegationRequest {
-            policy_issuer: app_config.client_eori.to_owned(),
-            target: DelegationTarget {
-                access_subject: controller_eori.to_string(),
-            },
-            policy_sets: vec![PolicySet {
-                policies: vec![Policy {
-                    target: ResourceTarget {
-                        resource: Resource {
-                            resource_type: "AuditLog".to_owned(),
-                            identifiers: vec!["*".to_owned()],
-                            attributes: vec!["*".to_owned()],
-                        },
-                        actions: vec!["Read".to_owned()],
-                        environment: Some(Environment {
-                            service_providers: vec![app_config.client_eori.to_string()],
-                        }),
+    // the pdp is asked the same question as the delegation evidence below
+    let access = match authorization_provider {
+        Some(authorization_provider) => {
+            authorization_provider
+                .authorize(
+                    controller_eori,
+                    &app_config.client_eori,
+                    "Read",
+                    "AuditLog",
+                    Some(vec!["*".to_owned()]),
+                    Some(vec!["*".to_owned()]),
+                )
+                .await?
+        }
+        None => {
+            let delegation_evidence_container = create_delegation_evidence(
+                &DelegationRequest {
+                    policy_issuer: app_config.client_eori.to_owned(),
+                    target: DelegationTarget {
+                        access_subject: controller_eori.to_string(),
                     },
-                    rules: vec![ResourceRules {
-                        effect: "Permit".to_owned(),
+                    policy_sets: vec![PolicySet {
+                        policies: vec![Policy {
+                            target: ResourceTarget {
+                                resource: Resource {
+                                    resource_type: "AuditLog".to_owned(),
+                                    identifiers: vec!["*".to_owned()],
+                                    attributes: vec!["*".to_owned()],
+                                },
+                                actions: vec!["Read".to_owned()],
+                                environment: Some(Environment {
+                                    service_providers: vec![app_config.client_eori.to_string()],
+                                }),
+                            },
+                            rules: vec![ResourceRules {
+                                effect: "Permit".to_owned(),
+                            }],
+                        }],
                     }],
-                }],
-            }],
-        },
-        time_provider,
-        30,
-        db,
-    )
-    .await
-    .context("Error creating delegation evidence")?;
+                },
+                time_provider,
+                30,
+                None,
+                db,
+            )
+            .await
+            .context("Error creating delegation evidence")?;
 
-    let access = verify_delegation_evidence(
-        &delegation_evidence_container.delegation_evidence,
-        "AuditLog".to_owned(),
-    );
+            verify_delegation_evidence(
+                &delegation_evidence_container.delegation_evidence,
+                "AuditLog".to_owned(),
+            )
+        }
+    };
 
     if access {
         tracing::info!("access granted because there is delegation evidence")
@@ -261,32 +367,75 @@ pub async fn retrieve_events(
         }));
     }
 
-    let max_results = match max_results {
-        mr if mr > 1000 => {
-            tracing::info!(
-                "max_results '{}' value higher than 1000, using 1000 instead",
-                mr
-            );
-            1000
-        }
-        mr if mr < 1 => {
-            tracing::info!("max_results '{}' value lower than 1, using 1 instead", mr);
-            1
+    return Ok(());
+}
+
+#[derive(Default, Clone)]
+pub struct AuditEventFilter {
+    pub from: Option<chrono::DateTime<Utc>>,
+    pub to: Option<chrono::DateTime<Utc>>,
+    // comma separated list of event types
+    pub event_types: Option<String>,
+    // eori of a party appearing anywhere in the context of the event
+    pub party: Option<String>,
+    pub entry_id: Option<String>,
+    // key/value pairs that all have to be present (at any depth) in the context
+    pub context: Vec<(String, String)>,
+}
+
+// parses a comma separated list of `key:value` pairs, e.g. `policy_id:abc,policyIssuer:NL.1`
+pub fn parse_context_filter(context: &str) -> Result<Vec<(String, String)>, AppError> {
+    let mut pairs = vec![];
+
+    for pair in context.split(",").filter(|p| !p.is_empty()) {
+        match pair.split_once(":") {
+            Some((key, value)) if !key.is_empty() => {
+                pairs.push((key.to_owned(), value.to_owned()));
+            }
+            _ => {
+                return Err(AppError::Expected(ExpectedError {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message: format!(
+                        "Invalid context filter '{}'. Expected format is 'key:value'",
+                        pair
+                    ),
+                    reason: format!("invalid context filter '{}'", pair),
+                    metadata: None,
+                }));
+            }
         }
-        mr => mr,
-    };
+    }
 
-    let mut query = ar_entity::audit_event::Entity::find();
+    return Ok(pairs);
+}
 
-    if let Some(from) = from {
+// builds a jsonpath predicate that is supported by the GIN index on the context column
+fn context_jsonpath(key: Option<&str>, value: &str) -> String {
+    let value = serde_json::to_string(value).unwrap_or_default();
+
+    return match key {
+        Some(key) => format!(
+            "$.**.{} == {}",
+            serde_json::to_string(key).unwrap_or_default(),
+            value
+        ),
+        None => format!("$.** == {}", value),
+    };
+}
+
+fn apply_filter(
+    mut query: Select<AuditEventEntity>,
+    filter: &AuditEventFilter,
+) -> Select<AuditEventEntity> {
+    if let Some(from) = filter.from {
         query = query.filter(ar_entity::audit_event::Column::Timestamp.gte(from))
     }
 
-    if let Some(to) = to {
+    if let Some(to) = filter.to {
         query = query.filter(ar_entity::audit_event::Column::Timestamp.lte(to))
     }
 
-    if let Some(event_types) = event_types {
+    if let Some(event_types) = &filter.event_types {
         let splitted_event_types: Vec<&str> = event_types.split(",").collect();
 
         let mut event_types_condition = Condition::any();
@@ -299,6 +448,62 @@ pub async fn retrieve_events(
         query = query.filter(event_types_condition);
     }
 
+    if let Some(party) = &filter.party {
+        query = query.filter(Expr::cust_with_values(
+            "context @@ ($1::jsonpath)",
+            [context_jsonpath(None, party)],
+        ));
+    }
+
+    if let Some(entry_id) = &filter.entry_id {
+        query = query.filter(ar_entity::audit_event::Column::EntryId.eq(entry_id));
+    }
+
+    for (key, value) in filter.context.iter() {
+        query = query.filter(Expr::cust_with_values(
+            "context @@ ($1::jsonpath)",
+            [context_jsonpath(Some(key), value)],
+        ));
+    }
+
+    return query;
+}
+
+pub async fn retrieve_events(
+    controller_eori: &str,
+    filter: &AuditEventFilter,
+    max_results: u64,
+    time_provider: Arc<dyn TimeProvider>,
+    authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
+    app_config: &AppConfig,
+    db: &DatabaseConnection,
+) -> Result<Vec<AuditEventWithIssAndSub>, AppError> {
+    check_audit_log_access(
+        controller_eori,
+        time_provider,
+        authorization_provider,
+        app_config,
+        db,
+    )
+    .await?;
+
+    let max_results = match max_results {
+        mr if mr > 1000 => {
+            tracing::info!(
+                "max_results '{}' value higher than 1000, using 1000 instead",
+                mr
+            );
+            1000
+        }
+        mr if mr < 1 => {
+            tracing::info!("max_results '{}' value lower than 1, using 1 instead", mr);
+            1
+        }
+        mr => mr,
+    };
+
+    let query = apply_filter(ar_entity::audit_event::Entity::find(), filter);
+
     let events = query
         .limit(max_results)
         .all(db)
@@ -320,13 +525,327 @@ pub async fn retrieve_events(
     return Ok(events_with_iss_and_sub);
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsBucket {
+    Hour,
+    Day,
+    Week,
+}
+
+impl StatsBucket {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventStatsRow {
+    pub bucket: DateTime<Utc>,
+    pub event_type: String,
+    pub party: Option<String>,
+    // only set for delegation requests
+    pub resource_type: Option<String>,
+    pub outcome: Option<String>,
+    pub count: i64,
+}
+
+// the party an event is attributed to: the policy issuer of delegation requests,
+// the company of created companies/users and the client of failed logins
+const STATS_PARTY_SQL: &str =
+    "coalesce(context ->> 'policyIssuer', context ->> 'company_id', context ->> 'client_id')";
+
+pub async fn retrieve_event_stats(
+    controller_eori: &str,
+    filter: &AuditEventFilter,
+    bucket: StatsBucket,
+    time_provider: Arc<dyn TimeProvider>,
+    authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
+    app_config: &AppConfig,
+    db: &DatabaseConnection,
+) -> Result<Vec<AuditEventStatsRow>, AppError> {
+    check_audit_log_access(
+        controller_eori,
+        time_provider,
+        authorization_provider,
+        app_config,
+        db,
+    )
+    .await?;
+
+    // truncate in UTC so buckets don't depend on the timezone of the db session
+    let bucket_sql = format!(
+        "date_trunc('{}', timestamp AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'",
+        bucket.as_sql()
+    );
+
+    // a delegation request is counted once for every resource type it requests. The outcome is
+    // logged with the request: Permit when the evidence permits every requested policy
+    let events =
+        apply_filter(ar_entity::audit_event::Entity::find(), filter).build(DbBackend::Postgres);
+    let sql = format!(
+        r#"
+            select
+                {bucket} as bucket,
+                e.event_type as event_type,
+                {party} as party,
+                r.resource_type as resource_type,
+                e.data ->> 'outcome' as outcome,
+                count(*) as count
+            from ({events}) e
+            left join lateral (
+                select distinct t #>> '{{}}' as resource_type
+                from jsonb_path_query(
+                    e.context,
+                    '$.policySets[*].policies[*].target.resource.type'
+                ) t
+            ) r on true
+            group by 1, 2, 3, 4, 5
+            order by 1, 2, 3, 4, 5
+        "#,
+        bucket = bucket_sql,
+        party = STATS_PARTY_SQL,
+        events = events.sql,
+    );
+
+    let rows = AuditEventStatsRow::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        events.values.map(|v| v.0).unwrap_or_default(),
+    ))
+    .all(db)
+    .await
+    .context("Error retrieving audit log stats")?;
+
+    return Ok(rows);
+}
+
+const EXPORT_PAGE_SIZE: u64 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuditLogExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl AuditLogExportFormat {
+    pub fn from_accept_header(accept: &str) -> Option<Self> {
+        for media_type in accept.split(",") {
+            let media_type = media_type.split(";").next().unwrap_or("").trim();
+            match media_type {
+                "text/csv" => return Some(Self::Csv),
+                "application/x-ndjson" | "application/ndjson" => return Some(Self::Ndjson),
+                _ => {}
+            }
+        }
+
+        return None;
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+
+    return field.to_owned();
+}
+
+fn write_csv_row(fields: &[&str], out: &mut String) {
+    let escaped: Vec<String> = fields.iter().map(|f| escape_csv_field(f)).collect();
+    out.push_str(&escaped.join(","));
+    out.push_str("\r\n");
+}
+
+fn serialize_export_event(
+    event: &AuditEventWithIssAndSub,
+    format: AuditLogExportFormat,
+    out: &mut String,
+) -> anyhow::Result<()> {
+    match format {
+        AuditLogExportFormat::Ndjson => {
+            out.push_str(
+                &serde_json::to_string(event).context("Error serializing audit log entry")?,
+            );
+            out.push('\n');
+        }
+        AuditLogExportFormat::Csv => {
+            let context =
+                serde_json::to_string(&event.context).context("Error serializing context")?;
+            let data = match &event.data {
+                Some(data) => serde_json::to_string(data).context("Error serializing data")?,
+                None => "".to_owned(),
+            };
+
+            write_csv_row(
+                &[
+                    &event.id,
+                    &event.timestamp.to_rfc3339(),
+                    &event.event_type,
+                    &event.source,
+                    &event.iss,
+                    &event.sub,
+                    &context,
+                    &data,
+                ],
+                out,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+struct ExportState {
+    cursor: Option<(DateTime<Utc>, Uuid)>,
+    header_written: bool,
+    done: bool,
+}
+
+// streams all audit events matching the filter. events are read from the database
+// page by page (keyset pagination on timestamp and id), so the export is never held in memory.
+pub async fn export_events(
+    controller_eori: String,
+    filter: AuditEventFilter,
+    format: AuditLogExportFormat,
+    time_provider: Arc<dyn TimeProvider>,
+    authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
+    app_config: Arc<AppConfig>,
+    db: DatabaseConnection,
+) -> Result<impl Stream<Item = anyhow::Result<Bytes>>, AppError> {
+    check_audit_log_access(
+        &controller_eori,
+        time_provider,
+        authorization_provider,
+        &app_config,
+        &db,
+    )
+    .await?;
+
+    let initial_state = ExportState {
+        cursor: None,
+        header_written: false,
+        done: false,
+    };
+
+    let stream = futures::stream::unfold(initial_state, move |mut state| {
+        let controller_eori = controller_eori.clone();
+        let filter = filter.clone();
+        let app_config = app_config.clone();
+        let db = db.clone();
+
+        async move {
+            if state.done {
+                return None;
+            }
+
+            let mut out = String::new();
+
+            if !state.header_written && format == AuditLogExportFormat::Csv {
+                write_csv_row(
+                    &[
+                        "id",
+                        "timestamp",
+                        "type",
+                        "source",
+                        "iss",
+                        "sub",
+                        "context",
+                        "data",
+                    ],
+                    &mut out,
+                );
+            }
+            state.header_written = true;
+
+            let mut query = apply_filter(ar_entity::audit_event::Entity::find(), &filter);
+
+            if let Some((timestamp, id)) = state.cursor {
+                query = query.filter(
+                    Condition::any()
+                        .add(ar_entity::audit_event::Column::Timestamp.gt(timestamp))
+                        .add(
+                            Condition::all()
+                                .add(ar_entity::audit_event::Column::Timestamp.eq(timestamp))
+                                .add(ar_entity::audit_event::Column::Id.gt(id)),
+                        ),
+                );
+            }
+
+            let events = match query
+                .order_by_asc(ar_entity::audit_event::Column::Timestamp)
+                .order_by_asc(ar_entity::audit_event::Column::Id)
+                .limit(EXPORT_PAGE_SIZE)
+                .all(&db)
+                .await
+                .context("Error retrieving audit log entries for export")
+            {
+                Ok(events) => events,
+                Err(e) => {
+                    tracing::error!("error exporting audit log: {:?}", e);
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+            };
+
+            if (events.len() as u64) < EXPORT_PAGE_SIZE {
+                state.done = true;
+            }
+
+            if let Some(last) = events.last() {
+                state.cursor = Some((last.timestamp, last.id));
+            }
+
+            for event in events {
+                let event = add_iss_and_sub_and_id_to_context(
+                    &app_config.client_eori,
+                    &controller_eori,
+                    event,
+                    &app_config.service_name,
+                );
+
+                if let Err(e) = serialize_export_event(&event, format, &mut out) {
+                    tracing::error!("error exporting audit log: {:?}", e);
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+            }
+
+            Some((Ok(Bytes::from(out)), state))
+        }
+    });
+
+    return Ok(stream);
+}
+
 #[cfg(test)]
 
 mod tests {
     use serde_json::json;
     use uuid::Uuid;
 
-    use crate::services::audit_log::add_entry_id_to_context;
+    use crate::services::audit_log::{
+        add_entry_id_to_context, context_jsonpath, parse_context_filter,
+    };
 
     #[test]
     fn test_add_id_to_context() {
@@ -340,4 +859,27 @@ mod tests {
         assert_eq!(context.get("entryId").unwrap(), &id);
         assert_eq!(context.get("something").unwrap(), "whatever");
     }
+
+    #[test]
+    fn test_context_jsonpath() {
+        assert_eq!(context_jsonpath(None, "NL.1"), r#"$.** == "NL.1""#);
+        assert_eq!(
+            context_jsonpath(Some("policy_id"), "a\"b"),
+            r#"$.**."policy_id" == "a\"b""#
+        );
+    }
+
+    #[test]
+    fn test_parse_context_filter() {
+        assert_eq!(
+            parse_context_filter("policy_id:abc,source:urn:x").unwrap(),
+            vec![
+                ("policy_id".to_owned(), "abc".to_owned()),
+                ("source".to_owned(), "urn:x".to_owned())
+            ]
+        );
+        assert!(parse_context_filter("").unwrap().is_empty());
+        assert!(parse_context_filter("policy_id").is_err());
+        assert!(parse_context_filter(":abc").is_err());
+    }
 }
@@ -0,0 +1,452 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use sea_orm::{
+    sea_query::{LockBehavior, LockType},
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+    QueryOrder, QuerySelect, Statement, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{config::AuditLogRetentionConfig, TimeProvider};
+
+const ARCHIVE_BATCH_SIZE: u64 = 10000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditLogArchiveManifest {
+    pub archive_file: String,
+    pub sha256: String,
+    pub event_count: usize,
+    pub oldest_event: DateTime<Utc>,
+    pub newest_event: DateTime<Utc>,
+    pub event_types: Vec<String>,
+    pub created: DateTime<Utc>,
+}
+
+fn build_expired_condition(now: DateTime<Utc>, config: &AuditLogRetentionConfig) -> Condition {
+    let mut condition = Condition::any();
+
+    for (event_type, retention_days) in config.event_types.iter() {
+        condition = condition.add(
+            Condition::all()
+                .add(ar_entity::audit_event::Column::EventType.eq(event_type))
+                .add(
+                    ar_entity::audit_event::Column::Timestamp
+                        .lt(now - chrono::Duration::days(*retention_days)),
+                ),
+        );
+    }
+
+    if let Some(default_retention_days) = config.default_retention_days {
+        condition = condition.add(
+            Condition::all()
+                .add(
+                    ar_entity::audit_event::Column::EventType
+                        .is_not_in(config.event_types.keys().cloned()),
+                )
+                .add(
+                    ar_entity::audit_event::Column::Timestamp
+                        .lt(now - chrono::Duration::days(default_retention_days)),
+                ),
+        );
+    }
+
+    condition
+}
+
+// an existing archive is never overwritten, its events have been deleted already
+async fn write_new_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+
+    Ok(())
+}
+
+async fn write_archive(
+    archive_folder: &Path,
+    archive_name: &str,
+    now: DateTime<Utc>,
+    events: &[ar_entity::audit_event::Model],
+) -> anyhow::Result<AuditLogArchiveManifest> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+    for event in events.iter() {
+        serde_json::to_writer(&mut encoder, event).context("Error serializing audit event")?;
+        encoder
+            .write_all(b"\n")
+            .context("Error writing audit event to archive")?;
+    }
+
+    let compressed = encoder
+        .finish()
+        .context("Error compressing audit log archive")?;
+    let checksum = hex::encode(Sha256::digest(&compressed));
+
+    let archive_file = format!("{}.ndjson.gz", archive_name);
+    write_new_file(&archive_folder.join(&archive_file), &compressed)
+        .await
+        .context(format!(
+            "Error writing audit log archive '{}'",
+            &archive_file
+        ))?;
+
+    let mut event_types: Vec<String> = events.iter().map(|e| e.event_type.clone()).collect();
+    event_types.sort();
+    event_types.dedup();
+
+    let manifest = AuditLogArchiveManifest {
+        archive_file,
+        sha256: checksum,
+        event_count: events.len(),
+        oldest_event: events.iter().map(|e| e.timestamp).min().unwrap_or(now),
+        newest_event: events.iter().map(|e| e.timestamp).max().unwrap_or(now),
+        event_types,
+        created: now,
+    };
+
+    let manifest_file = format!("{}.manifest.json", archive_name);
+    write_new_file(
+        &archive_folder.join(&manifest_file),
+        &serde_json::to_vec_pretty(&manifest).context("Error serializing archive manifest")?,
+    )
+    .await
+    .context(format!(
+        "Error writing audit log archive manifest '{}'",
+        &manifest_file
+    ))?;
+
+    Ok(manifest)
+}
+
+// exports all audit events that are past their retention to compressed NDJSON archives
+// and deletes them from the database afterwards. returns the manifests of the written archives.
+pub async fn archive_and_purge(
+    now: DateTime<Utc>,
+    config: &AuditLogRetentionConfig,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Vec<AuditLogArchiveManifest>> {
+    if config.event_types.is_empty() && config.default_retention_days.is_none() {
+        tracing::info!("no audit log retention configured, keeping all events");
+        return Ok(vec![]);
+    }
+
+    let archive_folder = PathBuf::from(&config.archive_folder);
+    tokio::fs::create_dir_all(&archive_folder)
+        .await
+        .context(format!(
+            "Error creating audit log archive folder '{}'",
+            &config.archive_folder
+        ))?;
+
+    let condition = build_expired_condition(now, config);
+    let mut manifests = vec![];
+
+    loop {
+        let transaction = db.begin().await.context("Error starting db transaction")?;
+
+        // instances that share the database each archive other events
+        let events = ar_entity::audit_event::Entity::find()
+            .filter(condition.clone())
+            .order_by_asc(ar_entity::audit_event::Column::Timestamp)
+            .limit(ARCHIVE_BATCH_SIZE)
+            .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+            .all(&transaction)
+            .await
+            .context("Error retrieving expired audit events")?;
+
+        if events.is_empty() {
+            break;
+        }
+
+        let archive_name = format!(
+            "audit-log-{}-{}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            Uuid::new_v4()
+        );
+        let manifest = write_archive(&archive_folder, &archive_name, now, &events).await?;
+
+        ar_entity::audit_event::Entity::delete_many()
+            .filter(ar_entity::audit_event::Column::Id.is_in(events.iter().map(|e| e.id)))
+            .exec(&transaction)
+            .await
+            .context("Error deleting archived audit events")?;
+
+        transaction
+            .commit()
+            .await
+            .context("Error commiting transaction to db")?;
+
+        tracing::info!(
+            "archived {} audit events to '{}'",
+            manifest.event_count,
+            &manifest.archive_file
+        );
+
+        manifests.push(manifest);
+
+        if (events.len() as u64) < ARCHIVE_BATCH_SIZE {
+            break;
+        }
+    }
+
+    Ok(manifests)
+}
+
+pub fn spawn_retention_job(
+    config: AuditLogRetentionConfig,
+    time_provider: Arc<dyn TimeProvider>,
+    db: DatabaseConnection,
+) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(config.interval_seconds));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = archive_and_purge(time_provider.now(), &config, &db).await {
+                tracing::error!("error archiving audit log: {:?}", e);
+            }
+        }
+    });
+}
+
+#[derive(Serialize, Deserialize, Debug, FromQueryResult, ToSchema)]
+pub struct AuditLogTableStats {
+    pub table_size_bytes: i64,
+    pub event_count: i64,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub oldest_event: Option<DateTime<Utc>>,
+}
+
+pub async fn get_audit_log_table_stats(
+    db: &DatabaseConnection,
+) -> anyhow::Result<AuditLogTableStats> {
+    let stmt = Statement::from_string(
+        sea_orm::DatabaseBackend::Postgres,
+        r#"
+            select
+                pg_total_relation_size('audit_event') as table_size_bytes,
+                count(*) as event_count,
+                min(timestamp) as oldest_event
+            from
+                audit_event
+        "#,
+    );
+
+    let stats = AuditLogTableStats::find_by_statement(stmt)
+        .one(db)
+        .await
+        .context("Error retrieving audit log table stats")?
+        .context("Expected a row with audit log table stats")?;
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use ishare::delegation_request::{DelegationRequest, DelegationTarget};
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::services::audit_log::{log_event, EventType, PolicySetDeletedEventMetadata};
+    use crate::test_helpers::helpers::init_test_db;
+
+    async fn insert_events(db: &DatabaseConnection) {
+        for timestamp in ["2025-01-01T09:00:00Z", "2025-06-01T09:00:00Z"] {
+            log_event(
+                chrono::DateTime::parse_from_rfc3339(timestamp)
+                    .unwrap()
+                    .to_utc(),
+                "".to_owned(),
+                EventType::DmiDelegationRequest(DelegationRequest {
+                    policy_issuer: "pi".to_owned(),
+                    target: DelegationTarget {
+                        access_subject: "as".to_owned(),
+                    },
+                    policy_sets: vec![],
+                }),
+                None,
+                None,
+                db,
+            )
+            .await
+            .unwrap();
+
+            let policy_set_id = Uuid::new_v4();
+            log_event(
+                chrono::DateTime::parse_from_rfc3339(timestamp)
+                    .unwrap()
+                    .to_utc(),
+                policy_set_id.to_string(),
+                EventType::ArPolicySetDeleted(PolicySetDeletedEventMetadata { policy_set_id }),
+                None,
+                None,
+                db,
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_archive_and_purge(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        insert_events(&db).await;
+
+        let archive_folder = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let config = AuditLogRetentionConfig {
+            archive_folder: archive_folder.to_str().unwrap().to_owned(),
+            interval_seconds: 3600,
+            default_retention_days: None,
+            event_types: HashMap::from([("dmi:ar:delegation:request".to_owned(), 90)]),
+        };
+
+        let now = chrono::DateTime::parse_from_rfc3339("2025-07-01T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let manifests = archive_and_purge(now, &config, &db).await.unwrap();
+
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].event_count, 1);
+        assert_eq!(
+            manifests[0].event_types,
+            vec!["dmi:ar:delegation:request".to_owned()]
+        );
+
+        let compressed = std::fs::read(archive_folder.join(&manifests[0].archive_file)).unwrap();
+        assert_eq!(
+            hex::encode(Sha256::digest(&compressed)),
+            manifests[0].sha256
+        );
+
+        let mut ndjson = String::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut ndjson)
+            .unwrap();
+        let archived: Vec<ar_entity::audit_event::Model> = ndjson
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].event_type, "dmi:ar:delegation:request");
+
+        let remaining = ar_entity::audit_event::Entity::find()
+            .all(&db)
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 3);
+
+        std::fs::remove_dir_all(archive_folder).unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_archive_and_purge_default_retention(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        insert_events(&db).await;
+
+        let archive_folder = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let config = AuditLogRetentionConfig {
+            archive_folder: archive_folder.to_str().unwrap().to_owned(),
+            interval_seconds: 3600,
+            default_retention_days: Some(10),
+            event_types: HashMap::from([("dmi:ar:delegation:request".to_owned(), 365)]),
+        };
+
+        let now = chrono::DateTime::parse_from_rfc3339("2025-07-01T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let manifests = archive_and_purge(now, &config, &db).await.unwrap();
+
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].event_count, 2);
+        assert_eq!(
+            manifests[0].event_types,
+            vec!["dmi:ar:policy_set:deleted".to_owned()]
+        );
+
+        let remaining = ar_entity::audit_event::Entity::find()
+            .all(&db)
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining
+            .iter()
+            .all(|e| e.event_type == "dmi:ar:delegation:request"));
+
+        std::fs::remove_dir_all(archive_folder).unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_archive_and_purge_concurrently(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        insert_events(&db).await;
+
+        let archive_folder = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let config = AuditLogRetentionConfig {
+            archive_folder: archive_folder.to_str().unwrap().to_owned(),
+            interval_seconds: 3600,
+            default_retention_days: Some(10),
+            event_types: HashMap::new(),
+        };
+
+        // two instances sharing the archive folder run in the same second
+        let now = chrono::DateTime::parse_from_rfc3339("2025-07-01T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let (first, second) = tokio::join!(
+            archive_and_purge(now, &config, &db),
+            archive_and_purge(now, &config, &db)
+        );
+        let manifests: Vec<AuditLogArchiveManifest> =
+            first.unwrap().into_iter().chain(second.unwrap()).collect();
+
+        // every event is archived once, in a file of its own run
+        assert_eq!(manifests.iter().map(|m| m.event_count).sum::<usize>(), 4);
+        let archived: usize = manifests
+            .iter()
+            .map(|m| {
+                let compressed = std::fs::read(archive_folder.join(&m.archive_file)).unwrap();
+                let mut ndjson = String::new();
+                GzDecoder::new(&compressed[..])
+                    .read_to_string(&mut ndjson)
+                    .unwrap();
+                ndjson.lines().count()
+            })
+            .sum();
+        assert_eq!(archived, 4);
+        assert!(ar_entity::audit_event::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(archive_folder).unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::StatusCode;
+use sea_orm::DatabaseConnection;
+
+use ar_entity::auth_request::Model as AuthRequestModel;
+
+use crate::{
+    db::auth_request::{self as auth_request_store, InsertAuthRequest},
+    error::{AppError, ExpectedError},
+    TimeProvider,
+};
+
+use super::identity_provider::AuthorizationRequest;
+
+const PURGE_INTERVAL_SECONDS: u64 = 3600;
+
+// a login that has been started and not yet completed by the callback
+pub struct PendingAuthRequest {
+    pub authorization_request: AuthorizationRequest,
+    pub redirect_uri: String,
+    // state of the frontend, handed back when redirecting to `redirect_uri`
+    pub client_state: Option<String>,
+}
+
+fn generate_random_value() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).context("Error generating random value")?;
+
+    return Ok(URL_SAFE_NO_PAD.encode(bytes));
+}
+
+fn invalid_auth_request(reason: &str) -> AppError {
+    return AppError::Expected(ExpectedError {
+        status_code: StatusCode::UNAUTHORIZED,
+        message: "Invalid authorization callback".to_owned(),
+        reason: reason.to_owned(),
+        metadata: None,
+    });
+}
+
+pub async fn create_auth_request(
+    now: DateTime<Utc>,
+    redirect_uri: String,
+    client_state: Option<String>,
+    auth_request_expiry_seconds: i64,
+    db: &DatabaseConnection,
+) -> anyhow::Result<AuthorizationRequest> {
+    let authorization_request = AuthorizationRequest {
+        state: generate_random_value()?,
+        nonce: generate_random_value()?,
+        code_verifier: generate_random_value()?,
+    };
+
+    auth_request_store::insert_auth_request(
+        now,
+        InsertAuthRequest {
+            state: authorization_request.state.clone(),
+            nonce: authorization_request.nonce.clone(),
+            code_verifier: authorization_request.code_verifier.clone(),
+            redirect_uri,
+            client_state,
+            expires_at: now + TimeDelta::seconds(auth_request_expiry_seconds),
+        },
+        db,
+    )
+    .await?;
+
+    return Ok(authorization_request);
+}
+
+// the stored auth request of `state`, or the reason it can not be used (anymore)
+async fn get_pending_auth_request(
+    now: DateTime<Utc>,
+    state: &str,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Result<AuthRequestModel, &'static str>> {
+    let stored = match auth_request_store::get_by_state(state, db).await? {
+        Some(stored) => stored,
+        None => return Ok(Err("State is unknown")),
+    };
+
+    if stored.expires_at <= now {
+        return Ok(Err("Authorization request has expired"));
+    }
+
+    if stored.used_at.is_some() {
+        tracing::warn!("authorization request for state '{}' is reused", state);
+        return Ok(Err("Authorization request has already been used"));
+    }
+
+    return Ok(Ok(stored));
+}
+
+// only a login that has been started here, and is still pending, may be bound to a browser
+pub async fn check_pending_auth_request(
+    now: DateTime<Utc>,
+    state: &str,
+    db: &DatabaseConnection,
+) -> Result<(), AppError> {
+    if let Err(reason) = get_pending_auth_request(now, state, db).await? {
+        return Err(AppError::Expected(ExpectedError {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "Invalid authorization request".to_owned(),
+            reason: reason.to_owned(),
+            metadata: None,
+        }));
+    }
+
+    return Ok(());
+}
+
+// looks up the auth request of a callback. Every auth request can only be completed once,
+// so a replayed callback is rejected
+pub async fn consume_auth_request(
+    now: DateTime<Utc>,
+    state: &str,
+    db: &DatabaseConnection,
+) -> Result<PendingAuthRequest, AppError> {
+    let stored = get_pending_auth_request(now, state, db)
+        .await?
+        .map_err(invalid_auth_request)?;
+
+    if !auth_request_store::mark_used(stored.id, now, db).await? {
+        tracing::warn!("replayed authorization callback for state '{}'", state);
+        return Err(invalid_auth_request(
+            "Authorization request has already been used",
+        ));
+    }
+
+    return Ok(PendingAuthRequest {
+        authorization_request: AuthorizationRequest {
+            state: stored.state,
+            nonce: stored.nonce,
+            code_verifier: stored.code_verifier,
+        },
+        redirect_uri: stored.redirect_uri,
+        client_state: stored.client_state,
+    });
+}
+
+pub fn spawn_purge_job(time_provider: Arc<dyn TimeProvider>, db: DatabaseConnection) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECONDS));
+
+        loop {
+            interval.tick().await;
+
+            match auth_request_store::delete_expired(time_provider.now(), &db).await {
+                Ok(count) => tracing::info!("purged {} expired auth requests", count),
+                Err(e) => tracing::error!("error purging auth requests: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use crate::test_helpers::helpers::init_test_db;
+
+    use super::*;
+
+    fn reason(err: AppError) -> String {
+        return match err {
+            AppError::Expected(e) => e.reason,
+            e => panic!("unexpected error: {:?}", e),
+        };
+    }
+
+    #[sqlx::test]
+    async fn test_consume_auth_request(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let request = create_auth_request(
+            now,
+            "http://frontend/callback".to_owned(),
+            Some("/policies".to_owned()),
+            60,
+            &db,
+        )
+        .await
+        .unwrap();
+
+        let err = consume_auth_request(now, "unknown", &db)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(reason(err), "State is unknown");
+
+        let pending = consume_auth_request(now + TimeDelta::seconds(30), &request.state, &db)
+            .await
+            .unwrap();
+        assert_eq!(pending.redirect_uri, "http://frontend/callback");
+        assert_eq!(pending.client_state, Some("/policies".to_owned()));
+        assert_eq!(pending.authorization_request.nonce, request.nonce);
+        assert_eq!(
+            pending.authorization_request.code_verifier,
+            request.code_verifier
+        );
+
+        let err = consume_auth_request(now + TimeDelta::seconds(31), &request.state, &db)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(reason(err), "Authorization request has already been used");
+    }
+
+    #[sqlx::test]
+    async fn test_expired_auth_request(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) {
+        let db = init_test_db(&conn_option).await;
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let request =
+            create_auth_request(now, "http://frontend/callback".to_owned(), None, 60, &db)
+                .await
+                .unwrap();
+
+        let err = consume_auth_request(now + TimeDelta::seconds(60), &request.state, &db)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(reason(err), "Authorization request has expired");
+    }
+
+    #[test]
+    fn test_code_challenge() {
+        // example of RFC 7636 appendix B
+        let request = AuthorizationRequest {
+            state: "state".to_owned(),
+            nonce: "nonce".to_owned(),
+            code_verifier: "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_owned(),
+        };
+
+        assert_eq!(
+            request.code_challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use sea_orm::DatabaseConnection;
+
+use crate::{db::client_assertion_jti as client_assertion_jti_store, TimeProvider};
+
+const PURGE_INTERVAL_SECONDS: u64 = 3600;
+
+// client assertions are accepted up to this long after their exp because of clock skew
+const EXPIRY_LEEWAY_SECONDS: i64 = 60;
+
+// remembers the jti of every accepted client assertion until it has expired, so a captured
+// client assertion can only be used once
+#[async_trait]
+pub trait ReplayStore: Send + Sync {
+    // returns false when the jti of the issuer has been used before
+    async fn register(
+        &self,
+        now: DateTime<Utc>,
+        issuer: &str,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> anyhow::Result<u64>;
+}
+
+pub fn get_jti_expiry(exp: i64) -> DateTime<Utc> {
+    return DateTime::from_timestamp(exp, 0).unwrap_or(DateTime::<Utc>::MAX_UTC)
+        + TimeDelta::seconds(EXPIRY_LEEWAY_SECONDS);
+}
+
+// only protects a single instance of the authorization registry
+#[derive(Default)]
+pub struct InMemoryReplayStore {
+    jtis: Mutex<HashMap<(String, String), DateTime<Utc>>>,
+}
+
+impl InMemoryReplayStore {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+}
+
+#[async_trait]
+impl ReplayStore for InMemoryReplayStore {
+    async fn register(
+        &self,
+        now: DateTime<Utc>,
+        issuer: &str,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let mut jtis = self
+            .jtis
+            .lock()
+            .map_err(|_| anyhow::anyhow!("client assertion replay store lock is poisoned"))?;
+
+        jtis.retain(|_, expires_at| *expires_at >= now);
+
+        let key = (issuer.to_owned(), jti.to_owned());
+        if jtis.contains_key(&key) {
+            return Ok(false);
+        }
+        jtis.insert(key, expires_at);
+
+        return Ok(true);
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> anyhow::Result<u64> {
+        let mut jtis = self
+            .jtis
+            .lock()
+            .map_err(|_| anyhow::anyhow!("client assertion replay store lock is poisoned"))?;
+
+        let count = jtis.len();
+        jtis.retain(|_, expires_at| *expires_at >= now);
+
+        return Ok((count - jtis.len()) as u64);
+    }
+}
+
+// shared by all instances of the authorization registry that use the same database
+pub struct DbReplayStore {
+    db: DatabaseConnection,
+}
+
+impl DbReplayStore {
+    pub fn new(db: DatabaseConnection) -> Self {
+        return Self { db };
+    }
+}
+
+#[async_trait]
+impl ReplayStore for DbReplayStore {
+    async fn register(
+        &self,
+        now: DateTime<Utc>,
+        issuer: &str,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        client_assertion_jti_store::insert_if_absent(now, issuer, jti, expires_at, &self.db).await
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> anyhow::Result<u64> {
+        client_assertion_jti_store::delete_expired(now, &self.db).await
+    }
+}
+
+pub fn spawn_purge_job(replay_store: Arc<dyn ReplayStore>, time_provider: Arc<dyn TimeProvider>) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECONDS));
+
+        loop {
+            interval.tick().await;
+
+            match replay_store.purge_expired(time_provider.now()).await {
+                Ok(count) => tracing::info!("purged {} expired client assertion jtis", count),
+                Err(e) => tracing::error!("error purging client assertion jtis: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use crate::test_helpers::helpers::init_test_db;
+
+    use super::*;
+
+    async fn test_replay(store: &dyn ReplayStore) {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let expires_at = now + TimeDelta::seconds(90);
+
+        assert!(store
+            .register(now, "NL.A", "jti", expires_at)
+            .await
+            .unwrap());
+        assert!(!store
+            .register(now + TimeDelta::seconds(10), "NL.A", "jti", expires_at)
+            .await
+            .unwrap());
+
+        // the jti is only unique per issuer
+        assert!(store
+            .register(now, "NL.B", "jti", expires_at)
+            .await
+            .unwrap());
+
+        assert_eq!(
+            store
+                .purge_expired(now + TimeDelta::seconds(91))
+                .await
+                .unwrap(),
+            2
+        );
+
+        // once a client assertion has expired its jti can not be replayed anymore anyway
+        assert!(store
+            .register(now, "NL.C", "jti", now + TimeDelta::seconds(10))
+            .await
+            .unwrap());
+        assert!(store
+            .register(
+                now + TimeDelta::seconds(20),
+                "NL.C",
+                "jti",
+                now + TimeDelta::seconds(110)
+            )
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_replay_store() {
+        test_replay(&InMemoryReplayStore::new()).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_replay_store(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+
+        test_replay(&DbReplayStore::new(db)).await;
+    }
+
+    #[test]
+    fn test_get_jti_expiry() {
+        assert_eq!(
+            get_jti_expiry(1749632400),
+            chrono::DateTime::parse_from_rfc3339("2025-06-11T09:01:00Z")
+                .unwrap()
+                .to_utc()
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::http::HeaderMap;
+use reqwest::StatusCode;
+
+use crate::error::{AppError, ExpectedError};
+
+use super::{ishare_provider::SatelliteProvider, party_cache::PartyCache, server_token::Role};
+
+// selects the dataspace of a token or delegation request
+pub const DATASPACE_HEADER: &str = "dataspace-id";
+
+pub struct Dataspace {
+    pub satellite_provider: Arc<dyn SatelliteProvider>,
+    pub party_cache: Arc<PartyCache>,
+}
+
+// the dataspaces the authorization registry serves, each with its own satellite. Policy sets of
+// the default dataspace are stored without a dataspace
+pub struct Dataspaces {
+    default_id: String,
+    dataspaces: BTreeMap<String, Dataspace>,
+}
+
+impl Dataspaces {
+    pub fn new(default_id: String, default: Dataspace) -> Self {
+        return Self {
+            dataspaces: BTreeMap::from([(default_id.clone(), default)]),
+            default_id,
+        };
+    }
+
+    pub fn add(&mut self, id: String, dataspace: Dataspace) -> anyhow::Result<()> {
+        if self.dataspaces.contains_key(&id) {
+            anyhow::bail!("dataspace '{}' is configured more than once", id);
+        }
+        self.dataspaces.insert(id, dataspace);
+
+        return Ok(());
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        return self.dataspaces.keys().cloned().collect();
+    }
+
+    // the dataspace as stored with policy sets: None for the default dataspace
+    pub fn resolve(&self, id: Option<&str>) -> Result<Option<String>, AppError> {
+        return match id {
+            None => Ok(None),
+            Some(id) if id == self.default_id => Ok(None),
+            Some(id) if self.dataspaces.contains_key(id) => Ok(Some(id.to_owned())),
+            Some(id) => Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!("Unknown dataspace '{}'", id),
+                reason: format!(
+                    "dataspace '{}' is not one of the configured dataspaces: {}",
+                    id,
+                    self.ids().join(", ")
+                ),
+                metadata: None,
+            })),
+        };
+    }
+
+    // the dataspace of a request by `role`. A machine token is only valid in the dataspace the
+    // client authenticated in, which is also used when the request does not name a dataspace
+    pub fn resolve_for_role(
+        &self,
+        id: Option<&str>,
+        role: &Role,
+    ) -> Result<Option<String>, AppError> {
+        let dataspace = self.resolve(id)?;
+
+        return match role {
+            Role::Machine(machine) => {
+                if id.is_some() && dataspace != machine.dataspace {
+                    return Err(AppError::Expected(ExpectedError {
+                        status_code: StatusCode::FORBIDDEN,
+                        message: format!(
+                            "Access token is not valid for dataspace '{}'",
+                            id.unwrap_or_default()
+                        ),
+                        reason: format!(
+                            "access token was issued for dataspace '{:?}'",
+                            machine.dataspace
+                        ),
+                        metadata: None,
+                    }));
+                }
+                Ok(machine.dataspace.clone())
+            }
+            Role::Human(_) => Ok(dataspace),
+        };
+    }
+
+    pub fn get(&self, id: Option<&str>) -> Result<&Dataspace, AppError> {
+        let id = self.resolve(id)?.unwrap_or_else(|| self.default_id.clone());
+
+        return Ok(&self.dataspaces[&id]);
+    }
+
+    pub fn satellite_provider(
+        &self,
+        id: Option<&str>,
+    ) -> Result<Arc<dyn SatelliteProvider>, AppError> {
+        return Ok(self.get(id)?.satellite_provider.clone());
+    }
+
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<String>, AppError> {
+        return match headers.get(DATASPACE_HEADER) {
+            None => Ok(None),
+            Some(value) => match value.to_str() {
+                Ok(id) => Ok(Some(id.to_owned())),
+                Err(_) => Err(AppError::Expected(ExpectedError {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message: format!("Invalid '{}' header", DATASPACE_HEADER),
+                    reason: format!("'{}' header is not valid ascii", DATASPACE_HEADER),
+                    metadata: None,
+                })),
+            },
+        };
+    }
+}
@@ -8,11 +8,13 @@ use ishare::delegation_evidence::{
 };
 use ishare::delegation_request::{DelegationRequest, Policy, PolicySet};
 use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
 
 use crate::db::policy::{self as policy_store, DelegationEvidencePolicy, MatchingPolicySetRow};
 use crate::error::AppError;
 use crate::TimeProvider;
 
+use super::federation::Federation;
 use super::ishare_provider::SatelliteProvider;
 
 pub fn is_contained_by<T: PartialEq>(vec_a: &Vec<T>, vec_b: &Vec<T>) -> bool {
@@ -175,24 +177,372 @@ pub fn get_delegation_evidence_policy_sets(
     return policy_sets;
 }
 
-pub fn check_delegation_access(
+fn is_permitting_policy(policy: &ishare::delegation_evidence::Policy) -> bool {
+    return policy.rules.first().is_some_and(|r| r.effect == "Permit");
+}
+
+// true when the evidence permits every requested policy
+pub fn permits_all(delegation_evidence: &DelegationEvidence) -> bool {
+    return !delegation_evidence.policy_sets.is_empty()
+        && delegation_evidence
+            .policy_sets
+            .iter()
+            .all(|ps| ps.policies.iter().all(is_permitting_policy));
+}
+
+// a requested target in the form of the targets of delegation evidence
+fn to_evidence_target(requested: &ishare::delegation_request::ResourceTarget) -> ResourceTarget {
+    return ResourceTarget {
+        resource: Resource {
+            resource_type: requested.resource.resource_type.clone(),
+            identifiers: requested.resource.identifiers.clone(),
+            attributes: requested.resource.attributes.clone(),
+        },
+        actions: requested.actions.clone(),
+        environment: requested.environment.as_ref().map(|e| {
+            ishare::delegation_evidence::Environment {
+                service_providers: e.service_providers.clone(),
+            }
+        }),
+    };
+}
+
+fn is_same_target(a: &ResourceTarget, b: &ResourceTarget) -> bool {
+    return a.resource.resource_type == b.resource.resource_type
+        && a.resource.identifiers == b.resource.identifiers
+        && a.resource.attributes == b.resource.attributes
+        && a.actions == b.actions
+        && a.environment.as_ref().map(|e| &e.service_providers)
+            == b.environment.as_ref().map(|e| &e.service_providers);
+}
+
+// replaces the policies the local evidence denies with the policies the evidence of the remote
+// registry of the policy issuer permits. Only the requested policies are taken over, and the
+// combined evidence is valid as long as both evidences are
+pub fn merge_remote_evidence(
+    de_container: &mut DelegationEvidenceContainer,
+    delegation_request: &DelegationRequest,
+    remote_evidence: DelegationEvidence,
+) {
+    let requested: Vec<ResourceTarget> = delegation_request
+        .policy_sets
+        .iter()
+        .flat_map(|ps| ps.policies.iter().map(|p| to_evidence_target(&p.target)))
+        .collect();
+
+    let remote_policies: Vec<(
+        &ishare::delegation_evidence::PolicySet,
+        &ishare::delegation_evidence::Policy,
+    )> = remote_evidence
+        .policy_sets
+        .iter()
+        .flat_map(|ps| ps.policies.iter().map(move |p| (ps, p)))
+        .filter(|(_, p)| {
+            is_permitting_policy(p) && requested.iter().any(|r| is_same_target(r, &p.target))
+        })
+        .collect();
+
+    let delegation_evidence = &mut de_container.delegation_evidence;
+    let mut merged = false;
+    for policy_set in delegation_evidence.policy_sets.iter_mut() {
+        // a policy set without any local permit is taken over from the remote evidence as a whole,
+        // otherwise the most restrictive delegation depth applies
+        let mut has_permits = policy_set.policies.iter().any(is_permitting_policy);
+
+        for policy in policy_set.policies.iter_mut() {
+            if is_permitting_policy(policy) {
+                continue;
+            }
+            let Some((remote_policy_set, remote_policy)) = remote_policies
+                .iter()
+                .find(|(_, p)| is_same_target(&p.target, &policy.target))
+            else {
+                continue;
+            };
+
+            *policy = (*remote_policy).clone();
+            merged = true;
+
+            let licenses = &mut policy_set.target.environment.licenses;
+            if has_permits {
+                policy_set.max_delegation_depth = policy_set
+                    .max_delegation_depth
+                    .min(remote_policy_set.max_delegation_depth);
+                for license in remote_policy_set.target.environment.licenses.iter() {
+                    if !licenses.contains(license) {
+                        licenses.push(license.clone());
+                    }
+                }
+            } else {
+                policy_set.max_delegation_depth = remote_policy_set.max_delegation_depth;
+                *licenses = remote_policy_set.target.environment.licenses.clone();
+                has_permits = true;
+            }
+        }
+    }
+
+    if merged {
+        delegation_evidence.not_on_or_after = delegation_evidence
+            .not_on_or_after
+            .min(remote_evidence.not_on_or_after);
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviousStepType {
+    ClientAssertion,
+    DelegationEvidence,
+    Unknown,
+}
+
+// the outcome of the verification of one entry of previous_steps
+#[derive(Serialize, Debug)]
+pub struct PreviousStepOutcome {
+    pub step: usize,
+    #[serde(rename = "type")]
+    pub step_type: PreviousStepType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DelegationAccess {
+    pub allowed: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub previous_steps: Vec<PreviousStepOutcome>,
+}
+
+// logged as data of the delegation request event. The outcome is only set when evidence is created
+#[derive(Serialize, Debug)]
+pub struct DelegationRequestData<'a> {
+    #[serde(flatten)]
+    pub access: &'a DelegationAccess,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<&'static str>,
+}
+
+pub fn get_outcome(delegation_evidence: &DelegationEvidence) -> &'static str {
+    return if permits_all(delegation_evidence) {
+        "Permit"
+    } else {
+        "Deny"
+    };
+}
+
+// the claims of a previous step that are needed to know how to verify it
+#[derive(Deserialize)]
+struct UnverifiedPreviousStep {
+    iss: String,
+    #[serde(rename = "delegationEvidence")]
+    delegation_evidence: Option<serde_json::Value>,
+}
+
+fn peek_previous_step(previous_step: &str) -> anyhow::Result<UnverifiedPreviousStep> {
+    let mut validation = jsonwebtoken::Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+
+    let token = jsonwebtoken::decode::<UnverifiedPreviousStep>(
+        previous_step,
+        &jsonwebtoken::DecodingKey::from_secret(&[]),
+        &validation,
+    )
+    .context("previous step is not a jwt")?;
+
+    return Ok(token.claims);
+}
+
+fn is_covered_by(
+    requested: &ishare::delegation_request::ResourceTarget,
+    target: &ResourceTarget,
+) -> bool {
+    return requested.resource.resource_type == target.resource.resource_type
+        && star_or_contained_by(
+            &requested.resource.identifiers,
+            &target.resource.identifiers,
+        )
+        && star_or_contained_by(&requested.resource.attributes, &target.resource.attributes)
+        && is_contained_by(&requested.actions, &target.actions)
+        && match &target.environment {
+            None => true,
+            Some(environment) => requested.environment.as_ref().is_some_and(|e| {
+                star_or_contained_by(&e.service_providers, &environment.service_providers)
+            }),
+        };
+}
+
+// a delegation evidence of the policy issuer or access subject, or of their remote registry,
+// that gives the requestor at least the requested rights
+async fn verify_previous_step_delegation_evidence(
+    now: chrono::DateTime<chrono::Utc>,
+    requester_company_id: &str,
+    delegation_request: &DelegationRequest,
+    delegation_token: &str,
+    issuer: &str,
+    satellite_provider: &dyn SatelliteProvider,
+    federation: &Federation,
+) -> anyhow::Result<()> {
+    let evidence = satellite_provider
+        .verify_delegation_token(now, delegation_token, issuer, Some(requester_company_id))
+        .await?;
+
+    if evidence.policy_issuer != delegation_request.policy_issuer
+        && evidence.policy_issuer != delegation_request.target.access_subject
+    {
+        anyhow::bail!(
+            "evidence is issued by '{}', who is neither policy issuer nor access subject",
+            evidence.policy_issuer
+        );
+    }
+
+    if issuer != evidence.policy_issuer
+        && federation.get_registry_eori(&evidence.policy_issuer) != Some(issuer)
+    {
+        anyhow::bail!(
+            "'{}' does not keep the policies of '{}'",
+            issuer,
+            evidence.policy_issuer
+        );
+    }
+
+    if evidence.target.access_subject != requester_company_id {
+        anyhow::bail!(
+            "evidence is for access subject '{}'",
+            evidence.target.access_subject
+        );
+    }
+
+    if evidence.not_before > now.timestamp() || evidence.not_on_or_after <= now.timestamp() {
+        anyhow::bail!(
+            "evidence is not valid, from {} until {}",
+            evidence.not_before,
+            evidence.not_on_or_after
+        );
+    }
+
+    let permitted: Vec<&ResourceTarget> = evidence
+        .policy_sets
+        .iter()
+        .flat_map(|ps| ps.policies.iter())
+        .filter(|p| is_permitting_policy(p))
+        .map(|p| &p.target)
+        .collect();
+    for policy in delegation_request
+        .policy_sets
+        .iter()
+        .flat_map(|ps| ps.policies.iter())
+    {
+        if !permitted.iter().any(|t| is_covered_by(&policy.target, t)) {
+            anyhow::bail!(
+                "evidence does not permit '{}' on resource type '{}'",
+                policy.target.actions.join(", "),
+                policy.target.resource.resource_type
+            );
+        }
+    }
+
+    return Ok(());
+}
+
+async fn verify_previous_step(
+    now: chrono::DateTime<chrono::Utc>,
+    requester_company_id: &str,
+    delegation_request: &DelegationRequest,
+    step: usize,
+    previous_step: &str,
+    satellite_provider: &dyn SatelliteProvider,
+    federation: &Federation,
+) -> PreviousStepOutcome {
+    let unverified = match peek_previous_step(previous_step) {
+        Ok(unverified) => unverified,
+        Err(e) => {
+            return PreviousStepOutcome {
+                step,
+                step_type: PreviousStepType::Unknown,
+                issuer: None,
+                valid: false,
+                reason: Some(format!("{:#}", e)),
+            };
+        }
+    };
+
+    let (step_type, result) = match unverified.delegation_evidence {
+        Some(_) => (
+            PreviousStepType::DelegationEvidence,
+            verify_previous_step_delegation_evidence(
+                now,
+                requester_company_id,
+                delegation_request,
+                previous_step,
+                &unverified.iss,
+                satellite_provider,
+                federation,
+            )
+            .await,
+        ),
+        None if unverified.iss != delegation_request.policy_issuer
+            && unverified.iss != delegation_request.target.access_subject =>
+        {
+            (
+                PreviousStepType::ClientAssertion,
+                Err(anyhow::anyhow!(
+                    "client assertion is issued by '{}', who is neither policy issuer nor access subject",
+                    unverified.iss
+                )),
+            )
+        }
+        None => (
+            PreviousStepType::ClientAssertion,
+            satellite_provider
+                .verify_previous_step_client_assertion(
+                    now,
+                    requester_company_id,
+                    previous_step,
+                    &unverified.iss,
+                )
+                .await,
+        ),
+    };
+
+    return PreviousStepOutcome {
+        step,
+        step_type,
+        issuer: Some(unverified.iss),
+        valid: result.is_ok(),
+        reason: result.err().map(|e| format!("{:#}", e)),
+    };
+}
+
+pub async fn check_delegation_access(
     now: chrono::DateTime<chrono::Utc>,
     requester_company_id: &str,
     delegation_request: &DelegationRequest,
     previous_steps: &Option<Vec<String>>,
     allows_service_provider_access: bool,
     satellite_provider: Arc<dyn SatelliteProvider>,
-) -> bool {
+    federation: &Federation,
+) -> DelegationAccess {
     tracing::info!("checking if requester is policy issuer or access subject");
 
+    let allowed = DelegationAccess {
+        allowed: true,
+        previous_steps: vec![],
+    };
+
     if requester_company_id == delegation_request.target.access_subject {
         tracing::info!("requester company is access subject. access allowed.");
-        return true;
+        return allowed;
     }
 
     if requester_company_id == delegation_request.policy_issuer {
         tracing::info!("requester company is policy issuer. access allows.");
-        return true;
+        return allowed;
     }
 
     if allows_service_provider_access {
@@ -212,37 +562,46 @@ pub fn check_delegation_access(
                 .iter()
                 .all(|sp| sp == requester_company_id)
         {
-            return true;
+            return allowed;
         }
     }
 
     tracing::info!("checking if previous steps gives access");
-    if let Some(previous_steps) = previous_steps {
-        match previous_steps.get(0) {
-            None => {
-                tracing::info!("previous steps is empty");
-            }
-            Some(previous_step_client_assertion) => {
-                if satellite_provider.handle_previous_step_client_assertion(
-                    now,
-                    requester_company_id,
-                    &previous_step_client_assertion,
-                    &delegation_request.policy_issuer,
-                    &delegation_request.target.access_subject,
-                ) {
-                    return true;
-                }
-            }
-        };
+    let mut outcomes = vec![];
+    for (step, previous_step) in previous_steps.iter().flatten().enumerate() {
+        let outcome = verify_previous_step(
+            now,
+            requester_company_id,
+            delegation_request,
+            step,
+            previous_step,
+            satellite_provider.as_ref(),
+            federation,
+        )
+        .await;
+
+        if !outcome.valid {
+            tracing::info!(
+                "previous step {} is not valid: {}",
+                step,
+                outcome.reason.as_deref().unwrap_or_default()
+            );
+        }
+        outcomes.push(outcome);
     }
 
-    false
+    // every previous step has to be valid
+    return DelegationAccess {
+        allowed: !outcomes.is_empty() && outcomes.iter().all(|o| o.valid),
+        previous_steps: outcomes,
+    };
 }
 
 pub async fn create_delegation_evidence(
     delegation_request: &DelegationRequest,
     time_provider: std::sync::Arc<dyn TimeProvider>,
     de_expiry_seconds: i64,
+    dataspace: Option<String>,
     db: &DatabaseConnection,
 ) -> Result<DelegationEvidenceContainer, AppError> {
     tracing::info!(
@@ -254,6 +613,7 @@ pub async fn create_delegation_evidence(
     let de_policy_sets = policy_store::get_policy_sets_with_policies_for_creating_de(
         delegation_request.target.access_subject.to_owned(),
         delegation_request.policy_issuer.to_owned(),
+        dataspace,
         &db,
     )
     .await
@@ -284,12 +644,16 @@ mod tests {
     };
     use uuid::Uuid;
 
-    use crate::test_helpers::helpers::TestSatelliteProvider;
+    use crate::test_helpers::helpers::{
+        create_test_client_assertion, create_test_delegation_token, get_test_federation,
+        FakeTimeProvider, TestSatelliteProvider,
+    };
+    use crate::TimeProvider;
 
     use super::*;
 
-    #[test]
-    fn test_check_delegation_access_as_match() {
+    #[tokio::test]
+    async fn test_check_delegation_access_as_match() {
         assert_eq!(
             check_delegation_access(
                 chrono::Utc::now(),
@@ -303,14 +667,17 @@ mod tests {
                 },
                 &None,
                 false,
-                Arc::new(TestSatelliteProvider {})
-            ),
+                Arc::new(TestSatelliteProvider {}),
+                &get_test_federation(Arc::default())
+            )
+            .await
+            .allowed,
             true
         );
     }
 
-    #[test]
-    fn test_check_delegation_access_pi_match() {
+    #[tokio::test]
+    async fn test_check_delegation_access_pi_match() {
         assert_eq!(
             check_delegation_access(
                 chrono::Utc::now(),
@@ -324,14 +691,17 @@ mod tests {
                 },
                 &None,
                 false,
-                Arc::new(TestSatelliteProvider {})
-            ),
+                Arc::new(TestSatelliteProvider {}),
+                &get_test_federation(Arc::default())
+            )
+            .await
+            .allowed,
             true
         );
     }
 
-    #[test]
-    fn test_check_delegation_access_service_providers_empty() {
+    #[tokio::test]
+    async fn test_check_delegation_access_service_providers_empty() {
         assert_eq!(
             check_delegation_access(
                 chrono::Utc::now(),
@@ -345,14 +715,17 @@ mod tests {
                 },
                 &None,
                 true,
-                Arc::new(TestSatelliteProvider {})
-            ),
+                Arc::new(TestSatelliteProvider {}),
+                &get_test_federation(Arc::default())
+            )
+            .await
+            .allowed,
             false
         );
     }
 
-    #[test]
-    fn test_check_delegation_access_service_providers_no_match() {
+    #[tokio::test]
+    async fn test_check_delegation_access_service_providers_no_match() {
         assert_eq!(
             check_delegation_access(
                 chrono::Utc::now(),
@@ -381,14 +754,17 @@ mod tests {
                 },
                 &None,
                 true,
-                Arc::new(TestSatelliteProvider {})
-            ),
+                Arc::new(TestSatelliteProvider {}),
+                &get_test_federation(Arc::default())
+            )
+            .await
+            .allowed,
             false
         );
     }
 
-    #[test]
-    fn test_check_delegation_access_service_providers_match() {
+    #[tokio::test]
+    async fn test_check_delegation_access_service_providers_match() {
         assert_eq!(
             check_delegation_access(
                 chrono::Utc::now(),
@@ -417,8 +793,11 @@ mod tests {
                 },
                 &None,
                 true,
-                Arc::new(TestSatelliteProvider {})
-            ),
+                Arc::new(TestSatelliteProvider {}),
+                &get_test_federation(Arc::default())
+            )
+            .await
+            .allowed,
             true
         );
     }
@@ -661,6 +1040,7 @@ mod tests {
     #[test]
     fn test_mask_matching_policy_sets_match() {
         let matching_policy_set_rows = vec![MatchingPolicySetRow {
+            dataspace: None,
             access_subject: "as".to_owned(),
             licenses: vec![],
             policy_set_id: Uuid::new_v4(),
@@ -705,6 +1085,7 @@ mod tests {
     #[test]
     fn test_mask_matching_policy_sets_no_match() {
         let matching_policy_set_rows = vec![MatchingPolicySetRow {
+            dataspace: None,
             access_subject: "as".to_owned(),
             licenses: vec![],
             policy_set_id: Uuid::new_v4(),
@@ -749,6 +1130,7 @@ mod tests {
     #[test]
     fn test_is_permit_permit() {
         let matching_policy_set_row = MatchingPolicySetRow {
+            dataspace: None,
             access_subject: "as".to_owned(),
             licenses: vec![],
             policy_set_id: Uuid::new_v4(),
@@ -791,6 +1173,7 @@ mod tests {
     #[test]
     fn test_is_permit_deny() {
         let matching_policy_set_row = MatchingPolicySetRow {
+            dataspace: None,
             access_subject: "as".to_owned(),
             licenses: vec![],
             policy_set_id: Uuid::new_v4(),
@@ -842,6 +1225,7 @@ mod tests {
     #[test]
     fn test_get_delegation_evidence_policy_sets() {
         let matching_policy_set_rows = vec![MatchingPolicySetRow {
+            dataspace: None,
             access_subject: "as".to_owned(),
             licenses: vec![],
             policy_set_id: Uuid::new_v4(),
@@ -905,6 +1289,7 @@ mod tests {
     #[test]
     fn test_get_delegation_evidence_policy_sets_deny() {
         let matching_policy_set_rows = vec![MatchingPolicySetRow {
+            dataspace: None,
             access_subject: "as".to_owned(),
             licenses: vec![],
             policy_set_id: Uuid::new_v4(),
@@ -981,6 +1366,7 @@ mod tests {
     fn test_get_delegation_evidence_policy_sets_cartesian() {
         let matching_policy_set_rows = vec![
             MatchingPolicySetRow {
+                dataspace: None,
                 access_subject: "as".to_owned(),
                 licenses: vec![],
                 policy_set_id: Uuid::new_v4(),
@@ -997,6 +1383,7 @@ mod tests {
                 }],
             },
             MatchingPolicySetRow {
+                dataspace: None,
                 access_subject: "as".to_owned(),
                 licenses: vec![],
                 policy_set_id: Uuid::new_v4(),
@@ -1064,4 +1451,305 @@ mod tests {
 
         assert_eq!(policy_sets.len(), 4)
     }
+
+    fn get_previous_steps_request(policy_issuer: &str) -> DelegationRequest {
+        return serde_json::from_value(serde_json::json!({
+            "policyIssuer": policy_issuer,
+            "target": { "accessSubject": "NL.44444" },
+            "policySets": [{
+                "policies": [{
+                    "target": {
+                        "resource": {
+                            "type": "TestResource",
+                            "identifiers": ["test4"],
+                            "attributes": ["zingers"]
+                        },
+                        "actions": ["Read"],
+                        "environment": { "serviceProviders": ["NL.SP"] }
+                    },
+                    "rules": [{ "effect": "Permit" }]
+                }]
+            }]
+        }))
+        .unwrap();
+    }
+
+    fn get_previous_step_evidence(
+        policy_issuer: &str,
+        access_subject: &str,
+        actions: Vec<&str>,
+    ) -> DelegationEvidence {
+        let now = FakeTimeProvider {}.now().timestamp();
+        return serde_json::from_value(serde_json::json!({
+            "notBefore": now - 60,
+            "notOnOrAfter": now + 600,
+            "policyIssuer": policy_issuer,
+            "target": { "accessSubject": access_subject },
+            "policySets": [{
+                "maxDelegationDepth": 1,
+                "target": { "environment": { "licenses": [] } },
+                "policies": [{
+                    "target": {
+                        "resource": {
+                            "type": "TestResource",
+                            "identifiers": ["*"],
+                            "attributes": ["*"]
+                        },
+                        "actions": actions
+                    },
+                    "rules": [{ "effect": "Permit" }]
+                }]
+            }]
+        }))
+        .unwrap();
+    }
+
+    async fn check_previous_steps(
+        delegation_request: &DelegationRequest,
+        previous_steps: Vec<String>,
+    ) -> DelegationAccess {
+        return check_delegation_access(
+            FakeTimeProvider {}.now(),
+            "NL.REQUESTER",
+            delegation_request,
+            &Some(previous_steps),
+            false,
+            Arc::new(TestSatelliteProvider {}),
+            &get_test_federation(Arc::default()),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_check_delegation_access_previous_step_client_assertion() {
+        let delegation_request = get_previous_steps_request("NL.24244");
+
+        let access = check_previous_steps(
+            &delegation_request,
+            vec![create_test_client_assertion("NL.24244", "NL.REQUESTER")],
+        )
+        .await;
+        assert!(access.allowed);
+        assert_eq!(
+            access.previous_steps[0].step_type,
+            PreviousStepType::ClientAssertion
+        );
+        assert_eq!(access.previous_steps[0].issuer.as_deref(), Some("NL.24244"));
+
+        // issued to another party
+        let access = check_previous_steps(
+            &delegation_request,
+            vec![create_test_client_assertion("NL.44444", "NL.OTHER")],
+        )
+        .await;
+        assert!(!access.allowed);
+
+        // issued by a party that is neither policy issuer nor access subject
+        let access = check_previous_steps(
+            &delegation_request,
+            vec![create_test_client_assertion("NL.OTHER", "NL.REQUESTER")],
+        )
+        .await;
+        assert!(!access.allowed);
+        assert_eq!(access.previous_steps[0].issuer.as_deref(), Some("NL.OTHER"));
+
+        let access = check_previous_steps(&delegation_request, vec![]).await;
+        assert!(!access.allowed);
+    }
+
+    #[tokio::test]
+    async fn test_check_delegation_access_previous_steps_all_verified() {
+        let delegation_request = get_previous_steps_request("NL.24244");
+
+        let access = check_previous_steps(
+            &delegation_request,
+            vec![
+                create_test_client_assertion("NL.24244", "NL.REQUESTER"),
+                "not a token".to_owned(),
+            ],
+        )
+        .await;
+        assert!(!access.allowed);
+        assert_eq!(access.previous_steps.len(), 2);
+        assert!(access.previous_steps[0].valid);
+        assert!(!access.previous_steps[1].valid);
+        assert_eq!(access.previous_steps[1].step, 1);
+        assert_eq!(
+            access.previous_steps[1].step_type,
+            PreviousStepType::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_delegation_access_previous_step_delegation_evidence() {
+        let delegation_request = get_previous_steps_request("NL.REMOTE_ISSUER");
+        let check = |previous_step: String| {
+            let delegation_request = delegation_request.clone();
+            async move { check_previous_steps(&delegation_request, vec![previous_step]).await }
+        };
+
+        // issued by the remote registry of the policy issuer
+        let access = check(create_test_delegation_token(
+            "NL.REMOTE_AR",
+            Some("NL.REQUESTER"),
+            get_previous_step_evidence("NL.REMOTE_ISSUER", "NL.REQUESTER", vec!["Read"]),
+        ))
+        .await;
+        assert!(access.allowed);
+        assert_eq!(
+            access.previous_steps[0].step_type,
+            PreviousStepType::DelegationEvidence
+        );
+
+        // issued by the access subject itself
+        let access = check(create_test_delegation_token(
+            "NL.44444",
+            Some("NL.REQUESTER"),
+            get_previous_step_evidence("NL.44444", "NL.REQUESTER", vec!["Read", "Edit"]),
+        ))
+        .await;
+        assert!(access.allowed);
+
+        // the requested rights are not covered
+        let access = check(create_test_delegation_token(
+            "NL.REMOTE_AR",
+            Some("NL.REQUESTER"),
+            get_previous_step_evidence("NL.REMOTE_ISSUER", "NL.REQUESTER", vec!["Edit"]),
+        ))
+        .await;
+        assert!(!access.allowed);
+
+        // a registry that does not keep the policies of the policy issuer
+        let access = check(create_test_delegation_token(
+            "NL.OTHER_AR",
+            Some("NL.REQUESTER"),
+            get_previous_step_evidence("NL.REMOTE_ISSUER", "NL.REQUESTER", vec!["Read"]),
+        ))
+        .await;
+        assert!(!access.allowed);
+
+        // the evidence is for another party
+        let access = check(create_test_delegation_token(
+            "NL.REMOTE_AR",
+            Some("NL.REQUESTER"),
+            get_previous_step_evidence("NL.REMOTE_ISSUER", "NL.OTHER", vec!["Read"]),
+        ))
+        .await;
+        assert!(!access.allowed);
+
+        // the token is issued to another party
+        let access = check(create_test_delegation_token(
+            "NL.REMOTE_AR",
+            Some("NL.OTHER"),
+            get_previous_step_evidence("NL.REMOTE_ISSUER", "NL.REQUESTER", vec!["Read"]),
+        ))
+        .await;
+        assert!(!access.allowed);
+    }
+
+    #[test]
+    fn test_merge_remote_evidence() {
+        let policy = |resource_type: &str, effect: &str| {
+            serde_json::json!({
+                "target": {
+                    "resource": {
+                        "type": resource_type,
+                        "identifiers": ["*"],
+                        "attributes": ["*"]
+                    },
+                    "actions": ["Read"]
+                },
+                "rules": [{ "effect": effect }]
+            })
+        };
+        let evidence = |not_on_or_after: i64, policies: Vec<serde_json::Value>| {
+            serde_json::from_value::<DelegationEvidence>(serde_json::json!({
+                "notBefore": 0,
+                "notOnOrAfter": not_on_or_after,
+                "policyIssuer": "pi",
+                "target": { "accessSubject": "as" },
+                "policySets": [{
+                    "maxDelegationDepth": 1,
+                    "target": { "environment": { "licenses": [] } },
+                    "policies": policies
+                }]
+            }))
+            .unwrap()
+        };
+        let delegation_request: DelegationRequest = serde_json::from_value(serde_json::json!({
+            "policyIssuer": "pi",
+            "target": { "accessSubject": "as" },
+            "policySets": [{ "policies": [policy("resource", "Permit")] }]
+        }))
+        .unwrap();
+
+        let mut de_container = DelegationEvidenceContainer {
+            delegation_evidence: evidence(200, vec![policy("resource", "Deny")]),
+        };
+        assert!(!permits_all(&de_container.delegation_evidence));
+
+        // a remote evidence that does not permit the request changes nothing
+        merge_remote_evidence(
+            &mut de_container,
+            &delegation_request,
+            evidence(100, vec![policy("resource", "Deny")]),
+        );
+        assert_eq!(de_container.delegation_evidence.not_on_or_after, 200);
+        assert!(!permits_all(&de_container.delegation_evidence));
+
+        merge_remote_evidence(
+            &mut de_container,
+            &delegation_request,
+            evidence(
+                100,
+                vec![policy("resource", "Permit"), policy("other", "Permit")],
+            ),
+        );
+        let delegation_evidence = &de_container.delegation_evidence;
+        assert!(permits_all(delegation_evidence));
+        assert_eq!(delegation_evidence.not_on_or_after, 100);
+        assert_eq!(delegation_evidence.policy_sets.len(), 1);
+        // policies that were not requested are left out
+        assert_eq!(delegation_evidence.policy_sets[0].policies.len(), 1);
+        assert_eq!(
+            delegation_evidence.policy_sets[0].policies[0]
+                .target
+                .resource
+                .resource_type,
+            "resource"
+        );
+
+        // a local policy set that permits part of the request gets the remote permits in place of
+        // its denies
+        let delegation_request: DelegationRequest = serde_json::from_value(serde_json::json!({
+            "policyIssuer": "pi",
+            "target": { "accessSubject": "as" },
+            "policySets": [{
+                "policies": [policy("resource", "Permit"), policy("other", "Permit")]
+            }]
+        }))
+        .unwrap();
+        let mut de_container = DelegationEvidenceContainer {
+            delegation_evidence: evidence(
+                200,
+                vec![policy("resource", "Permit"), policy("other", "Deny")],
+            ),
+        };
+        merge_remote_evidence(
+            &mut de_container,
+            &delegation_request,
+            evidence(
+                100,
+                vec![policy("resource", "Deny"), policy("other", "Permit")],
+            ),
+        );
+        let delegation_evidence = &de_container.delegation_evidence;
+        assert!(permits_all(delegation_evidence));
+        assert_eq!(delegation_evidence.not_on_or_after, 100);
+        assert_eq!(delegation_evidence.policy_sets.len(), 1);
+        let policies = &delegation_evidence.policy_sets[0].policies;
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies[0].target.resource.resource_type, "resource");
+        assert_eq!(policies[1].target.resource.resource_type, "other");
+    }
 }
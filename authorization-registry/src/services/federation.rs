@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use ishare::{delegation_evidence::DelegationEvidence, delegation_request::DelegationRequest};
+
+use crate::config::{FederationConfig, RemoteRegistryConfig};
+
+use super::ishare_provider::SatelliteProvider;
+
+// an authorization registry that keeps the policies of some policy issuers
+#[async_trait]
+pub trait RemoteRegistry: Send + Sync {
+    async fn request_delegation_token(
+        &self,
+        delegation_request: &DelegationRequest,
+    ) -> anyhow::Result<String>;
+}
+
+#[derive(Default)]
+struct CircuitBreaker {
+    failures: u32,
+    open_until: Option<DateTime<Utc>>,
+}
+
+struct FederatedRegistry {
+    id: String,
+    eori: String,
+    registry: Arc<dyn RemoteRegistry>,
+    circuit: Mutex<CircuitBreaker>,
+}
+
+// forwards delegation requests to the remote registry of the policy issuer
+pub struct Federation {
+    timeout: std::time::Duration,
+    failure_threshold: u32,
+    open_duration: TimeDelta,
+    policy_issuers: BTreeMap<String, Arc<FederatedRegistry>>,
+}
+
+impl Federation {
+    pub fn new(
+        config: &FederationConfig,
+        registries: Vec<(RemoteRegistryConfig, Arc<dyn RemoteRegistry>)>,
+    ) -> anyhow::Result<Self> {
+        let mut policy_issuers = BTreeMap::new();
+
+        for (registry_config, registry) in registries {
+            let federated = Arc::new(FederatedRegistry {
+                id: registry_config.id.clone(),
+                eori: registry_config.eori.clone(),
+                registry,
+                circuit: Mutex::new(CircuitBreaker::default()),
+            });
+
+            for policy_issuer in registry_config.policy_issuers.iter() {
+                if policy_issuers
+                    .insert(policy_issuer.clone(), federated.clone())
+                    .is_some()
+                {
+                    anyhow::bail!(
+                        "policy issuer '{}' is configured for more than one remote registry",
+                        policy_issuer
+                    );
+                }
+            }
+        }
+
+        return Ok(Self {
+            timeout: std::time::Duration::from_millis(config.timeout_ms),
+            failure_threshold: config.failure_threshold,
+            open_duration: TimeDelta::seconds(config.open_seconds),
+            policy_issuers,
+        });
+    }
+
+    pub fn get_policy_issuers(&self) -> Vec<String> {
+        return self.policy_issuers.keys().cloned().collect();
+    }
+
+    // the eori of the remote registry that keeps the policies of the policy issuer
+    pub fn get_registry_eori(&self, policy_issuer: &str) -> Option<&str> {
+        return self
+            .policy_issuers
+            .get(policy_issuer)
+            .map(|registry| registry.eori.as_str());
+    }
+
+    async fn request_evidence(
+        &self,
+        now: DateTime<Utc>,
+        registry: &FederatedRegistry,
+        delegation_request: &DelegationRequest,
+        satellite_provider: &dyn SatelliteProvider,
+    ) -> anyhow::Result<DelegationEvidence> {
+        let delegation_token = tokio::time::timeout(
+            self.timeout,
+            registry
+                .registry
+                .request_delegation_token(delegation_request),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("no response within {:?}", self.timeout))??;
+
+        let evidence = satellite_provider
+            .verify_delegation_token(now, &delegation_token, &registry.eori, None)
+            .await?;
+
+        if evidence.policy_issuer != delegation_request.policy_issuer
+            || evidence.target.access_subject != delegation_request.target.access_subject
+        {
+            anyhow::bail!(
+                "evidence is for policy issuer '{}' and access subject '{}'",
+                evidence.policy_issuer,
+                evidence.target.access_subject
+            );
+        }
+
+        if evidence.not_before > now.timestamp() || evidence.not_on_or_after <= now.timestamp() {
+            anyhow::bail!(
+                "evidence is not valid, from {} until {}",
+                evidence.not_before,
+                evidence.not_on_or_after
+            );
+        }
+
+        return Ok(evidence);
+    }
+
+    // the verified evidence of the remote registry of the policy issuer. None when the policy
+    // issuer keeps its policies here or the remote registry can not be used
+    pub async fn get_remote_evidence(
+        &self,
+        now: DateTime<Utc>,
+        delegation_request: &DelegationRequest,
+        satellite_provider: &dyn SatelliteProvider,
+    ) -> Option<DelegationEvidence> {
+        let registry = self.policy_issuers.get(&delegation_request.policy_issuer)?;
+
+        if let Some(open_until) = registry.circuit.lock().unwrap().open_until {
+            if open_until > now {
+                tracing::warn!(
+                    "not asking remote registry '{}' for delegation evidence until {}",
+                    registry.id,
+                    open_until
+                );
+                return None;
+            }
+        }
+
+        let result = self
+            .request_evidence(now, registry, delegation_request, satellite_provider)
+            .await;
+
+        let mut circuit = registry.circuit.lock().unwrap();
+        return match result {
+            Ok(evidence) => {
+                tracing::info!(
+                    "received delegation evidence of '{}' from remote registry '{}'",
+                    delegation_request.policy_issuer,
+                    registry.id
+                );
+                *circuit = CircuitBreaker::default();
+                Some(evidence)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "error requesting delegation evidence from remote registry '{}': {:?}",
+                    registry.id,
+                    e
+                );
+                circuit.failures += 1;
+                if circuit.failures >= self.failure_threshold {
+                    circuit.open_until = Some(now + self.open_duration);
+                }
+                None
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        test_helpers::helpers::{
+            get_test_federation, FakeTimeProvider, TestRemoteRegistry, TestSatelliteProvider,
+        },
+        TimeProvider,
+    };
+
+    use super::*;
+
+    fn get_delegation_request(policy_issuer: &str) -> DelegationRequest {
+        return serde_json::from_value(serde_json::json!({
+            "policyIssuer": policy_issuer,
+            "target": { "accessSubject": "NL.44444" },
+            "policySets": [{
+                "policies": [{
+                    "target": {
+                        "resource": {
+                            "type": "TestResource",
+                            "identifiers": ["test4"],
+                            "attributes": ["*"]
+                        },
+                        "actions": ["Read"]
+                    },
+                    "rules": [{ "effect": "Permit" }]
+                }]
+            }]
+        }))
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_remote_evidence() {
+        let registry = Arc::new(TestRemoteRegistry::default());
+        let federation = get_test_federation(registry.clone());
+        let now = FakeTimeProvider::new().now();
+
+        let evidence = federation
+            .get_remote_evidence(
+                now,
+                &get_delegation_request("NL.REMOTE_ISSUER"),
+                &TestSatelliteProvider {},
+            )
+            .await
+            .unwrap();
+        assert_eq!(evidence.policy_issuer, "NL.REMOTE_ISSUER");
+        assert_eq!(
+            evidence.policy_sets[0].policies[0].rules[0].effect,
+            "Permit"
+        );
+
+        // policy issuers that keep their policies here
+        assert!(federation
+            .get_remote_evidence(
+                now,
+                &get_delegation_request("NL.24244"),
+                &TestSatelliteProvider {}
+            )
+            .await
+            .is_none());
+        assert_eq!(*registry.calls.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_remote_evidence_wrong_issuer() {
+        let federation = Federation::new(
+            &FederationConfig::default(),
+            vec![(
+                RemoteRegistryConfig {
+                    id: "remote".to_owned(),
+                    url: "https://remote.example".to_owned(),
+                    eori: "NL.OTHER_AR".to_owned(),
+                    policy_issuers: vec!["NL.REMOTE_ISSUER".to_owned()],
+                },
+                Arc::new(TestRemoteRegistry::default()) as Arc<dyn RemoteRegistry>,
+            )],
+        )
+        .unwrap();
+
+        // the evidence is signed by "NL.REMOTE_AR"
+        assert!(federation
+            .get_remote_evidence(
+                FakeTimeProvider::new().now(),
+                &get_delegation_request("NL.REMOTE_ISSUER"),
+                &TestSatelliteProvider {}
+            )
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_remote_evidence_circuit_breaker() {
+        let registry = Arc::new(TestRemoteRegistry::default());
+        let federation = get_test_federation(registry.clone());
+        let now = FakeTimeProvider::new().now();
+        let request = get_delegation_request("NL.REMOTE_ISSUER");
+        let get = |now| federation.get_remote_evidence(now, &request, &TestSatelliteProvider {});
+
+        *registry.fail.lock().unwrap() = true;
+        assert!(get(now).await.is_none());
+        assert!(get(now).await.is_none());
+        // the registry is not asked after two failures in a row
+        assert!(get(now).await.is_none());
+        assert_eq!(*registry.calls.lock().unwrap(), 2);
+
+        *registry.fail.lock().unwrap() = false;
+        assert!(get(now + TimeDelta::seconds(30)).await.is_none());
+        assert!(get(now + TimeDelta::seconds(61)).await.is_some());
+        assert_eq!(*registry.calls.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_remote_evidence_timeout() {
+        let registry = Arc::new(TestRemoteRegistry::default());
+        *registry.delay.lock().unwrap() = Some(std::time::Duration::from_secs(2));
+        let federation = get_test_federation(registry.clone());
+
+        assert!(federation
+            .get_remote_evidence(
+                FakeTimeProvider::new().now(),
+                &get_delegation_request("NL.REMOTE_ISSUER"),
+                &TestSatelliteProvider {}
+            )
+            .await
+            .is_none());
+    }
+}
@@ -0,0 +1,102 @@
+use axum::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, ExpectedError};
+
+#[derive(Serialize)]
+pub struct OAuthRequestForm {
+    pub response_type: String,
+    pub scope: String,
+    // signed or encrypted request object, only used by iSHARE identity providers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+    pub client_id: String,
+    pub state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_challenge: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_challenge_method: Option<String>,
+}
+
+// a single login attempt, stored by the authorization registry until the callback
+pub struct AuthorizationRequest {
+    pub state: String,
+    pub nonce: String,
+    // PKCE code verifier, only its hash is sent to the identity provider
+    pub code_verifier: String,
+}
+
+impl AuthorizationRequest {
+    pub fn code_challenge(&self) -> String {
+        return URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()));
+    }
+
+    pub fn validate_nonce(&self, nonce: Option<&str>) -> Result<(), AppError> {
+        return match nonce {
+            Some(nonce) if nonce == self.nonce => Ok(()),
+            Some(_) => Err(login_failed("Nonce of id_token does not match".to_owned())),
+            None => Err(login_failed("id_token has no nonce".to_owned())),
+        };
+    }
+}
+
+// user as described by the identity provider after a successful login
+pub struct IdpUser {
+    pub sub: String,
+    pub email: String,
+    pub fullname: String,
+    pub company_id: String,
+    pub company_name: String,
+    pub roles: Vec<String>,
+    // eori of an iSHARE identity provider or the issuer of an OIDC identity provider
+    pub idp_id: String,
+    pub idp_url: String,
+}
+
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    // url the browser is redirected to for logging in
+    async fn get_authorization_url(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<String>;
+
+    // parameters the frontend posts to the authorization endpoint
+    async fn get_authorization_form(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<OAuthRequestForm>;
+
+    async fn get_authorization_endpoint(&self) -> anyhow::Result<String>;
+
+    // exchanges the authorization code of the callback for the logged in user. Implementations
+    // send the code verifier of `request` and check the nonce of the id_token against it
+    async fn handle_callback(
+        &self,
+        server_url: &str,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<IdpUser, AppError>;
+}
+
+pub fn login_failed(reason: String) -> AppError {
+    return AppError::Expected(ExpectedError {
+        status_code: StatusCode::UNAUTHORIZED,
+        message: "Login at identity provider failed".to_owned(),
+        reason,
+        metadata: None,
+    });
+}
+
+pub fn get_callback_url(server_base_url: &str) -> String {
+    return format!("{server_base_url}/connect/human/auth/code");
+}
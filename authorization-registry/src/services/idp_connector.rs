@@ -1,6 +1,5 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use textnonce::TextNonce;
 
 #[derive(Clone)]
 pub struct IdpConnector {
@@ -17,6 +16,8 @@ pub struct AuthRequestClaims {
     response_type: String,
     state: String,
     nonce: String,
+    code_challenge: String,
+    code_challenge_method: String,
     acr_values: String,
     language: Option<String>,
 }
@@ -40,11 +41,16 @@ impl IdpConnector {
         return format!("{idp_url}/protocol/openid-connect/auth");
     }
 
-    pub fn generate_auth_url(&self, client_assertion: &str, state: &str) -> String {
+    pub fn generate_auth_url(
+        &self,
+        client_assertion: &str,
+        state: &str,
+        code_challenge: &str,
+    ) -> String {
         let idp_url = self.idp_url.clone();
         let client_id = self.client_id.clone();
         let encoded_state = urlencoding::encode(state);
-        let url = format!("{idp_url}/protocol/openid-connect/auth?response_type=code&scope=openid+iSHARE&client_id={client_id}&request={client_assertion}&state={encoded_state}");
+        let url = format!("{idp_url}/protocol/openid-connect/auth?response_type=code&scope=openid+iSHARE&client_id={client_id}&request={client_assertion}&state={encoded_state}&code_challenge={code_challenge}&code_challenge_method=S256");
 
         return url;
     }
@@ -52,15 +58,12 @@ impl IdpConnector {
     pub fn get_auth_request_claims(
         &self,
         server_base_url: &str,
-        callback_url: &str,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
     ) -> AuthRequestClaims {
         let redirect_uri = self.get_redirect_uri(server_base_url);
 
-        let textnonce = TextNonce::sized_urlsafe(32).unwrap();
-
-        // to-do: generate random nonce
-        //let nonce = textnonce.to_string();
-        let nonce = textnonce.to_string();
         // once of: urn:http://eidas.europa.eu/LoA/NotNotified/low, urn:http://eidas.europa.eu/LoA/NotNotified/substantial or urn:http://eidas.europa.eu/LoA/NotNotified/high,
         //let acr_values = "";
         //let acr_values = "urn:http://eidas.europa.eu/LoA/NotNotified/substantial";
@@ -72,8 +75,10 @@ impl IdpConnector {
             scope: "openid iSHARE".to_owned(),
             redirect_uri: redirect_uri.to_owned(),
             response_type: "code".to_owned(),
-            state: callback_url.to_owned(),
+            state: state.to_owned(),
             nonce: nonce.to_owned(),
+            code_challenge: code_challenge.to_owned(),
+            code_challenge_method: "S256".to_owned(),
             acr_values: acr_values.to_owned(),
             language: Some(language.to_owned()),
         };
@@ -88,6 +93,7 @@ impl IdpConnector {
         &self,
         server_base_url: &str,
         code: &str,
+        code_verifier: &str,
         client_assertion: &str,
     ) -> anyhow::Result<TokenResponse> {
         let idp_url = self.idp_url.clone();
@@ -99,6 +105,7 @@ impl IdpConnector {
             ("redirect_uri", redirect_uri.as_str()),
             ("client_id", &self.client_id),
             ("code", code),
+            ("code_verifier", code_verifier),
             ("client_assertion", client_assertion),
             (
                 "client_assertion_type",
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::async_trait;
+use ishare::ishare::{CertificatesOrSpor, ISHARE};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+use super::{
+    identity_provider::{AuthorizationRequest, IdentityProvider, IdpUser, OAuthRequestForm},
+    idp_connector::{AuthRequestClaims, IdpConnector},
+    ishare_provider::SatelliteProvider,
+};
+
+#[derive(Deserialize)]
+struct RealmAccess {
+    pub roles: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    pub realm_access: RealmAccess,
+    pub company_id: String,
+    pub company_name: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub nonce: Option<String>,
+}
+
+// identity provider that is an iSHARE party, authenticated with iSHARE client assertions
+pub struct IShareIdentityProvider {
+    ishare: Arc<ISHARE>,
+    satellite_provider: Arc<dyn SatelliteProvider>,
+    idp_connector: IdpConnector,
+}
+
+impl IShareIdentityProvider {
+    pub fn new(
+        ishare: Arc<ISHARE>,
+        satellite_provider: Arc<dyn SatelliteProvider>,
+        idp_connector: IdpConnector,
+    ) -> Self {
+        return Self {
+            ishare,
+            satellite_provider,
+            idp_connector,
+        };
+    }
+
+    fn get_auth_request_claims(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> AuthRequestClaims {
+        return self.idp_connector.get_auth_request_claims(
+            server_url,
+            &request.state,
+            &request.nonce,
+            &request.code_challenge(),
+        );
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for IShareIdentityProvider {
+    async fn get_authorization_url(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<String> {
+        let auth_claims = self.get_auth_request_claims(server_url, request);
+        let client_assertion = self
+            .ishare
+            .create_client_assertion_with_extra_claims(
+                self.idp_connector.idp_eori.clone(),
+                auth_claims,
+            )
+            .context("Error creating client assertion")?;
+
+        let url = self.idp_connector.generate_auth_url(
+            &client_assertion,
+            &request.state,
+            &request.code_challenge(),
+        );
+
+        Ok(url)
+    }
+
+    async fn get_authorization_form(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<OAuthRequestForm> {
+        let auth_claims = self.get_auth_request_claims(server_url, request);
+
+        let idp_eori = self.idp_connector.idp_eori.clone();
+        let now = chrono::Utc::now();
+
+        let idp_info = self
+            .satellite_provider
+            .validate_party(now, idp_eori.as_str())
+            .await
+            .context("Error getting party info IDP")?;
+
+        let idp_cert = match idp_info.certificates_or_spor {
+            CertificatesOrSpor::Spor(_) => {
+                anyhow::bail!("Error: IDP should have certificate instead of spor");
+            }
+            CertificatesOrSpor::Certificates(certificates) => certificates
+                .into_iter()
+                .next()
+                .context("No certificate found for IDP")?,
+        };
+
+        let encrypted_client_assertion = self
+            .ishare
+            .create_client_assertion_with_extra_claims_encrypted(
+                self.idp_connector.idp_eori.clone(),
+                auth_claims,
+                &idp_cert,
+            )
+            .context("Error creating encyrpted client assertion")?;
+
+        let oauth_params = OAuthRequestForm {
+            response_type: "code".to_string(),
+            scope: "openid iSHARE".to_string(),
+            request: Some(encrypted_client_assertion),
+            client_id: self.idp_connector.client_id.clone(),
+            state: request.state.clone(),
+            redirect_uri: None,
+            nonce: None,
+            code_challenge: Some(request.code_challenge()),
+            code_challenge_method: Some("S256".to_owned()),
+        };
+
+        Ok(oauth_params)
+    }
+
+    async fn get_authorization_endpoint(&self) -> anyhow::Result<String> {
+        return Ok(self.idp_connector.get_realm_url());
+    }
+
+    async fn handle_callback(
+        &self,
+        server_url: &str,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<IdpUser, AppError> {
+        let client_assertion = self
+            .ishare
+            .create_client_assertion(self.idp_connector.idp_eori.clone())
+            .context("Error creating client assertion")?;
+
+        let response = self
+            .idp_connector
+            .fetch_token(server_url, code, &request.code_verifier, &client_assertion)
+            .await
+            .context("Error fetching token from idp")?;
+
+        let decoded_id_token = self
+            .ishare
+            .decode_token_custom_claims::<IdTokenClaims>(&response.id_token, None)
+            .context("Error decoding id_token")?;
+
+        let claims = decoded_id_token.claims.extra;
+        request.validate_nonce(claims.nonce.as_deref())?;
+
+        return Ok(IdpUser {
+            sub: decoded_id_token.claims.ishare_claims.sub,
+            email: claims.email,
+            fullname: format!("{} {}", claims.first_name, claims.last_name),
+            company_id: claims.company_id,
+            company_name: claims.company_name,
+            roles: claims.realm_access.roles,
+            idp_id: self.idp_connector.idp_eori.clone(),
+            idp_url: self.idp_connector.idp_url.clone(),
+        });
+    }
+}
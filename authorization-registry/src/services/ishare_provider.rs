@@ -1,72 +1,37 @@
 use anyhow::Context;
 use reqwest::StatusCode;
-use sea_orm::DatabaseConnection;
-use serde::{Deserialize, Serialize};
+use sea_orm::{DatabaseConnection, TransactionTrait};
 
 use axum::async_trait;
 use ishare::{
-    delegation_evidence::DelegationEvidenceContainer,
-    ishare::{Capabilities, CertificatesOrSpor, PartyInfo, ValidatePartyError, ISHARE},
+    delegation_evidence::{DelegationEvidence, DelegationEvidenceContainer},
+    ishare::{
+        AllowedDataspaces, Capabilities, IshareClaims, PartyInfo, ValidatePartyError, ISHARE,
+    },
 };
+use jsonwebtoken::TokenData;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::{
-    db::{company as company_store, user::insert_if_not_exists},
     error::{AppError, ExpectedError},
     token_cache::TokenCache,
 };
 
-use super::{idp_connector::IdpConnector, server_token::UserOption};
-
-#[derive(Deserialize)]
-struct RealmAccess {
-    pub roles: Vec<String>,
-}
-
-#[derive(Deserialize)]
-struct IdTokenClaims {
-    pub realm_access: RealmAccess,
-    pub company_id: String,
-    pub company_name: String,
-    pub email: String,
-    pub first_name: String,
-    pub last_name: String,
-}
-
-#[derive(Serialize)]
-pub struct OAuthRequestForm {
-    pub response_type: String,
-    pub scope: String,
-    pub request: String,
-    pub client_id: String,
-    pub state: String,
-}
+use super::{
+    account::ensure_company,
+    audit_log::LoginType,
+    client_assertion_replay::{get_jti_expiry, ReplayStore},
+    party_cache::PartyCache,
+    party_info::{
+        check_party_info, fetch_party_json, parse_party_details, parse_party_info, PartyDetails,
+    },
+};
 
 #[async_trait]
 pub trait SatelliteProvider: Send + Sync {
     async fn get_satellite_token(&self) -> anyhow::Result<String>;
 
-    fn handle_h2m_redirect_url_request(
-        &self,
-        server_url: &str,
-        redirect_url: &str,
-    ) -> anyhow::Result<String>;
-
-    fn get_h2m_redirect_base_url(&self) -> String;
-
-    async fn get_h2m_redirect_form(
-        &self,
-        server_url: &str,
-        redirect_url: &str,
-    ) -> anyhow::Result<OAuthRequestForm>;
-
-    async fn handle_h2m_auth_callback(
-        &self,
-        server_url: &str,
-        code: &str,
-    ) -> Result<(String, UserOption), AppError>;
-
     async fn handle_m2m_authentication(
         &self,
         now: chrono::DateTime<chrono::Utc>,
@@ -84,6 +49,13 @@ pub trait SatelliteProvider: Send + Sync {
         eori: &str,
     ) -> Result<PartyInfo, ValidatePartyError>;
 
+    // the adherence dates and roles of a valid party
+    async fn get_party_details(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        eori: &str,
+    ) -> Result<PartyDetails, ValidatePartyError>;
+
     fn create_delegation_token(
         &self,
         audience: &str,
@@ -96,37 +68,339 @@ pub trait SatelliteProvider: Send + Sync {
         capabilities: &Capabilities,
     ) -> anyhow::Result<String>;
 
-    fn handle_previous_step_client_assertion(
+    // verifies a client assertion passed as previous step: issued by `issuer` to the requestor,
+    // signed with one of the iSHARE certificates of `issuer` and not used before
+    async fn verify_previous_step_client_assertion(
         &self,
         now: chrono::DateTime<chrono::Utc>,
         requestor_company_id: &str,
         client_assertion: &str,
-        policy_issuer: &str,
-        access_subject: &str,
-    ) -> bool;
+        issuer: &str,
+    ) -> anyhow::Result<()>;
+    // verifies a delegation token of another registry: issued by `issuer` to `audience` (this
+    // registry when None) and signed with one of the iSHARE certificates of `issuer`. Returns the
+    // delegation evidence
+    async fn verify_delegation_token(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        delegation_token: &str,
+        issuer: &str,
+        audience: Option<&str>,
+    ) -> anyhow::Result<DelegationEvidence>;
 }
 
+// the handling of client assertions that does not need the satellite, shared by the satellite
+// providers
 #[derive(Clone)]
-pub struct ISHAREProvider {
+pub struct ClientAssertionValidator {
     ishare: Arc<ISHARE>,
     db: DatabaseConnection,
-    idp_connector: IdpConnector,
+    replay_store: Arc<dyn ReplayStore>,
+}
+
+impl ClientAssertionValidator {
+    pub fn new(
+        ishare: Arc<ISHARE>,
+        db: &DatabaseConnection,
+        replay_store: Arc<dyn ReplayStore>,
+    ) -> ClientAssertionValidator {
+        return ClientAssertionValidator {
+            ishare,
+            db: db.clone(),
+            replay_store,
+        };
+    }
+
+    // returns false when the client assertion has been used before
+    async fn register_client_assertion(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        issuer: &str,
+        token: &TokenData<IshareClaims>,
+    ) -> anyhow::Result<bool> {
+        // the ishare crate does not expose the jti of the claims
+        let claims = serde_json::to_value(&token.claims)
+            .context("Error serializing client assertion claims")?;
+        let jti = claims["jti"]
+            .as_str()
+            .filter(|jti| !jti.is_empty())
+            .context("Client assertion has no jti")?;
+        let exp = i64::try_from(token.claims.exp).context("Error converting exp to i64")?;
+
+        return self
+            .replay_store
+            .register(now, issuer, jti, get_jti_expiry(exp))
+            .await;
+    }
+
+    // validates the arguments and the client assertion of an m2m token request
+    pub async fn validate_client_assertion(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        client_id: &str,
+        grant_type: &str,
+        client_assertion: &str,
+        client_assertion_type: &str,
+        scope: &str,
+    ) -> Result<TokenData<IshareClaims>, AppError> {
+        tracing::info!(
+            "handeling machine 2 machine authentication for client_id: {}",
+            client_id
+        );
+
+        match ishare::ishare::validate_request_arguments(grant_type, client_assertion_type, scope) {
+            Err(message) => {
+                return Err(AppError::Expected(ExpectedError {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message,
+                    reason: "Invalid iSHARE Request arguments!".to_owned(),
+                    metadata: None,
+                }));
+            }
+            _ => {}
+        }
+
+        match self
+            .ishare
+            .validate_token(&client_assertion.to_string())
+            .context("Error validating client assertion")
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(AppError::Expected(ExpectedError {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message: "client assertion is invalid".to_owned(),
+                    reason: "invalid client assertion".to_owned(),
+                    metadata: None,
+                }));
+            }
+            Err(e) => {
+                return Err(AppError::Expected(ExpectedError {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message: "client assertion is invalid".to_owned(),
+                    reason: format!("error while validating client assertion: {}", e),
+                    metadata: None,
+                }));
+            }
+        }
+
+        // probably need to be more explicit here in case the token has expired etc
+        let client_assertion_token = self
+            .ishare
+            .decode_token(now, &client_assertion, client_id, None)
+            .map_err(|e| {
+                return AppError::Expected(ExpectedError {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message: "client assertion is invalid".to_owned(),
+                    reason: format!("{:?}", e),
+                    metadata: None,
+                });
+            })?;
+
+        if !self
+            .register_client_assertion(now, client_id, &client_assertion_token)
+            .await?
+        {
+            tracing::warn!("client assertion of '{}' has already been used", client_id);
+            return Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "client assertion is invalid".to_owned(),
+                reason: "client assertion has already been used".to_owned(),
+                metadata: None,
+            }));
+        }
+
+        return Ok(client_assertion_token);
+    }
+
+    // checks the certificate of the client assertion against the party and stores the company
+    pub async fn complete_m2m_authentication(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        client_id: &str,
+        client_assertion_token: &TokenData<IshareClaims>,
+        party_info: &PartyInfo,
+        validate_certificate: bool,
+    ) -> Result<String, AppError> {
+        if validate_certificate {
+            if !self
+                .ishare
+                .validate_party_certificate(client_assertion_token, party_info)
+                .context(format!(
+                    "Error validating party certificate for ishare party: '{}'",
+                    &client_id
+                ))?
+            {
+                return Err(AppError::Expected(ExpectedError {
+                    status_code: StatusCode::UNAUTHORIZED,
+                    message: "x5c header does not match any of the certificates from parties endpoint at the iSHARE satelite".to_owned(),
+                    reason: "The client assertion x5c does not match any of the valid tokens from /parties".to_owned(),
+                    metadata: None
+                }));
+            }
+        }
+
+        let transaction = self
+            .db
+            .begin()
+            .await
+            .context("Error starting db transaction")?;
+
+        let company_id = ensure_company(
+            now,
+            &client_id,
+            &party_info.party_name,
+            LoginType::M2M,
+            &transaction,
+        )
+        .await?;
+
+        transaction
+            .commit()
+            .await
+            .context("Error commiting transaction to db")?;
+
+        return Ok(company_id);
+    }
+
+    pub fn verify_delegation_token(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        delegation_token: &str,
+        issuer: &str,
+        audience: Option<&str>,
+        party_info: &PartyInfo,
+    ) -> anyhow::Result<DelegationEvidence> {
+        if !self
+            .ishare
+            .validate_token(&delegation_token.to_owned())
+            .context("Error validating certificate of delegation token")?
+        {
+            anyhow::bail!("delegation token is not signed with an iSHARE certificate");
+        }
+
+        let token = self
+            .ishare
+            .decode_token(now, delegation_token, issuer, audience)
+            .map_err(|e| anyhow::anyhow!("invalid delegation token of '{}': {:?}", issuer, e))?;
+
+        if !self
+            .ishare
+            .validate_party_certificate(&token, party_info)
+            .context("Error validating party certificate of delegation token")?
+        {
+            anyhow::bail!(
+                "delegation token is not signed with a certificate of '{}'",
+                issuer
+            );
+        }
+
+        let decoded = self
+            .ishare
+            .decode_token_custom_claims::<DelegationEvidenceContainer>(delegation_token, audience)
+            .map_err(|e| anyhow::anyhow!("invalid delegation token of '{}': {:?}", issuer, e))?;
+
+        return Ok(decoded.claims.extra.delegation_evidence);
+    }
+
+    pub async fn verify_previous_step_client_assertion(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        requestor_company_id: &str,
+        client_assertion: &str,
+        issuer: &str,
+        party_info: &PartyInfo,
+    ) -> anyhow::Result<()> {
+        if !self
+            .ishare
+            .validate_token(&client_assertion.to_owned())
+            .context("Error validating certificate of client assertion")?
+        {
+            anyhow::bail!("client assertion is not signed with an iSHARE certificate");
+        }
+
+        let token = self
+            .ishare
+            .decode_token(now, client_assertion, issuer, Some(requestor_company_id))
+            .map_err(|e| anyhow::anyhow!("invalid client assertion of '{}': {:?}", issuer, e))?;
+
+        if !self
+            .ishare
+            .validate_party_certificate(&token, party_info)
+            .context("Error validating party certificate of client assertion")?
+        {
+            anyhow::bail!(
+                "client assertion is not signed with a certificate of '{}'",
+                issuer
+            );
+        }
+
+        if !self.register_client_assertion(now, issuer, &token).await? {
+            anyhow::bail!("client assertion of '{}' has already been used", issuer);
+        }
+
+        return Ok(());
+    }
+}
+
+#[derive(Clone)]
+pub struct ISHAREProvider {
+    ishare: Arc<ISHARE>,
     satellite_token_cache: Arc<RwLock<TokenCache>>,
+    party_cache: Arc<PartyCache>,
+    dataspace_config: Option<AllowedDataspaces>,
+    client_assertions: ClientAssertionValidator,
 }
 
 impl ISHAREProvider {
     pub fn new(
         ishare: Arc<ISHARE>,
         db: &DatabaseConnection,
-        idp_connector: &IdpConnector,
+        replay_store: Arc<dyn ReplayStore>,
+        party_cache: Arc<PartyCache>,
+        dataspace_config: Option<AllowedDataspaces>,
     ) -> ISHAREProvider {
         return ISHAREProvider {
             ishare: ishare.clone(),
-            db: db.clone(),
-            idp_connector: idp_connector.clone(),
             satellite_token_cache: TokenCache::new(),
+            party_cache,
+            dataspace_config,
+            client_assertions: ClientAssertionValidator::new(ishare, db, replay_store),
         };
     }
+
+    async fn fetch_party_info(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        eori: &str,
+    ) -> Result<serde_json::Value, ValidatePartyError> {
+        let token = self
+            .get_satellite_token()
+            .await
+            .context("Error getting sattelite token")?;
+
+        // the party is fetched as json to keep the roles, the party cache only remembers what
+        // the satellite answered
+        let party = fetch_party_json(&self.ishare, &token, eori)
+            .await
+            .context(format!(
+                "error validating company '{}' is ishare party",
+                eori
+            ))?;
+
+        return check_party_info(now, eori, party, self.dataspace_config.as_ref());
+    }
+
+    async fn get_cached_party(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        eori: &str,
+    ) -> Result<serde_json::Value, ValidatePartyError> {
+        return self
+            .party_cache
+            .get_or_fetch(now, eori, || self.fetch_party_info(now, eori))
+            .await;
+    }
 }
 
 #[async_trait]
@@ -184,194 +458,60 @@ impl SatelliteProvider for ISHAREProvider {
         now: chrono::DateTime<chrono::Utc>,
         eori: &str,
     ) -> Result<PartyInfo, ValidatePartyError> {
-        let token = self
-            .get_satellite_token()
-            .await
-            .context("Error getting sattelite token")?;
-
-        let party_info = self
-            .ishare
-            .validate_party(now, eori, &token)
-            .await
-            .context(format!(
-                "error validating company '{}' is ishare party",
-                eori
-            ))?;
-
-        return Ok(party_info);
+        return parse_party_info(&self.get_cached_party(now, eori).await?);
     }
 
-    fn handle_h2m_redirect_url_request(
+    async fn get_party_details(
         &self,
-        server_url: &str,
-        redirect_url: &str,
-    ) -> anyhow::Result<String> {
-        let auth_claims = self
-            .idp_connector
-            .get_auth_request_claims(server_url, redirect_url);
-        let client_assertion = self
-            .ishare
-            .create_client_assertion_with_extra_claims(
-                self.idp_connector.idp_eori.clone(),
-                auth_claims,
-            )
-            .context("Error creating client assertion")?;
-
-        let url = self
-            .idp_connector
-            .generate_auth_url(&client_assertion, redirect_url);
-
-        Ok(url)
-    }
-
-    fn get_h2m_redirect_base_url(&self) -> String {
-        return self.idp_connector.get_realm_url();
-    }
-
-    async fn get_h2m_redirect_form(
-        &self,
-        server_url: &str,
-        redirect_url: &str,
-    ) -> anyhow::Result<OAuthRequestForm> {
-        let auth_claims = self
-            .idp_connector
-            .get_auth_request_claims(server_url, redirect_url);
-
-        let idp_eori = self.idp_connector.idp_eori.clone();
-        let now = chrono::Utc::now();
-
-        let idp_info = self
-            .validate_party(now, idp_eori.as_str())
-            .await
-            .context("Error getting party info IDP")?;
-
-        let idp_cert = match idp_info.certificates_or_spor {
-            CertificatesOrSpor::Spor(_) => {
-                anyhow::bail!("Error: IDP should have certificate instead of spor");
-            }
-            CertificatesOrSpor::Certificates(certificates) => certificates
-                .into_iter()
-                .next()
-                .context("No certificate found for IDP")?,
-        };
-
-        let encrypted_client_assertion = self
-            .ishare
-            .create_client_assertion_with_extra_claims_encrypted(
-                self.idp_connector.idp_eori.clone(),
-                auth_claims,
-                &idp_cert,
-            )
-            .context("Error creating encyrpted client assertion")?;
-
-        let oauth_params = OAuthRequestForm {
-            response_type: "code".to_string(),
-            scope: "openid iSHARE".to_string(),
-            request: encrypted_client_assertion,
-            client_id: self.idp_connector.client_id.clone(),
-            state: redirect_url.to_string(),
-        };
-
-        Ok(oauth_params)
+        now: chrono::DateTime<chrono::Utc>,
+        eori: &str,
+    ) -> Result<PartyDetails, ValidatePartyError> {
+        return parse_party_details(&self.get_cached_party(now, eori).await?);
     }
 
-    fn handle_previous_step_client_assertion(
+    async fn verify_previous_step_client_assertion(
         &self,
         now: chrono::DateTime<chrono::Utc>,
         requestor_company_id: &str,
         client_assertion: &str,
-        policy_issuer: &str,
-        access_subject: &str,
-    ) -> bool {
-        let policy_issuer_access = match self.ishare.decode_token(
-            now,
-            client_assertion,
-            policy_issuer,
-            Some(requestor_company_id),
-        ) {
-            Err(e) => {
-                tracing::info!("no acces for policy issuer via previous step: {}", e);
-                false
-            }
-            Ok(_) => {
-                tracing::info!("access granted for policy issuer via previous step");
-                true
-            }
-        };
-
-        let access_subject_access = match self.ishare.decode_token(
-            now,
-            client_assertion,
-            access_subject,
-            Some(requestor_company_id),
-        ) {
-            Err(e) => {
-                tracing::info!("no acces for access subject via previous step: {}", e);
-                false
-            }
-            Ok(_) => {
-                tracing::info!("access granted for access subject via previous step");
-                true
-            }
-        };
-
-        policy_issuer_access || access_subject_access
+        issuer: &str,
+    ) -> anyhow::Result<()> {
+        let party_info = self
+            .validate_party(now, issuer)
+            .await
+            .context(format!("error validating ishare party '{}'", issuer))?;
+
+        return self
+            .client_assertions
+            .verify_previous_step_client_assertion(
+                now,
+                requestor_company_id,
+                client_assertion,
+                issuer,
+                &party_info,
+            )
+            .await;
     }
 
-    async fn handle_h2m_auth_callback(
+    async fn verify_delegation_token(
         &self,
-        server_url: &str,
-        code: &str,
-    ) -> Result<(String, UserOption), AppError> {
-        let client_assertion = self
-            .ishare
-            .create_client_assertion(self.idp_connector.idp_eori.clone())
-            .context("Error creating client assertion")?;
-
-        let response = self
-            .idp_connector
-            .fetch_token(&server_url, code, &client_assertion)
+        now: chrono::DateTime<chrono::Utc>,
+        delegation_token: &str,
+        issuer: &str,
+        audience: Option<&str>,
+    ) -> anyhow::Result<DelegationEvidence> {
+        let party_info = self
+            .validate_party(now, issuer)
             .await
-            .context("Error fetching token from idp")?;
-
-        let decoded_id_token = self
-            .ishare
-            .decode_token_custom_claims::<IdTokenClaims>(&response.id_token, None)
-            .context("Error decoding id_token")?;
+            .context(format!("error validating ishare party '{}'", issuer))?;
 
-        let company_id = company_store::insert_if_not_exists(
-            &decoded_id_token.claims.extra.company_id,
-            &decoded_id_token.claims.extra.company_name,
-            &self.db,
-        )
-        .await
-        .context("Error inserting company into db")?;
-
-        let fullname = format!(
-            "{} {}",
-            decoded_id_token.claims.extra.first_name, decoded_id_token.claims.extra.last_name
+        return self.client_assertions.verify_delegation_token(
+            now,
+            delegation_token,
+            issuer,
+            audience,
+            &party_info,
         );
-        let user_id = insert_if_not_exists(
-            decoded_id_token.claims.ishare_claims.sub,
-            decoded_id_token.claims.extra.email,
-            fullname,
-            company_id.clone(),
-            self.idp_connector.idp_eori.clone(),
-            self.idp_connector.idp_url.clone(),
-            &self.db,
-        )
-        .await
-        .context("Error inserting user into db")?;
-
-        let realm_access_roles = decoded_id_token.claims.extra.realm_access.roles;
-
-        return Ok((
-            company_id,
-            UserOption {
-                user_id,
-                realm_access_roles,
-            },
-        ));
     }
 
     async fn handle_m2m_authentication(
@@ -384,91 +524,32 @@ impl SatelliteProvider for ISHAREProvider {
         scope: &str,
         validate_certificate: bool,
     ) -> Result<String, AppError> {
-        tracing::info!(
-            "handeling machine 2 machine authentication for client_id: {}",
-            client_id
-        );
-
-        match ishare::ishare::validate_request_arguments(grant_type, client_assertion_type, scope) {
-            Err(message) => {
-                return Err(AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message,
-                    reason: "Invalid iSHARE Request arguments!".to_owned(),
-                    metadata: None,
-                }));
-            }
-            _ => {}
-        }
-
-        match self
-            .ishare
-            .validate_token(&client_assertion.to_string())
-            .context("Error validating client assertion")
-        {
-            Ok(true) => {}
-            Ok(false) => {
-                return Err(AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: "client assertion is invalid".to_owned(),
-                    reason: "invalid client assertion".to_owned(),
-                    metadata: None,
-                }));
-            }
-            Err(e) => {
-                return Err(AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: "client assertion is invalid".to_owned(),
-                    reason: format!("error while validating client assertion: {}", e),
-                    metadata: None,
-                }));
-            }
-        }
-
-        // probably need to be more explicit here in case the token has expired etc
         let client_assertion_token = self
-            .ishare
-            .decode_token(now, &client_assertion, client_id, None)
-            .map_err(|e| {
-                return AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: "client assertion is invalid".to_owned(),
-                    reason: format!("{:?}", e),
-                    metadata: None,
-                });
-            })?;
-
-        let token = self.get_satellite_token().await?;
+            .client_assertions
+            .validate_client_assertion(
+                now,
+                client_id,
+                grant_type,
+                client_assertion,
+                client_assertion_type,
+                scope,
+            )
+            .await?;
 
         let party_info = self
-            .ishare
-            .validate_party(now, &client_id.to_string(), &token)
+            .validate_party(now, client_id)
             .await
             .context(format!("error validating ishare party '{}'", &client_id))?;
 
-        if validate_certificate {
-            if !self
-                .ishare
-                .validate_party_certificate(&client_assertion_token, &party_info)
-                .context(format!(
-                    "Error validating party certificate for ishare party: '{}'",
-                    &client_id
-                ))?
-            {
-                return Err(AppError::Expected(ExpectedError {
-                    status_code: StatusCode::UNAUTHORIZED,
-                    message: "x5c header does not match any of the certificates from parties endpoint at the iSHARE satelite".to_owned(),
-                    reason: "The client assertion x5c does not match any of the valid tokens from /parties".to_owned(),
-                    metadata: None
-                }));
-            }
-        }
-
-        let company_id =
-            company_store::insert_if_not_exists(&client_id, &party_info.party_name, &self.db)
-                .await
-                .context("Error inserting company into db")?;
-
-        return Ok(company_id);
+        return self
+            .client_assertions
+            .complete_m2m_authentication(
+                now,
+                client_id,
+                &client_assertion_token,
+                &party_info,
+                validate_certificate,
+            )
+            .await;
     }
 }
@@ -1,6 +1,26 @@
+pub mod account;
 pub mod audit_log;
+pub mod audit_log_retention;
+pub mod auth_request;
+pub mod client_assertion_replay;
+pub mod dataspace;
 pub mod delegation;
+pub mod federation;
+pub mod identity_provider;
 pub mod idp_connector;
+pub mod ishare_idp;
 pub mod ishare_provider;
+pub mod oidc_idp;
+pub mod parties_snapshot;
+pub mod party_cache;
+pub mod party_compliance;
+pub mod party_info;
+pub mod party_revalidation;
+pub mod pdp_provider;
 pub mod policy;
+pub mod rate_limit;
+pub mod redirect_uri;
+pub mod refresh_token;
+pub mod replication;
 pub mod server_token;
+pub mod token_revocation;
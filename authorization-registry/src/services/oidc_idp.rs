@@ -0,0 +1,689 @@
+use anyhow::Context;
+use axum::async_trait;
+use std::str::FromStr;
+
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::{config::OidcIdentityProviderConfig, error::AppError};
+
+use super::server_token::to_jsonwebtoken_algorithm;
+
+use super::identity_provider::{
+    get_callback_url, login_failed, AuthorizationRequest, IdentityProvider, IdpUser,
+    OAuthRequestForm,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+struct DiscoveryDocument {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+// standard OpenID Connect identity provider, configured through its discovery document
+pub struct OidcIdentityProvider {
+    config: OidcIdentityProviderConfig,
+    client: reqwest::Client,
+    discovery_document: OnceCell<DiscoveryDocument>,
+    // refreshed when an id token is signed with an unknown key
+    jwks: RwLock<Option<JwkSet>>,
+}
+
+// looks up a dot separated claim path, e.g. "realm_access.roles"
+fn get_claim<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    return path
+        .split('.')
+        .try_fold(claims, |value, key| value.get(key));
+}
+
+fn get_string_claim(claims: &Value, path: &str) -> Option<String> {
+    return get_claim(claims, path)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_owned());
+}
+
+// roles can be a list or a space separated string
+fn get_roles_claim(claims: &Value, path: &str) -> Vec<String> {
+    return match get_claim(claims, path) {
+        Some(Value::Array(roles)) => roles
+            .iter()
+            .filter_map(|r| r.as_str())
+            .map(|r| r.to_owned())
+            .collect(),
+        Some(Value::String(roles)) => roles.split_whitespace().map(|r| r.to_owned()).collect(),
+        _ => vec![],
+    };
+}
+
+impl OidcIdentityProvider {
+    pub fn new(config: OidcIdentityProviderConfig) -> Self {
+        return Self {
+            config,
+            client: reqwest::Client::new(),
+            discovery_document: OnceCell::new(),
+            jwks: RwLock::new(None),
+        };
+    }
+
+    async fn get_discovery_document(&self) -> anyhow::Result<&DiscoveryDocument> {
+        return self
+            .discovery_document
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer_url.trim_end_matches('/')
+                );
+
+                tracing::info!("retrieving OIDC discovery document from '{}'", &url);
+
+                let document = self
+                    .client
+                    .get(&url)
+                    .send()
+                    .await
+                    .context(format!("Error retrieving discovery document '{}'", &url))?
+                    .error_for_status()
+                    .context("Error response retrieving discovery document")?
+                    .json::<DiscoveryDocument>()
+                    .await
+                    .context("Error decoding discovery document")?;
+
+                Ok(document)
+            })
+            .await;
+    }
+
+    fn get_request_form(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> OAuthRequestForm {
+        return OAuthRequestForm {
+            response_type: "code".to_owned(),
+            scope: self.config.scope.clone(),
+            request: None,
+            client_id: self.config.client_id.clone(),
+            state: request.state.clone(),
+            redirect_uri: Some(get_callback_url(server_url)),
+            nonce: Some(request.nonce.clone()),
+            code_challenge: Some(request.code_challenge()),
+            code_challenge_method: Some("S256".to_owned()),
+        };
+    }
+
+    async fn fetch_id_token(
+        &self,
+        server_url: &str,
+        code: &str,
+        code_verifier: &str,
+    ) -> anyhow::Result<String> {
+        let discovery_document = self.get_discovery_document().await?;
+        let redirect_uri = get_callback_url(server_url);
+
+        let mut form_data = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+
+        if let Some(client_secret) = &self.config.client_secret {
+            form_data.push(("client_secret", client_secret.as_str()));
+        }
+
+        let response = self
+            .client
+            .post(&discovery_document.token_endpoint)
+            .form(&form_data)
+            .send()
+            .await
+            .context("Error fetching token from identity provider")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("error response from identity provider: {:?}", response);
+        }
+
+        let token_response = response
+            .json::<TokenResponse>()
+            .await
+            .context("Error decoding token response")?;
+
+        return Ok(token_response.id_token);
+    }
+
+    async fn fetch_jwks(&self) -> anyhow::Result<JwkSet> {
+        let discovery_document = self.get_discovery_document().await?;
+
+        tracing::info!(
+            "retrieving identity provider keys from '{}'",
+            &discovery_document.jwks_uri
+        );
+
+        let jwks = self
+            .client
+            .get(&discovery_document.jwks_uri)
+            .send()
+            .await
+            .context("Error retrieving identity provider keys")?
+            .error_for_status()
+            .context("Error response retrieving identity provider keys")?
+            .json::<JwkSet>()
+            .await
+            .context("Error decoding identity provider keys")?;
+
+        *self.jwks.write().await = Some(jwks.clone());
+
+        return Ok(jwks);
+    }
+
+    async fn get_jwks(&self) -> anyhow::Result<JwkSet> {
+        if let Some(jwks) = self.jwks.read().await.as_ref() {
+            return Ok(jwks.clone());
+        }
+
+        return self.fetch_jwks().await;
+    }
+
+    async fn validate_id_token(&self, id_token: &str) -> Result<Value, AppError> {
+        let discovery_document = self.get_discovery_document().await?;
+
+        let header = decode_header(id_token)
+            .map_err(|e| login_failed(format!("Error decoding id_token header: {}", e)))?;
+
+        let find_key = |jwks: &JwkSet| match &header.kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+            None => None,
+        };
+
+        // the provider may have rotated its keys
+        let jwk = match find_key(&self.get_jwks().await?) {
+            Some(jwk) => Some(jwk),
+            None => find_key(&self.fetch_jwks().await?),
+        }
+        .ok_or_else(|| {
+            login_failed(format!(
+                "No key found for id_token with kid '{:?}'",
+                &header.kid
+            ))
+        })?;
+
+        // the algorithm of the header is chosen by whoever made the token
+        let algorithm = match jwk.common.key_algorithm {
+            Some(key_algorithm) => Algorithm::from_str(&key_algorithm.to_string())
+                .context(format!("Unsupported key algorithm '{}'", key_algorithm))?,
+            None => to_jsonwebtoken_algorithm(self.config.id_token_algorithm),
+        };
+        if header.alg != algorithm {
+            return Err(login_failed(format!(
+                "id_token is signed with '{:?}' instead of '{:?}'",
+                header.alg, algorithm
+            )));
+        }
+
+        let decoding_key = DecodingKey::from_jwk(&jwk).context("Error creating decoding key")?;
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&discovery_document.issuer]);
+
+        let token = decode::<Value>(id_token, &decoding_key, &validation)
+            .map_err(|e| login_failed(format!("Invalid id_token: {}", e)))?;
+
+        return Ok(token.claims);
+    }
+
+    fn map_claims(&self, claims: &Value, issuer: &str) -> Result<IdpUser, AppError> {
+        let mapping = &self.config.claims;
+
+        let sub = get_string_claim(claims, "sub")
+            .ok_or_else(|| login_failed("id_token has no 'sub' claim".to_owned()))?;
+        let company_id = get_string_claim(claims, &mapping.company_id).ok_or_else(|| {
+            login_failed(format!(
+                "id_token has no company claim '{}'",
+                &mapping.company_id
+            ))
+        })?;
+
+        return Ok(IdpUser {
+            company_name: get_string_claim(claims, &mapping.company_name)
+                .unwrap_or(company_id.clone()),
+            company_id,
+            email: get_string_claim(claims, &mapping.email).unwrap_or_default(),
+            fullname: get_string_claim(claims, &mapping.name).unwrap_or_default(),
+            roles: get_roles_claim(claims, &mapping.roles),
+            sub,
+            idp_id: issuer.to_owned(),
+            idp_url: self.config.issuer_url.clone(),
+        });
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for OidcIdentityProvider {
+    async fn get_authorization_url(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<String> {
+        let discovery_document = self.get_discovery_document().await?;
+        let form = self.get_request_form(server_url, request);
+
+        let mut url = Url::parse(&discovery_document.authorization_endpoint)
+            .context("Error parsing authorization endpoint")?;
+        url.query_pairs_mut()
+            .append_pair("response_type", &form.response_type)
+            .append_pair("client_id", &form.client_id)
+            .append_pair("redirect_uri", &get_callback_url(server_url))
+            .append_pair("scope", &form.scope)
+            .append_pair("state", &form.state)
+            .append_pair("nonce", &form.nonce.unwrap_or_default())
+            .append_pair("code_challenge", &form.code_challenge.unwrap_or_default())
+            .append_pair("code_challenge_method", "S256");
+
+        return Ok(url.to_string());
+    }
+
+    async fn get_authorization_form(
+        &self,
+        server_url: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<OAuthRequestForm> {
+        return Ok(self.get_request_form(server_url, request));
+    }
+
+    async fn get_authorization_endpoint(&self) -> anyhow::Result<String> {
+        let discovery_document = self.get_discovery_document().await?;
+
+        return Ok(discovery_document.authorization_endpoint.clone());
+    }
+
+    async fn handle_callback(
+        &self,
+        server_url: &str,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<IdpUser, AppError> {
+        let id_token = self
+            .fetch_id_token(server_url, code, &request.code_verifier)
+            .await
+            .map_err(|e| login_failed(format!("{:?}", e)))?;
+        let claims = self.validate_id_token(&id_token).await?;
+        request.validate_nonce(get_claim(&claims, "nonce").and_then(|n| n.as_str()))?;
+        let issuer = &self.get_discovery_document().await?.issuer;
+
+        return self.map_claims(&claims, issuer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use axum::{extract::State, http::StatusCode, routing::get, routing::post, Form, Json, Router};
+    use jsonwebtoken::{encode, jwk::JwkSet, Algorithm, EncodingKey, Header};
+    use openssl::{pkey::PKey, rsa::Rsa};
+    use serde_json::json;
+
+    use crate::{
+        config::{JwtAlgorithm, OidcClaimMapping},
+        services::server_token::{public_key_to_jwk, PemKey},
+    };
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct MockIdp {
+        issuer: String,
+        id_token: String,
+        jwks: JwkSet,
+        jwks_requests: Arc<AtomicUsize>,
+    }
+
+    // starts an identity provider that hands out `claims` as id_token for every code
+    async fn start_mock_idp(claims: Value) -> String {
+        return start_mock_idp_with_algorithm(claims, Algorithm::RS256)
+            .await
+            .0;
+    }
+
+    // the id_token is signed with `algorithm`, the key is published as an RS256 key. Returns the
+    // issuer and the number of times the keys have been retrieved
+    async fn start_mock_idp_with_algorithm(
+        claims: Value,
+        algorithm: Algorithm,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let public_key = PemKey {
+            kid: "mock".to_owned(),
+            algorithm: JwtAlgorithm::RS256,
+            pem: key.public_key_to_pem().unwrap(),
+        };
+
+        let mut claims = claims;
+        if claims.get("nonce").is_none() {
+            claims["nonce"] = json!("test-nonce");
+        }
+        claims["iss"] = json!(issuer.clone());
+        claims["exp"] = json!(chrono::Utc::now().timestamp() + 300);
+
+        let mut header = Header::new(algorithm);
+        header.kid = Some("mock".to_owned());
+        let id_token = encode(
+            &header,
+            &claims,
+            &EncodingKey::from_rsa_pem(&key.private_key_to_pem_pkcs8().unwrap()).unwrap(),
+        )
+        .unwrap();
+
+        let mock_idp = MockIdp {
+            issuer: issuer.clone(),
+            id_token,
+            jwks: JwkSet {
+                keys: vec![public_key_to_jwk(&public_key).unwrap()],
+            },
+            jwks_requests: Arc::new(AtomicUsize::new(0)),
+        };
+        let jwks_requests = mock_idp.jwks_requests.clone();
+
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(|State(idp): State<MockIdp>| async move {
+                    Json(json!({
+                        "issuer": idp.issuer,
+                        "authorization_endpoint": format!("{}/auth", idp.issuer),
+                        "token_endpoint": format!("{}/token", idp.issuer),
+                        "jwks_uri": format!("{}/jwks", idp.issuer),
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                post(
+                    |State(idp): State<MockIdp>,
+                     Form(form): Form<HashMap<String, String>>| async move {
+                        // PKCE: the code is only exchanged with the right code verifier
+                        if form.get("code_verifier").map(|v| v.as_str()) != Some("test-verifier") {
+                            return Err(StatusCode::BAD_REQUEST);
+                        }
+
+                        Ok(Json(json!({ "id_token": idp.id_token, "token_type": "Bearer" })))
+                    },
+                ),
+            )
+            .route(
+                "/jwks",
+                get(|State(idp): State<MockIdp>| async move {
+                    idp.jwks_requests.fetch_add(1, Ordering::SeqCst);
+                    Json(idp.jwks)
+                }),
+            )
+            .with_state(mock_idp);
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        return (issuer, jwks_requests);
+    }
+
+    fn get_auth_request() -> AuthorizationRequest {
+        return AuthorizationRequest {
+            state: "some-state".to_owned(),
+            nonce: "test-nonce".to_owned(),
+            code_verifier: "test-verifier".to_owned(),
+        };
+    }
+
+    fn get_provider(issuer_url: &str, claims: OidcClaimMapping) -> OidcIdentityProvider {
+        return OidcIdentityProvider::new(OidcIdentityProviderConfig {
+            issuer_url: issuer_url.to_owned(),
+            client_id: "authorization-registry".to_owned(),
+            client_secret: Some("secret".to_owned()),
+            scope: "openid profile email".to_owned(),
+            claims,
+            id_token_algorithm: JwtAlgorithm::RS256,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_authorization_url() {
+        let issuer = start_mock_idp(json!({})).await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        let url = provider
+            .get_authorization_url("https://ar.example.com", &get_auth_request())
+            .await
+            .unwrap();
+        let url = Url::parse(&url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(url.path(), "/auth");
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], "authorization-registry");
+        assert_eq!(
+            params["redirect_uri"],
+            "https://ar.example.com/connect/human/auth/code"
+        );
+        assert_eq!(params["scope"], "openid profile email");
+        assert_eq!(params["state"], "some-state");
+        assert_eq!(params["nonce"], "test-nonce");
+        assert_eq!(
+            params["code_challenge"],
+            get_auth_request().code_challenge()
+        );
+        assert_eq!(params["code_challenge_method"], "S256");
+    }
+
+    #[tokio::test]
+    async fn test_custom_claim_mapping() {
+        let issuer = start_mock_idp(json!({
+            "sub": "user-1",
+            "aud": "authorization-registry",
+            "org": { "id": "NL.COMPANY", "name": "Company" },
+            "preferred_username": "Jane Doe",
+            "email": "jane@example.com",
+            "groups": "dexspace_admin reader",
+        }))
+        .await;
+
+        let provider = get_provider(
+            &issuer,
+            OidcClaimMapping {
+                company_id: "org.id".to_owned(),
+                company_name: "org.name".to_owned(),
+                name: "preferred_username".to_owned(),
+                email: "email".to_owned(),
+                roles: "groups".to_owned(),
+            },
+        );
+
+        let user = provider
+            .handle_callback("https://ar.example.com", "code", &get_auth_request())
+            .await
+            .unwrap();
+
+        assert_eq!(user.sub, "user-1");
+        assert_eq!(user.company_id, "NL.COMPANY");
+        assert_eq!(user.company_name, "Company");
+        assert_eq!(user.fullname, "Jane Doe");
+        assert_eq!(user.email, "jane@example.com");
+        assert_eq!(user.roles, vec!["dexspace_admin", "reader"]);
+        assert_eq!(user.idp_id, issuer);
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_cached() {
+        let (issuer, jwks_requests) = start_mock_idp_with_algorithm(
+            json!({
+                "sub": "user-1",
+                "aud": "authorization-registry",
+                "company_id": "NL.COMPANY",
+            }),
+            Algorithm::RS256,
+        )
+        .await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        for _ in 0..2 {
+            provider
+                .handle_callback("https://ar.example.com", "code", &get_auth_request())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(jwks_requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_algorithm_of_key() {
+        // signed with the same RSA key, but with another algorithm than the key is meant for
+        let (issuer, _) = start_mock_idp_with_algorithm(
+            json!({
+                "sub": "user-1",
+                "aud": "authorization-registry",
+                "company_id": "NL.COMPANY",
+            }),
+            Algorithm::RS384,
+        )
+        .await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        let err = provider
+            .handle_callback("https://ar.example.com", "code", &get_auth_request())
+            .await
+            .err()
+            .unwrap();
+
+        match err {
+            AppError::Expected(e) => {
+                assert_eq!(e.status_code, StatusCode::UNAUTHORIZED);
+                assert_eq!(
+                    e.reason,
+                    "id_token is signed with 'RS384' instead of 'RS256'"
+                );
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_audience() {
+        let issuer = start_mock_idp(json!({
+            "sub": "user-1",
+            "aud": "another-client",
+            "company_id": "NL.COMPANY",
+        }))
+        .await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        let err = provider
+            .handle_callback("https://ar.example.com", "code", &get_auth_request())
+            .await
+            .err()
+            .unwrap();
+
+        match err {
+            AppError::Expected(e) => {
+                assert_eq!(e.status_code, StatusCode::UNAUTHORIZED);
+                assert!(e.reason.contains("InvalidAudience"), "{}", e.reason);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_company_claim() {
+        let issuer = start_mock_idp(json!({
+            "sub": "user-1",
+            "aud": "authorization-registry",
+        }))
+        .await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        let err = provider
+            .handle_callback("https://ar.example.com", "code", &get_auth_request())
+            .await
+            .err()
+            .unwrap();
+
+        match err {
+            AppError::Expected(e) => {
+                assert_eq!(e.reason, "id_token has no company claim 'company_id'")
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_nonce() {
+        let issuer = start_mock_idp(json!({
+            "sub": "user-1",
+            "aud": "authorization-registry",
+            "company_id": "NL.COMPANY",
+            "nonce": "nonce-of-another-login",
+        }))
+        .await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        let err = provider
+            .handle_callback("https://ar.example.com", "code", &get_auth_request())
+            .await
+            .err()
+            .unwrap();
+
+        match err {
+            AppError::Expected(e) => {
+                assert_eq!(e.status_code, StatusCode::UNAUTHORIZED);
+                assert_eq!(e.reason, "Nonce of id_token does not match");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_code_verifier() {
+        let issuer = start_mock_idp(json!({
+            "sub": "user-1",
+            "aud": "authorization-registry",
+            "company_id": "NL.COMPANY",
+        }))
+        .await;
+        let provider = get_provider(&issuer, OidcClaimMapping::default());
+
+        let auth_request = AuthorizationRequest {
+            code_verifier: "another-verifier".to_owned(),
+            ..get_auth_request()
+        };
+
+        let err = provider
+            .handle_callback("https://ar.example.com", "code", &auth_request)
+            .await
+            .err()
+            .unwrap();
+
+        match err {
+            AppError::Expected(e) => assert_eq!(e.status_code, StatusCode::UNAUTHORIZED),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use ishare::{
+    delegation_evidence::{DelegationEvidence, DelegationEvidenceContainer},
+    ishare::{AllowedDataspaces, Capabilities, PartyInfo, ValidatePartyError, ISHARE},
+};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+use super::{
+    client_assertion_replay::ReplayStore,
+    ishare_provider::{ClientAssertionValidator, SatelliteProvider},
+    party_info::{
+        check_party_info, fetch_party_json, parse_party_details, parse_party_info, PartyDetails,
+    },
+};
+
+// local copy of the party information of the satellite, for environments without a satellite
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PartiesSnapshot {
+    pub created_at: DateTime<Utc>,
+    pub satellite_eori: String,
+    // party_info of the satellite per party id. Kept as json so fields the ishare crate does
+    // not know about, such as the roles, are retained
+    pub parties: BTreeMap<String, serde_json::Value>,
+}
+
+impl PartiesSnapshot {
+    pub fn read(path: &str) -> anyhow::Result<Self> {
+        let content =
+            std::fs::read(path).context(format!("Error reading parties snapshot '{}'", path))?;
+
+        return serde_json::from_slice(&content)
+            .context(format!("Error parsing parties snapshot '{}'", path));
+    }
+
+    pub fn write(&self, path: &str) -> anyhow::Result<()> {
+        let content =
+            serde_json::to_vec_pretty(self).context("Error serializing parties snapshot")?;
+
+        // write next to the snapshot first, so a running registry never reads half a file
+        let tmp_path = format!("{}.tmp", path);
+        std::fs::write(&tmp_path, content)
+            .context(format!("Error writing parties snapshot '{}'", tmp_path))?;
+        std::fs::rename(&tmp_path, path)
+            .context(format!("Error moving parties snapshot to '{}'", path))?;
+
+        return Ok(());
+    }
+}
+
+// fetches the parties of the existing snapshot and `eoris` from the satellite and writes a new
+// snapshot to `path`
+pub async fn refresh_snapshot(
+    ishare: &ISHARE,
+    path: &str,
+    eoris: &[String],
+) -> anyhow::Result<PartiesSnapshot> {
+    let mut parties: Vec<String> = match std::path::Path::new(path).exists() {
+        true => PartiesSnapshot::read(path)?.parties.into_keys().collect(),
+        false => vec![],
+    };
+    parties.extend(eoris.iter().cloned());
+    parties.sort();
+    parties.dedup();
+
+    let client_assertion = ishare.create_client_assertion(ishare.satellite_eori.clone())?;
+    let satellite_token = ishare
+        .get_satelite_access_token(&client_assertion)
+        .await
+        .context("Error retrieving satelite access token")?
+        .access_token;
+
+    let mut snapshot = PartiesSnapshot {
+        created_at: Utc::now(),
+        satellite_eori: ishare.satellite_eori.clone(),
+        parties: BTreeMap::new(),
+    };
+
+    for eori in parties {
+        match fetch_party_json(ishare, &satellite_token, &eori).await? {
+            Some(party_info) => {
+                tracing::info!("added party '{}' to the snapshot", eori);
+                snapshot.parties.insert(eori, party_info);
+            }
+            None => tracing::warn!("party '{}' is unknown at the satellite, skipping it", eori),
+        }
+    }
+
+    snapshot.write(path)?;
+
+    return Ok(snapshot);
+}
+
+// serves the party information from a parties snapshot, the satellite is never contacted
+pub struct SnapshotSatelliteProvider {
+    ishare: Arc<ISHARE>,
+    snapshot: PartiesSnapshot,
+    dataspace_config: Option<AllowedDataspaces>,
+    client_assertions: ClientAssertionValidator,
+}
+
+impl SnapshotSatelliteProvider {
+    pub fn new(
+        ishare: Arc<ISHARE>,
+        snapshot: PartiesSnapshot,
+        dataspace_config: Option<AllowedDataspaces>,
+        db: &DatabaseConnection,
+        replay_store: Arc<dyn ReplayStore>,
+    ) -> SnapshotSatelliteProvider {
+        return SnapshotSatelliteProvider {
+            ishare: ishare.clone(),
+            snapshot,
+            dataspace_config,
+            client_assertions: ClientAssertionValidator::new(ishare, db, replay_store),
+        };
+    }
+
+    // same checks as the satellite lookup
+    fn get_party(
+        &self,
+        now: DateTime<Utc>,
+        eori: &str,
+    ) -> Result<serde_json::Value, ValidatePartyError> {
+        return check_party_info(
+            now,
+            eori,
+            self.snapshot.parties.get(eori).cloned(),
+            self.dataspace_config.as_ref(),
+        );
+    }
+}
+
+#[async_trait]
+impl SatelliteProvider for SnapshotSatelliteProvider {
+    fn create_delegation_token(
+        &self,
+        audience: &str,
+        de_container: &DelegationEvidenceContainer,
+    ) -> anyhow::Result<String> {
+        self.ishare
+            .create_client_assertion_with_extra_claims(audience.to_owned(), de_container)
+            .context("Error creating delegation token")
+    }
+
+    fn create_capabilities_token(
+        &self,
+        audience: &str,
+        capabilities: &Capabilities,
+    ) -> anyhow::Result<String> {
+        self.ishare
+            .create_client_assertion_with_extra_claims(audience.to_string(), capabilities)
+            .context("Error creating delegation token")
+    }
+
+    async fn get_satellite_token(&self) -> anyhow::Result<String> {
+        anyhow::bail!("the satellite is not available when using a parties snapshot")
+    }
+
+    async fn validate_party(
+        &self,
+        now: DateTime<Utc>,
+        eori: &str,
+    ) -> Result<PartyInfo, ValidatePartyError> {
+        return parse_party_info(&self.get_party(now, eori)?);
+    }
+
+    async fn get_party_details(
+        &self,
+        now: DateTime<Utc>,
+        eori: &str,
+    ) -> Result<PartyDetails, ValidatePartyError> {
+        return parse_party_details(&self.get_party(now, eori)?);
+    }
+
+    async fn verify_previous_step_client_assertion(
+        &self,
+        now: DateTime<Utc>,
+        requestor_company_id: &str,
+        client_assertion: &str,
+        issuer: &str,
+    ) -> anyhow::Result<()> {
+        let party_info = self
+            .validate_party(now, issuer)
+            .await
+            .context(format!("error validating ishare party '{}'", issuer))?;
+
+        return self
+            .client_assertions
+            .verify_previous_step_client_assertion(
+                now,
+                requestor_company_id,
+                client_assertion,
+                issuer,
+                &party_info,
+            )
+            .await;
+    }
+
+    async fn verify_delegation_token(
+        &self,
+        now: DateTime<Utc>,
+        delegation_token: &str,
+        issuer: &str,
+        audience: Option<&str>,
+    ) -> anyhow::Result<DelegationEvidence> {
+        let party_info = self
+            .validate_party(now, issuer)
+            .await
+            .context(format!("error validating ishare party '{}'", issuer))?;
+
+        return self.client_assertions.verify_delegation_token(
+            now,
+            delegation_token,
+            issuer,
+            audience,
+            &party_info,
+        );
+    }
+
+    async fn handle_m2m_authentication(
+        &self,
+        now: DateTime<Utc>,
+        client_id: &str,
+        grant_type: &str,
+        client_assertion: &str,
+        client_assertion_type: &str,
+        scope: &str,
+        validate_certificate: bool,
+    ) -> Result<String, AppError> {
+        let client_assertion_token = self
+            .client_assertions
+            .validate_client_assertion(
+                now,
+                client_id,
+                grant_type,
+                client_assertion,
+                client_assertion_type,
+                scope,
+            )
+            .await?;
+
+        let party_info = self
+            .validate_party(now, client_id)
+            .await
+            .context(format!("error validating ishare party '{}'", &client_id))?;
+
+        return self
+            .client_assertions
+            .complete_m2m_authentication(
+                now,
+                client_id,
+                &client_assertion_token,
+                &party_info,
+                validate_certificate,
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn get_snapshot() -> PartiesSnapshot {
+        let party = |eori: &str, status: &str| {
+            json!({
+                "party_id": eori,
+                "party_name": "Party",
+                "adherence": {
+                    "status": status,
+                    "start_date": "2024-01-01T00:00:00.000Z",
+                    "end_date": "2099-01-01T00:00:00.000Z"
+                },
+                "certificates": [],
+                "capability_url": "",
+                "agreements": [],
+                "roles": [{ "role": "ServiceProvider" }]
+            })
+        };
+
+        return PartiesSnapshot {
+            created_at: chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+                .unwrap()
+                .to_utc(),
+            satellite_eori: "EU.EORI.SATELLITE".to_owned(),
+            parties: BTreeMap::from([
+                ("NL.ACTIVE".to_owned(), party("NL.ACTIVE", "Active")),
+                ("NL.REVOKED".to_owned(), party("NL.REVOKED", "Revoked")),
+            ]),
+        };
+    }
+
+    #[test]
+    fn test_get_party() {
+        let snapshot = get_snapshot();
+        let now = snapshot.created_at;
+        let get_party =
+            |eori: &str| check_party_info(now, eori, snapshot.parties.get(eori).cloned(), None);
+
+        let party_info = parse_party_info(&get_party("NL.ACTIVE").unwrap()).unwrap();
+        assert_eq!(party_info.party_id, "NL.ACTIVE");
+
+        assert!(matches!(
+            get_party("NL.REVOKED"),
+            Err(ValidatePartyError::Inactive(_))
+        ));
+        assert!(matches!(
+            get_party("NL.UNKNOWN"),
+            Err(ValidatePartyError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_snapshot() {
+        let path = std::env::temp_dir().join(format!("parties-{}.json", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+
+        get_snapshot().write(path).unwrap();
+        let snapshot = PartiesSnapshot::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(snapshot.satellite_eori, "EU.EORI.SATELLITE");
+        assert_eq!(snapshot.parties.len(), 2);
+        // fields unknown to the ishare crate are kept
+        assert_eq!(
+            snapshot.parties["NL.ACTIVE"]["roles"][0]["role"],
+            "ServiceProvider"
+        );
+    }
+}
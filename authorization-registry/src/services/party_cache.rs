@@ -0,0 +1,373 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use ishare::ishare::ValidatePartyError;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::config::PartyCacheConfig;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PartyCacheStatus {
+    Valid,
+    Inactive,
+    NotFound,
+    AdherenceExpired,
+    DataspaceAgreementNotFound,
+}
+
+enum CachedResult {
+    Valid(serde_json::Value),
+    Inactive,
+    NotFound,
+    AdherenceExpired,
+    DataspaceAgreementNotFound(String),
+}
+
+struct CacheEntry {
+    result: CachedResult,
+    fetched_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct PartyCacheEntry {
+    pub eori: String,
+    pub status: PartyCacheStatus,
+    pub party_name: Option<String>,
+    #[schema(value_type = String, format = DateTime)]
+    pub fetched_at: DateTime<Utc>,
+    #[schema(value_type = String, format = DateTime)]
+    pub expires_at: DateTime<Utc>,
+    // expired, but still used while the satellite can not be reached
+    pub stale: bool,
+}
+
+impl CachedResult {
+    fn from_result(result: &Result<serde_json::Value, ValidatePartyError>) -> Option<Self> {
+        let cached = match result {
+            Ok(party) => CachedResult::Valid(party.clone()),
+            Err(ValidatePartyError::Inactive(_)) => CachedResult::Inactive,
+            Err(ValidatePartyError::NotFound(_)) => CachedResult::NotFound,
+            Err(ValidatePartyError::AdherenceExpired) => CachedResult::AdherenceExpired,
+            Err(ValidatePartyError::DataspaceAgreementNotFound(dataspaces)) => {
+                CachedResult::DataspaceAgreementNotFound(dataspaces.clone())
+            }
+            // the satellite could not be asked, nothing is known about the party
+            Err(ValidatePartyError::Unexpected(_)) => return None,
+        };
+
+        return Some(cached);
+    }
+
+    fn to_result(&self, eori: &str) -> Result<serde_json::Value, ValidatePartyError> {
+        return match self {
+            CachedResult::Valid(party) => Ok(party.clone()),
+            CachedResult::Inactive => Err(ValidatePartyError::Inactive(eori.to_owned())),
+            CachedResult::NotFound => Err(ValidatePartyError::NotFound(eori.to_owned())),
+            CachedResult::AdherenceExpired => Err(ValidatePartyError::AdherenceExpired),
+            CachedResult::DataspaceAgreementNotFound(dataspaces) => Err(
+                ValidatePartyError::DataspaceAgreementNotFound(dataspaces.clone()),
+            ),
+        };
+    }
+
+    fn status(&self) -> PartyCacheStatus {
+        return match self {
+            CachedResult::Valid(_) => PartyCacheStatus::Valid,
+            CachedResult::Inactive => PartyCacheStatus::Inactive,
+            CachedResult::NotFound => PartyCacheStatus::NotFound,
+            CachedResult::AdherenceExpired => PartyCacheStatus::AdherenceExpired,
+            CachedResult::DataspaceAgreementNotFound(_) => {
+                PartyCacheStatus::DataspaceAgreementNotFound
+            }
+        };
+    }
+}
+
+// caches the answers of the satellite per party. Unknown and inactive parties are cached for
+// a shorter time, and when the satellite can not be reached an expired entry is used for at
+// most `max_stale_seconds`
+pub struct PartyCache {
+    config: PartyCacheConfig,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl PartyCache {
+    pub fn new(config: PartyCacheConfig) -> Self {
+        return Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        };
+    }
+
+    fn expires_at(&self, entry: &CacheEntry) -> DateTime<Utc> {
+        let ttl = match entry.result {
+            CachedResult::Valid(_) => self.config.ttl_seconds,
+            _ => self.config.negative_ttl_seconds,
+        };
+
+        return entry.fetched_at + TimeDelta::seconds(ttl);
+    }
+
+    fn stale_until(&self, entry: &CacheEntry) -> DateTime<Utc> {
+        return self.expires_at(entry) + TimeDelta::seconds(self.config.max_stale_seconds);
+    }
+
+    fn lock_entries(
+        &self,
+    ) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, CacheEntry>>> {
+        return self
+            .entries
+            .lock()
+            .map_err(|_| anyhow::anyhow!("party cache lock is poisoned"));
+    }
+
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        now: DateTime<Utc>,
+        eori: &str,
+        fetch: F,
+    ) -> Result<serde_json::Value, ValidatePartyError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<serde_json::Value, ValidatePartyError>>,
+    {
+        if self.config.ttl_seconds <= 0 {
+            return fetch().await;
+        }
+
+        {
+            let entries = self.lock_entries()?;
+            if let Some(entry) = entries.get(eori) {
+                if self.expires_at(entry) > now {
+                    return entry.result.to_result(eori);
+                }
+            }
+        }
+
+        let result = fetch().await;
+        let mut entries = self.lock_entries()?;
+
+        match CachedResult::from_result(&result) {
+            Some(cached) => {
+                entries.retain(|_, entry| self.stale_until(entry) > now);
+                entries.insert(
+                    eori.to_owned(),
+                    CacheEntry {
+                        result: cached,
+                        fetched_at: now,
+                    },
+                );
+
+                return result;
+            }
+            None => match entries.get(eori) {
+                Some(entry) if self.stale_until(entry) > now => {
+                    tracing::warn!(
+                        "satellite could not be reached, using party info of '{}' fetched at {}: {:?}",
+                        eori,
+                        entry.fetched_at,
+                        result.err()
+                    );
+
+                    return entry.result.to_result(eori);
+                }
+                _ => return result,
+            },
+        }
+    }
+
+    pub fn get_entries(&self, now: DateTime<Utc>) -> anyhow::Result<Vec<PartyCacheEntry>> {
+        let entries = self.lock_entries()?;
+
+        let mut result: Vec<PartyCacheEntry> = entries
+            .iter()
+            .filter(|(_, entry)| self.stale_until(entry) > now)
+            .map(|(eori, entry)| {
+                let expires_at = self.expires_at(entry);
+                let party_name = match &entry.result {
+                    CachedResult::Valid(value) => {
+                        value["party_name"].as_str().map(|n| n.to_owned())
+                    }
+                    _ => None,
+                };
+
+                PartyCacheEntry {
+                    eori: eori.clone(),
+                    status: entry.result.status(),
+                    party_name,
+                    fetched_at: entry.fetched_at,
+                    expires_at,
+                    stale: expires_at <= now,
+                }
+            })
+            .collect();
+        result.sort_by(|a, b| a.eori.cmp(&b.eori));
+
+        return Ok(result);
+    }
+
+    // returns false when the party was not cached
+    pub fn remove(&self, eori: &str) -> anyhow::Result<bool> {
+        return Ok(self.lock_entries()?.remove(eori).is_some());
+    }
+
+    pub fn clear(&self) -> anyhow::Result<u64> {
+        let mut entries = self.lock_entries()?;
+        let count = entries.len();
+        entries.clear();
+
+        return Ok(count as u64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn party_info(eori: &str) -> serde_json::Value {
+        return json!({ "party_id": eori, "party_name": "Party" });
+    }
+
+    fn get_cache() -> PartyCache {
+        return PartyCache::new(PartyCacheConfig {
+            ttl_seconds: 60,
+            negative_ttl_seconds: 10,
+            max_stale_seconds: 120,
+        });
+    }
+
+    async fn lookup(
+        cache: &PartyCache,
+        now: DateTime<Utc>,
+        calls: &AtomicU32,
+        response: Result<serde_json::Value, ValidatePartyError>,
+    ) -> Result<serde_json::Value, ValidatePartyError> {
+        return cache
+            .get_or_fetch(now, "NL.A", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                response
+            })
+            .await;
+    }
+
+    fn unreachable() -> Result<serde_json::Value, ValidatePartyError> {
+        return Err(ValidatePartyError::Unexpected(anyhow::anyhow!(
+            "satellite is down"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_party_cache() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let cache = get_cache();
+        let calls = AtomicU32::new(0);
+
+        let party = lookup(&cache, now, &calls, Ok(party_info("NL.A")))
+            .await
+            .unwrap();
+        assert_eq!(party["party_id"], "NL.A");
+
+        // served from the cache within the ttl
+        let party = lookup(&cache, now + TimeDelta::seconds(59), &calls, unreachable())
+            .await
+            .unwrap();
+        assert_eq!(party["party_name"], "Party");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // stale entries are only used when the satellite can not be reached
+        assert!(
+            lookup(&cache, now + TimeDelta::seconds(90), &calls, unreachable())
+                .await
+                .is_ok()
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let entries = cache.get_entries(now + TimeDelta::seconds(90)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].status, PartyCacheStatus::Valid);
+        assert_eq!(entries[0].party_name, Some("Party".to_owned()));
+        assert!(entries[0].stale);
+
+        // beyond the maximum staleness the error is returned
+        assert!(matches!(
+            lookup(&cache, now + TimeDelta::seconds(180), &calls, unreachable()).await,
+            Err(ValidatePartyError::Unexpected(_))
+        ));
+        assert!(cache
+            .get_entries(now + TimeDelta::seconds(180))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_party_cache_negative() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let cache = get_cache();
+        let calls = AtomicU32::new(0);
+
+        let not_found = || Err(ValidatePartyError::NotFound("NL.A".to_owned()));
+
+        assert!(matches!(
+            lookup(&cache, now, &calls, not_found()).await,
+            Err(ValidatePartyError::NotFound(_))
+        ));
+        assert!(matches!(
+            lookup(
+                &cache,
+                now + TimeDelta::seconds(9),
+                &calls,
+                Ok(party_info("NL.A"))
+            )
+            .await,
+            Err(ValidatePartyError::NotFound(_))
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // the negative ttl is shorter than the ttl of valid parties
+        assert!(lookup(
+            &cache,
+            now + TimeDelta::seconds(10),
+            &calls,
+            Ok(party_info("NL.A"))
+        )
+        .await
+        .is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_party_cache_flush() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let cache = get_cache();
+        let calls = AtomicU32::new(0);
+
+        lookup(&cache, now, &calls, Ok(party_info("NL.A")))
+            .await
+            .unwrap();
+
+        assert!(cache.remove("NL.A").unwrap());
+        assert!(!cache.remove("NL.A").unwrap());
+
+        // removed entries are fetched again, and can not be used when the satellite is down
+        assert!(lookup(&cache, now, &calls, unreachable()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        lookup(&cache, now, &calls, Ok(party_info("NL.A")))
+            .await
+            .unwrap();
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(cache.get_entries(now).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+use chrono::{DateTime, Utc};
+use ishare::ishare::ValidatePartyError;
+use reqwest::StatusCode;
+
+use crate::{
+    config::PartyChecksConfig,
+    error::{AppError, ExpectedError},
+};
+
+use super::{
+    ishare_provider::SatelliteProvider,
+    party_info::{PartyDetails, PartyRole},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PartyFunction {
+    PolicyIssuer,
+    AccessSubject,
+    ServiceProvider,
+}
+
+impl PartyFunction {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            PartyFunction::PolicyIssuer => "policy issuer",
+            PartyFunction::AccessSubject => "access subject",
+            PartyFunction::ServiceProvider => "service provider",
+        };
+    }
+
+    fn required_roles<'a>(&self, config: &'a PartyChecksConfig) -> &'a [String] {
+        return match self {
+            PartyFunction::PolicyIssuer => &config.policy_issuer_roles,
+            PartyFunction::AccessSubject => &config.access_subject_roles,
+            PartyFunction::ServiceProvider => &config.service_provider_roles,
+        };
+    }
+}
+
+fn covers(
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    valid_from: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+) -> bool {
+    return start_date.is_none_or(|start| start <= valid_from)
+        && end_date.is_none_or(|end| end >= valid_until);
+}
+
+fn holds_role(
+    role: &PartyRole,
+    required_roles: &[String],
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> bool {
+    return required_roles.contains(&role.role)
+        && covers(role.start_date, role.end_date, from, until);
+}
+
+// returns the rule the party does not comply with
+pub fn check_party_details(
+    details: &PartyDetails,
+    function: PartyFunction,
+    config: &PartyChecksConfig,
+    valid_from: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+) -> Result<(), String> {
+    if config.adherence
+        && !covers(
+            details.adherence.start_date,
+            details.adherence.end_date,
+            valid_from,
+            valid_until,
+        )
+    {
+        return Err(format!(
+            "adherence (from {} until {}) does not cover the period from {} until {}",
+            details
+                .adherence
+                .start_date
+                .map_or("-".to_owned(), |d| d.to_rfc3339()),
+            details
+                .adherence
+                .end_date
+                .map_or("-".to_owned(), |d| d.to_rfc3339()),
+            valid_from.to_rfc3339(),
+            valid_until.to_rfc3339(),
+        ));
+    }
+
+    let required_roles = function.required_roles(config);
+    if !required_roles.is_empty()
+        && !details
+            .roles
+            .iter()
+            .any(|role| holds_role(role, required_roles, valid_from, valid_until))
+    {
+        return Err(format!(
+            "does not hold any of the roles [{}] from {} until {}",
+            required_roles.join(", "),
+            valid_from.to_rfc3339(),
+            valid_until.to_rfc3339(),
+        ));
+    }
+
+    return Ok(());
+}
+
+// the rule the party does not comply with for the period in which it acts as `function`
+pub async fn get_party_compliance(
+    satellite_provider: &dyn SatelliteProvider,
+    config: &PartyChecksConfig,
+    eori: &str,
+    function: PartyFunction,
+    valid_from: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+) -> Result<Option<String>, ValidatePartyError> {
+    if !config.adherence && function.required_roles(config).is_empty() {
+        return Ok(None);
+    }
+
+    let details = satellite_provider
+        .get_party_details(valid_from, eori)
+        .await?;
+
+    return Ok(check_party_details(&details, function, config, valid_from, valid_until).err());
+}
+
+// checks the adherence and roles of a party that is already validated with `validate_party`,
+// for the period in which it acts as `function`
+pub async fn check_party_compliance(
+    satellite_provider: &dyn SatelliteProvider,
+    config: &PartyChecksConfig,
+    eori: &str,
+    function: PartyFunction,
+    valid_from: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let failed_rule = get_party_compliance(
+        satellite_provider,
+        config,
+        eori,
+        function,
+        valid_from,
+        valid_until,
+    )
+    .await
+    .map_err(|e| {
+        AppError::Expected(ExpectedError {
+            status_code: StatusCode::BAD_REQUEST,
+            message: format!(
+                "Unable to verify {} '{}' as valid iSHARE party",
+                function.name(),
+                eori
+            ),
+            reason: format!("{:?}", e),
+            metadata: None,
+        })
+    })?;
+
+    return match failed_rule {
+        None => Ok(()),
+        Some(rule) => Err(AppError::Expected(ExpectedError {
+            status_code: StatusCode::BAD_REQUEST,
+            message: format!("{} '{}' {}", function.name(), eori, rule),
+            reason: format!(
+                "{} '{}' does not comply with the party checks",
+                function.name(),
+                eori
+            ),
+            metadata: None,
+        })),
+    };
+}
+
+// validates the party at the satellite and checks that it complies as `function` now
+pub async fn validate_party(
+    now: DateTime<Utc>,
+    satellite_provider: &dyn SatelliteProvider,
+    config: &PartyChecksConfig,
+    eori: &str,
+    function: PartyFunction,
+) -> Result<(), AppError> {
+    satellite_provider
+        .validate_party(now, eori)
+        .await
+        .map_err(|e| {
+            AppError::Expected(ExpectedError {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!(
+                    "Unable to verify {} '{}' as valid iSHARE party",
+                    function.name(),
+                    eori
+                ),
+                reason: format!("{:?}", e),
+                metadata: None,
+            })
+        })?;
+
+    return check_party_compliance(satellite_provider, config, eori, function, now, now).await;
+}
+
+pub async fn validate_service_providers(
+    now: DateTime<Utc>,
+    satellite_provider: &dyn SatelliteProvider,
+    config: &PartyChecksConfig,
+    service_providers: &[String],
+) -> Result<(), AppError> {
+    for sp in service_providers.iter() {
+        validate_party(
+            now,
+            satellite_provider,
+            config,
+            sp,
+            PartyFunction::ServiceProvider,
+        )
+        .await?;
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeDelta;
+
+    use crate::services::party_info::PartyAdherence;
+    use crate::test_helpers::helpers::get_test_party_checks;
+
+    use super::*;
+
+    fn date(date: &str) -> Option<DateTime<Utc>> {
+        return Some(DateTime::parse_from_rfc3339(date).unwrap().to_utc());
+    }
+
+    fn get_details() -> PartyDetails {
+        return PartyDetails {
+            party_id: "NL.A".to_owned(),
+            adherence: PartyAdherence {
+                status: "Active".to_owned(),
+                start_date: date("2024-01-01T00:00:00Z"),
+                end_date: date("2025-01-01T00:00:00Z"),
+            },
+            roles: vec![
+                PartyRole {
+                    role: "ServiceConsumer".to_owned(),
+                    start_date: None,
+                    end_date: None,
+                },
+                PartyRole {
+                    role: "ServiceProvider".to_owned(),
+                    start_date: date("2024-01-01T00:00:00Z"),
+                    end_date: date("2024-06-01T00:00:00Z"),
+                },
+            ],
+        };
+    }
+
+    #[test]
+    fn test_check_party_details_adherence() {
+        let details = get_details();
+        let config = PartyChecksConfig {
+            service_provider_roles: vec![],
+            ..get_test_party_checks()
+        };
+        let from = date("2024-05-09T00:00:00Z").unwrap();
+        let check = |from, until| {
+            check_party_details(
+                &details,
+                PartyFunction::ServiceProvider,
+                &config,
+                from,
+                until,
+            )
+        };
+
+        assert!(check(from, from + TimeDelta::hours(1)).is_ok());
+        // the adherence ends within the period
+        assert!(check(from, date("2025-02-01T00:00:00Z").unwrap()).is_err());
+        // the adherence starts after the period
+        assert!(check(date("2023-12-01T00:00:00Z").unwrap(), from).is_err());
+
+        let config = PartyChecksConfig {
+            adherence: false,
+            service_provider_roles: vec![],
+            ..get_test_party_checks()
+        };
+        assert!(check_party_details(
+            &details,
+            PartyFunction::ServiceProvider,
+            &config,
+            from,
+            date("2025-02-01T00:00:00Z").unwrap()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_check_party_details_roles() {
+        let details = get_details();
+        let config = PartyChecksConfig {
+            access_subject_roles: vec!["EntitledParty".to_owned()],
+            ..get_test_party_checks()
+        };
+        let from = date("2024-05-09T00:00:00Z").unwrap();
+        let check = |function, until| check_party_details(&details, function, &config, from, until);
+
+        assert!(check(PartyFunction::ServiceProvider, from + TimeDelta::hours(1)).is_ok());
+        // the role ends before the adherence does
+        assert!(check(
+            PartyFunction::ServiceProvider,
+            date("2024-07-01T00:00:00Z").unwrap()
+        )
+        .is_err());
+        assert_eq!(
+            check(PartyFunction::AccessSubject, from).unwrap_err(),
+            "does not hold any of the roles [EntitledParty] from 2024-05-09T00:00:00+00:00 until 2024-05-09T00:00:00+00:00"
+        );
+        // no roles are required of policy issuers by default
+        assert!(check(PartyFunction::PolicyIssuer, from).is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use ishare::ishare::{AllowedDataspaces, PartyInfo, ValidatePartyError, ISHARE};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PartyAdherence {
+    pub status: String,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PartyRole {
+    pub role: String,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+// the parts of the party info of the satellite that PartyInfo of the ishare crate does not
+// contain
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PartyDetails {
+    pub party_id: String,
+    pub adherence: PartyAdherence,
+    #[serde(default)]
+    pub roles: Vec<PartyRole>,
+}
+
+#[derive(Deserialize)]
+struct PartyInfoClaims {
+    party_info: serde_json::Value,
+}
+
+// fetches the party info of the satellite as json, so no fields are lost. Returns None for
+// parties that are unknown at the satellite
+pub async fn fetch_party_json(
+    ishare: &ISHARE,
+    satellite_token: &str,
+    eori: &str,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let party_token = ishare
+        .parties(eori, satellite_token)
+        .await
+        .context("Error fetching ishare parties information")?;
+
+    if !ishare
+        .validate_token(&party_token)
+        .context("Error validating parties token")?
+    {
+        anyhow::bail!("Party token of '{}' is invalid", eori);
+    }
+
+    let decoded = ishare
+        .decode_token_custom_claims::<PartyInfoClaims>(&party_token, None)
+        .context(format!("Error decoding party token of '{}'", eori))?;
+
+    // the satellite answers with an empty object for unknown parties
+    let party_info = decoded.claims.extra.party_info;
+    if party_info.as_object().is_some_and(|p| p.is_empty()) {
+        return Ok(None);
+    }
+
+    return Ok(Some(party_info));
+}
+
+// any of the dataspaces of which the party has all required agreement types
+fn dataspace_agreement_exists(
+    party: &serde_json::Value,
+    allowed_dataspaces: &AllowedDataspaces,
+) -> bool {
+    let agreements = party["agreements"].as_array().cloned().unwrap_or_default();
+
+    return allowed_dataspaces.dataspace_ids.iter().any(|dataspace| {
+        allowed_dataspaces
+            .required_agreement_types
+            .iter()
+            .all(|agreement_type| {
+                agreements.iter().any(|agreement| {
+                    agreement["type"].as_str() == Some(agreement_type)
+                        && agreement["dataspace_id"].as_str() == Some(dataspace)
+                })
+            })
+    });
+}
+
+// the checks of the ishare crate on the party info of the satellite
+pub fn check_party_info(
+    now: DateTime<Utc>,
+    eori: &str,
+    party: Option<serde_json::Value>,
+    allowed_dataspaces: Option<&AllowedDataspaces>,
+) -> Result<serde_json::Value, ValidatePartyError> {
+    let party = party.ok_or_else(|| ValidatePartyError::NotFound(eori.to_owned()))?;
+    let party_info = parse_party_info(&party)?;
+
+    if party_info.adherence.status != "Active" {
+        return Err(ValidatePartyError::Inactive(eori.to_owned()));
+    }
+
+    if let Some(allowed_dataspaces) = allowed_dataspaces {
+        if !dataspace_agreement_exists(&party, allowed_dataspaces) {
+            return Err(ValidatePartyError::DataspaceAgreementNotFound(
+                allowed_dataspaces.dataspace_ids.join(", "),
+            ));
+        }
+    }
+
+    let end_date =
+        DateTime::parse_from_rfc3339(&party_info.adherence.end_date).context(format!(
+            "Error parsing adherence end date {}",
+            &party_info.adherence.end_date
+        ))?;
+
+    if now > end_date {
+        tracing::warn!("end_date of adherence is expired");
+    }
+
+    return Ok(party);
+}
+
+pub fn parse_party_info(party: &serde_json::Value) -> Result<PartyInfo, ValidatePartyError> {
+    return Ok(serde_json::from_value(party.clone()).context("Error parsing party info")?);
+}
+
+pub fn parse_party_details(party: &serde_json::Value) -> Result<PartyDetails, ValidatePartyError> {
+    return Ok(serde_json::from_value(party.clone()).context("Error parsing party details")?);
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn party(eori: &str, status: &str) -> serde_json::Value {
+        return json!({
+            "party_id": eori,
+            "party_name": "Party",
+            "adherence": {
+                "status": status,
+                "start_date": "2024-01-01T00:00:00.000Z",
+                "end_date": "2099-01-01T00:00:00.000Z"
+            },
+            "certificates": [],
+            "capability_url": "",
+            "agreements": [
+                { "dataspace_id": "DS1", "type": "TermsOfUse" },
+                { "dataspace_id": "DS1", "type": "Accession" },
+                { "dataspace_id": "DS2", "type": "TermsOfUse" }
+            ],
+            "roles": [{
+                "role": "ServiceProvider",
+                "start_date": "2024-01-01T00:00:00.000Z",
+                "end_date": "2099-01-01T00:00:00.000Z",
+                "loa": 1
+            }]
+        });
+    }
+
+    fn dataspaces(dataspace_ids: &[&str]) -> AllowedDataspaces {
+        return AllowedDataspaces {
+            dataspace_ids: dataspace_ids.iter().map(|d| d.to_string()).collect(),
+            required_agreement_types: vec!["TermsOfUse".to_owned(), "Accession".to_owned()],
+        };
+    }
+
+    #[test]
+    fn test_check_party_info() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let checked = check_party_info(now, "NL.A", Some(party("NL.A", "Active")), None).unwrap();
+        assert_eq!(parse_party_info(&checked).unwrap().party_id, "NL.A");
+
+        let details = parse_party_details(&checked).unwrap();
+        assert_eq!(details.roles[0].role, "ServiceProvider");
+        assert!(details.adherence.start_date.is_some());
+
+        assert!(matches!(
+            check_party_info(now, "NL.A", Some(party("NL.A", "Revoked")), None),
+            Err(ValidatePartyError::Inactive(_))
+        ));
+        assert!(matches!(
+            check_party_info(now, "NL.A", None, None),
+            Err(ValidatePartyError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_party_info_dataspaces() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let check = |dataspace_ids: &[&str]| {
+            check_party_info(
+                now,
+                "NL.A",
+                Some(party("NL.A", "Active")),
+                Some(&dataspaces(dataspace_ids)),
+            )
+        };
+
+        assert!(check(&["DS1"]).is_ok());
+        assert!(check(&["DS2", "DS1"]).is_ok());
+        // the party lacks the Accession agreement for DS2
+        assert!(matches!(
+            check(&["DS2"]),
+            Err(ValidatePartyError::DataspaceAgreementNotFound(d)) if d == "DS2"
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use ishare::ishare::ValidatePartyError;
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    config::{PartyChecksConfig, PartyRevalidationConfig},
+    db::{policy_set_suspension as suspension_store, replication as replication_store},
+    TimeProvider,
+};
+
+use super::{
+    audit_log::{
+        log_event, EventType, PolicySetSuspendedEventMetadata,
+        PolicySetSuspensionLiftedEventMetadata,
+    },
+    dataspace::Dataspaces,
+    party_compliance::{get_party_compliance, PartyFunction},
+};
+
+#[derive(Serialize, Debug, Default, ToSchema)]
+pub struct RevalidationSummary {
+    pub parties_checked: u64,
+    // parties the satellite could not be asked about, their policy sets are left as they are
+    pub parties_unavailable: u64,
+    pub suspended: u64,
+    pub lifted: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Compliance {
+    Compliant,
+    NonCompliant(String),
+    Unknown,
+}
+
+// a party in one of its functions, validated at the satellite of the dataspace of the policy set
+type PartyKey = (Option<String>, String, PartyFunction);
+
+fn parse_function(function: &str) -> anyhow::Result<PartyFunction> {
+    return match function {
+        "policy_issuer" => Ok(PartyFunction::PolicyIssuer),
+        "access_subject" => Ok(PartyFunction::AccessSubject),
+        "service_provider" => Ok(PartyFunction::ServiceProvider),
+        _ => anyhow::bail!("unknown party function '{}'", function),
+    };
+}
+
+async fn get_compliance(
+    now: DateTime<Utc>,
+    dataspaces: &Dataspaces,
+    dataspace: Option<&str>,
+    config: &PartyChecksConfig,
+    eori: &str,
+    function: PartyFunction,
+) -> Compliance {
+    let satellite_provider = match dataspaces.satellite_provider(dataspace) {
+        Ok(satellite_provider) => satellite_provider,
+        Err(e) => {
+            tracing::warn!("unable to revalidate party '{}': {:?}", eori, e);
+            return Compliance::Unknown;
+        }
+    };
+    let satellite_provider = satellite_provider.as_ref();
+
+    let result = match satellite_provider.validate_party(now, eori).await {
+        Ok(_) => get_party_compliance(satellite_provider, config, eori, function, now, now).await,
+        Err(e) => Err(e),
+    };
+
+    return match result {
+        Ok(None) => Compliance::Compliant,
+        Ok(Some(rule)) => {
+            Compliance::NonCompliant(format!("{} '{}' {}", function.name(), eori, rule))
+        }
+        Err(ValidatePartyError::Unexpected(e)) => {
+            tracing::warn!("unable to revalidate party '{}': {:?}", eori, e);
+            Compliance::Unknown
+        }
+        Err(e) => Compliance::NonCompliant(format!(
+            "{} '{}' is not a valid iSHARE party: {}",
+            function.name(),
+            eori,
+            e
+        )),
+    };
+}
+
+// a party that has several functions in a policy set has to comply with all of them
+fn combine(results: &[&Compliance]) -> Compliance {
+    if let Some(non_compliant) = results
+        .iter()
+        .find(|r| matches!(r, Compliance::NonCompliant(_)))
+    {
+        return (*non_compliant).clone();
+    }
+
+    if results.iter().any(|r| **r == Compliance::Unknown) {
+        return Compliance::Unknown;
+    }
+
+    return Compliance::Compliant;
+}
+
+// revalidates every party of the stored policy sets. Policy sets with a party that is no longer
+// valid are suspended, and the suspension is lifted when the party is valid again
+pub async fn revalidate_parties(
+    now: DateTime<Utc>,
+    dataspaces: &Dataspaces,
+    config: &PartyChecksConfig,
+    db: &DatabaseConnection,
+) -> anyhow::Result<RevalidationSummary> {
+    let references = suspension_store::get_party_references(db).await?;
+
+    let mut results: HashMap<PartyKey, Compliance> = HashMap::new();
+    let mut policy_set_parties: BTreeMap<(Uuid, String), Vec<PartyKey>> = BTreeMap::new();
+
+    for reference in references.iter() {
+        let function = parse_function(&reference.function)?;
+        let key = (
+            reference.dataspace.clone(),
+            reference.party.clone(),
+            function,
+        );
+        policy_set_parties
+            .entry((reference.policy_set_id, reference.party.clone()))
+            .or_default()
+            .push(key.clone());
+
+        if let Entry::Vacant(entry) = results.entry(key) {
+            entry.insert(
+                get_compliance(
+                    now,
+                    dataspaces,
+                    reference.dataspace.as_deref(),
+                    config,
+                    &reference.party,
+                    function,
+                )
+                .await,
+            );
+        }
+    }
+
+    let mut summary = RevalidationSummary::default();
+    let mut parties: Vec<&String> = results.keys().map(|(_, party, _)| party).collect();
+    parties.sort();
+    parties.dedup();
+    summary.parties_checked = parties.len() as u64;
+    summary.parties_unavailable = parties
+        .iter()
+        .filter(|party| {
+            results
+                .iter()
+                .any(|((_, p, _), c)| p == **party && *c == Compliance::Unknown)
+        })
+        .count() as u64;
+
+    let mut suspensions: HashMap<(Uuid, String), ar_entity::policy_set_suspension::Model> =
+        suspension_store::get_suspensions(db)
+            .await?
+            .into_iter()
+            .map(|s| ((s.policy_set_id, s.party.clone()), s))
+            .collect();
+
+    let transaction = db.begin().await.context("Error opening db transaction")?;
+
+    for ((policy_set_id, party), keys) in policy_set_parties.iter() {
+        let party_results: Vec<&Compliance> =
+            keys.iter().filter_map(|key| results.get(key)).collect();
+        let suspended = suspensions
+            .remove(&(*policy_set_id, party.clone()))
+            .is_some();
+
+        match (combine(&party_results), suspended) {
+            (Compliance::NonCompliant(reason), false) => {
+                tracing::warn!("suspending policy set '{}': {}", policy_set_id, reason);
+                suspension_store::insert_suspension(
+                    now,
+                    *policy_set_id,
+                    party,
+                    &reason,
+                    &transaction,
+                )
+                .await?;
+                log_event(
+                    now,
+                    policy_set_id.to_string(),
+                    EventType::ArPolicySetSuspended(PolicySetSuspendedEventMetadata {
+                        policy_set_id: *policy_set_id,
+                        party: party.clone(),
+                        reason,
+                    }),
+                    None,
+                    None,
+                    &transaction,
+                )
+                .await
+                .context("error logging policy set suspended event")?;
+                // the copies at the replication targets are removed
+                replication_store::record_change(now, *policy_set_id, &transaction)
+                    .await
+                    .context("error recording policy set change for replication")?;
+                summary.suspended += 1;
+            }
+            (Compliance::Compliant, true) => {
+                lift_suspension(now, *policy_set_id, party, &transaction).await?;
+                summary.lifted += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // the party is no longer part of the policy set
+    for (policy_set_id, party) in suspensions.into_keys() {
+        lift_suspension(now, policy_set_id, &party, &transaction).await?;
+        summary.lifted += 1;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Error commiting transaction to db")?;
+
+    return Ok(summary);
+}
+
+async fn lift_suspension(
+    now: DateTime<Utc>,
+    policy_set_id: Uuid,
+    party: &str,
+    transaction: &sea_orm::DatabaseTransaction,
+) -> anyhow::Result<()> {
+    tracing::info!(
+        "lifting suspension of policy set '{}' for party '{}'",
+        policy_set_id,
+        party
+    );
+    suspension_store::delete_suspension(policy_set_id, party, transaction).await?;
+    log_event(
+        now,
+        policy_set_id.to_string(),
+        EventType::ArPolicySetSuspensionLifted(PolicySetSuspensionLiftedEventMetadata {
+            policy_set_id,
+            party: party.to_owned(),
+        }),
+        None,
+        None,
+        transaction,
+    )
+    .await
+    .context("error logging policy set suspension lifted event")?;
+    replication_store::record_change(now, policy_set_id, transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
+    return Ok(());
+}
+
+pub fn spawn_revalidation_job(
+    config: PartyRevalidationConfig,
+    party_checks: PartyChecksConfig,
+    dataspaces: Arc<Dataspaces>,
+    time_provider: Arc<dyn TimeProvider>,
+    db: DatabaseConnection,
+) {
+    if config.interval_seconds == 0 {
+        tracing::info!("revalidation of the parties of policy sets is disabled");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(config.interval_seconds));
+
+        loop {
+            interval.tick().await;
+
+            match revalidate_parties(time_provider.now(), &dataspaces, &party_checks, &db).await {
+                Ok(summary) => tracing::info!("revalidated parties of policy sets: {:?}", summary),
+                Err(e) => tracing::error!("error revalidating parties of policy sets: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use crate::{
+        db::policy::get_policy_sets_with_policies_for_creating_de,
+        fixtures::fixtures::insert_policy_set_fixture,
+        test_helpers::helpers::{get_test_dataspaces, get_test_party_checks, init_test_db},
+    };
+
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        return DateTime::parse_from_rfc3339("2024-05-09T09:33:25Z")
+            .unwrap()
+            .to_utc();
+    }
+
+    #[sqlx::test]
+    async fn test_revalidate_parties(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+
+        let policy_set_id = Uuid::parse_str("84b7fba4-05f3-4af8-9d84-dde384abe881").unwrap();
+        ar_entity::policy::Entity::update_many()
+            .col_expr(
+                ar_entity::policy::Column::ServiceProviders,
+                sea_orm::sea_query::Expr::value(vec!["NL.NO_ROLES".to_owned()]),
+            )
+            .filter(ar_entity::policy::Column::PolicySet.eq(policy_set_id))
+            .exec(&db)
+            .await
+            .unwrap();
+
+        let dataspaces = get_test_dataspaces();
+        let config = get_test_party_checks();
+
+        // the service provider does not hold the ServiceProvider role
+        let summary = revalidate_parties(now(), &dataspaces, &config, &db)
+            .await
+            .unwrap();
+        assert_eq!(summary.parties_checked, 3);
+        assert_eq!(summary.suspended, 1);
+
+        let suspensions = suspension_store::get_suspensions(&db).await.unwrap();
+        assert_eq!(suspensions.len(), 1);
+        assert_eq!(suspensions[0].party, "NL.NO_ROLES");
+        assert!(get_policy_sets_with_policies_for_creating_de(
+            "NL.44444".to_owned(),
+            "NL.24244".to_owned(),
+            None,
+            &db
+        )
+        .await
+        .unwrap()
+        .is_empty());
+
+        // nothing changes while the party does not comply
+        let summary = revalidate_parties(now(), &dataspaces, &config, &db)
+            .await
+            .unwrap();
+        assert_eq!(summary.suspended, 0);
+        assert_eq!(summary.lifted, 0);
+
+        let config = PartyChecksConfig {
+            service_provider_roles: vec![],
+            ..get_test_party_checks()
+        };
+        let summary = revalidate_parties(now(), &dataspaces, &config, &db)
+            .await
+            .unwrap();
+        assert_eq!(summary.lifted, 1);
+        assert!(suspension_store::get_suspensions(&db)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            get_policy_sets_with_policies_for_creating_de(
+                "NL.44444".to_owned(),
+                "NL.24244".to_owned(),
+                None,
+                &db
+            )
+            .await
+            .unwrap()
+            .len(),
+            1
+        );
+
+        let event_types: Vec<String> = ar_entity::audit_event::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.event_type)
+            .filter(|e| e.starts_with("dmi:ar:policy_set:suspe"))
+            .collect();
+        assert_eq!(
+            event_types.len(),
+            2,
+            "one suspended and one lifted event: {:?}",
+            event_types
+        );
+
+        Ok(())
+    }
+}
@@ -1,94 +1,34 @@
-use crate::error::{AppError, ExpectedError};
+use crate::services::federation::RemoteRegistry;
+use crate::services::replication::{ReplicatedPolicySet, ReplicationTarget};
 use crate::token_cache::TokenCache;
 use anyhow::Context;
 use axum::async_trait;
+use ishare::delegation_request::{DelegationRequest, DelegationRequestContainer};
 use ishare::ishare::ISHARE;
-use ishare::pdp::PolicySetInsertResponse;
-use ishare::pdp::PDP;
+use ishare::pdp::{PolicySetInsertResponse, PDP};
 use reqwest::StatusCode;
+use serde::Deserialize;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-#[derive(Debug, Clone)]
-pub enum ResourceAction {
-    Create,
-    Delete,
-    Read,
-    Edit,
-}
-
-impl ResourceAction {
-    pub fn as_str(&self) -> &str {
-        match self {
-            Self::Create => "Create",
-            Self::Delete => "Delete",
-            Self::Read => "Read",
-            Self::Edit => "Edit",
-        }
-    }
-}
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum PolicyResourceType {
-    OfferPolicy,
-    ClearingEvidence,
-    DataAccess,
-    DataAccessRequest,
-    Clearing,
-    DataHub,
-    AuditLog,
-}
-
-impl PolicyResourceType {
-    pub fn as_str(&self) -> &str {
-        match self {
-            Self::Clearing => "Clearing",
-            Self::ClearingEvidence => "ClearingEvidence",
-            Self::DataAccess => "DMI.DataAccess",
-            Self::DataAccessRequest => "DMI.DataAccessRequest",
-            Self::OfferPolicy => "OfferPolicy",
-            Self::DataHub => "DataHub",
-            Self::AuditLog => "AuditLog",
-        }
-    }
-}
-
-#[derive(PartialEq, Clone, Debug)]
-pub enum DataHubAttributes {
-    Member,
-}
-
-impl DataHubAttributes {
-    pub fn as_str(&self) -> &str {
-        match self {
-            Self::Member => "Member",
-        }
-    }
-}
-
+// decides whether an access subject may perform an action on behalf of a policy issuer. Without
+// an authorization provider these decisions are made with the policy sets of this registry
 #[async_trait]
 pub trait AuthorizationProvider: Send + Sync {
     async fn authorize(
         &self,
         access_subject: &str,
         policy_issuer: &str,
-        action: ResourceAction,
-        resource_type: PolicyResourceType,
+        action: &str,
+        resource_type: &str,
         identifiers: Option<Vec<String>>,
         attributes: Option<Vec<String>>,
-    ) -> Result<(), AppError>;
-
-    async fn append_delegation_evidence(
-        &self,
-        policy_issuer: &str,
-        access_subject: &str,
-        service_provider: &str,
-        resource_type: PolicyResourceType,
-        actions: Vec<ResourceAction>,
-        identifiers: Option<Vec<String>>,
-    ) -> Result<PolicySetInsertResponse, AppError>;
+    ) -> anyhow::Result<bool>;
 }
 
+// an external iSHARE PDP, asked for delegation evidence with this registry as service provider.
+// As replication target it receives the policy sets of this registry at its `/policy-set` api,
+// and as remote registry it is asked for delegation evidence on behalf of the requester
 #[derive(Clone)]
 pub struct PDPProvider {
     eori: String,
@@ -99,138 +39,164 @@ pub struct PDPProvider {
 
 impl PDPProvider {
     pub fn new(base_url: &str, eori: &str, ishare: Arc<ISHARE>) -> PDPProvider {
-        let token_cache = TokenCache::new();
         return PDPProvider {
             eori: eori.to_string(),
             base_url: base_url.to_string(),
-            ishare: ishare.clone(),
-            token_cache: token_cache.clone(),
+            ishare,
+            token_cache: TokenCache::new(),
         };
     }
-}
 
-#[async_trait]
-impl<'a> AuthorizationProvider for PDPProvider {
-    async fn authorize(
-        &self,
-        access_subject: &str,
-        policy_issuer: &str,
-        action: ResourceAction,
-        resource_type: PolicyResourceType,
-        identifiers: Option<Vec<String>>,
-        attributes: Option<Vec<String>>,
-    ) -> Result<(), AppError> {
-        let pdp = PDP::new(
+    fn pdp(&self) -> PDP<'_> {
+        return PDP::new(
             self.ishare.as_ref(),
             self.eori.to_string(),
             self.base_url.to_string(),
         );
+    }
 
+    async fn get_token(&self, pdp: &PDP<'_>) -> anyhow::Result<String> {
         let now = chrono::Utc::now().timestamp();
 
         let mut write_lock = self.token_cache.write().await;
 
-        let token = if write_lock.is_invalid(now) {
-            tracing::debug!("pdp access token has expired. fetching new one");
+        if !write_lock.is_invalid(now) {
+            tracing::debug!("retrieving pdp access token from cache");
+            return Ok(write_lock.access_token.clone());
+        }
+
+        tracing::debug!("pdp access token has expired. fetching new one");
+        let token_response = pdp.connect().await.context("Error connecting to pdp")?;
+        write_lock.update(
+            token_response.access_token.clone(),
+            token_response.expires_in + now,
+        );
 
-            let token_response = pdp.connect().await.context("Error connecting to pdp")?;
-            write_lock.update(
-                token_response.access_token.clone(),
-                token_response.expires_in + now,
-            );
+        return Ok(token_response.access_token);
+    }
+}
 
-            token_response.access_token
-        } else {
-            tracing::debug!("retrieving pdp access token from cache");
-            write_lock.access_token.clone()
-        };
+#[async_trait]
+impl AuthorizationProvider for PDPProvider {
+    async fn authorize(
+        &self,
+        access_subject: &str,
+        policy_issuer: &str,
+        action: &str,
+        resource_type: &str,
+        identifiers: Option<Vec<String>>,
+        attributes: Option<Vec<String>>,
+    ) -> anyhow::Result<bool> {
+        let pdp = self.pdp();
+
+        let token = self.get_token(&pdp).await?;
 
         let authorized = pdp
             .authorize(
                 &token,
-                action.as_str(),
+                action,
                 access_subject,
                 policy_issuer,
-                resource_type.as_str(),
+                resource_type,
                 identifiers,
                 attributes,
             )
-            .await;
-
-        match authorized {
-            Err(e) => {
-                Err(AppError::Expected(ExpectedError {
-                    status_code: StatusCode::UNAUTHORIZED,
-                    message: "unauthorized".to_owned(),
-                    reason: format!("Error calling authorize on PDP. access_subject: {}, policy_issuer: {}. Error: {}", 
-                    &access_subject,
-                    &policy_issuer,
-                    e.message,
-                ),
-                errors: None,
-                error_type: crate::error::ErrorType::PDPUnauthorized,
-                }))
-            }
-            Ok(success) => match success {
-                true => {
-                    tracing::debug!(
-                        "access granted for access_subject: {}, policy_issuer: {}",
-                        &access_subject,
-                        &policy_issuer,
-                    );
-                    Ok(())
-                }
-                false => {
-
-                    Err(AppError::Expected(ExpectedError {
-                        status_code: StatusCode::UNAUTHORIZED,
-                        message: "unauthorized".to_owned(),
-                        reason: format!(
-                            "no access for access_subject: {}, policy_issuer: {} for resource type: {:?} and action: {:?}",
-                            &access_subject,
-                            &policy_issuer,
-                            &resource_type,
-                            &action,
-                        ),
-                    errors: None,
-                    error_type: crate::error::ErrorType::PDPUnauthorized,
-                    }))
-                }
-            },
-        }
+            .await
+            .context(format!(
+                "Error calling authorize on PDP. access_subject: {}, policy_issuer: {}",
+                access_subject, policy_issuer
+            ))?;
+
+        tracing::debug!(
+            "pdp {} '{}' for access_subject: {}, policy_issuer: {} and resource type: {}",
+            if authorized { "granted" } else { "denied" },
+            action,
+            access_subject,
+            policy_issuer,
+            resource_type,
+        );
+
+        return Ok(authorized);
     }
+}
 
-    async fn append_delegation_evidence(
-        &self,
-        policy_issuer: &str,
-        access_subject: &str,
-        service_provider: &str,
-        resource_type: PolicyResourceType,
-        actions: Vec<ResourceAction>,
-        identifiers: Option<Vec<String>>,
-    ) -> Result<PolicySetInsertResponse, AppError> {
-        let pdp = PDP::new(
-            self.ishare.as_ref(),
-            self.eori.to_string(),
-            self.base_url.to_string(),
-        );
+#[async_trait]
+impl ReplicationTarget for PDPProvider {
+    fn eori(&self) -> &str {
+        return &self.eori;
+    }
 
-        let actions_as_strings = actions.iter().map(|a| a.as_str().to_owned()).collect();
-        let token_response = pdp.connect().await.context("Error connecting to pdp")?;
+    async fn create_policy_set(&self, policy_set: &ReplicatedPolicySet) -> anyhow::Result<String> {
+        let token = self.get_token(&self.pdp()).await?;
 
-        let response = pdp
-            .put_policy_set(
-                &token_response.access_token,
-                policy_issuer,
-                access_subject,
-                service_provider,
-                resource_type.as_str().to_owned(),
-                actions_as_strings,
-                identifiers,
-            )
+        let response = reqwest::Client::new()
+            .post(format!("{}/policy-set", self.base_url))
+            .bearer_auth(token)
+            .json(policy_set)
+            .send()
+            .await
+            .context("Error sending policy set to pdp")?
+            .error_for_status()
+            .context("Pdp rejected policy set")?
+            .json::<PolicySetInsertResponse>()
+            .await
+            .context("Error parsing policy set insert response of pdp")?;
+
+        return Ok(response.uuid.to_string());
+    }
+
+    async fn delete_policy_set(&self, remote_id: &str) -> anyhow::Result<()> {
+        let token = self.get_token(&self.pdp()).await?;
+
+        let response = reqwest::Client::new()
+            .delete(format!("{}/policy-set/{}", self.base_url, remote_id))
+            .bearer_auth(token)
+            .send()
+            .await
+            .context("Error deleting policy set at pdp")?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            tracing::debug!("policy set '{}' is already removed from pdp", remote_id);
+            return Ok(());
+        }
+
+        response
+            .error_for_status()
+            .context("Pdp rejected deleting policy set")?;
+
+        return Ok(());
+    }
+}
+
+#[derive(Deserialize)]
+struct DelegationTokenResponse {
+    delegation_token: String,
+}
+
+#[async_trait]
+impl RemoteRegistry for PDPProvider {
+    async fn request_delegation_token(
+        &self,
+        delegation_request: &DelegationRequest,
+    ) -> anyhow::Result<String> {
+        let token = self.get_token(&self.pdp()).await?;
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/delegation", self.base_url))
+            .bearer_auth(token)
+            .json(&DelegationRequestContainer {
+                delegation_request: delegation_request.clone(),
+                previous_steps: None,
+            })
+            .send()
+            .await
+            .context("Error sending delegation request to remote registry")?
+            .error_for_status()
+            .context("Remote registry rejected delegation request")?
+            .json::<DelegationTokenResponse>()
             .await
-            .context("Error updating Delegation Evidence on PDP")?;
+            .context("Error parsing delegation response of remote registry")?;
 
-        Ok(response)
+        return Ok(response.delegation_token);
     }
 }
@@ -9,7 +9,9 @@ use serde::Deserialize;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::config::PartyChecksConfig;
 use crate::db::policy::{self as policy_store, AccessSubjectTarget, MatchingPolicySetRow};
+use crate::db::replication as replication_store;
 use crate::error::{AppError, ExpectedError};
 use crate::services::audit_log::{
     log_event, PolicyAdded, PolicyRemoved, PolicyReplaced, PolicySetCreatedEventMetadata,
@@ -18,57 +20,42 @@ use crate::services::audit_log::{
 use crate::services::delegation::create_delegation_evidence;
 use crate::TimeProvider;
 
+use super::dataspace::Dataspaces;
 use super::ishare_provider::SatelliteProvider;
+use super::party_compliance::{validate_party, validate_service_providers, PartyFunction};
+use super::pdp_provider::AuthorizationProvider;
 
 pub async fn validate_policy_set_ishare_parties(
     now: chrono::DateTime<chrono::Utc>,
     args: &InsertPolicySetWithPolicies,
-    ishare: std::sync::Arc<dyn SatelliteProvider>,
+    ishare: &dyn SatelliteProvider,
+    party_checks: &PartyChecksConfig,
 ) -> Result<(), AppError> {
-    ishare
-        .validate_party(now, &args.target.access_subject)
-        .await
-        .map_err(|e| {
-            AppError::Expected(ExpectedError {
-                status_code: StatusCode::BAD_REQUEST,
-                message: format!(
-                    "Unable to verify access subject '{}' as valid iSHARE party",
-                    &args.target.access_subject
-                ),
-                reason: format!("{:?}", e),
-                metadata: None,
-            })
-        })?;
-
-    ishare
-        .validate_party(now, &args.policy_issuer)
-        .await
-        .map_err(|e| {
-            AppError::Expected(ExpectedError {
-                status_code: StatusCode::BAD_REQUEST,
-                message: format!(
-                    "Unable to verify policy issuer '{}' as valid iSHARE party",
-                    &args.policy_issuer
-                ),
-                reason: format!("{:?}", e),
-                metadata: None,
-            })
-        })?;
+    validate_party(
+        now,
+        ishare,
+        party_checks,
+        &args.target.access_subject,
+        PartyFunction::AccessSubject,
+    )
+    .await?;
+    validate_party(
+        now,
+        ishare,
+        party_checks,
+        &args.policy_issuer,
+        PartyFunction::PolicyIssuer,
+    )
+    .await?;
 
     for p in args.policies.iter() {
-        for sp in p.target.environment.service_providers.iter() {
-            ishare.validate_party(now, sp).await.map_err(|e| {
-                AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: format!(
-                        "Unable to verify service provider '{}' as valid iSHARE party",
-                        &sp
-                    ),
-                    reason: format!("{:?}", e),
-                    metadata: None,
-                })
-            })?;
-        }
+        validate_service_providers(
+            now,
+            ishare,
+            party_checks,
+            &p.target.environment.service_providers,
+        )
+        .await?;
     }
 
     Ok(())
@@ -81,9 +68,14 @@ pub async fn insert_policy_set_with_policies(
     db: &DatabaseConnection,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
-    ishare: std::sync::Arc<dyn SatelliteProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
+    dataspaces: &Dataspaces,
+    // the resolved dataspace of the request, see `Dataspaces::resolve_for_role`
+    dataspace: Option<String>,
+    party_checks: &PartyChecksConfig,
 ) -> Result<Uuid, AppError> {
-    validate_policy_set_ishare_parties(now, args, ishare).await?;
+    let ishare = dataspaces.satellite_provider(dataspace.as_deref())?;
+    validate_policy_set_ishare_parties(now, args, ishare.as_ref(), party_checks).await?;
 
     let identifiers = args
         .policies
@@ -99,6 +91,8 @@ pub async fn insert_policy_set_with_policies(
         identifiers,
         client_eori,
         time_provider,
+        authorization_provider,
+        dataspace.clone(),
         &db,
     )
     .await
@@ -113,7 +107,7 @@ pub async fn insert_policy_set_with_policies(
         }));
     }
 
-    let policy_set_id = insert_policy_set_with_policies_into_db(now, args, db)
+    let policy_set_id = insert_policy_set_with_policies_into_db(now, args, dataspace, db)
         .await
         .context("Error inserting policy set with policies")?;
 
@@ -128,11 +122,15 @@ pub struct InsertPolicySetWithPolicies {
     pub licences: Vec<String>,
     pub policies: Vec<ar_entity::delegation_evidence::Policy>,
     pub max_delegation_depth: i32,
+    // id of the dataspace the policy set belongs to, the default dataspace when left out
+    #[serde(default)]
+    pub dataspace: Option<String>,
 }
 
 pub async fn insert_policy_set_with_policies_into_db(
     now: chrono::DateTime<Utc>,
     args: &InsertPolicySetWithPolicies,
+    dataspace: Option<String>,
     db: &DatabaseConnection,
 ) -> anyhow::Result<Uuid> {
     let transaction = db.begin().await.context("Error opening db transaction")?;
@@ -143,6 +141,7 @@ pub async fn insert_policy_set_with_policies_into_db(
         &args.policy_issuer,
         &args.licences,
         &args.max_delegation_depth,
+        dataspace,
         &transaction,
     )
     .await
@@ -167,6 +166,10 @@ pub async fn insert_policy_set_with_policies_into_db(
     .await
     .context("error logging policy set created event")?;
 
+    replication_store::record_change(now, policy_set_id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -179,17 +182,44 @@ pub async fn insert_policy_set_with_policies_admin(
     now: chrono::DateTime<chrono::Utc>,
     args: &InsertPolicySetWithPolicies,
     db: &DatabaseConnection,
-    ishare: std::sync::Arc<dyn SatelliteProvider>,
+    dataspaces: &Dataspaces,
+    party_checks: &PartyChecksConfig,
 ) -> Result<Uuid, AppError> {
-    validate_policy_set_ishare_parties(now, args, ishare).await?;
+    let dataspace = dataspaces.resolve(args.dataspace.as_deref())?;
+    let ishare = dataspaces.satellite_provider(dataspace.as_deref())?;
+    validate_policy_set_ishare_parties(now, args, ishare.as_ref(), party_checks).await?;
 
-    let policy_set_id = insert_policy_set_with_policies_into_db(now, args, db)
+    let policy_set_id = insert_policy_set_with_policies_into_db(now, args, dataspace, db)
         .await
         .context("Error inserting policy set with policies")?;
 
     Ok(policy_set_id)
 }
 
+// the satellite of the dataspace a stored policy set belongs to
+pub async fn get_policy_set_satellite_provider(
+    policy_set_id: &Uuid,
+    dataspaces: &Dataspaces,
+    db: &DatabaseConnection,
+) -> Result<std::sync::Arc<dyn SatelliteProvider>, AppError> {
+    let policy_set = match policy_store::get_policy_set_by_id(policy_set_id, db)
+        .await
+        .context("Error getting policy set")?
+    {
+        None => {
+            return Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::NOT_FOUND,
+                message: "Can't find policy set".to_owned(),
+                reason: "not found".to_owned(),
+                metadata: None,
+            }));
+        }
+        Some(ps) => ps,
+    };
+
+    return dataspaces.satellite_provider(policy_set.dataspace.as_deref());
+}
+
 pub enum PolicySetAction {
     Read,
     Edit,
@@ -216,6 +246,9 @@ pub async fn verify_policy_set_access(
     resource_types: Vec<String>,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
+    // management grants are looked up in the dataspace of the policy set
+    dataspace: Option<String>,
     db: &DatabaseConnection,
 ) -> anyhow::Result<bool> {
     tracing::info!(
@@ -234,6 +267,25 @@ pub async fn verify_policy_set_access(
         return Ok(true);
     }
 
+    if let Some(authorization_provider) = authorization_provider {
+        let access = authorization_provider
+            .authorize(
+                requestor_company_id,
+                policy_issuer,
+                &action.to_string(),
+                "PDP.Policy",
+                Some(resource_types),
+                Some(vec!["*".to_string()]),
+            )
+            .await?;
+
+        tracing::info!(
+            "access {} by the pdp",
+            if access { "granted" } else { "denied" }
+        );
+        return Ok(access);
+    }
+
     let delegation_request = DelegationRequest {
         policy_issuer: policy_issuer.to_string(),
         target: DelegationTarget {
@@ -269,7 +321,7 @@ pub async fn verify_policy_set_access(
     );
 
     let delegation_evidence_container =
-        create_delegation_evidence(&delegation_request, time_provider, 30, db)
+        create_delegation_evidence(&delegation_request, time_provider, 30, dataspace, db)
             .await
             .context("Error creating delegation evidence")?;
 
@@ -293,6 +345,7 @@ pub async fn delete_policy_set(
     id: &Uuid,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
     db: &DatabaseConnection,
 ) -> Result<(), AppError> {
     let policy_set = match policy_store::get_policy_set_by_id(&id, &db)
@@ -327,6 +380,8 @@ pub async fn delete_policy_set(
         identifiers,
         client_eori,
         time_provider,
+        authorization_provider,
+        policy_set.dataspace.clone(),
         &db,
     )
     .await
@@ -360,6 +415,10 @@ pub async fn delete_policy_set(
     .await
     .context("Error logging policy set deleted event")?;
 
+    replication_store::record_change(now, *id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -375,7 +434,9 @@ pub async fn add_policy_to_policy_set(
     policy: ar_entity::delegation_evidence::Policy,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
-    satellite_provider: std::sync::Arc<dyn SatelliteProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
+    dataspaces: &Dataspaces,
+    party_checks: &PartyChecksConfig,
     db: &DatabaseConnection,
 ) -> Result<ar_entity::policy::Model, AppError> {
     match policy.rules.get(0) {
@@ -390,23 +451,6 @@ pub async fn add_policy_to_policy_set(
         }
     }
 
-    for sp in policy.target.environment.service_providers.iter() {
-        satellite_provider
-            .validate_party(now, sp)
-            .await
-            .map_err(|e| {
-                AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: format!(
-                        "Unable to verify service provider '{}' as valid iSHARE party",
-                        &sp
-                    ),
-                    reason: format!("{:?}", e),
-                    metadata: None,
-                })
-            })?;
-    }
-
     let policy_set = match policy_store::get_policy_set_by_id(&policy_set_id, &db)
         .await
         .context("Error getting policy set")?
@@ -422,6 +466,16 @@ pub async fn add_policy_to_policy_set(
         Some(ps) => ps,
     };
 
+    // the service providers are validated at the satellite of the dataspace of the policy set
+    let satellite_provider = dataspaces.satellite_provider(policy_set.dataspace.as_deref())?;
+    validate_service_providers(
+        now,
+        satellite_provider.as_ref(),
+        party_checks,
+        &policy.target.environment.service_providers,
+    )
+    .await?;
+
     let policies = policy_store::get_policies_by_policy_set(policy_set_id, db)
         .await
         .context(format!(
@@ -439,6 +493,8 @@ pub async fn add_policy_to_policy_set(
         identifiers,
         client_eori,
         time_provider,
+        authorization_provider,
+        policy_set.dataspace.clone(),
         &db,
     )
     .await
@@ -475,6 +531,10 @@ pub async fn add_policy_to_policy_set(
     .await
     .context("error logging policy added event")?;
 
+    replication_store::record_change(now, *policy_set_id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -491,7 +551,9 @@ pub async fn replace_policy_in_policy_set(
     policy: ar_entity::delegation_evidence::Policy,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
-    satellite_provider: std::sync::Arc<dyn SatelliteProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
+    dataspaces: &Dataspaces,
+    party_checks: &PartyChecksConfig,
     db: &DatabaseConnection,
 ) -> Result<ar_entity::policy::Model, AppError> {
     match policy.rules.get(0) {
@@ -506,23 +568,6 @@ pub async fn replace_policy_in_policy_set(
         }
     }
 
-    for sp in policy.target.environment.service_providers.iter() {
-        satellite_provider
-            .validate_party(now, sp)
-            .await
-            .map_err(|e| {
-                AppError::Expected(ExpectedError {
-                    status_code: StatusCode::BAD_REQUEST,
-                    message: format!(
-                        "Unable to verify service provider '{}' as valid iSHARE party",
-                        &sp
-                    ),
-                    reason: format!("{:?}", e),
-                    metadata: None,
-                })
-            })?;
-    }
-
     let policy_set = match policy_store::get_policy_set_by_id(&policy_set_id, &db)
         .await
         .context("Error getting policy set")?
@@ -538,6 +583,16 @@ pub async fn replace_policy_in_policy_set(
         Some(ps) => ps,
     };
 
+    // the service providers are validated at the satellite of the dataspace of the policy set
+    let satellite_provider = dataspaces.satellite_provider(policy_set.dataspace.as_deref())?;
+    validate_service_providers(
+        now,
+        satellite_provider.as_ref(),
+        party_checks,
+        &policy.target.environment.service_providers,
+    )
+    .await?;
+
     let policies = policy_store::get_policies_by_policy_set(&policy_set_id, db)
         .await
         .context(format!(
@@ -555,6 +610,8 @@ pub async fn replace_policy_in_policy_set(
         identifiers,
         client_eori,
         time_provider,
+        authorization_provider,
+        policy_set.dataspace.clone(),
         &db,
     )
     .await
@@ -592,6 +649,10 @@ pub async fn replace_policy_in_policy_set(
     .await
     .context("Error logging policy set edited event")?;
 
+    replication_store::record_change(now, policy_set_id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -605,6 +666,7 @@ pub async fn get_policy_set_with_policies(
     policy_set_id: &Uuid,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
     db: &DatabaseConnection,
 ) -> Result<Option<MatchingPolicySetRow>, AppError> {
     let policy_set = match policy_store::get_policy_set_by_id(&policy_set_id, &db)
@@ -639,6 +701,8 @@ pub async fn get_policy_set_with_policies(
         identifiers,
         client_eori,
         time_provider,
+        authorization_provider,
+        policy_set.dataspace.clone(),
         &db,
     )
     .await
@@ -665,6 +729,7 @@ pub async fn remove_policy_from_policy_set(
     policy_id: &Uuid,
     client_eori: &str,
     time_provider: std::sync::Arc<dyn TimeProvider>,
+    authorization_provider: Option<std::sync::Arc<dyn AuthorizationProvider>>,
     db: &DatabaseConnection,
 ) -> Result<(), AppError> {
     let policy_set = match policy_store::get_policy_set_by_id(&policy_set_id, &db)
@@ -711,6 +776,8 @@ pub async fn remove_policy_from_policy_set(
         identifiers,
         client_eori,
         time_provider,
+        authorization_provider,
+        policy_set.dataspace.clone(),
         &db,
     )
     .await
@@ -747,6 +814,10 @@ pub async fn remove_policy_from_policy_set(
     .await
     .context("Error logging policy set edited event")?;
 
+    replication_store::record_change(now, *policy_set_id, &transaction)
+        .await
+        .context("error recording policy set change for replication")?;
+
     transaction
         .commit()
         .await
@@ -779,6 +850,8 @@ mod test {
             vec!["*".to_owned()],
             "antother-company",
             time_provider,
+            None,
+            None,
             &db,
         )
         .await
@@ -805,6 +878,8 @@ mod test {
             vec!["*".to_owned()],
             "another-company",
             time_provider,
+            None,
+            None,
             &db,
         )
         .await
@@ -831,6 +906,8 @@ mod test {
             vec!["*".to_owned()],
             "antother-company",
             time_provider,
+            None,
+            None,
             &db,
         )
         .await
@@ -857,6 +934,8 @@ mod test {
             vec!["*".to_owned()],
             "antother-company",
             time_provider,
+            None,
+            None,
             &db,
         )
         .await
@@ -903,7 +982,7 @@ mod test {
             ]
         }))
         .unwrap();
-        insert_policy_set_with_policies_into_db(chrono::Utc::now(), &policy_set, &db)
+        insert_policy_set_with_policies_into_db(chrono::Utc::now(), &policy_set, None, &db)
             .await
             .unwrap();
 
@@ -915,6 +994,8 @@ mod test {
             vec!["LovelyResource".to_string()],
             "NL.CONSUME_TOO_MUCH",
             time_provider,
+            None,
+            None,
             &db,
         )
         .await
@@ -924,4 +1005,72 @@ mod test {
 
         Ok(())
     }
+
+    #[sqlx::test]
+    async fn test_verify_policy_set_access_via_de_dataspace(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let time_provider = std::sync::Arc::new(FakeTimeProvider::new());
+        let policy_set: InsertPolicySetWithPolicies = serde_json::from_value(json!({
+            "target": {
+                "accessSubject": "NL.24244",
+            },
+            "policyIssuer": "NL.44444",
+            "licences": [],
+            "maxDelegationDepth": 2,
+            "policies": [
+                {
+                    "target": {
+                        "resource": {
+                            "type": "PDP.Policy",
+                            "identifiers": ["LovelyResource"],
+                            "attributes": ["*"],
+                        },
+                        "actions": ["Delete"],
+                        "environment": {
+                            "serviceProviders": ["NL.CONSUME_TOO_MUCH"],
+                        },
+                    },
+                    "rules": [
+                        {
+                            "effect": "Permit"
+                        }
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+        insert_policy_set_with_policies_into_db(
+            chrono::Utc::now(),
+            &policy_set,
+            Some("other".to_owned()),
+            &db,
+        )
+        .await
+        .unwrap();
+
+        // the grant only holds for policy sets of its own dataspace
+        for (dataspace, expected) in [(None, false), (Some("other".to_owned()), true)] {
+            let access = verify_policy_set_access(
+                "NL.24244",
+                &PolicySetAction::Delete,
+                "NL.44444",
+                "as",
+                vec!["LovelyResource".to_string()],
+                "NL.CONSUME_TOO_MUCH",
+                time_provider.clone(),
+                None,
+                dataspace,
+                &db,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(access, expected);
+        }
+
+        Ok(())
+    }
 }
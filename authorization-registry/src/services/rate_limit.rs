@@ -0,0 +1,510 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    config::{RateLimitBudget, RateLimitConfig, RateLimitGroupConfig},
+    TimeProvider,
+};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitGroup {
+    MachineToken,
+    Delegation,
+}
+
+impl RateLimitGroup {
+    pub const ALL: [RateLimitGroup; 2] = [RateLimitGroup::MachineToken, RateLimitGroup::Delegation];
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKind {
+    Party,
+    Ip,
+}
+
+struct Window {
+    started_at: DateTime<Utc>,
+    window_seconds: i64,
+    requests: u64,
+}
+
+impl Window {
+    fn resets_at(&self) -> DateTime<Utc> {
+        return self.started_at + TimeDelta::seconds(self.window_seconds);
+    }
+}
+
+type WindowKey = (RateLimitGroup, RateLimitKind, String);
+
+// expired windows are dropped at most once per interval instead of on every request
+const PRUNE_INTERVAL_SECONDS: i64 = 30;
+// a spray of source addresses can not grow the windows beyond this number of ip addresses
+const MAX_TRACKED_IPS: usize = 100_000;
+
+struct Windows {
+    entries: HashMap<WindowKey, Window>,
+    ip_keys: usize,
+    max_ip_keys: usize,
+    pruned_at: Option<DateTime<Utc>>,
+}
+
+impl Windows {
+    fn prune(&mut self, now: DateTime<Utc>) {
+        if self
+            .pruned_at
+            .is_some_and(|pruned_at| now < pruned_at + TimeDelta::seconds(PRUNE_INTERVAL_SECONDS))
+        {
+            return;
+        }
+
+        self.entries.retain(|_, window| window.resets_at() > now);
+        self.ip_keys = self
+            .entries
+            .keys()
+            .filter(|(_, kind, _)| *kind == RateLimitKind::Ip)
+            .count();
+        self.pruned_at = Some(now);
+    }
+
+    // the current window of the key, started again when it has ended. None when the key is an ip
+    // address and the maximum number of ip addresses is tracked already
+    fn get_window(
+        &mut self,
+        key: WindowKey,
+        budget: RateLimitBudget,
+        now: DateTime<Utc>,
+    ) -> Option<&mut Window> {
+        if key.1 == RateLimitKind::Ip && !self.entries.contains_key(&key) {
+            if self.ip_keys >= self.max_ip_keys {
+                return None;
+            }
+            self.ip_keys += 1;
+        }
+
+        let window = self.entries.entry(key).or_insert(Window {
+            started_at: now,
+            window_seconds: budget.window_seconds,
+            requests: 0,
+        });
+        if window.resets_at() <= now {
+            window.started_at = now;
+            window.window_seconds = budget.window_seconds;
+            window.requests = 0;
+        }
+
+        return Some(window);
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: u64,
+    rejected: u64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RateLimitedKey {
+    pub kind: RateLimitKind,
+    pub key: String,
+    pub requests: u64,
+    #[schema(value_type = String, format = DateTime)]
+    pub resets_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RateLimitGroupStatus {
+    pub group: RateLimitGroup,
+    #[schema(value_type = Option<Object>)]
+    pub per_party: Option<RateLimitBudget>,
+    #[schema(value_type = Option<Object>)]
+    pub per_ip: Option<RateLimitBudget>,
+    // since the authorization registry has been started
+    pub requests: u64,
+    pub rejected: u64,
+    // parties and ip addresses that have used up their budget right now
+    pub limited: Vec<RateLimitedKey>,
+}
+
+// fixed window rate limiting of route groups per party and per source ip. The counters are kept
+// in memory, so every instance of the authorization registry has its own budget
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    time_provider: Arc<dyn TimeProvider>,
+    windows: Mutex<Windows>,
+    counters: Mutex<HashMap<RateLimitGroup, Counters>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, time_provider: Arc<dyn TimeProvider>) -> Self {
+        return Self {
+            config,
+            time_provider,
+            windows: Mutex::new(Windows {
+                entries: HashMap::new(),
+                ip_keys: 0,
+                max_ip_keys: MAX_TRACKED_IPS,
+                pruned_at: None,
+            }),
+            counters: Mutex::new(HashMap::new()),
+        };
+    }
+
+    pub fn trust_forwarded_for(&self) -> bool {
+        return self.config.trust_forwarded_for;
+    }
+
+    fn get_group_config(&self, group: RateLimitGroup) -> &RateLimitGroupConfig {
+        return match group {
+            RateLimitGroup::MachineToken => &self.config.machine_token,
+            RateLimitGroup::Delegation => &self.config.delegation,
+        };
+    }
+
+    pub fn is_enabled(&self, group: RateLimitGroup) -> bool {
+        let config = self.get_group_config(group);
+        return config.per_party.is_some() || config.per_ip.is_some();
+    }
+
+    // counts a request and returns the number of seconds until it may be retried when the party
+    // or the ip address is over its budget
+    pub fn check(
+        &self,
+        group: RateLimitGroup,
+        party: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<Option<i64>> {
+        let now = self.time_provider.now();
+        let config = self.get_group_config(group);
+
+        let keys = [
+            (
+                RateLimitKind::Party,
+                party.map(|p| p.to_owned()),
+                config.per_party,
+            ),
+            (
+                RateLimitKind::Ip,
+                ip.map(|ip| ip.to_string()),
+                config.per_ip,
+            ),
+        ];
+
+        let mut windows = self
+            .windows
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+        windows.prune(now);
+
+        let mut retry_after: Option<i64> = None;
+
+        for (kind, key, budget) in keys {
+            let (key, budget) = match (key, budget) {
+                (Some(key), Some(budget)) => (key, budget),
+                _ => continue,
+            };
+
+            let window = match windows.get_window((group, kind, key.clone()), budget, now) {
+                Some(window) => window,
+                None => {
+                    tracing::warn!(
+                        "rate limit of {:?} does not track ip '{}', too many ip addresses",
+                        group,
+                        key
+                    );
+                    continue;
+                }
+            };
+            window.requests += 1;
+
+            if window.requests > budget.requests {
+                let seconds = (window.resets_at() - now).num_seconds().max(1);
+                retry_after = Some(retry_after.unwrap_or(0).max(seconds));
+
+                tracing::warn!(
+                    "rate limit of {:?} exceeded for {:?} '{}', {} requests in the current window",
+                    group,
+                    kind,
+                    key,
+                    window.requests
+                );
+            }
+        }
+
+        let mut counters = self
+            .counters
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+        let counter = counters.entry(group).or_default();
+        counter.requests += 1;
+        if retry_after.is_some() {
+            counter.rejected += 1;
+        }
+
+        return Ok(retry_after);
+    }
+
+    // returns the number of seconds until the party may retry when its authenticated requests
+    // have used up its budget. The request is not counted for the party, it is not authenticated
+    // yet
+    pub fn check_unauthenticated_party(
+        &self,
+        group: RateLimitGroup,
+        party: &str,
+    ) -> anyhow::Result<Option<i64>> {
+        let now = self.time_provider.now();
+        let budget = match self.get_group_config(group).per_party {
+            Some(budget) => budget,
+            None => return Ok(None),
+        };
+
+        let windows = self
+            .windows
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+        let retry_after = windows
+            .entries
+            .get(&(group, RateLimitKind::Party, party.to_owned()))
+            .filter(|window| window.resets_at() > now && window.requests >= budget.requests)
+            .map(|window| (window.resets_at() - now).num_seconds().max(1));
+
+        if retry_after.is_some() {
+            tracing::warn!("rate limit of {:?} exceeded for party '{}'", group, party);
+
+            let mut counters = self
+                .counters
+                .lock()
+                .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+            counters.entry(group).or_default().rejected += 1;
+        }
+
+        return Ok(retry_after);
+    }
+
+    // counts a request of a party that has been authenticated by the route itself
+    pub fn count_authenticated_party(
+        &self,
+        group: RateLimitGroup,
+        party: &str,
+    ) -> anyhow::Result<()> {
+        let now = self.time_provider.now();
+        let budget = match self.get_group_config(group).per_party {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+
+        let mut windows = self
+            .windows
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+        windows.prune(now);
+
+        if let Some(window) =
+            windows.get_window((group, RateLimitKind::Party, party.to_owned()), budget, now)
+        {
+            window.requests += 1;
+        }
+
+        return Ok(());
+    }
+
+    pub fn get_status(&self) -> anyhow::Result<Vec<RateLimitGroupStatus>> {
+        let now = self.time_provider.now();
+        let windows = self
+            .windows
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+        let counters = self
+            .counters
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit lock is poisoned"))?;
+
+        let status = RateLimitGroup::ALL
+            .iter()
+            .map(|group| {
+                let config = self.get_group_config(*group);
+                let limited = windows
+                    .entries
+                    .iter()
+                    .filter(|((g, _, _), window)| g == group && window.resets_at() > now)
+                    .filter(|((_, kind, _), window)| {
+                        let budget = match kind {
+                            RateLimitKind::Party => config.per_party,
+                            RateLimitKind::Ip => config.per_ip,
+                        };
+                        budget.is_some_and(|budget| window.requests >= budget.requests)
+                    })
+                    .map(|((_, kind, key), window)| RateLimitedKey {
+                        kind: *kind,
+                        key: key.clone(),
+                        requests: window.requests,
+                        resets_at: window.resets_at(),
+                    })
+                    .collect();
+                let counter = counters.get(group);
+
+                RateLimitGroupStatus {
+                    group: *group,
+                    per_party: config.per_party,
+                    per_ip: config.per_ip,
+                    requests: counter.map(|c| c.requests).unwrap_or(0),
+                    rejected: counter.map(|c| c.rejected).unwrap_or(0),
+                    limited,
+                }
+            })
+            .collect();
+
+        return Ok(status);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::RwLock;
+
+    use super::*;
+
+    struct MovableTimeProvider {
+        now: RwLock<DateTime<Utc>>,
+    }
+
+    impl TimeProvider for MovableTimeProvider {
+        fn now(&self) -> DateTime<Utc> {
+            return *self.now.read().unwrap();
+        }
+    }
+
+    fn budget(requests: u64, window_seconds: i64) -> Option<RateLimitBudget> {
+        return Some(RateLimitBudget {
+            requests,
+            window_seconds,
+        });
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let time_provider = Arc::new(MovableTimeProvider {
+            now: RwLock::new(now),
+        });
+        let limiter = RateLimiter::new(
+            RateLimitConfig {
+                machine_token: RateLimitGroupConfig {
+                    per_party: budget(2, 60),
+                    per_ip: budget(3, 60),
+                },
+                delegation: RateLimitGroupConfig::default(),
+                trust_forwarded_for: false,
+            },
+            time_provider.clone(),
+        );
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let group = RateLimitGroup::MachineToken;
+
+        assert_eq!(limiter.check(group, Some("NL.A"), Some(ip)).unwrap(), None);
+        assert_eq!(limiter.check(group, Some("NL.A"), Some(ip)).unwrap(), None);
+
+        *time_provider.now.write().unwrap() = now + TimeDelta::seconds(20);
+        assert_eq!(
+            limiter.check(group, Some("NL.A"), Some(ip)).unwrap(),
+            Some(40)
+        );
+
+        // another party from the same ip runs into the ip budget
+        assert_eq!(
+            limiter.check(group, Some("NL.B"), Some(ip)).unwrap(),
+            Some(40)
+        );
+        assert_eq!(
+            limiter
+                .check(group, Some("NL.B"), Some("10.0.0.2".parse().unwrap()))
+                .unwrap(),
+            None
+        );
+
+        // groups without budgets are not limited
+        for _ in 0..10 {
+            assert_eq!(
+                limiter
+                    .check(RateLimitGroup::Delegation, Some("NL.A"), Some(ip))
+                    .unwrap(),
+                None
+            );
+        }
+
+        let status = limiter.get_status().unwrap();
+        assert_eq!(status[0].group, RateLimitGroup::MachineToken);
+        assert_eq!(status[0].requests, 5);
+        assert_eq!(status[0].rejected, 2);
+        // NL.A and the ip address are over their budget, NL.B has used it up
+        assert_eq!(status[0].limited.len(), 3);
+        assert_eq!(status[1].requests, 10);
+        assert_eq!(status[1].rejected, 0);
+
+        *time_provider.now.write().unwrap() = now + TimeDelta::seconds(60);
+        assert_eq!(limiter.check(group, Some("NL.A"), Some(ip)).unwrap(), None);
+        // only the window of NL.B, that started later, is still used up
+        let limited = &limiter.get_status().unwrap()[0].limited;
+        assert_eq!(limited.len(), 1);
+        assert_eq!(limited[0].key, "NL.B");
+    }
+
+    #[test]
+    fn test_rate_limit_windows() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let time_provider = Arc::new(MovableTimeProvider {
+            now: RwLock::new(now),
+        });
+        let limiter = RateLimiter::new(
+            RateLimitConfig {
+                machine_token: RateLimitGroupConfig {
+                    per_party: budget(1, 60),
+                    per_ip: budget(1, 60),
+                },
+                delegation: RateLimitGroupConfig::default(),
+                trust_forwarded_for: false,
+            },
+            time_provider.clone(),
+        );
+        limiter.windows.lock().unwrap().max_ip_keys = 2;
+        let group = RateLimitGroup::MachineToken;
+        let ip = |i: u8| -> Option<IpAddr> { Some(IpAddr::from([10, 0, 0, i])) };
+
+        assert_eq!(limiter.check(group, None, ip(1)).unwrap(), None);
+        assert_eq!(limiter.check(group, None, ip(2)).unwrap(), None);
+        // further ip addresses are not tracked, the tracked ones keep their budget
+        assert_eq!(limiter.check(group, None, ip(3)).unwrap(), None);
+        assert_eq!(limiter.check(group, None, ip(3)).unwrap(), None);
+        assert_eq!(limiter.check(group, None, ip(1)).unwrap(), Some(60));
+        // parties are still limited
+        assert_eq!(limiter.check(group, Some("NL.A"), ip(4)).unwrap(), None);
+        assert_eq!(limiter.check(group, Some("NL.A"), ip(4)).unwrap(), Some(60));
+        assert_eq!(limiter.windows.lock().unwrap().entries.len(), 3);
+
+        // an ended window starts again before it is pruned
+        *time_provider.now.write().unwrap() = now + TimeDelta::seconds(45);
+        assert_eq!(limiter.check(group, Some("NL.B"), None).unwrap(), None);
+        *time_provider.now.write().unwrap() = now + TimeDelta::seconds(61);
+        assert_eq!(limiter.check(group, Some("NL.A"), None).unwrap(), None);
+        assert_eq!(limiter.windows.lock().unwrap().entries.len(), 4);
+
+        // the ended windows are pruned once the prune interval has passed
+        *time_provider.now.write().unwrap() = now + TimeDelta::seconds(90);
+        assert_eq!(limiter.check(group, None, ip(3)).unwrap(), None);
+        let windows = limiter.windows.lock().unwrap();
+        assert_eq!(windows.entries.len(), 3);
+        assert_eq!(windows.ip_keys, 1);
+    }
+}
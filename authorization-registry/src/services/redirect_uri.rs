@@ -0,0 +1,145 @@
+use reqwest::{StatusCode, Url};
+
+use crate::error::{AppError, ExpectedError};
+
+// `*` matches any part of the uri that does not contain a '/'
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    return match pattern.split_once('*') {
+        None => pattern == value,
+        Some((prefix, rest)) => match value.strip_prefix(prefix) {
+            None => false,
+            Some(value) => (0..=value.len())
+                .take_while(|i| !value[..*i].contains('/'))
+                .filter(|i| value.is_char_boundary(*i))
+                .any(|i| matches_pattern(rest, &value[i..])),
+        },
+    };
+}
+
+fn matches_entry(entry: &str, url: &Url) -> bool {
+    let origin = url.origin().ascii_serialization();
+    let has_path = entry
+        .split_once("://")
+        .map(|(_, rest)| rest.contains('/'))
+        .unwrap_or(false);
+
+    // entries without a path allow every path of the origin, the query is never compared
+    let value = match has_path {
+        true => format!("{}{}", origin, url.path()),
+        false => origin,
+    };
+
+    if entry.contains('*') {
+        return matches_pattern(entry, &value);
+    }
+
+    return match Url::parse(entry) {
+        Ok(entry) if has_path => {
+            format!("{}{}", entry.origin().ascii_serialization(), entry.path()) == value
+        }
+        Ok(entry) => entry.origin().ascii_serialization() == value,
+        Err(_) => false,
+    };
+}
+
+pub fn is_allowed_redirect_uri(redirect_uri: &str, allowed_redirect_uris: &[String]) -> bool {
+    let url = match Url::parse(redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+
+    if !["http", "https"].contains(&url.scheme())
+        || url.fragment().is_some()
+        || !url.username().is_empty()
+        || url.password().is_some()
+    {
+        return false;
+    }
+
+    return allowed_redirect_uris
+        .iter()
+        .any(|entry| matches_entry(entry, &url));
+}
+
+pub fn validate_redirect_uri(
+    redirect_uri: &str,
+    allowed_redirect_uris: &[String],
+) -> Result<(), AppError> {
+    if !is_allowed_redirect_uri(redirect_uri, allowed_redirect_uris) {
+        return Err(AppError::Expected(ExpectedError {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "Redirect URI is not allowed".to_owned(),
+            reason: format!(
+                "'{}' does not match any of the allowed redirect URIs",
+                redirect_uri
+            ),
+            metadata: None,
+        }));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn allowed(redirect_uri: &str, entry: &str) -> bool {
+        return is_allowed_redirect_uri(redirect_uri, &[entry.to_owned()]);
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let entry = "https://ar.example.com/callback";
+
+        assert!(allowed("https://ar.example.com/callback", entry));
+        assert!(allowed(
+            "https://ar.example.com/callback?state=/policies",
+            entry
+        ));
+        assert!(allowed("https://ar.example.com:443/callback", entry));
+        assert!(!allowed("https://ar.example.com/callback/other", entry));
+        assert!(!allowed("http://ar.example.com/callback", entry));
+        assert!(!allowed("https://ar.example.com.evil.com/callback", entry));
+        assert!(!allowed("https://ar.example.com/callback#token", entry));
+        assert!(!allowed("https://ar.example.com@evil.com/callback", entry));
+    }
+
+    #[test]
+    fn test_origin_match() {
+        let entry = "https://ar.example.com";
+
+        assert!(allowed("https://ar.example.com/callback", entry));
+        assert!(allowed("https://ar.example.com/", entry));
+        assert!(!allowed("https://ar.example.com:8443/callback", entry));
+        assert!(!allowed("https://evil.com/https://ar.example.com", entry));
+    }
+
+    #[test]
+    fn test_pattern_match() {
+        let entry = "https://*.example.com/callback";
+
+        assert!(allowed("https://ar.example.com/callback", entry));
+        assert!(allowed("https://test.ar.example.com/callback", entry));
+        assert!(!allowed("https://example.com/callback", entry));
+        assert!(!allowed("https://evil.com/.example.com/callback", entry));
+        assert!(!allowed("https://ar.example.com/other/callback", entry));
+
+        let entry = "http://localhost:*";
+        assert!(allowed("http://localhost:5173/callback", entry));
+        assert!(!allowed("http://localhost.evil.com/callback", entry));
+
+        let entry = "https://ar.example.com/*/callback";
+        assert!(allowed("https://ar.example.com/nl/callback", entry));
+        assert!(!allowed("https://ar.example.com/nl/other/callback", entry));
+    }
+
+    #[test]
+    fn test_invalid_redirect_uri() {
+        let entries = vec!["https://*".to_owned()];
+
+        assert!(!is_allowed_redirect_uri("not a url", &entries));
+        assert!(!is_allowed_redirect_uri("javascript:alert(1)", &entries));
+        assert!(!is_allowed_redirect_uri("https://ar.example.com", &[]));
+    }
+}
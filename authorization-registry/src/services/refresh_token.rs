@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::StatusCode;
+use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    db::{
+        refresh_token::{self as refresh_token_store, InsertRefreshToken},
+        token_revocation as token_revocation_store,
+    },
+    error::{AppError, ExpectedError},
+    TimeProvider,
+};
+
+use super::server_token::UserOption;
+
+const PURGE_INTERVAL_SECONDS: u64 = 3600;
+
+pub struct Session {
+    // the refresh token family, every rotation stays in the same session
+    pub session_id: String,
+    pub refresh_token: String,
+}
+
+pub struct RefreshedSession {
+    pub company_id: String,
+    pub user: UserOption,
+    pub session: Session,
+}
+
+fn generate_refresh_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).context("Error generating refresh token")?;
+
+    return Ok(URL_SAFE_NO_PAD.encode(bytes));
+}
+
+// only the hash is stored, so a leaked table can not be used to refresh sessions
+fn hash_refresh_token(refresh_token: &str) -> String {
+    return hex::encode(Sha256::digest(refresh_token.as_bytes()));
+}
+
+fn invalid_refresh_token(reason: &str) -> AppError {
+    return AppError::Expected(ExpectedError {
+        status_code: StatusCode::UNAUTHORIZED,
+        message: "Invalid refresh token".to_owned(),
+        reason: reason.to_owned(),
+        metadata: None,
+    });
+}
+
+async fn issue_refresh_token(
+    now: DateTime<Utc>,
+    family_id: Uuid,
+    company_id: String,
+    user: &UserOption,
+    session_expires_at: DateTime<Utc>,
+    refresh_token_expiry_seconds: i64,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Session> {
+    let refresh_token = generate_refresh_token()?;
+    let expires_at = std::cmp::min(
+        now + TimeDelta::seconds(refresh_token_expiry_seconds),
+        session_expires_at,
+    );
+
+    refresh_token_store::insert_refresh_token(
+        now,
+        InsertRefreshToken {
+            token_hash: hash_refresh_token(&refresh_token),
+            family_id,
+            company_id,
+            user_id: user.user_id.clone(),
+            realm_access_roles: user.realm_access_roles.clone(),
+            expires_at,
+            session_expires_at,
+        },
+        db,
+    )
+    .await?;
+
+    return Ok(Session {
+        session_id: family_id.to_string(),
+        refresh_token,
+    });
+}
+
+pub async fn start_session(
+    now: DateTime<Utc>,
+    company_id: String,
+    user: &UserOption,
+    refresh_token_expiry_seconds: i64,
+    session_lifetime_seconds: i64,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Session> {
+    return issue_refresh_token(
+        now,
+        Uuid::new_v4(),
+        company_id,
+        user,
+        now + TimeDelta::seconds(session_lifetime_seconds),
+        refresh_token_expiry_seconds,
+        db,
+    )
+    .await;
+}
+
+// exchanges a refresh token for a new one. A refresh token can only be used once,
+// using it again revokes the whole session because the token has probably been stolen
+pub async fn refresh_session(
+    now: DateTime<Utc>,
+    refresh_token: &str,
+    refresh_token_expiry_seconds: i64,
+    db: &DatabaseConnection,
+) -> Result<RefreshedSession, AppError> {
+    let stored = refresh_token_store::get_by_hash(&hash_refresh_token(refresh_token), db)
+        .await?
+        .ok_or_else(|| invalid_refresh_token("Refresh token is unknown"))?;
+
+    if stored.revoked_at.is_some() {
+        return Err(invalid_refresh_token("Refresh token has been revoked"));
+    }
+
+    if stored.expires_at <= now {
+        return Err(invalid_refresh_token("Refresh token has expired"));
+    }
+
+    // all tokens of the user or company may have been revoked after this refresh token was issued
+    if token_revocation_store::is_revoked(
+        "",
+        Some(&stored.user_id),
+        &stored.company_id,
+        stored.created_at,
+        db,
+    )
+    .await?
+    {
+        refresh_token_store::revoke_family(stored.family_id, now, db).await?;
+        return Err(invalid_refresh_token(
+            "Tokens of the user or company have been revoked",
+        ));
+    }
+
+    if stored.used_at.is_some() || !refresh_token_store::mark_used(stored.id, now, db).await? {
+        tracing::warn!(
+            "refresh token reuse detected for user '{}', revoking session '{}'",
+            &stored.user_id,
+            &stored.family_id
+        );
+        refresh_token_store::revoke_family(stored.family_id, now, db).await?;
+
+        return Err(invalid_refresh_token("Refresh token has already been used"));
+    }
+
+    let user = UserOption {
+        user_id: stored.user_id,
+        realm_access_roles: serde_json::from_value(stored.realm_access_roles)
+            .context("Error deserializing realm access roles of refresh token")?,
+    };
+
+    let session = issue_refresh_token(
+        now,
+        stored.family_id,
+        stored.company_id.clone(),
+        &user,
+        stored.session_expires_at,
+        refresh_token_expiry_seconds,
+        db,
+    )
+    .await?;
+
+    return Ok(RefreshedSession {
+        company_id: stored.company_id,
+        user,
+        session,
+    });
+}
+
+pub async fn end_session(
+    now: DateTime<Utc>,
+    session_id: &str,
+    db: &DatabaseConnection,
+) -> anyhow::Result<()> {
+    let family_id = Uuid::parse_str(session_id)
+        .context(format!("Error parsing session id '{}'", session_id))?;
+    refresh_token_store::revoke_family(family_id, now, db).await?;
+
+    Ok(())
+}
+
+pub fn spawn_purge_job(time_provider: Arc<dyn TimeProvider>, db: DatabaseConnection) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECONDS));
+
+        loop {
+            interval.tick().await;
+
+            match refresh_token_store::delete_expired(time_provider.now(), &db).await {
+                Ok(count) => tracing::info!("purged {} expired refresh tokens", count),
+                Err(e) => tracing::error!("error purging refresh tokens: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use crate::test_helpers::helpers::init_test_db;
+
+    use super::*;
+
+    fn user() -> UserOption {
+        return UserOption {
+            user_id: "user".to_owned(),
+            realm_access_roles: vec!["dexspace_admin".to_owned()],
+        };
+    }
+
+    fn reason(err: AppError) -> String {
+        return match err {
+            AppError::Expected(e) => e.reason,
+            e => panic!("unexpected error: {:?}", e),
+        };
+    }
+
+    #[sqlx::test]
+    async fn test_session_lifetime(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        let login = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let session = start_session(login, "company".to_owned(), &user(), 60, 100, &db)
+            .await
+            .unwrap();
+
+        // not used within the refresh token expiry
+        let err = refresh_session(
+            login + TimeDelta::seconds(61),
+            &session.refresh_token,
+            60,
+            &db,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(reason(err), "Refresh token has expired");
+
+        let refreshed = refresh_session(
+            login + TimeDelta::seconds(50),
+            &session.refresh_token,
+            60,
+            &db,
+        )
+        .await
+        .unwrap();
+        assert_eq!(refreshed.company_id, "company");
+        assert_eq!(refreshed.user.user_id, "user");
+        assert_eq!(refreshed.user.realm_access_roles, vec!["dexspace_admin"]);
+        assert_eq!(refreshed.session.session_id, session.session_id);
+
+        let refreshed = refresh_session(
+            login + TimeDelta::seconds(99),
+            &refreshed.session.refresh_token,
+            60,
+            &db,
+        )
+        .await
+        .unwrap();
+
+        // the session can not be extended beyond its lifetime
+        let err = refresh_session(
+            login + TimeDelta::seconds(100),
+            &refreshed.session.refresh_token,
+            60,
+            &db,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(reason(err), "Refresh token has expired");
+    }
+
+    #[sqlx::test]
+    async fn test_revoked_user(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        let login = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let session = start_session(login, "company".to_owned(), &user(), 60, 100, &db)
+            .await
+            .unwrap();
+
+        token_revocation_store::insert_token_revocation(
+            login + TimeDelta::seconds(10),
+            crate::db::token_revocation::InsertTokenRevocation {
+                jti: None,
+                user_id: Some("user".to_owned()),
+                company_id: None,
+                issued_before: Some(login + TimeDelta::seconds(10)),
+                expires_at: login + TimeDelta::seconds(1000),
+            },
+            &db,
+        )
+        .await
+        .unwrap();
+
+        let err = refresh_session(
+            login + TimeDelta::seconds(20),
+            &session.refresh_token,
+            60,
+            &db,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(
+            reason(err),
+            "Tokens of the user or company have been revoked"
+        );
+
+        // a new login after the revocation is not affected
+        let session = start_session(
+            login + TimeDelta::seconds(30),
+            "company".to_owned(),
+            &user(),
+            60,
+            100,
+            &db,
+        )
+        .await
+        .unwrap();
+        refresh_session(
+            login + TimeDelta::seconds(40),
+            &session.refresh_token,
+            60,
+            &db,
+        )
+        .await
+        .unwrap();
+    }
+}
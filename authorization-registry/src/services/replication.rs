@@ -0,0 +1,647 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Context;
+use ar_entity::delegation_evidence::{Environment, Policy, Resource, ResourceTarget};
+use axum::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::StatusCode;
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    config::ReplicationConfig,
+    db::{
+        policy::{self as policy_store, MatchingPolicySetRow},
+        policy_set_suspension as suspension_store,
+        replication::{self as replication_store, PendingDelivery},
+    },
+    error::{AppError, ExpectedError},
+    TimeProvider,
+};
+
+// changes delivered to a target per run of the replication job
+const DELIVERY_BATCH_SIZE: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicatedPolicySetTarget {
+    pub access_subject: String,
+}
+
+// a policy set as it is inserted at the `/policy-set` api of the target
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicatedPolicySet {
+    pub target: ReplicatedPolicySetTarget,
+    pub policy_issuer: String,
+    pub licences: Vec<String>,
+    pub policies: Vec<Policy>,
+    pub max_delegation_depth: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataspace: Option<String>,
+}
+
+impl From<MatchingPolicySetRow> for ReplicatedPolicySet {
+    fn from(row: MatchingPolicySetRow) -> Self {
+        return Self {
+            target: ReplicatedPolicySetTarget {
+                access_subject: row.access_subject,
+            },
+            policy_issuer: row.policy_issuer,
+            licences: row.licenses,
+            policies: row
+                .policies
+                .into_iter()
+                .map(|p| Policy {
+                    target: ResourceTarget {
+                        resource: Resource {
+                            resource_type: p.resource_type,
+                            identifiers: p.identifiers,
+                            attributes: p.attributes,
+                        },
+                        actions: p.actions,
+                        environment: Environment {
+                            service_providers: p.service_providers,
+                        },
+                    },
+                    rules: p.rules,
+                })
+                .collect(),
+            max_delegation_depth: row.max_delegation_depth,
+            dataspace: row.dataspace,
+        };
+    }
+}
+
+// a pdp or peer registry that keeps a copy of the policy sets of this registry
+#[async_trait]
+pub trait ReplicationTarget: Send + Sync {
+    // the party that runs the target, it only receives the policy sets in which it is a service
+    // provider
+    fn eori(&self) -> &str;
+    // returns the id of the copy at the target
+    async fn create_policy_set(&self, policy_set: &ReplicatedPolicySet) -> anyhow::Result<String>;
+    // a copy that no longer exists at the target counts as deleted
+    async fn delete_policy_set(&self, remote_id: &str) -> anyhow::Result<()>;
+}
+
+pub struct Replication {
+    config: ReplicationConfig,
+    targets: BTreeMap<String, Arc<dyn ReplicationTarget>>,
+}
+
+impl Replication {
+    pub fn new(
+        config: ReplicationConfig,
+        targets: BTreeMap<String, Arc<dyn ReplicationTarget>>,
+    ) -> Self {
+        return Self { config, targets };
+    }
+
+    fn get_target(&self, id: &str) -> Result<&Arc<dyn ReplicationTarget>, AppError> {
+        return self.targets.get(id).ok_or_else(|| {
+            AppError::Expected(ExpectedError {
+                status_code: StatusCode::NOT_FOUND,
+                message: format!("Unknown replication target '{}'", id),
+                reason: format!(
+                    "replication target '{}' is not one of the configured targets",
+                    id
+                ),
+                metadata: None,
+            })
+        });
+    }
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, ToSchema)]
+pub struct ReplicationSummary {
+    pub delivered: u64,
+    pub failed: u64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ReplicationFailure {
+    pub policy_set_id: Uuid,
+    pub attempts: i32,
+    pub last_error: String,
+    // not set when the delivery is no longer retried
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ReplicationTargetStatus {
+    pub target: String,
+    // policy sets of which the last change is delivered
+    pub delivered: i64,
+    pub pending: i64,
+    // policy sets of which the delivery failed `max_attempts` times
+    pub failed: i64,
+    pub failures: Vec<ReplicationFailure>,
+}
+
+fn is_service_provider(policy_set: &MatchingPolicySetRow, eori: &str) -> bool {
+    return policy_set
+        .policies
+        .iter()
+        .any(|p| p.service_providers.iter().any(|sp| sp == eori));
+}
+
+// the target has no way to update a policy set, so the copy at the target is replaced. Policy
+// sets that are suspended, or no longer concern the target, are only removed
+async fn deliver(
+    target: &dyn ReplicationTarget,
+    policy_set_id: Uuid,
+    remote_id: &mut Option<String>,
+    db: &DatabaseConnection,
+) -> anyhow::Result<()> {
+    if let Some(id) = remote_id.as_deref() {
+        target.delete_policy_set(id).await?;
+        *remote_id = None;
+    }
+
+    let policy_set = policy_store::get_policy_set_with_policies(&policy_set_id, db)
+        .await?
+        .filter(|policy_set| is_service_provider(policy_set, target.eori()));
+    if let Some(policy_set) = policy_set {
+        if !suspension_store::is_suspended(policy_set_id, db).await? {
+            *remote_id = Some(target.create_policy_set(&policy_set.into()).await?);
+        }
+    }
+
+    return Ok(());
+}
+
+fn retry_delay(config: &ReplicationConfig, attempts: i32) -> TimeDelta {
+    let factor = 1_i64 << (attempts - 1).clamp(0, 10);
+
+    return TimeDelta::seconds(config.retry_seconds * factor);
+}
+
+async fn replicate_delivery(
+    now: DateTime<Utc>,
+    target_id: &str,
+    target: &dyn ReplicationTarget,
+    config: &ReplicationConfig,
+    pending: PendingDelivery,
+    db: &DatabaseConnection,
+) -> anyhow::Result<bool> {
+    let mut remote_id = pending.remote_id.clone();
+    let result = deliver(target, pending.policy_set_id, &mut remote_id, db).await;
+
+    let delivery = match result {
+        Ok(()) => ar_entity::replication_delivery::Model {
+            target: target_id.to_owned(),
+            policy_set_id: pending.policy_set_id,
+            remote_id,
+            delivered_version: pending.version,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: None,
+            updated_at: now,
+        },
+        Err(ref e) => {
+            let attempts = pending.attempts + 1;
+            tracing::warn!(
+                "error replicating policy set '{}' to '{}' (attempt {}): {:?}",
+                pending.policy_set_id,
+                target_id,
+                attempts,
+                e
+            );
+
+            ar_entity::replication_delivery::Model {
+                target: target_id.to_owned(),
+                policy_set_id: pending.policy_set_id,
+                remote_id,
+                delivered_version: pending.delivered_version,
+                attempts,
+                last_error: Some(format!("{:#}", e)),
+                next_attempt_at: (attempts < config.max_attempts)
+                    .then(|| now + retry_delay(config, attempts)),
+                updated_at: now,
+            }
+        }
+    };
+
+    replication_store::save_delivery(delivery, db).await?;
+
+    return Ok(result.is_ok());
+}
+
+// delivers the pending policy set changes to every target
+pub async fn replicate(
+    now: DateTime<Utc>,
+    replication: &Replication,
+    db: &DatabaseConnection,
+) -> anyhow::Result<ReplicationSummary> {
+    let mut summary = ReplicationSummary::default();
+
+    for (target_id, target) in replication.targets.iter() {
+        let pending = replication_store::get_pending_deliveries(
+            now,
+            target_id,
+            replication.config.max_attempts,
+            DELIVERY_BATCH_SIZE,
+            db,
+        )
+        .await?;
+
+        for delivery in pending {
+            let delivered = replicate_delivery(
+                now,
+                target_id,
+                target.as_ref(),
+                &replication.config,
+                delivery,
+                db,
+            )
+            .await?;
+
+            if delivered {
+                summary.delivered += 1;
+            } else {
+                summary.failed += 1;
+            }
+        }
+    }
+
+    return Ok(summary);
+}
+
+async fn get_target_status(
+    target_id: &str,
+    config: &ReplicationConfig,
+    db: &DatabaseConnection,
+) -> anyhow::Result<ReplicationTargetStatus> {
+    let counts = replication_store::get_delivery_counts(target_id, config.max_attempts, db).await?;
+    let failures = replication_store::get_failed_deliveries(target_id, db)
+        .await?
+        .into_iter()
+        .map(|d| ReplicationFailure {
+            policy_set_id: d.policy_set_id,
+            attempts: d.attempts,
+            last_error: d.last_error.unwrap_or_default(),
+            next_attempt_at: d.next_attempt_at,
+        })
+        .collect();
+
+    return Ok(ReplicationTargetStatus {
+        target: target_id.to_owned(),
+        delivered: counts.delivered,
+        pending: counts.pending,
+        failed: counts.failed,
+        failures,
+    });
+}
+
+pub async fn get_replication_status(
+    replication: &Replication,
+    db: &DatabaseConnection,
+) -> anyhow::Result<Vec<ReplicationTargetStatus>> {
+    let mut statuses = vec![];
+    for target_id in replication.targets.keys() {
+        statuses.push(get_target_status(target_id, &replication.config, db).await?);
+    }
+
+    return Ok(statuses);
+}
+
+// sends every stored policy set to the target again, and removes the copies of deleted policy
+// sets that are still at the target
+pub async fn resync_target(
+    now: DateTime<Utc>,
+    replication: &Replication,
+    target_id: &str,
+    db: &DatabaseConnection,
+) -> Result<ReplicationTargetStatus, AppError> {
+    replication.get_target(target_id)?;
+
+    let transaction = db.begin().await.context("Error opening db transaction")?;
+    replication_store::record_all_policy_sets(now, &transaction).await?;
+    replication_store::reset_deliveries(now, target_id, &transaction).await?;
+    transaction
+        .commit()
+        .await
+        .context("Error commiting transaction to db")?;
+
+    tracing::info!("resyncing replication target '{}'", target_id);
+
+    return Ok(get_target_status(target_id, &replication.config, db).await?);
+}
+
+pub fn spawn_replication_job(
+    replication: Arc<Replication>,
+    time_provider: Arc<dyn TimeProvider>,
+    db: DatabaseConnection,
+) {
+    if replication.targets.is_empty() || replication.config.interval_seconds == 0 {
+        tracing::info!("replication of policy sets is disabled");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            replication.config.interval_seconds,
+        ));
+
+        loop {
+            interval.tick().await;
+
+            match replicate(time_provider.now(), &replication, &db).await {
+                Ok(summary) if summary == ReplicationSummary::default() => {}
+                Ok(summary) => tracing::info!("replicated policy sets: {:?}", summary),
+                Err(e) => tracing::error!("error replicating policy sets: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+    use crate::{
+        config::PartyChecksConfig,
+        fixtures::fixtures::insert_policy_set_fixture,
+        services::party_revalidation::revalidate_parties,
+        test_helpers::helpers::{
+            get_test_dataspaces, get_test_party_checks, get_test_replication, init_test_db,
+            TestReplicationTarget,
+        },
+    };
+
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        return DateTime::parse_from_rfc3339("2024-05-09T09:33:25Z")
+            .unwrap()
+            .to_utc();
+    }
+
+    #[sqlx::test]
+    async fn test_replicate(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let target = Arc::new(TestReplicationTarget::default());
+        let replication = get_test_replication(target.clone());
+
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+        let policy_set_id = Uuid::parse_str("84b7fba4-05f3-4af8-9d84-dde384abe881").unwrap();
+        replication_store::record_change(now(), policy_set_id, &db)
+            .await
+            .unwrap();
+
+        let summary = replicate(now(), &replication, &db).await.unwrap();
+        assert_eq!(summary.delivered, 1);
+        {
+            let policy_sets = target.policy_sets.lock().unwrap();
+            assert_eq!(policy_sets.len(), 1);
+            let policy_set = policy_sets.values().next().unwrap();
+            assert_eq!(policy_set.policy_issuer, "NL.24244");
+            assert_eq!(policy_set.target.access_subject, "NL.44444");
+            assert_eq!(
+                policy_set.policies[0].target.resource.identifiers,
+                ["test4"]
+            );
+        }
+        // nothing left to deliver
+        assert_eq!(
+            replicate(now(), &replication, &db).await.unwrap(),
+            ReplicationSummary::default()
+        );
+
+        // an edit that can not be delivered is retried after the retry delay
+        *target.fail.lock().unwrap() = true;
+        replication_store::record_change(now(), policy_set_id, &db)
+            .await
+            .unwrap();
+        assert_eq!(replicate(now(), &replication, &db).await.unwrap().failed, 1);
+        assert_eq!(
+            replicate(now(), &replication, &db).await.unwrap(),
+            ReplicationSummary::default()
+        );
+
+        let status = get_replication_status(&replication, &db).await.unwrap();
+        assert_eq!(status[0].pending, 1);
+        assert_eq!(status[0].failures.len(), 1);
+        assert_eq!(status[0].failures[0].last_error, "target unavailable");
+        assert_eq!(
+            status[0].failures[0].next_attempt_at,
+            Some(now() + TimeDelta::seconds(60))
+        );
+
+        *target.fail.lock().unwrap() = false;
+        let later = now() + TimeDelta::seconds(61);
+        assert_eq!(
+            replicate(later, &replication, &db).await.unwrap().delivered,
+            1
+        );
+        assert_eq!(target.policy_sets.lock().unwrap().len(), 1);
+
+        let status = get_replication_status(&replication, &db).await.unwrap();
+        assert_eq!(status[0].delivered, 1);
+        assert!(status[0].failures.is_empty());
+
+        // the copy of a deleted policy set is removed from the target
+        policy_store::delete_policy_set(&policy_set_id, &db)
+            .await
+            .unwrap();
+        replication_store::record_change(later, policy_set_id, &db)
+            .await
+            .unwrap();
+        assert_eq!(
+            replicate(later, &replication, &db).await.unwrap().delivered,
+            1
+        );
+        assert!(target.policy_sets.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_replicate_to_service_providers(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let peer = Arc::new(TestReplicationTarget::default());
+        let other = Arc::new(TestReplicationTarget {
+            eori: "NL.44444".to_owned(),
+            ..TestReplicationTarget::default()
+        });
+        let replication = Replication::new(
+            ReplicationConfig::default(),
+            BTreeMap::from([
+                (
+                    "peer".to_owned(),
+                    peer.clone() as Arc<dyn ReplicationTarget>,
+                ),
+                (
+                    "other".to_owned(),
+                    other.clone() as Arc<dyn ReplicationTarget>,
+                ),
+            ]),
+        );
+
+        // "good-company" is the service provider of policy_set1, "NL.44444" of policy_set5
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+        insert_policy_set_fixture("./fixtures/policy_set5.json", &db).await;
+        let policy_set_id = Uuid::parse_str("ff044535-bcac-448e-8863-49c916650e3a").unwrap();
+        ar_entity::policy_set::Entity::update_many()
+            .col_expr(
+                ar_entity::policy_set::Column::Dataspace,
+                sea_orm::sea_query::Expr::value("dataspace"),
+            )
+            .filter(ar_entity::policy_set::Column::Id.eq(policy_set_id))
+            .exec(&db)
+            .await
+            .unwrap();
+        replication_store::record_all_policy_sets(now(), &db)
+            .await
+            .unwrap();
+
+        replicate(now(), &replication, &db).await.unwrap();
+        {
+            let policy_sets = peer.policy_sets.lock().unwrap();
+            assert_eq!(policy_sets.len(), 1);
+            assert_eq!(
+                policy_sets.values().next().unwrap().policy_issuer,
+                "NL.24244"
+            );
+        }
+        {
+            let policy_sets = other.policy_sets.lock().unwrap();
+            assert_eq!(policy_sets.len(), 1);
+            let policy_set = policy_sets.values().next().unwrap();
+            assert_eq!(policy_set.policy_issuer, "NL.44444");
+            assert_eq!(policy_set.dataspace.as_deref(), Some("dataspace"));
+        }
+
+        // the copy is removed from a target that is no longer a service provider
+        ar_entity::policy::Entity::update_many()
+            .col_expr(
+                ar_entity::policy::Column::ServiceProviders,
+                sea_orm::sea_query::Expr::value(vec!["good-company".to_owned()]),
+            )
+            .filter(ar_entity::policy::Column::PolicySet.eq(policy_set_id))
+            .exec(&db)
+            .await
+            .unwrap();
+        replication_store::record_change(now(), policy_set_id, &db)
+            .await
+            .unwrap();
+
+        replicate(now(), &replication, &db).await.unwrap();
+        assert_eq!(peer.policy_sets.lock().unwrap().len(), 2);
+        assert!(other.policy_sets.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_replicate_suspension(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let target = Arc::new(TestReplicationTarget {
+            eori: "NL.NO_ROLES".to_owned(),
+            ..TestReplicationTarget::default()
+        });
+        let replication = get_test_replication(target.clone());
+
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+        let policy_set_id = Uuid::parse_str("84b7fba4-05f3-4af8-9d84-dde384abe881").unwrap();
+        ar_entity::policy::Entity::update_many()
+            .col_expr(
+                ar_entity::policy::Column::ServiceProviders,
+                sea_orm::sea_query::Expr::value(vec!["NL.NO_ROLES".to_owned()]),
+            )
+            .filter(ar_entity::policy::Column::PolicySet.eq(policy_set_id))
+            .exec(&db)
+            .await
+            .unwrap();
+        replication_store::record_change(now(), policy_set_id, &db)
+            .await
+            .unwrap();
+        replicate(now(), &replication, &db).await.unwrap();
+        assert_eq!(target.policy_sets.lock().unwrap().len(), 1);
+
+        // the service provider does not hold the ServiceProvider role
+        let dataspaces = get_test_dataspaces();
+        let summary = revalidate_parties(now(), &dataspaces, &get_test_party_checks(), &db)
+            .await
+            .unwrap();
+        assert_eq!(summary.suspended, 1);
+        replicate(now(), &replication, &db).await.unwrap();
+        assert!(target.policy_sets.lock().unwrap().is_empty());
+
+        let config = PartyChecksConfig {
+            service_provider_roles: vec![],
+            ..get_test_party_checks()
+        };
+        let summary = revalidate_parties(now(), &dataspaces, &config, &db)
+            .await
+            .unwrap();
+        assert_eq!(summary.lifted, 1);
+        replicate(now(), &replication, &db).await.unwrap();
+        assert_eq!(target.policy_sets.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_resync_target(
+        _pool_options: PgPoolOptions,
+        conn_option: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        let db = init_test_db(&conn_option).await;
+        let target = Arc::new(TestReplicationTarget::default());
+        let replication = get_test_replication(target.clone());
+
+        // policy sets that are stored without a recorded change
+        insert_policy_set_fixture("./fixtures/policy_set1.json", &db).await;
+        insert_policy_set_fixture("./fixtures/policy_set2.json", &db).await;
+        assert_eq!(
+            replicate(now(), &replication, &db).await.unwrap(),
+            ReplicationSummary::default()
+        );
+
+        let status = resync_target(now(), &replication, "peer", &db)
+            .await
+            .unwrap();
+        assert_eq!(status.pending, 2);
+        assert_eq!(
+            replicate(now(), &replication, &db).await.unwrap().delivered,
+            2
+        );
+        assert_eq!(target.policy_sets.lock().unwrap().len(), 2);
+
+        // the copies at the target are replaced, not duplicated
+        resync_target(now(), &replication, "peer", &db)
+            .await
+            .unwrap();
+        assert_eq!(
+            replicate(now(), &replication, &db).await.unwrap().delivered,
+            2
+        );
+        assert_eq!(target.policy_sets.lock().unwrap().len(), 2);
+
+        assert!(matches!(
+            resync_target(now(), &replication, "unknown", &db).await,
+            Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::NOT_FOUND,
+                ..
+            }))
+        ));
+
+        Ok(())
+    }
+}
@@ -1,18 +1,145 @@
-use crate::error::{AppError, ExpectedError};
+use std::sync::Arc;
+
+use crate::{
+    config::{JwtAlgorithm, JwtSigningConfig},
+    db::token_revocation::InsertTokenRevocation,
+    error::{AppError, ExpectedError},
+};
 use anyhow::Context;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse, RSAKeyParameters,
+        RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use openssl::{
+    bn::BigNumContext,
+    ec::EcKey,
+    nid::Nid,
+    pkey::{Id, PKey},
+};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::token_revocation::RevocationStore;
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    // only set for asymmetric keys, the hs256 secret is never published
+    jwk: Option<Jwk>,
+}
 
 pub struct ServerToken {
+    algorithm: Algorithm,
+    kid: Option<String>,
     encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
+    verification_keys: Vec<VerificationKey>,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
     pub jwt_expiry_seconds: u64,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationSubject {
+    Company(String),
+    User(String),
+}
+
+#[derive(Clone)]
+pub struct PemKey {
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    pub pem: Vec<u8>,
+}
+
+pub fn to_jsonwebtoken_algorithm(algorithm: JwtAlgorithm) -> Algorithm {
+    match algorithm {
+        JwtAlgorithm::RS256 => Algorithm::RS256,
+        JwtAlgorithm::ES256 => Algorithm::ES256,
+    }
+}
+
+pub fn public_key_to_jwk(key: &PemKey) -> anyhow::Result<Jwk> {
+    let public_key = PKey::public_key_from_pem(&key.pem)
+        .context(format!("Error parsing public key '{}'", &key.kid))?;
+
+    let (key_algorithm, algorithm) = match key.algorithm {
+        JwtAlgorithm::RS256 => {
+            if public_key.id() != Id::RSA {
+                anyhow::bail!("public key '{}' is not an RSA key", &key.kid);
+            }
+            let rsa = public_key.rsa().context("Error reading RSA public key")?;
+
+            (
+                KeyAlgorithm::RS256,
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                    e: URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+                }),
+            )
+        }
+        JwtAlgorithm::ES256 => {
+            let ec: EcKey<_> = public_key.ec_key().context("Error reading EC public key")?;
+            if ec.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+                anyhow::bail!("public key '{}' is not a P-256 key", &key.kid);
+            }
+
+            let mut ctx = BigNumContext::new()?;
+            let mut x = openssl::bn::BigNum::new()?;
+            let mut y = openssl::bn::BigNum::new()?;
+            ec.public_key()
+                .affine_coordinates(ec.group(), &mut x, &mut y, &mut ctx)
+                .context("Error reading EC public key coordinates")?;
+
+            (
+                KeyAlgorithm::ES256,
+                AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve: EllipticCurve::P256,
+                    x: URL_SAFE_NO_PAD.encode(x.to_vec_padded(32)?),
+                    y: URL_SAFE_NO_PAD.encode(y.to_vec_padded(32)?),
+                }),
+            )
+        }
+    };
+
+    return Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(key.kid.clone()),
+            ..Default::default()
+        },
+        algorithm,
+    });
+}
+
+fn to_verification_key(key: &PemKey) -> anyhow::Result<VerificationKey> {
+    let jwk = public_key_to_jwk(key)?;
+
+    return Ok(VerificationKey {
+        kid: Some(key.kid.clone()),
+        algorithm: to_jsonwebtoken_algorithm(key.algorithm),
+        decoding_key: DecodingKey::from_jwk(&jwk)
+            .context(format!("Error creating decoding key '{}'", &key.kid))?,
+        jwk: Some(jwk),
+    });
+}
+
 #[derive(Serialize, Debug, Deserialize, Clone)]
 pub struct Machine {
     pub company_id: String,
+    // the dataspace the client authenticated in, left out for the default dataspace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataspace: Option<String>,
 }
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
@@ -33,20 +160,115 @@ impl Role {
     pub fn get_company_id(&self) -> String {
         match self {
             Self::Human(Human { company_id, .. }) => company_id.to_owned(),
-            Self::Machine(Machine { company_id }) => company_id.to_owned(),
+            Self::Machine(Machine { company_id, .. }) => company_id.to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    #[serde(rename = "delegation")]
+    Delegation,
+    #[serde(rename = "policy:read")]
+    PolicyRead,
+    #[serde(rename = "policy:write")]
+    PolicyWrite,
+    #[serde(rename = "audit:read")]
+    AuditRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::Delegation,
+        Scope::PolicyRead,
+        Scope::PolicyWrite,
+        Scope::AuditRead,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Delegation => "delegation",
+            Scope::PolicyRead => "policy:read",
+            Scope::PolicyWrite => "policy:write",
+            Scope::AuditRead => "audit:read",
+        }
+    }
+
+    // parses the space separated scope of a m2m token request. The iSHARE scope is
+    // mandatory, requesting only the iSHARE scope grants all scopes
+    pub fn parse_request_scope(scope: &str) -> Result<Vec<Scope>, AppError> {
+        let invalid_scope = |reason: String| {
+            AppError::Expected(ExpectedError {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "invalid scope".to_owned(),
+                reason,
+                metadata: None,
+            })
+        };
+
+        let mut has_ishare_scope = false;
+        let mut scopes = vec![];
+
+        for requested in scope.split_whitespace() {
+            if requested == "iSHARE" {
+                has_ishare_scope = true;
+                continue;
+            }
+
+            match Scope::ALL.iter().find(|s| s.as_str() == requested) {
+                Some(scope) if !scopes.contains(scope) => scopes.push(*scope),
+                Some(_) => {}
+                None => return Err(invalid_scope(format!("unknown scope '{}'", requested))),
+            }
         }
+
+        if !has_ishare_scope {
+            return Err(invalid_scope("scope should contain iSHARE".to_owned()));
+        }
+
+        if scopes.is_empty() {
+            return Ok(Scope::ALL.to_vec());
+        }
+
+        return Ok(scopes);
+    }
+
+    pub fn to_claim(scopes: &[Scope]) -> String {
+        return scopes
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ServiceAccessTokenClaims {
-    exp: u64,
-    iat: u64,
+    pub exp: u64,
+    pub iat: u64,
+    // tokens issued before the jti was introduced don't have one
+    #[serde(default)]
+    pub jti: String,
+    // id of the refresh token family of h2m sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    // space separated scopes of m2m tokens. h2m tokens are restricted by their roles instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
 
     #[serde(flatten)]
     pub role: Role,
 }
 
+impl ServiceAccessTokenClaims {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        return match &self.scope {
+            Some(granted) => granted.split_whitespace().any(|s| s == scope.as_str()),
+            None => true,
+        };
+    }
+}
+
 #[cfg(test)]
 pub mod server_token_test_helper {
     use uuid::Uuid;
@@ -73,6 +295,22 @@ pub mod server_token_test_helper {
         return format!("Bearer {}", token);
     }
 
+    pub fn get_dataspace_machine_token_header(company_id: String, dataspace: &str) -> String {
+        let token = get_test_service()
+            .create_machine_token(company_id, &Scope::ALL, Some(dataspace.to_owned()))
+            .unwrap();
+
+        return format!("Bearer {}", token);
+    }
+
+    pub fn get_scoped_machine_token_header(company_id: String, scopes: &[Scope]) -> String {
+        let token = get_test_service()
+            .create_machine_token(company_id, scopes, None)
+            .unwrap();
+
+        return format!("Bearer {}", token);
+    }
+
     pub fn get_human_token_header(
         company_id_option: Option<String>,
         user_id_option: Option<String>,
@@ -101,6 +339,20 @@ pub mod server_token_test_helper {
 
         return format!("Bearer {}", token);
     }
+
+    pub fn get_human_token_header_with_roles(realm_access_roles: &[&str]) -> String {
+        let token = get_test_service()
+            .create_token(
+                Uuid::new_v4().to_string(),
+                Some(UserOption {
+                    user_id: Uuid::new_v4().to_string(),
+                    realm_access_roles: realm_access_roles.iter().map(|r| r.to_string()).collect(),
+                }),
+            )
+            .unwrap();
+
+        return format!("Bearer {}", token);
+    }
 }
 
 pub struct UserOption {
@@ -111,9 +363,218 @@ pub struct UserOption {
 impl ServerToken {
     pub fn new(private_key: String, jwt_expiry_seconds: u64) -> Self {
         return Self {
-            decoding_key: DecodingKey::from_secret(private_key.as_bytes()),
+            algorithm: Algorithm::HS256,
+            kid: None,
             encoding_key: EncodingKey::from_secret(private_key.as_bytes()),
+            verification_keys: vec![VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                decoding_key: DecodingKey::from_secret(private_key.as_bytes()),
+                jwk: None,
+            }],
+            revocation_store: None,
+            jwt_expiry_seconds,
+        };
+    }
+
+    // signs with the private key and accepts tokens signed by its public key or by
+    // any of the additional verification keys
+    pub fn new_asymmetric(
+        private_key: PemKey,
+        public_key: PemKey,
+        additional_verification_keys: Vec<PemKey>,
+        jwt_expiry_seconds: u64,
+    ) -> anyhow::Result<Self> {
+        let encoding_key = match private_key.algorithm {
+            JwtAlgorithm::RS256 => EncodingKey::from_rsa_pem(&private_key.pem),
+            JwtAlgorithm::ES256 => EncodingKey::from_ec_pem(&private_key.pem),
+        }
+        .context(format!("Error parsing private key '{}'", &private_key.kid))?;
+
+        let mut verification_keys = vec![to_verification_key(&public_key)?];
+        for key in additional_verification_keys.iter() {
+            verification_keys.push(to_verification_key(key)?);
+        }
+
+        return Ok(Self {
+            algorithm: to_jsonwebtoken_algorithm(private_key.algorithm),
+            kid: Some(private_key.kid),
+            encoding_key,
+            verification_keys,
+            revocation_store: None,
+            jwt_expiry_seconds,
+        });
+    }
+
+    pub fn from_signing_config(
+        config: &JwtSigningConfig,
+        jwt_expiry_seconds: u64,
+    ) -> anyhow::Result<Self> {
+        let read_key = |kid: &str, algorithm: JwtAlgorithm, path: &str| {
+            std::fs::read(path)
+                .context(format!("Error reading key file '{}'", path))
+                .map(|pem| PemKey {
+                    kid: kid.to_owned(),
+                    algorithm,
+                    pem,
+                })
+        };
+
+        let mut verification_keys = vec![];
+        for key in config.verification_keys.iter() {
+            verification_keys.push(read_key(&key.kid, key.algorithm, &key.public_key_path)?);
+        }
+
+        return Self::new_asymmetric(
+            read_key(&config.kid, config.algorithm, &config.private_key_path)?,
+            read_key(&config.kid, config.algorithm, &config.public_key_path)?,
+            verification_keys,
             jwt_expiry_seconds,
+        );
+    }
+
+    pub fn with_revocation_store(mut self, revocation_store: Arc<dyn RevocationStore>) -> Self {
+        self.revocation_store = Some(revocation_store);
+        return self;
+    }
+
+    fn get_revocation_store(&self) -> anyhow::Result<&Arc<dyn RevocationStore>> {
+        return self
+            .revocation_store
+            .as_ref()
+            .context("No token revocation store configured");
+    }
+
+    fn now() -> anyhow::Result<u64> {
+        return Ok(std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .context("Error getting the current time")?
+            .as_secs());
+    }
+
+    fn to_datetime(timestamp: u64) -> anyhow::Result<chrono::DateTime<chrono::Utc>> {
+        return chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .context(format!("Invalid timestamp '{}'", timestamp));
+    }
+
+    // revokes a single token until it expires
+    pub async fn revoke_token(&self, claims: &ServiceAccessTokenClaims) -> anyhow::Result<()> {
+        if claims.jti.is_empty() {
+            anyhow::bail!("token without jti can't be revoked");
+        }
+
+        self.get_revocation_store()?
+            .revoke(
+                Self::to_datetime(Self::now()?)?,
+                InsertTokenRevocation {
+                    jti: Some(claims.jti.clone()),
+                    user_id: None,
+                    company_id: None,
+                    issued_before: None,
+                    expires_at: Self::to_datetime(claims.exp)?,
+                },
+            )
+            .await
+    }
+
+    // revokes all tokens of the user or company that have been issued up to now, and the refresh
+    // tokens of their sessions. The iat of a token is in whole seconds, so tokens of the current
+    // second are revoked too and this returns once the next second has started: a token issued
+    // afterwards, e.g. by a new login, is not revoked
+    pub async fn revoke_all_tokens(&self, subject: RevocationSubject) -> anyhow::Result<()> {
+        let now = Self::now()?;
+        let (company_id, user_id) = match subject {
+            RevocationSubject::Company(company_id) => (Some(company_id), None),
+            RevocationSubject::User(user_id) => (None, Some(user_id)),
+        };
+
+        let revocation_store = self.get_revocation_store()?;
+        let sessions = revocation_store
+            .revoke_sessions(
+                Self::to_datetime(now)?,
+                user_id.as_deref(),
+                company_id.as_deref(),
+            )
+            .await?;
+        tracing::info!("revoked {} refresh tokens", sessions);
+
+        revocation_store
+            .revoke(
+                Self::to_datetime(now)?,
+                InsertTokenRevocation {
+                    jti: None,
+                    user_id,
+                    company_id,
+                    issued_before: Some(Self::to_datetime(now + 1)?),
+                    // every token issued up to now has expired by then
+                    expires_at: Self::to_datetime(now + 1 + self.jwt_expiry_seconds)?,
+                },
+            )
+            .await?;
+
+        let next_second = std::time::UNIX_EPOCH + std::time::Duration::from_secs(now + 1);
+        if let Ok(remaining) = next_second.duration_since(std::time::SystemTime::now()) {
+            tokio::time::sleep(remaining).await;
+        }
+
+        Ok(())
+    }
+
+    pub async fn purge_expired_revocations(&self) -> anyhow::Result<u64> {
+        self.get_revocation_store()?
+            .purge_expired(Self::to_datetime(Self::now()?)?)
+            .await
+    }
+
+    // decodes the token and checks it has not been revoked
+    pub async fn verify_token(
+        &self,
+        raw_token: &String,
+    ) -> Result<TokenData<ServiceAccessTokenClaims>, AppError> {
+        let token = self.decode_token(raw_token)?;
+
+        let revocation_store = match &self.revocation_store {
+            Some(revocation_store) => revocation_store,
+            None => return Ok(token),
+        };
+
+        let user_id = match &token.claims.role {
+            Role::Human(human) => Some(human.user_id.as_str()),
+            Role::Machine(_) => None,
+        };
+
+        let revoked = revocation_store
+            .is_revoked(
+                &token.claims.jti,
+                user_id,
+                &token.claims.role.get_company_id(),
+                Self::to_datetime(token.claims.iat)?,
+            )
+            .await?;
+
+        if revoked {
+            return Err(AppError::Expected(ExpectedError {
+                status_code: StatusCode::UNAUTHORIZED,
+                message: "unauthorized".to_owned(),
+                metadata: None,
+                reason: format!(
+                    "Server access token '{}' has been revoked",
+                    &token.claims.jti
+                ),
+            }));
+        }
+
+        return Ok(token);
+    }
+
+    // public keys that can be used to verify our tokens. empty when HS256 is used
+    pub fn get_jwks(&self) -> JwkSet {
+        return JwkSet {
+            keys: self
+                .verification_keys
+                .iter()
+                .filter_map(|k| k.jwk.clone())
+                .collect(),
         };
     }
 
@@ -121,6 +582,41 @@ impl ServerToken {
         &self,
         company_id: String,
         user: Option<UserOption>,
+    ) -> Result<String, AppError> {
+        return self.issue_token(company_id, user, None, None, None);
+    }
+
+    pub fn create_session_token(
+        &self,
+        company_id: String,
+        user: Option<UserOption>,
+        session_id: Option<String>,
+    ) -> Result<String, AppError> {
+        return self.issue_token(company_id, user, session_id, None, None);
+    }
+
+    pub fn create_machine_token(
+        &self,
+        company_id: String,
+        scopes: &[Scope],
+        dataspace: Option<String>,
+    ) -> Result<String, AppError> {
+        return self.issue_token(
+            company_id,
+            None,
+            None,
+            Some(Scope::to_claim(scopes)),
+            dataspace,
+        );
+    }
+
+    fn issue_token(
+        &self,
+        company_id: String,
+        user: Option<UserOption>,
+        session_id: Option<String>,
+        scope: Option<String>,
+        dataspace: Option<String>,
     ) -> Result<String, AppError> {
         let iat = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -136,17 +632,24 @@ impl ServerToken {
                 user_id,
                 realm_access_roles,
             }),
-            None => Role::Machine(Machine { company_id }),
+            None => Role::Machine(Machine {
+                company_id,
+                dataspace,
+            }),
         };
 
         let service_access_claims = ServiceAccessTokenClaims {
             exp: iat + self.jwt_expiry_seconds,
             iat,
+            jti: uuid::Uuid::new_v4().to_string(),
+            sid: session_id,
+            scope,
             role,
         };
         let header = Header {
             typ: Some("JWT".to_owned()),
-            alg: jsonwebtoken::Algorithm::HS256,
+            alg: self.algorithm,
+            kid: self.kid.clone(),
             ..Default::default()
         };
 
@@ -158,17 +661,260 @@ impl ServerToken {
         &self,
         raw_roken: &String,
     ) -> Result<TokenData<ServiceAccessTokenClaims>, AppError> {
-        let validation = Validation::new(jsonwebtoken::Algorithm::HS256);
-        match decode::<ServiceAccessTokenClaims>(&raw_roken, &self.decoding_key, &validation) {
+        let unauthorized = |reason: String| {
+            AppError::Expected(ExpectedError {
+                status_code: StatusCode::UNAUTHORIZED,
+                message: "unauthorized".to_owned(),
+                metadata: None,
+                reason,
+            })
+        };
+
+        let header = decode_header(&raw_roken).map_err(|err| {
+            unauthorized(format!(
+                "Unable to decode server access token header: {:?}",
+                err
+            ))
+        })?;
+
+        let verification_key = self
+            .verification_keys
+            .iter()
+            .find(|k| k.kid == header.kid && k.algorithm == header.alg)
+            .ok_or_else(|| {
+                unauthorized(format!(
+                    "No verification key for server access token with kid '{:?}' and alg '{:?}'",
+                    header.kid, header.alg
+                ))
+            })?;
+
+        let validation = Validation::new(verification_key.algorithm);
+        match decode::<ServiceAccessTokenClaims>(
+            &raw_roken,
+            &verification_key.decoding_key,
+            &validation,
+        ) {
             Ok(token_data) => Ok(token_data),
             Err(err) => {
-                return Err(AppError::Expected(ExpectedError {
-                    status_code: StatusCode::UNAUTHORIZED,
-                    message: "unauthorized".to_owned(),
-                    metadata: None,
-                    reason: format!("Unable to decode server access token: {:?}", err),
-                }))
+                return Err(unauthorized(format!(
+                    "Unable to decode server access token: {:?}",
+                    err
+                )))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use openssl::{ec::EcGroup, rsa::Rsa};
+
+    use super::*;
+
+    fn generate_rsa_keys(kid: &str) -> (PemKey, PemKey) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        return (
+            PemKey {
+                kid: kid.to_owned(),
+                algorithm: JwtAlgorithm::RS256,
+                pem: key.private_key_to_pem_pkcs8().unwrap(),
+            },
+            PemKey {
+                kid: kid.to_owned(),
+                algorithm: JwtAlgorithm::RS256,
+                pem: key.public_key_to_pem().unwrap(),
+            },
+        );
+    }
+
+    fn generate_ec_keys(kid: &str) -> (PemKey, PemKey) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        return (
+            PemKey {
+                kid: kid.to_owned(),
+                algorithm: JwtAlgorithm::ES256,
+                pem: key.private_key_to_pem_pkcs8().unwrap(),
+            },
+            PemKey {
+                kid: kid.to_owned(),
+                algorithm: JwtAlgorithm::ES256,
+                pem: key.public_key_to_pem().unwrap(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_hs256() {
+        let server_token = ServerToken::new("secret".to_owned(), 3600);
+        let token = server_token
+            .create_token("NL.COMPANY".to_owned(), None)
+            .unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::HS256);
+        assert_eq!(header.kid, None);
+
+        let decoded = server_token.decode_token(&token).unwrap();
+        assert_eq!(decoded.claims.role.get_company_id(), "NL.COMPANY");
+        assert!(server_token.get_jwks().keys.is_empty());
+
+        let other_server_token = ServerToken::new("other-secret".to_owned(), 3600);
+        assert!(other_server_token.decode_token(&token).is_err());
+    }
+
+    #[test]
+    fn test_rs256() {
+        let (private_key, public_key) = generate_rsa_keys("key-1");
+        let server_token =
+            ServerToken::new_asymmetric(private_key, public_key, vec![], 3600).unwrap();
+
+        let token = server_token
+            .create_token("NL.COMPANY".to_owned(), None)
+            .unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        assert_eq!(header.kid, Some("key-1".to_owned()));
+
+        let decoded = server_token.decode_token(&token).unwrap();
+        assert_eq!(decoded.claims.role.get_company_id(), "NL.COMPANY");
+
+        // a third party only holding the published key is able to verify the token
+        let jwks = server_token.get_jwks();
+        assert_eq!(jwks.keys.len(), 1);
+        let jwk = jwks.find("key-1").unwrap();
+        let decoded = decode::<ServiceAccessTokenClaims>(
+            &token,
+            &DecodingKey::from_jwk(jwk).unwrap(),
+            &Validation::new(Algorithm::RS256),
+        )
+        .unwrap();
+        assert_eq!(decoded.claims.role.get_company_id(), "NL.COMPANY");
+    }
+
+    #[test]
+    fn test_es256() {
+        let (private_key, public_key) = generate_ec_keys("key-ec");
+        let server_token =
+            ServerToken::new_asymmetric(private_key, public_key, vec![], 3600).unwrap();
+
+        let token = server_token
+            .create_token(
+                "NL.COMPANY".to_owned(),
+                Some(UserOption {
+                    user_id: "user".to_owned(),
+                    realm_access_roles: vec![],
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(decode_header(&token).unwrap().alg, Algorithm::ES256);
+        assert!(server_token.decode_token(&token).is_ok());
+
+        let jwks = serde_json::to_value(server_token.get_jwks()).unwrap();
+        assert_eq!(jwks["keys"][0]["kty"], "EC");
+        assert_eq!(jwks["keys"][0]["crv"], "P-256");
+        assert_eq!(jwks["keys"][0]["kid"], "key-ec");
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let (old_private_key, old_public_key) = generate_rsa_keys("old");
+        let old_server_token =
+            ServerToken::new_asymmetric(old_private_key, old_public_key.clone(), vec![], 3600)
+                .unwrap();
+        let old_token = old_server_token
+            .create_token("NL.COMPANY".to_owned(), None)
+            .unwrap();
+
+        let (new_private_key, new_public_key) = generate_ec_keys("new");
+        let server_token = ServerToken::new_asymmetric(
+            new_private_key.clone(),
+            new_public_key.clone(),
+            vec![old_public_key],
+            3600,
+        )
+        .unwrap();
+
+        assert!(server_token.decode_token(&old_token).is_ok());
+        assert_eq!(server_token.get_jwks().keys.len(), 2);
+
+        let new_token = server_token
+            .create_token("NL.COMPANY".to_owned(), None)
+            .unwrap();
+        assert_eq!(
+            decode_header(&new_token).unwrap().kid,
+            Some("new".to_owned())
+        );
+        assert!(old_server_token.decode_token(&new_token).is_err());
+
+        // once the old key is removed its tokens are no longer accepted
+        let server_token =
+            ServerToken::new_asymmetric(new_private_key, new_public_key, vec![], 3600).unwrap();
+        assert!(server_token.decode_token(&old_token).is_err());
+
+        // a different key pair using the same kid is rejected as well
+        let (_, other_public_key) = generate_rsa_keys("old");
+        let (new_private_key, new_public_key) = generate_ec_keys("new");
+        let server_token = ServerToken::new_asymmetric(
+            new_private_key,
+            new_public_key,
+            vec![other_public_key],
+            3600,
+        )
+        .unwrap();
+        assert!(server_token.decode_token(&old_token).is_err());
+    }
+
+    #[test]
+    fn test_hs256_token_rejected_by_asymmetric_server_token() {
+        let (private_key, public_key) = generate_rsa_keys("key-1");
+        let server_token =
+            ServerToken::new_asymmetric(private_key, public_key, vec![], 3600).unwrap();
+
+        let hs256_token = ServerToken::new("secret".to_owned(), 3600)
+            .create_token("NL.COMPANY".to_owned(), None)
+            .unwrap();
+
+        assert!(server_token.decode_token(&hs256_token).is_err());
+    }
+
+    #[test]
+    fn test_parse_request_scope() {
+        assert_eq!(
+            Scope::parse_request_scope("iSHARE").unwrap(),
+            Scope::ALL.to_vec()
+        );
+        assert_eq!(
+            Scope::parse_request_scope("iSHARE policy:read  audit:read policy:read").unwrap(),
+            vec![Scope::PolicyRead, Scope::AuditRead]
+        );
+        assert!(Scope::parse_request_scope("policy:read").is_err());
+        assert!(Scope::parse_request_scope("iSHARE policy:delete").is_err());
+    }
+
+    #[test]
+    fn test_machine_token_scope() {
+        let server_token = ServerToken::new("secret".to_owned(), 3600);
+
+        let token = server_token
+            .create_machine_token("NL.COMPANY".to_owned(), &[Scope::PolicyRead], None)
+            .unwrap();
+        let claims = server_token.decode_token(&token).unwrap().claims;
+
+        assert_eq!(claims.scope, Some("policy:read".to_owned()));
+        assert!(claims.has_scope(Scope::PolicyRead));
+        assert!(!claims.has_scope(Scope::PolicyWrite));
+
+        // tokens without a scope claim are restricted by their role only
+        let token = server_token
+            .create_token("NL.COMPANY".to_owned(), None)
+            .unwrap();
+        let claims = server_token.decode_token(&token).unwrap().claims;
+
+        assert!(Scope::ALL.iter().all(|scope| claims.has_scope(*scope)));
+    }
+}
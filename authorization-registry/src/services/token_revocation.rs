@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sea_orm::DatabaseConnection;
+
+use crate::db::refresh_token as refresh_token_store;
+use crate::db::token_revocation::{self as token_revocation_store, InsertTokenRevocation};
+
+use super::server_token::ServerToken;
+
+const PURGE_INTERVAL_SECONDS: u64 = 3600;
+
+#[async_trait]
+pub trait RevocationStore: Send + Sync {
+    async fn revoke(
+        &self,
+        now: DateTime<Utc>,
+        revocation: InsertTokenRevocation,
+    ) -> anyhow::Result<()>;
+
+    async fn is_revoked(
+        &self,
+        jti: &str,
+        user_id: Option<&str>,
+        company_id: &str,
+        issued_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> anyhow::Result<u64>;
+
+    // revokes the refresh tokens of the sessions of the user or company, so no new access tokens
+    // can be obtained with them
+    async fn revoke_sessions(
+        &self,
+        now: DateTime<Utc>,
+        user_id: Option<&str>,
+        company_id: Option<&str>,
+    ) -> anyhow::Result<u64>;
+}
+
+pub struct DbRevocationStore {
+    db: DatabaseConnection,
+}
+
+impl DbRevocationStore {
+    pub fn new(db: DatabaseConnection) -> Self {
+        return Self { db };
+    }
+}
+
+#[async_trait]
+impl RevocationStore for DbRevocationStore {
+    async fn revoke(
+        &self,
+        now: DateTime<Utc>,
+        revocation: InsertTokenRevocation,
+    ) -> anyhow::Result<()> {
+        token_revocation_store::insert_token_revocation(now, revocation, &self.db).await
+    }
+
+    async fn is_revoked(
+        &self,
+        jti: &str,
+        user_id: Option<&str>,
+        company_id: &str,
+        issued_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        token_revocation_store::is_revoked(jti, user_id, company_id, issued_at, &self.db).await
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> anyhow::Result<u64> {
+        token_revocation_store::delete_expired(now, &self.db).await
+    }
+
+    async fn revoke_sessions(
+        &self,
+        now: DateTime<Utc>,
+        user_id: Option<&str>,
+        company_id: Option<&str>,
+    ) -> anyhow::Result<u64> {
+        refresh_token_store::revoke_subject(user_id, company_id, now, &self.db).await
+    }
+}
+
+// revocations are only needed until the revoked tokens have expired
+pub fn spawn_purge_job(server_token: Arc<ServerToken>) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECONDS));
+
+        loop {
+            interval.tick().await;
+
+            match server_token.purge_expired_revocations().await {
+                Ok(count) => tracing::info!("purged {} expired token revocations", count),
+                Err(e) => tracing::error!("error purging token revocations: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeDelta;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use crate::test_helpers::helpers::init_test_db;
+
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_purge_expired(_pool_options: PgPoolOptions, conn_option: PgConnectOptions) {
+        let db = init_test_db(&conn_option).await;
+        let store = DbRevocationStore::new(db);
+        let now = chrono::DateTime::parse_from_rfc3339("2025-06-11T09:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        for (jti, expires_at) in [
+            ("expired", now - TimeDelta::hours(1)),
+            ("active", now + TimeDelta::hours(1)),
+        ] {
+            store
+                .revoke(
+                    now - TimeDelta::hours(2),
+                    InsertTokenRevocation {
+                        jti: Some(jti.to_owned()),
+                        user_id: None,
+                        company_id: None,
+                        issued_before: None,
+                        expires_at,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(store.purge_expired(now).await.unwrap(), 1);
+
+        assert!(!store
+            .is_revoked("expired", None, "company", now)
+            .await
+            .unwrap());
+        assert!(store
+            .is_revoked("active", None, "company", now)
+            .await
+            .unwrap());
+    }
+}
@@ -3,28 +3,202 @@ pub mod helpers {
     use ar_migration::{Migrator, MigratorTrait};
     use axum::body::Body;
     use axum::{async_trait, Router};
-    use ishare::delegation_evidence::DelegationEvidenceContainer;
+    use ishare::delegation_evidence::{DelegationEvidence, DelegationEvidenceContainer};
     use ishare::ishare::{Adherence, Capabilities, PartyInfo, ValidatePartyError};
     use sea_orm::{Database, DatabaseConnection};
+    use serde::{Deserialize, Serialize};
     use serde_json::Value;
     use sqlx::{postgres::PgConnectOptions, ConnectOptions};
-    use std::sync::Arc;
+    use std::collections::BTreeMap;
     use std::sync::Once;
+    use std::sync::{Arc, Mutex};
     use tracing_subscriber::EnvFilter;
 
     static INIT: Once = Once::new();
 
     use crate::config::{
-        AddressConfig, ContactConfig, FooterConfig, FrontendConfig, GeneralConfig,
-        NavigationConfig, SocialsConfig,
+        AddressConfig, AdminRolesConfig, ContactConfig, FederationConfig, FooterConfig,
+        FrontendConfig, GeneralConfig, NavigationConfig, PartyCacheConfig, PartyChecksConfig,
+        RateLimitConfig, RemoteRegistryConfig, ReplicationConfig, SocialsConfig,
     };
-    use crate::error::AppError;
+    use crate::error::{AppError, ExpectedError};
     use crate::get_app;
-    use crate::services::ishare_provider::{OAuthRequestForm, SatelliteProvider};
-    use crate::services::server_token::{server_token_test_helper, UserOption};
+    use crate::services::dataspace::{Dataspace, Dataspaces};
+    use crate::services::federation::{Federation, RemoteRegistry};
+    use crate::services::identity_provider::{
+        AuthorizationRequest, IdentityProvider, IdpUser, OAuthRequestForm,
+    };
+    use crate::services::ishare_provider::SatelliteProvider;
+    use crate::services::party_cache::PartyCache;
+    use crate::services::party_info::{PartyAdherence, PartyDetails, PartyRole};
+    use crate::services::pdp_provider::AuthorizationProvider;
+    use crate::services::rate_limit::RateLimiter;
+    use crate::services::replication::{ReplicatedPolicySet, Replication, ReplicationTarget};
+    use crate::services::server_token::server_token_test_helper;
+    use crate::services::token_revocation::DbRevocationStore;
     use crate::AppState;
     use crate::TimeProvider;
 
+    // stands in for an external pdp: grants an access subject the actions on a resource type
+    // for any policy issuer
+    #[derive(Default)]
+    pub struct TestAuthorizationProvider {
+        pub grants: Vec<(String, String, String)>,
+    }
+
+    #[async_trait]
+    impl AuthorizationProvider for TestAuthorizationProvider {
+        async fn authorize(
+            &self,
+            access_subject: &str,
+            _policy_issuer: &str,
+            action: &str,
+            resource_type: &str,
+            _identifiers: Option<Vec<String>>,
+            _attributes: Option<Vec<String>>,
+        ) -> anyhow::Result<bool> {
+            return Ok(self.grants.iter().any(|(subject, r, a)| {
+                subject == access_subject && r == resource_type && a == action
+            }));
+        }
+    }
+
+    // stands in for a pdp that keeps a copy of the policy sets. Fails every call while `fail` is set
+    pub struct TestReplicationTarget {
+        pub eori: String,
+        pub policy_sets: Mutex<BTreeMap<String, ReplicatedPolicySet>>,
+        pub fail: Mutex<bool>,
+    }
+
+    // receives the policy sets of the fixtures with "good-company" as service provider
+    impl Default for TestReplicationTarget {
+        fn default() -> Self {
+            return Self {
+                eori: "good-company".to_owned(),
+                policy_sets: Mutex::default(),
+                fail: Mutex::default(),
+            };
+        }
+    }
+
+    #[async_trait]
+    impl ReplicationTarget for TestReplicationTarget {
+        fn eori(&self) -> &str {
+            return &self.eori;
+        }
+
+        async fn create_policy_set(
+            &self,
+            policy_set: &ReplicatedPolicySet,
+        ) -> anyhow::Result<String> {
+            if *self.fail.lock().unwrap() {
+                anyhow::bail!("target unavailable");
+            }
+            let remote_id = uuid::Uuid::new_v4().to_string();
+            self.policy_sets
+                .lock()
+                .unwrap()
+                .insert(remote_id.clone(), policy_set.clone());
+
+            return Ok(remote_id);
+        }
+
+        async fn delete_policy_set(&self, remote_id: &str) -> anyhow::Result<()> {
+            if *self.fail.lock().unwrap() {
+                anyhow::bail!("target unavailable");
+            }
+            self.policy_sets.lock().unwrap().remove(remote_id);
+
+            return Ok(());
+        }
+    }
+
+    // replication to the target "peer"
+    pub fn get_test_replication(target: Arc<TestReplicationTarget>) -> Replication {
+        return Replication::new(
+            ReplicationConfig::default(),
+            BTreeMap::from([("peer".to_owned(), target as Arc<dyn ReplicationTarget>)]),
+        );
+    }
+
+    // stands in for the registry "NL.REMOTE_AR" of the policy issuer "NL.REMOTE_ISSUER": permits
+    // every requested policy. Fails while `fail` is set and answers after `delay`
+    #[derive(Default)]
+    pub struct TestRemoteRegistry {
+        pub fail: Mutex<bool>,
+        pub delay: Mutex<Option<std::time::Duration>>,
+        pub calls: Mutex<u32>,
+    }
+
+    #[async_trait]
+    impl RemoteRegistry for TestRemoteRegistry {
+        async fn request_delegation_token(
+            &self,
+            delegation_request: &ishare::delegation_request::DelegationRequest,
+        ) -> anyhow::Result<String> {
+            *self.calls.lock().unwrap() += 1;
+            let delay = *self.delay.lock().unwrap();
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+            if *self.fail.lock().unwrap() {
+                anyhow::bail!("remote registry unavailable");
+            }
+
+            let now = FakeTimeProvider::new().now().timestamp();
+            let evidence = DelegationEvidence {
+                not_before: now - 60,
+                not_on_or_after: now + 600,
+                policy_issuer: delegation_request.policy_issuer.clone(),
+                target: ishare::delegation_evidence::DelegationTarget {
+                    access_subject: delegation_request.target.access_subject.clone(),
+                },
+                policy_sets: delegation_request
+                    .policy_sets
+                    .iter()
+                    .map(|ps| ishare::delegation_evidence::PolicySet {
+                        max_delegation_depth: 1,
+                        target: ishare::delegation_evidence::PolicySetTarget {
+                            environment: ishare::delegation_evidence::PolicySetTargetEnvironment {
+                                licenses: vec!["ISHARE.0001".to_owned()],
+                            },
+                        },
+                        policies: ps
+                            .policies
+                            .iter()
+                            .map(|p| {
+                                serde_json::from_value(serde_json::to_value(p).unwrap()).unwrap()
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            };
+
+            return Ok(create_test_delegation_token("NL.REMOTE_AR", None, evidence));
+        }
+    }
+
+    pub fn get_test_federation(registry: Arc<TestRemoteRegistry>) -> Federation {
+        return Federation::new(
+            &FederationConfig {
+                timeout_ms: 200,
+                failure_threshold: 2,
+                open_seconds: 60,
+                registries: vec![],
+            },
+            vec![(
+                RemoteRegistryConfig {
+                    id: "remote".to_owned(),
+                    url: "https://remote.example".to_owned(),
+                    eori: "NL.REMOTE_AR".to_owned(),
+                    policy_issuers: vec!["NL.REMOTE_ISSUER".to_owned()],
+                },
+                registry as Arc<dyn RemoteRegistry>,
+            )],
+        )
+        .unwrap();
+    }
+
     pub struct FakeTimeProvider;
 
     impl FakeTimeProvider {
@@ -60,6 +234,43 @@ pub mod helpers {
     }
 
     pub fn get_test_app(db: DatabaseConnection) -> Router {
+        return get_test_app_with_rate_limit(db, RateLimitConfig::default());
+    }
+
+    pub fn get_test_app_with_rate_limit(
+        db: DatabaseConnection,
+        rate_limit: RateLimitConfig,
+    ) -> Router {
+        return get_test_app_with(db, rate_limit, None);
+    }
+
+    pub fn get_test_app_with_authorization_provider(
+        db: DatabaseConnection,
+        authorization_provider: TestAuthorizationProvider,
+    ) -> Router {
+        return get_test_app_with(
+            db,
+            RateLimitConfig::default(),
+            Some(Arc::new(authorization_provider)),
+        );
+    }
+
+    fn get_test_app_with(
+        db: DatabaseConnection,
+        rate_limit: RateLimitConfig,
+        authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
+    ) -> Router {
+        let app_state = get_test_app_state(db.clone(), rate_limit, authorization_provider);
+        let app = get_app(db, app_state, true);
+
+        return app;
+    }
+
+    pub fn get_test_app_state(
+        db: DatabaseConnection,
+        rate_limit: RateLimitConfig,
+        authorization_provider: Option<Arc<dyn AuthorizationProvider>>,
+    ) -> AppState {
         INIT.call_once(|| {
             let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
                 EnvFilter::new("tower_http=debug,authorization_registry=debug,ishare=debug")
@@ -72,12 +283,20 @@ pub mod helpers {
         });
 
         let sat_provider = TestSatelliteProvider {};
-        let server_token = server_token_test_helper::get_test_service();
+        let time_provider: Arc<dyn TimeProvider> = Arc::new(FakeTimeProvider::new());
+        let server_token = server_token_test_helper::get_test_service()
+            .with_revocation_store(Arc::new(DbRevocationStore::new(db.clone())));
 
         let app_state = AppState {
             server_token: Arc::new(server_token),
             satellite_provider: Arc::new(sat_provider.clone()),
-            time_provider: Arc::new(FakeTimeProvider::new()),
+            identity_provider: Arc::new(TestIdentityProvider {}),
+            time_provider: time_provider.clone(),
+            rate_limiter: Arc::new(RateLimiter::new(rate_limit, time_provider)),
+            dataspaces: Arc::new(get_test_dataspaces()),
+            authorization_provider,
+            replication: Arc::new(get_test_replication(Arc::default())),
+            federation: Arc::new(get_test_federation(Arc::default())),
             de_expiry_seconds: 3600,
             config: Arc::new(crate::AppConfig {
                 service_name: "AR".to_owned(),
@@ -85,6 +304,19 @@ pub mod helpers {
                 client_eori: "NL.CONSUME_TOO_MUCH".to_owned(),
                 validate_m2m_certificate: true,
                 delegation_allows_service_providers: false,
+                audit_log_retention: None,
+                refresh_token_expiry_seconds: 86400,
+                session_lifetime_seconds: 604800,
+                auth_request_expiry_seconds: 600,
+                allowed_redirect_uris: vec!["http://frontend/callback".to_owned()],
+                admin_roles: AdminRolesConfig {
+                    admin: vec!["dexspace_admin".to_owned()],
+                    policy_admin: vec!["policy_admin".to_owned()],
+                    template_admin: vec!["template_admin".to_owned()],
+                    audit_reader: vec!["audit_reader".to_owned()],
+                    read_only_admin: vec!["read_only_admin".to_owned()],
+                },
+                party_checks: get_test_party_checks(),
                 frontend: FrontendConfig {
                     footer: FooterConfig {
                         navigation: NavigationConfig {
@@ -116,9 +348,94 @@ pub mod helpers {
                 },
             }),
         };
-        let app = get_app(db, app_state, true);
 
-        return app;
+        return app_state;
+    }
+
+    // adherence and the ServiceProvider role are checked, as recommended in the README
+    pub fn get_test_party_checks() -> PartyChecksConfig {
+        return PartyChecksConfig {
+            adherence: true,
+            service_provider_roles: vec!["ServiceProvider".to_owned()],
+            policy_issuer_roles: vec![],
+            access_subject_roles: vec![],
+        };
+    }
+
+    // the default dataspace and a second dataspace "other", both served by the test satellite
+    pub fn get_test_dataspaces() -> Dataspaces {
+        let dataspace = || Dataspace {
+            satellite_provider: Arc::new(TestSatelliteProvider {}),
+            party_cache: Arc::new(PartyCache::new(PartyCacheConfig::default())),
+        };
+
+        let mut dataspaces = Dataspaces::new("default".to_owned(), dataspace());
+        dataspaces.add("other".to_owned(), dataspace()).unwrap();
+
+        return dataspaces;
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestClientAssertionClaims {
+        iss: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        aud: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestDelegationClaims {
+        iss: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        aud: Option<String>,
+        #[serde(flatten)]
+        container: DelegationEvidenceContainer,
+    }
+
+    fn decode_test_token<T: serde::de::DeserializeOwned>(token: &str) -> anyhow::Result<T> {
+        let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+
+        return Ok(jsonwebtoken::decode::<T>(
+            token,
+            &jsonwebtoken::DecodingKey::from_secret(b"test"),
+            &validation,
+        )?
+        .claims);
+    }
+
+    fn encode_test_token<T: Serialize>(claims: &T) -> String {
+        return jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"test"),
+        )
+        .unwrap();
+    }
+
+    // a client assertion signed with a test secret instead of an iSHARE certificate
+    pub fn create_test_client_assertion(issuer: &str, audience: &str) -> String {
+        return encode_test_token(&TestClientAssertionClaims {
+            iss: issuer.to_owned(),
+            aud: Some(audience.to_owned()),
+        });
+    }
+
+    // a delegation token of another registry, signed with a test secret instead of an iSHARE
+    // certificate
+    pub fn create_test_delegation_token(
+        issuer: &str,
+        audience: Option<&str>,
+        delegation_evidence: DelegationEvidence,
+    ) -> String {
+        return encode_test_token(&TestDelegationClaims {
+            iss: issuer.to_owned(),
+            aud: audience.map(|a| a.to_owned()),
+            container: DelegationEvidenceContainer {
+                delegation_evidence,
+            },
+        });
     }
 
     #[derive(Clone)]
@@ -130,15 +447,24 @@ pub mod helpers {
             return Ok("token".to_string());
         }
 
-        fn handle_previous_step_client_assertion(
+        async fn verify_previous_step_client_assertion(
             &self,
             _now: chrono::DateTime<chrono::Utc>,
-            _requestor_company_id: &str,
-            _client_assertion: &str,
-            _policy_issuer: &str,
-            _access_subject: &str,
-        ) -> bool {
-            true
+            requestor_company_id: &str,
+            client_assertion: &str,
+            issuer: &str,
+        ) -> anyhow::Result<()> {
+            let claims: TestClientAssertionClaims = decode_test_token(client_assertion)?;
+
+            if claims.iss != issuer || claims.aud.as_deref() != Some(requestor_company_id) {
+                anyhow::bail!(
+                    "client assertion is issued by '{}' to '{:?}'",
+                    claims.iss,
+                    claims.aud
+                );
+            }
+
+            return Ok(());
         }
 
         fn create_delegation_token(
@@ -149,6 +475,26 @@ pub mod helpers {
             Ok("delegation token".to_owned())
         }
 
+        async fn verify_delegation_token(
+            &self,
+            _now: chrono::DateTime<chrono::Utc>,
+            delegation_token: &str,
+            issuer: &str,
+            audience: Option<&str>,
+        ) -> anyhow::Result<DelegationEvidence> {
+            let claims: TestDelegationClaims = decode_test_token(delegation_token)?;
+
+            if claims.iss != issuer || claims.aud.as_deref() != audience {
+                anyhow::bail!(
+                    "delegation token is issued by '{}' to '{:?}'",
+                    claims.iss,
+                    claims.aud
+                );
+            }
+
+            return Ok(claims.container.delegation_evidence);
+        }
+
         fn create_capabilities_token(
             &self,
             _aud: &str,
@@ -175,63 +521,120 @@ pub mod helpers {
             });
         }
 
-        fn handle_h2m_redirect_url_request(
+        async fn get_party_details(
+            &self,
+            _now: chrono::DateTime<chrono::Utc>,
+            eori: &str,
+        ) -> Result<PartyDetails, ValidatePartyError> {
+            let date =
+                |date: &str| Some(chrono::DateTime::parse_from_rfc3339(date).unwrap().to_utc());
+
+            // a party without roles, whose adherence ends within an hour of the fake time
+            if eori == "NL.NO_ROLES" {
+                return Ok(PartyDetails {
+                    party_id: eori.to_string(),
+                    adherence: PartyAdherence {
+                        status: "Active".to_string(),
+                        start_date: date("2023-01-01T00:00:00.000Z"),
+                        end_date: date("2024-05-09T10:00:00.000Z"),
+                    },
+                    roles: vec![],
+                });
+            }
+
+            return Ok(PartyDetails {
+                party_id: eori.to_string(),
+                adherence: PartyAdherence {
+                    status: "Active".to_string(),
+                    start_date: date("2023-01-01T00:00:00.000Z"),
+                    end_date: date("2026-03-25T00:00:00.000Z"),
+                },
+                roles: vec![PartyRole {
+                    role: "ServiceProvider".to_string(),
+                    start_date: None,
+                    end_date: None,
+                }],
+            });
+        }
+
+        async fn handle_m2m_authentication(
+            &self,
+            _now: chrono::DateTime<chrono::Utc>,
+            _client_id: &str,
+            _grant_type: &str,
+            client_assertion: &str,
+            _client_assertion_type: &str,
+            _scope: &str,
+            _validate_certificate: bool,
+        ) -> Result<String, AppError> {
+            if client_assertion == "invalid" {
+                return Err(AppError::Expected(ExpectedError {
+                    status_code: reqwest::StatusCode::BAD_REQUEST,
+                    message: "client assertion is invalid".to_owned(),
+                    reason: "invalid client assertion".to_owned(),
+                    metadata: None,
+                }));
+            }
+
+            return Ok("A_company".to_string());
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct TestIdentityProvider {}
+
+    #[async_trait]
+    impl IdentityProvider for TestIdentityProvider {
+        async fn get_authorization_url(
             &self,
             _server_url: &str,
-            _redirect_url: &str,
+            request: &AuthorizationRequest,
         ) -> anyhow::Result<String> {
-            let url = "a_url".to_string();
+            let url = format!("https://idp.example.com/auth?state={}", &request.state);
             Ok(url)
         }
 
-        fn get_h2m_redirect_base_url(&self) -> String {
-            let url = "a_url".to_string();
-            url
-        }
-
-        async fn get_h2m_redirect_form(
+        async fn get_authorization_form(
             &self,
             _server_url: &str,
-            _redirect_url: &str,
+            request: &AuthorizationRequest,
         ) -> anyhow::Result<OAuthRequestForm> {
             return Ok(OAuthRequestForm {
                 response_type: "code".to_owned(),
                 scope: "ishare openid".to_owned(),
-                request: "client_assertion".to_owned(),
+                request: Some("client_assertion".to_owned()),
                 client_id: "client_id".to_owned(),
-                state: "state".to_owned(),
+                state: request.state.clone(),
+                redirect_uri: None,
+                nonce: None,
+                code_challenge: Some(request.code_challenge()),
+                code_challenge_method: Some("S256".to_owned()),
             });
         }
 
-        async fn handle_h2m_auth_callback(
+        async fn get_authorization_endpoint(&self) -> anyhow::Result<String> {
+            let url = "a_url".to_string();
+            Ok(url)
+        }
+
+        async fn handle_callback(
             &self,
             _server_url: &str,
             _code: &str,
-        ) -> Result<(String, UserOption), AppError> {
-            let company_id = "A_company".to_string();
+            _request: &AuthorizationRequest,
+        ) -> Result<IdpUser, AppError> {
             let user_id = uuid::Uuid::new_v4().to_string();
-            let realm_access_roles = vec!["role1".to_string(), "role2".to_string()];
 
-            return Ok((
-                company_id,
-                UserOption {
-                    user_id,
-                    realm_access_roles,
-                },
-            ));
-        }
-
-        async fn handle_m2m_authentication(
-            &self,
-            _now: chrono::DateTime<chrono::Utc>,
-            _client_id: &str,
-            _grant_type: &str,
-            _client_assertion: &str,
-            _client_assertion_type: &str,
-            _scope: &str,
-            _validate_certificate: bool,
-        ) -> Result<String, AppError> {
-            return Ok("A_company".to_string());
+            return Ok(IdpUser {
+                sub: user_id.clone(),
+                email: format!("{}@example.com", user_id),
+                fullname: "Test User".to_owned(),
+                company_id: "A_company".to_string(),
+                company_name: "A company".to_owned(),
+                roles: vec!["role1".to_string(), "role2".to_string()],
+                idp_id: "NL.IDP".to_owned(),
+                idp_url: "https://idp.example.com".to_owned(),
+            });
         }
     }
 }